@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+CREATE TYPE role AS ENUM ('pacilian', 'caregiver');
+
+ALTER TABLE users
+ADD role role NOT NULL DEFAULT 'pacilian';
+
+UPDATE users u
+SET role = 'caregiver'
+FROM user_roles ur
+JOIN roles r ON r.id = ur.role_id
+WHERE ur.user_id = u.id AND r.name = 'caregiver';
+
+DROP TABLE IF EXISTS "user_roles";
+DROP TABLE IF EXISTS "role_permissions";
+DROP TABLE IF EXISTS "permissions";
+DROP TABLE IF EXISTS "roles";
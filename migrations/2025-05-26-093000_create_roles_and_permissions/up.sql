@@ -0,0 +1,76 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS "roles" (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    description VARCHAR(255) NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS "permissions" (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(128) NOT NULL UNIQUE,
+    description VARCHAR(255) NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS "role_permissions" (
+    role_id INTEGER NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+    permission_id INTEGER NOT NULL REFERENCES permissions(id) ON DELETE CASCADE,
+    PRIMARY KEY (role_id, permission_id)
+);
+
+CREATE TABLE IF NOT EXISTS "user_roles" (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role_id INTEGER NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+    granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    granted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role_id)
+);
+
+INSERT INTO roles (name, description) VALUES
+    ('pacilian', 'Patient using PandaCare'),
+    ('caregiver', 'Doctor or nurse providing care to pacilians'),
+    ('doctor', 'Licensed physician'),
+    ('pharmacist', 'Licensed pharmacist'),
+    ('support', 'Customer support staff'),
+    ('admin', 'Administrator of the authentication service');
+
+INSERT INTO permissions (name, description) VALUES
+    ('profile:read', 'Read your own profile'),
+    ('profile:write', 'Update your own profile'),
+    ('patients:read', 'Read medical data of assigned patients'),
+    ('prescriptions:write', 'Write prescriptions'),
+    ('prescriptions:dispense', 'Dispense prescribed medication'),
+    ('users:read', 'Look up other user accounts'),
+    ('users:manage', 'Manage other user accounts'),
+    ('roles:manage', 'Grant and revoke roles');
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT r.id, p.id
+FROM roles r
+JOIN permissions p ON (r.name, p.name) IN (
+    ('pacilian', 'profile:read'),
+    ('pacilian', 'profile:write'),
+    ('caregiver', 'profile:read'),
+    ('caregiver', 'profile:write'),
+    ('caregiver', 'patients:read'),
+    ('doctor', 'profile:read'),
+    ('doctor', 'profile:write'),
+    ('doctor', 'patients:read'),
+    ('doctor', 'prescriptions:write'),
+    ('pharmacist', 'profile:read'),
+    ('pharmacist', 'profile:write'),
+    ('pharmacist', 'prescriptions:dispense'),
+    ('support', 'profile:read'),
+    ('support', 'profile:write'),
+    ('support', 'users:read')
+) OR r.name = 'admin';
+
+-- Carry the existing single role of every user over before dropping the column
+INSERT INTO user_roles (user_id, role_id)
+SELECT u.id, r.id
+FROM users u
+JOIN roles r ON r.name = u.role::text;
+
+ALTER TABLE users
+DROP COLUMN role;
+
+DROP TYPE role;
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS "audit_events";
+DROP TABLE IF EXISTS "action_tokens";
+
+ALTER TABLE users
+DROP COLUMN must_reset_password,
+DROP COLUMN is_disabled,
+DROP COLUMN is_verified;
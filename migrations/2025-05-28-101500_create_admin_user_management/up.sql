@@ -0,0 +1,34 @@
+-- Your SQL goes here
+ALTER TABLE users
+ADD is_verified BOOLEAN NOT NULL DEFAULT FALSE,
+ADD is_disabled BOOLEAN NOT NULL DEFAULT FALSE,
+ADD must_reset_password BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Single-use tokens sent to users by email, e.g. password reset links.
+-- Only a SHA-256 hash of the token is stored.
+CREATE TABLE IF NOT EXISTS "action_tokens" (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose VARCHAR(32) NOT NULL,
+    expired_at TIMESTAMP NOT NULL,
+    consumed_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_action_tokens_on_user_id ON action_tokens (user_id);
+
+-- Actor and subject are deliberately not foreign keys so the trail outlives deleted users
+CREATE TABLE IF NOT EXISTS "audit_events" (
+    id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    actor_id UUID,
+    subject_id UUID,
+    event_type VARCHAR(64) NOT NULL,
+    ip_address VARCHAR(45),
+    user_agent TEXT,
+    outcome VARCHAR(16) NOT NULL,
+    reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS index_audit_events_on_actor_id ON audit_events (actor_id);
+CREATE INDEX IF NOT EXISTS index_audit_events_on_subject_id ON audit_events (subject_id);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- Failed login attempts, tracked both per account (subject is the user id)
+-- and per source IP address (subject is the address)
+CREATE TABLE IF NOT EXISTS "login_failures" (
+    scope VARCHAR(16) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    failure_count INTEGER NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    blocked_until TIMESTAMP,
+    PRIMARY KEY (scope, subject)
+);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- Token buckets shared by every instance when the Postgres rate limit store is used
+CREATE TABLE IF NOT EXISTS "rate_limit_buckets" (
+    bucket_key VARCHAR(255) PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
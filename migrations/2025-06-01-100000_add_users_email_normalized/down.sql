@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS index_users_on_email_normalized;
+
+ALTER TABLE "users" DROP COLUMN IF EXISTS email_normalized;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- Emails in NFKC and case folded, so that differently written forms of one
+-- address cannot be registered twice. Existing rows are backfilled with the
+-- closest Postgres equivalent; new rows are normalized by the application.
+ALTER TABLE "users" ADD COLUMN email_normalized VARCHAR(255);
+
+UPDATE "users" SET email_normalized = lower(normalize(btrim(email), NFKC));
+
+ALTER TABLE "users" ALTER COLUMN email_normalized SET NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS index_users_on_email_normalized ON "users" (email_normalized);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE refresh_tokens
+DROP COLUMN IF EXISTS amr;
+
+DROP TABLE IF EXISTS "totp_credentials";
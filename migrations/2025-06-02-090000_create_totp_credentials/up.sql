@@ -0,0 +1,15 @@
+-- Your SQL goes here
+-- One TOTP authenticator per user. The secret is encrypted with the data key,
+-- and the last accepted time step is kept so that a code cannot be replayed.
+CREATE TABLE IF NOT EXISTS "totp_credentials" (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    secret_encrypted TEXT NOT NULL,
+    confirmed_at TIMESTAMP,
+    last_used_step BIGINT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Space separated authentication methods (RFC 8176) of the login a refresh
+-- token descends from, so that refreshed access tokens keep them
+ALTER TABLE refresh_tokens
+ADD amr VARCHAR(64) NOT NULL DEFAULT 'pwd';
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Single-use codes for signing in without the TOTP authenticator. Only
+-- Argon2 hashes of the codes are stored.
+CREATE TABLE IF NOT EXISTS "recovery_codes" (
+    id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR(255) NOT NULL,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_recovery_codes_on_user_id ON recovery_codes (user_id);
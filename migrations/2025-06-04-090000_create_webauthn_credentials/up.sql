@@ -0,0 +1,24 @@
+-- Your SQL goes here
+-- Passkeys and security keys. The public key is an uncompressed P-256 point,
+-- and the sign count is kept to spot cloned authenticators.
+CREATE TABLE IF NOT EXISTS "webauthn_credentials" (
+    credential_id VARCHAR(1024) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    public_key BYTEA NOT NULL,
+    sign_count BIGINT NOT NULL DEFAULT 0,
+    name VARCHAR(255),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS index_webauthn_credentials_on_user_id ON webauthn_credentials (user_id);
+
+-- Challenges handed out for a ceremony, each usable once. Passwordless
+-- sign-in challenges are not tied to a user until a credential answers them.
+CREATE TABLE IF NOT EXISTS "webauthn_challenges" (
+    challenge VARCHAR(64) PRIMARY KEY,
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    ceremony VARCHAR(16) NOT NULL,
+    expired_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
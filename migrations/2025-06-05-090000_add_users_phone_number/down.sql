@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS "sms_codes";
+
+-- Phone-only accounts cannot be kept once email and password are required again
+DELETE FROM "users" WHERE email IS NULL OR password IS NULL;
+
+ALTER TABLE "users" DROP CONSTRAINT IF EXISTS users_email_normalized_present;
+ALTER TABLE "users" DROP CONSTRAINT IF EXISTS users_email_or_phone_number;
+
+ALTER TABLE "users" ALTER COLUMN password SET NOT NULL;
+ALTER TABLE "users" ALTER COLUMN email_normalized SET NOT NULL;
+ALTER TABLE "users" ALTER COLUMN email SET NOT NULL;
+
+DROP INDEX IF EXISTS index_users_on_phone_number;
+
+ALTER TABLE "users" DROP COLUMN IF EXISTS phone_number;
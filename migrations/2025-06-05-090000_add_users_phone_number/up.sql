@@ -0,0 +1,26 @@
+-- Your SQL goes here
+-- Users may sign up with a phone number instead of an email address. They
+-- sign in with codes sent by SMS and have no password.
+ALTER TABLE "users" ADD COLUMN IF NOT EXISTS phone_number VARCHAR(16);
+
+CREATE UNIQUE INDEX IF NOT EXISTS index_users_on_phone_number ON "users" (phone_number);
+
+ALTER TABLE "users" ALTER COLUMN email DROP NOT NULL;
+ALTER TABLE "users" ALTER COLUMN email_normalized DROP NOT NULL;
+ALTER TABLE "users" ALTER COLUMN password DROP NOT NULL;
+
+ALTER TABLE "users" ADD CONSTRAINT users_email_or_phone_number
+    CHECK (email IS NOT NULL OR phone_number IS NOT NULL);
+
+ALTER TABLE "users" ADD CONSTRAINT users_email_normalized_present
+    CHECK ((email IS NULL) = (email_normalized IS NULL));
+
+-- The pending SMS code of a phone number, whether or not it belongs to an
+-- account yet. Sending a new code replaces the previous one.
+CREATE TABLE IF NOT EXISTS "sms_codes" (
+    phone_number VARCHAR(16) PRIMARY KEY,
+    code_hash VARCHAR(64) NOT NULL,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    sent_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expired_at TIMESTAMP NOT NULL
+);
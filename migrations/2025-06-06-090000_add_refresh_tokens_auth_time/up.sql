@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+-- When the user last proved who they are in the chain of refreshes a token
+-- belongs to. Unlike `issued_at`, refreshing does not move it forward.
+ALTER TABLE refresh_tokens
+ADD auth_time TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+UPDATE refresh_tokens SET auth_time = issued_at;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permissions WHERE name = 'caregivers:verify';
+
+DROP TABLE IF EXISTS "caregiver_documents";
+DROP TABLE IF EXISTS "caregiver_applications";
@@ -0,0 +1,46 @@
+-- Your SQL goes here
+
+-- Requests to work as a caregiver. Applicants keep pacilian-level access
+-- until an administrator checks their STR (Surat Tanda Registrasi) license
+-- and documents, and the caregiver role is only granted on approval.
+CREATE TABLE IF NOT EXISTS "caregiver_applications" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    str_number VARCHAR(32) NOT NULL,
+    status VARCHAR(32) NOT NULL DEFAULT 'pending_verification',
+    rejection_reason TEXT,
+    reviewed_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMP,
+    submitted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT caregiver_applications_status
+        CHECK (status IN ('pending_verification', 'approved', 'rejected'))
+);
+
+CREATE INDEX IF NOT EXISTS index_caregiver_applications_on_user_id
+ON caregiver_applications (user_id);
+
+-- At most one application per user waits for review at a time
+CREATE UNIQUE INDEX IF NOT EXISTS index_caregiver_applications_pending_on_user_id
+ON caregiver_applications (user_id)
+WHERE status = 'pending_verification';
+
+-- Supporting documents, stored elsewhere and referenced by URL
+CREATE TABLE IF NOT EXISTS "caregiver_documents" (
+    id BIGSERIAL PRIMARY KEY,
+    application_id UUID NOT NULL REFERENCES caregiver_applications(id) ON DELETE CASCADE,
+    document_type VARCHAR(32) NOT NULL,
+    url VARCHAR(2048) NOT NULL,
+    submitted_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_caregiver_documents_on_application_id
+ON caregiver_documents (application_id);
+
+INSERT INTO permissions (name, description) VALUES
+    ('caregivers:verify', 'Review caregiver applications');
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT r.id, p.id
+FROM roles r
+JOIN permissions p ON p.name = 'caregivers:verify'
+WHERE r.name = 'admin';
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+
+-- Lets one user act for another, e.g. a family member managing appointments
+-- for an elderly or child patient. The patient (subject) invites the delegate
+-- (actor) by email, and the delegation takes effect once the delegate signs
+-- in and accepts. Only a SHA-256 hash of the invitation token is stored.
+CREATE TABLE IF NOT EXISTS "delegations" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    subject_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    actor_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    invited_email VARCHAR(255) NOT NULL,
+    -- Space separated, like the `scope` claim of tokens issued with it
+    scopes VARCHAR(255) NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    invitation_token_hash VARCHAR(64) UNIQUE,
+    invitation_expired_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    accepted_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    CONSTRAINT delegations_status CHECK (status IN ('pending', 'active', 'revoked'))
+);
+
+CREATE INDEX IF NOT EXISTS index_delegations_on_subject_id ON delegations (subject_id);
+CREATE INDEX IF NOT EXISTS index_delegations_on_actor_id ON delegations (actor_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS index_delegations_active_on_subject_id_and_actor_id
+ON delegations (subject_id, actor_id)
+WHERE status = 'active';
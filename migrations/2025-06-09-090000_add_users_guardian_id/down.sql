@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+-- Dependents cannot be kept once every user needs a way to sign in
+DELETE FROM "users" WHERE guardian_id IS NOT NULL;
+
+ALTER TABLE "users" DROP CONSTRAINT IF EXISTS users_dependent_without_credentials;
+ALTER TABLE "users" DROP CONSTRAINT IF EXISTS users_email_or_phone_number;
+
+ALTER TABLE "users" ADD CONSTRAINT users_email_or_phone_number
+    CHECK (email IS NOT NULL OR phone_number IS NOT NULL);
+
+DROP INDEX IF EXISTS index_users_on_guardian_id;
+
+ALTER TABLE "users" DROP COLUMN IF EXISTS guardian_id;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- Dependents, such as young children, are profile-only users owned by a
+-- guardian. They have no credentials until they are converted into a full
+-- account, which clears the guardian. Deleting a guardian who still has
+-- dependents is refused, so that children's records never go with them.
+ALTER TABLE "users" ADD COLUMN IF NOT EXISTS guardian_id UUID REFERENCES users(id) ON DELETE RESTRICT;
+
+CREATE INDEX IF NOT EXISTS index_users_on_guardian_id ON "users" (guardian_id);
+
+ALTER TABLE "users" DROP CONSTRAINT IF EXISTS users_email_or_phone_number;
+
+ALTER TABLE "users" ADD CONSTRAINT users_email_or_phone_number
+    CHECK (email IS NOT NULL OR phone_number IS NOT NULL OR guardian_id IS NOT NULL);
+
+ALTER TABLE "users" ADD CONSTRAINT users_dependent_without_credentials
+    CHECK (guardian_id IS NULL OR (email IS NULL AND password IS NULL AND phone_number IS NULL));
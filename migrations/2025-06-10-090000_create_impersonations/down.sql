@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permissions WHERE name = 'users:impersonate';
+
+DROP TABLE IF EXISTS "impersonations";
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+
+-- Support staff signing in as a user to troubleshoot what they see. Each
+-- impersonation issues a single short-lived access token, and users can list
+-- the impersonations of their account.
+CREATE TABLE IF NOT EXISTS "impersonations" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    admin_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    -- Space separated, like the `scope` claim of the token
+    scopes VARCHAR(255) NOT NULL,
+    issued_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expired_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS index_impersonations_on_user_id ON impersonations (user_id);
+
+INSERT INTO permissions (name, description) VALUES
+    ('users:impersonate', 'Act as another user to troubleshoot their account');
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT r.id, p.id
+FROM roles r
+JOIN permissions p ON p.name = 'users:impersonate'
+WHERE r.name = 'admin';
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permissions WHERE name = 'audit:read';
+
+DROP INDEX IF EXISTS index_audit_events_on_occurred_at;
+DROP INDEX IF EXISTS index_audit_events_on_event_type;
+
+DROP TRIGGER IF EXISTS audit_events_no_truncate ON audit_events;
+DROP TRIGGER IF EXISTS audit_events_no_update_or_delete ON audit_events;
+
+DROP FUNCTION IF EXISTS audit_events_append_only();
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+
+-- The audit trail is append-only: rows can be added but never changed or
+-- removed, not even by the application's own database user
+CREATE OR REPLACE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_no_update_or_delete
+BEFORE UPDATE OR DELETE ON audit_events
+FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
+
+CREATE TRIGGER audit_events_no_truncate
+BEFORE TRUNCATE ON audit_events
+FOR EACH STATEMENT EXECUTE FUNCTION audit_events_append_only();
+
+-- Administrators search the trail by event type and time
+CREATE INDEX IF NOT EXISTS index_audit_events_on_event_type ON audit_events (event_type);
+CREATE INDEX IF NOT EXISTS index_audit_events_on_occurred_at ON audit_events (occurred_at);
+
+INSERT INTO permissions (name, description) VALUES
+    ('audit:read', 'Search and export the security audit log');
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT r.id, p.id
+FROM roles r
+JOIN permissions p ON p.name = 'audit:read'
+WHERE r.name = 'admin';
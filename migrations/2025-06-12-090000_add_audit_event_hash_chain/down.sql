@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS audit_checkpoints;
+
+ALTER TABLE audit_events
+    DROP COLUMN IF EXISTS entry_hash,
+    DROP COLUMN IF EXISTS previous_hash;
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+
+-- Each entry carries the hash of the one before it, so that editing or
+-- removing an entry breaks every hash after it. Entries written before the
+-- chain existed keep both columns empty.
+ALTER TABLE audit_events
+    ADD COLUMN previous_hash VARCHAR(64),
+    ADD COLUMN entry_hash VARCHAR(64);
+
+-- Hashes of the chain signed with the service's signing key from time to
+-- time, so that rewriting the whole chain needs the key too
+CREATE TABLE IF NOT EXISTS audit_checkpoints (
+    id BIGSERIAL PRIMARY KEY,
+    last_event_id BIGINT NOT NULL REFERENCES audit_events,
+    entry_hash VARCHAR(64) NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER audit_checkpoints_no_update_or_delete
+BEFORE UPDATE OR DELETE ON audit_checkpoints
+FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
+
+CREATE TRIGGER audit_checkpoints_no_truncate
+BEFORE TRUNCATE ON audit_checkpoints
+FOR EACH STATEMENT EXECUTE FUNCTION audit_events_append_only();
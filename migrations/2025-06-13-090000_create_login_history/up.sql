@@ -0,0 +1,19 @@
+-- Your SQL goes here
+
+-- Every sign in with a password, so that users can see where their account
+-- was used and be told about devices and places it was never used from
+CREATE TABLE IF NOT EXISTS "login_history" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip_address VARCHAR(45),
+    user_agent TEXT,
+    -- Coarse location from the GeoIP database, when there is one
+    country_code VARCHAR(2),
+    city VARCHAR(255),
+    new_device BOOLEAN NOT NULL DEFAULT FALSE,
+    new_location BOOLEAN NOT NULL DEFAULT FALSE,
+    logged_in_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_login_history_on_user_id_and_logged_in_at
+    ON login_history (user_id, logged_in_at);
@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS set_updated_at ON users;
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_deleted_at;
+
+ALTER TABLE users ADD COLUMN is_disabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE users SET is_disabled = TRUE WHERE status <> 'active';
+
+ALTER TABLE users
+    DROP COLUMN IF EXISTS deleted_at,
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS created_at,
+    DROP COLUMN IF EXISTS status;
+
+DROP TYPE IF EXISTS user_status;
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+
+CREATE TYPE user_status AS ENUM ('active', 'suspended', 'disabled', 'deleted');
+
+-- Accounts are blocked or removed by changing their status rather than
+-- deleting the row, which other services still point at
+ALTER TABLE users
+    ADD COLUMN status user_status NOT NULL DEFAULT 'active',
+    ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    ADD COLUMN deleted_at TIMESTAMP;
+
+UPDATE users SET status = 'disabled' WHERE is_disabled;
+
+ALTER TABLE users DROP COLUMN is_disabled;
+
+ALTER TABLE users
+    ADD CONSTRAINT users_deleted_at
+        CHECK ((status = 'deleted') = (deleted_at IS NOT NULL));
+
+SELECT diesel_manage_updated_at('users');
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS index_refresh_tokens_on_user_id;
+
+ALTER TABLE refresh_tokens
+    DROP CONSTRAINT IF EXISTS refresh_tokens_user_id_fkey,
+    ALTER COLUMN user_id TYPE VARCHAR(255) USING user_id::text;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+
+-- Tokens that do not belong to an existing user cannot be converted and
+-- could never be refreshed anyway. Non-UUIDs go first, so that the cast in
+-- the second statement cannot fail.
+DELETE FROM refresh_tokens
+WHERE user_id !~* '^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$';
+
+DELETE FROM refresh_tokens
+WHERE NOT EXISTS (SELECT 1 FROM users WHERE users.id = refresh_tokens.user_id::uuid);
+
+-- Deleting a user takes their tokens with them
+ALTER TABLE refresh_tokens
+    ALTER COLUMN user_id TYPE UUID USING user_id::uuid,
+    ADD CONSTRAINT refresh_tokens_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS index_refresh_tokens_on_user_id ON refresh_tokens (user_id);
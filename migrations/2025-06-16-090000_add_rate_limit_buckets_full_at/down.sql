@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS index_rate_limit_buckets_on_full_at;
+
+ALTER TABLE rate_limit_buckets DROP COLUMN full_at;
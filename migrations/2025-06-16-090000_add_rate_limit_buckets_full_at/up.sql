@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- When a bucket has refilled completely. From then on it behaves like a
+-- missing one, so it can be deleted.
+ALTER TABLE rate_limit_buckets ADD COLUMN full_at TIMESTAMP NOT NULL DEFAULT NOW();
+
+CREATE INDEX IF NOT EXISTS index_rate_limit_buckets_on_full_at ON rate_limit_buckets (full_at);
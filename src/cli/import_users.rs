@@ -0,0 +1,54 @@
+use std::{
+    fs::File,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use crate::{db::DbPool, services::import};
+
+const USAGE: &str = "Usage: pandacare-auth import-users <file.csv|file.jsonl> [--format csv|jsonl]";
+
+/// Imports users from a CSV or JSON Lines export of another system, picking
+/// the format from the file extension unless `--format` is given.
+///
+/// CSV files need an `email,password_hash,roles` header, with roles separated
+/// by `;`. JSON Lines files hold one `{"email", "password_hash", "roles"}`
+/// object per line.
+pub fn run(pool: &DbPool, args: &[String]) -> std::io::Result<()> {
+    let (path, format) = match args {
+        [path] => (path, extension_of(path)),
+        [path, flag, format] if flag == "--format" => (path, Some(format.to_lowercase())),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    };
+
+    let file = File::open(path)?;
+    let mut conn = pool
+        .get()
+        .map_err(|err| Error::new(ErrorKind::ConnectionRefused, err.to_string()))?;
+
+    let summary = match format.as_deref() {
+        Some("csv") => import::import_users(&mut conn, import::read_csv(file)),
+        Some("jsonl") => import::import_users(&mut conn, import::read_jsonl(file)),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    };
+
+    for (record, reason) in &summary.failed {
+        log::warn!("Record {} not imported: {}", record, reason);
+    }
+
+    println!(
+        "Imported {} users, skipped {} already registered, {} failed",
+        summary.imported,
+        summary.already_existing,
+        summary.failed.len()
+    );
+
+    Ok(())
+}
+
+fn extension_of(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+}
@@ -0,0 +1,21 @@
+use std::io::{Error, ErrorKind};
+
+use crate::db::DbPool;
+
+mod import_users;
+mod verify_audit_log;
+
+/// Runs a maintenance command given on the command line instead of the server.
+pub fn run(pool: &DbPool, command: &str, args: &[String]) -> std::io::Result<()> {
+    match command {
+        "import-users" => import_users::run(pool, args),
+        "verify-audit-log" => verify_audit_log::run(pool, args),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Unknown command: {}. Available commands: import-users, verify-audit-log",
+                command
+            ),
+        )),
+    }
+}
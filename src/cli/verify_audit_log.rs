@@ -0,0 +1,42 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+};
+
+use crate::{db::DbPool, services::audit};
+
+const USAGE: &str = "Usage: pandacare-auth verify-audit-log [path/to/rsa-private.pem]";
+
+/// Walks the audit log from its first entry, recomputing the hash chain and
+/// checking the signed checkpoints, and reports the first break. Exits with
+/// an error if there is one.
+///
+/// Checkpoints are checked against the server's signing key, read from
+/// `keys/rsa-private.pem` unless another path is given.
+pub fn run(pool: &DbPool, args: &[String]) -> std::io::Result<()> {
+    let key_path = match args {
+        [] => "keys/rsa-private.pem",
+        [path] => path.as_str(),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, USAGE)),
+    };
+
+    let secret_key = fs::read_to_string(key_path)?;
+    let mut conn = pool
+        .get()
+        .map_err(|err| Error::new(ErrorKind::ConnectionRefused, err.to_string()))?;
+
+    let summary = audit::verify_chain(&mut conn, &secret_key)
+        .map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?;
+
+    println!(
+        "Audit log intact: {} chained events, {} signed checkpoints, {} events from before the chain",
+        summary.chained_events, summary.checkpoints, summary.unchained_events
+    );
+
+    match summary.last_checkpoint_at {
+        Some(created_at) => println!("Last checkpoint signed at {} UTC", created_at),
+        None => println!("No checkpoint has been signed yet"),
+    }
+
+    Ok(())
+}
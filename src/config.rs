@@ -0,0 +1,250 @@
+use std::{env, str::FromStr, sync::LazyLock};
+
+use crate::models::rate_limit::Quota;
+
+static CONFIG: LazyLock<Config> = LazyLock::new(Config::from_env);
+
+pub struct Config {
+    /// Whether issued tokens carry the user's full name from their profile
+    pub include_name_in_tokens: bool,
+    /// Whether to take the client IP from `Forwarded`/`X-Forwarded-For` headers.
+    /// Only enable this behind a reverse proxy that overwrites them.
+    pub trust_proxy_headers: bool,
+    /// Base URL of the web frontend, used to build links sent by email
+    pub frontend_url: String,
+    pub password_reset_ttl_minutes: i64,
+    /// How long a sign in link or code sent by email stays valid
+    pub email_login_ttl_minutes: i64,
+    /// How recently the user must have signed in to see sensitive data such
+    /// as their NIK. Older sessions have to step up first.
+    pub sensitive_data_max_auth_age_minutes: i64,
+    /// How long an invitation to act for a patient stays open
+    pub delegation_invitation_ttl_hours: i64,
+    /// Age at which a dependent can be converted into a full account
+    pub age_of_majority_years: u32,
+    /// How often the server signs the newest hash of the audit log
+    pub audit_checkpoint_interval_minutes: u64,
+    /// CSV of networks and their locations used to tell where sign ins come
+    /// from. Unset records sign ins without a location.
+    pub geoip_database_path: Option<String>,
+    /// How long the link in a new sign in email can sign the user out everywhere
+    pub login_alert_ttl_hours: i64,
+    pub lockout: LockoutConfig,
+    pub rate_limit: RateLimitConfig,
+    pub password_hashing: PasswordHashingConfig,
+    pub password_policy: PasswordPolicyConfig,
+    pub mfa: MfaConfig,
+    pub webauthn: WebAuthnConfig,
+    pub sms: SmsConfig,
+}
+
+pub struct SmsConfig {
+    /// Calling code assumed for numbers written in national form, e.g.
+    /// `0812...` in Indonesia
+    pub default_country_code: String,
+    pub code_ttl_minutes: i64,
+    /// Time a number has to wait before another code is sent to it
+    pub resend_cooldown_seconds: i64,
+    /// Wrong guesses after which a code stops working
+    pub max_code_attempts: i32,
+}
+
+pub struct WebAuthnConfig {
+    /// The domain passkeys are bound to, e.g. `pandacare.id`
+    pub rp_id: String,
+    /// Shown by the browser and authenticator when creating a passkey
+    pub rp_name: String,
+    /// Origins ceremonies may come from. Defaults to the frontend URL.
+    pub allowed_origins: Vec<String>,
+    pub challenge_ttl_minutes: i64,
+}
+
+pub struct MfaConfig {
+    /// Roles whose members cannot sign in without a second factor, e.g.
+    /// `caregiver`. Members without one set it up while signing in.
+    pub required_roles: Vec<String>,
+    /// Shown next to the account in authenticator apps
+    pub totp_issuer: String,
+    /// How long a user has to enter their code after their password
+    pub challenge_ttl_minutes: i64,
+}
+
+pub struct PasswordPolicyConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// From 0 (trivial to guess) to 4 (very hard to guess)
+    pub min_strength_score: u8,
+    /// A sorted SHA-1 file or a directory of k-anonymity range files of
+    /// breached passwords. Unset skips the check.
+    pub breached_passwords_path: Option<String>,
+}
+
+/// Argon2id cost settings. Raising them makes existing hashes outdated, and
+/// each is rehashed with the new settings the next time its owner logs in.
+pub struct PasswordHashingConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Secret mixed into every hash and kept out of the database, so that a
+    /// leaked users table alone is not enough to crack passwords. Changing it
+    /// invalidates every password hashed with the old one.
+    pub pepper: Option<String>,
+}
+
+pub struct LockoutConfig {
+    /// Failures older than this no longer count towards delays or lockouts
+    pub failure_window_minutes: i64,
+    /// Failures allowed before every further attempt is delayed
+    pub backoff_threshold: i32,
+    /// Delay after the first failure past the threshold; it doubles with each failure
+    pub backoff_base_seconds: i64,
+    /// Failures on one account that lock it temporarily
+    pub account_lockout_threshold: i32,
+    /// Failures from one IP address that block it temporarily. Kept higher than
+    /// the account threshold since many users can share an address.
+    pub ip_lockout_threshold: i32,
+    pub lockout_minutes: i64,
+}
+
+/// Quotas are written as `<capacity>/<period_seconds>`, e.g. `10/60`
+pub struct RateLimitConfig {
+    pub store: RateLimitStoreKind,
+    /// How often buckets that have refilled completely are forgotten
+    pub prune_interval_seconds: u64,
+    pub obtain_per_ip: Quota,
+    pub obtain_per_email: Quota,
+    /// Sign in links and codes sent to one address
+    pub email_login_per_email: Quota,
+    /// SMS codes sent to one number, on top of the resend cooldown
+    pub sms_code_per_number: Quota,
+    /// SMS codes requested from one IP address, whatever the number
+    pub sms_code_per_ip: Quota,
+    pub register_per_ip: Quota,
+    pub refresh_per_ip: Quota,
+    /// Email lookups by one signed in user, or one IP address otherwise
+    pub email_lookup_per_client: Quota,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitStoreKind {
+    /// Buckets live in each process; fine for a single instance
+    Memory,
+    /// Buckets are shared between instances through the database
+    Postgres,
+}
+
+impl FromStr for RateLimitStoreKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(format!("Unknown rate limit store: {}", value)),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            include_name_in_tokens: env_or("TOKEN_INCLUDE_NAME", false),
+            trust_proxy_headers: env_or("TRUST_PROXY_HEADERS", false),
+            frontend_url: env_or("FRONTEND_URL", "http://localhost:3000".to_string()),
+            password_reset_ttl_minutes: env_or("PASSWORD_RESET_TTL_MINUTES", 60),
+            email_login_ttl_minutes: env_or("EMAIL_LOGIN_TTL_MINUTES", 10),
+            sensitive_data_max_auth_age_minutes: env_or("SENSITIVE_DATA_MAX_AUTH_AGE_MINUTES", 15),
+            delegation_invitation_ttl_hours: env_or("DELEGATION_INVITATION_TTL_HOURS", 72),
+            age_of_majority_years: env_or("AGE_OF_MAJORITY_YEARS", 18),
+            audit_checkpoint_interval_minutes: env_or("AUDIT_CHECKPOINT_INTERVAL_MINUTES", 60),
+            geoip_database_path: env::var("GEOIP_DATABASE_PATH").ok(),
+            login_alert_ttl_hours: env_or("LOGIN_ALERT_TTL_HOURS", 72),
+            lockout: LockoutConfig {
+                failure_window_minutes: env_or("LOGIN_FAILURE_WINDOW_MINUTES", 60),
+                backoff_threshold: env_or("LOGIN_BACKOFF_THRESHOLD", 3),
+                backoff_base_seconds: env_or("LOGIN_BACKOFF_BASE_SECONDS", 1),
+                account_lockout_threshold: env_or("LOGIN_ACCOUNT_LOCKOUT_THRESHOLD", 10),
+                ip_lockout_threshold: env_or("LOGIN_IP_LOCKOUT_THRESHOLD", 50),
+                lockout_minutes: env_or("LOGIN_LOCKOUT_MINUTES", 15),
+            },
+            password_hashing: PasswordHashingConfig {
+                memory_kib: env_or("ARGON2_MEMORY_KIB", 19_456),
+                iterations: env_or("ARGON2_ITERATIONS", 2),
+                parallelism: env_or("ARGON2_PARALLELISM", 1),
+                pepper: env::var("PASSWORD_PEPPER")
+                    .ok()
+                    .filter(|pepper| !pepper.is_empty()),
+            },
+            password_policy: PasswordPolicyConfig {
+                min_length: env_or("PASSWORD_MIN_LENGTH", 8),
+                max_length: env_or("PASSWORD_MAX_LENGTH", 128),
+                min_strength_score: env_or("PASSWORD_MIN_STRENGTH_SCORE", 3),
+                breached_passwords_path: env::var("BREACHED_PASSWORDS_PATH").ok(),
+            },
+            mfa: MfaConfig {
+                required_roles: env::var("MFA_REQUIRED_ROLES")
+                    .map(|roles| {
+                        roles
+                            .split(',')
+                            .map(|role| role.trim().to_string())
+                            .filter(|role| !role.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                totp_issuer: env_or("TOTP_ISSUER", "PandaCare".to_string()),
+                challenge_ttl_minutes: env_or("MFA_CHALLENGE_TTL_MINUTES", 5),
+            },
+            webauthn: WebAuthnConfig {
+                rp_id: env_or("WEBAUTHN_RP_ID", "localhost".to_string()),
+                rp_name: env_or("WEBAUTHN_RP_NAME", "PandaCare".to_string()),
+                allowed_origins: env::var("WEBAUTHN_ALLOWED_ORIGINS")
+                    .map(|origins| {
+                        origins
+                            .split(',')
+                            .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                            .filter(|origin| !origin.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_else(|_| {
+                        vec![env_or("FRONTEND_URL", "http://localhost:3000".to_string())]
+                    }),
+                challenge_ttl_minutes: env_or("WEBAUTHN_CHALLENGE_TTL_MINUTES", 5),
+            },
+            sms: SmsConfig {
+                default_country_code: env_or("SMS_DEFAULT_COUNTRY_CODE", "62".to_string()),
+                code_ttl_minutes: env_or("SMS_CODE_TTL_MINUTES", 5),
+                resend_cooldown_seconds: env_or("SMS_RESEND_COOLDOWN_SECONDS", 60),
+                max_code_attempts: env_or("SMS_MAX_CODE_ATTEMPTS", 5),
+            },
+            rate_limit: RateLimitConfig {
+                store: env_or("RATE_LIMIT_STORE", RateLimitStoreKind::Memory),
+                prune_interval_seconds: env_or("RATE_LIMIT_PRUNE_INTERVAL_SECONDS", 60),
+                obtain_per_ip: env_or("RATE_LIMIT_OBTAIN_PER_IP", Quota::new(30, 60)),
+                obtain_per_email: env_or("RATE_LIMIT_OBTAIN_PER_EMAIL", Quota::new(10, 60)),
+                email_login_per_email: env_or(
+                    "RATE_LIMIT_EMAIL_LOGIN_PER_EMAIL",
+                    Quota::new(5, 900),
+                ),
+                sms_code_per_number: env_or("RATE_LIMIT_SMS_CODE_PER_NUMBER", Quota::new(5, 3600)),
+                sms_code_per_ip: env_or("RATE_LIMIT_SMS_CODE_PER_IP", Quota::new(20, 3600)),
+                register_per_ip: env_or("RATE_LIMIT_REGISTER_PER_IP", Quota::new(5, 600)),
+                refresh_per_ip: env_or("RATE_LIMIT_REFRESH_PER_IP", Quota::new(60, 60)),
+                email_lookup_per_client: env_or(
+                    "RATE_LIMIT_EMAIL_LOOKUP_PER_CLIENT",
+                    Quota::new(120, 60),
+                ),
+            },
+        }
+    }
+}
+
+pub fn get() -> &'static Config {
+    &CONFIG
+}
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse::<T>().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,11 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ActionTokenError {
+    #[error("Token is invalid, expired or has already been used")]
+    InvalidToken,
+    #[error("Failed to create token")]
+    TokenCreationFailure,
+    #[error("Failed to redeem token")]
+    TokenRedemptionFailure,
+}
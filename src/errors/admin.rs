@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+use super::{action_tokens::ActionTokenError, lockout::LockoutError, mail::MailError};
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    #[error("User not found")]
+    UserNotFound,
+    #[error("User has no email address to send a reset link to")]
+    NoEmailAddress,
+    #[error("Pagination cursor is invalid")]
+    InvalidCursor,
+    #[error("Failed to query users")]
+    QueryFailure,
+    #[error("Failed to update user")]
+    UserUpdateFailure,
+    #[error("Failed to revoke tokens")]
+    TokenRevocationFailure,
+    #[error(transparent)]
+    ActionToken(#[from] ActionTokenError),
+    #[error(transparent)]
+    Mail(#[from] MailError),
+    #[error(transparent)]
+    Lockout(#[from] LockoutError),
+}
@@ -0,0 +1,36 @@
+use thiserror::Error;
+
+use super::jwt::{JWTCreationError, JWTValidationError};
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Pagination cursor is invalid")]
+    InvalidCursor,
+    #[error("Failed to query audit events")]
+    QueryFailure(#[from] diesel::result::Error),
+    #[error("Failed to export audit events")]
+    ExportFailure(#[from] serde_json::Error),
+    #[error("Failed to sign audit checkpoint: {0}")]
+    Signing(#[from] JWTCreationError),
+    #[error("Failed to load the checkpoint verification key: {0}")]
+    VerificationKey(#[from] JWTValidationError),
+    #[error("Audit log is broken: {0}")]
+    ChainBroken(#[from] ChainBreak),
+}
+
+/// The first place where the audit chain does not add up
+#[derive(Debug, Error, PartialEq)]
+pub enum ChainBreak {
+    #[error("event {0} has no hash although earlier events do")]
+    MissingHash(i64),
+    #[error("event {0} does not point at the hash of the event before it")]
+    BrokenLink(i64),
+    #[error("event {0} no longer matches its hash")]
+    HashMismatch(i64),
+    #[error("checkpoint {0} has an invalid signature")]
+    InvalidSignature(i64),
+    #[error("checkpoint {checkpoint_id} does not match event {event_id}")]
+    CheckpointMismatch { checkpoint_id: i64, event_id: i64 },
+    #[error("event {event_id} signed by checkpoint {checkpoint_id} is missing")]
+    MissingEvent { checkpoint_id: i64, event_id: i64 },
+}
@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+use super::validation::ValidationErrors;
+
+#[derive(Debug, Error)]
+pub enum CaregiverError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Caregiver application not found")]
+    ApplicationNotFound,
+    #[error("An application is already waiting for review")]
+    ApplicationPending,
+    #[error("Application has already been reviewed")]
+    AlreadyReviewed,
+    #[error("You are already a verified caregiver")]
+    AlreadyCaregiver,
+    #[error("An email address is needed to apply as a caregiver")]
+    NoEmailAddress,
+    #[error("You cannot review your own application")]
+    SelfReview,
+    #[error("Failed to read or update caregiver applications")]
+    Database(#[from] diesel::result::Error),
+}
@@ -0,0 +1,11 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("Data encryption key is invalid")]
+    InvalidKey,
+    #[error("Failed to encrypt data")]
+    EncryptionFailure,
+    #[error("Failed to decrypt data")]
+    DecryptionFailure,
+}
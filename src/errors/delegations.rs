@@ -0,0 +1,33 @@
+use thiserror::Error;
+
+use super::{jwt::JWTCreationError, mail::MailError, validation::ValidationErrors};
+
+#[derive(Debug, Error)]
+pub enum DelegationError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("You cannot delegate access to yourself")]
+    SelfDelegation,
+    #[error("Invitation is invalid or has expired")]
+    InvalidInvitation,
+    #[error("This invitation was sent to a different email address")]
+    WrongInvitee,
+    #[error("You can already act for this user")]
+    AlreadyDelegated,
+    #[error("Delegation not found")]
+    DelegationNotFound,
+    #[error("Delegation has already been revoked")]
+    AlreadyRevoked,
+    #[error("You are not allowed to act for this user")]
+    NotDelegated,
+    #[error("Requested scope was not delegated: {0}")]
+    InvalidScope(String),
+    #[error("Account is disabled")]
+    AccountDisabled,
+    #[error(transparent)]
+    Mail(#[from] MailError),
+    #[error(transparent)]
+    TokenCreation(#[from] JWTCreationError),
+    #[error("Failed to read or update delegations")]
+    Database(#[from] diesel::result::Error),
+}
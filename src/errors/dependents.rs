@@ -0,0 +1,26 @@
+use thiserror::Error;
+
+use super::{
+    action_tokens::ActionTokenError, mail::MailError, profiles::ProfileError,
+    validation::ValidationErrors,
+};
+
+#[derive(Debug, Error)]
+pub enum DependentError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error(transparent)]
+    InvalidProfile(#[from] ProfileError),
+    #[error("Dependent not found")]
+    DependentNotFound,
+    #[error("Dependent has not come of age yet")]
+    NotOfAge,
+    #[error("Email is already used by another account")]
+    EmailTaken,
+    #[error(transparent)]
+    ActionToken(#[from] ActionTokenError),
+    #[error(transparent)]
+    Mail(#[from] MailError),
+    #[error("Failed to read or update dependents")]
+    Database(#[from] diesel::result::Error),
+}
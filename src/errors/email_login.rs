@@ -0,0 +1,28 @@
+use thiserror::Error;
+
+use super::{
+    action_tokens::ActionTokenError, lockout::LockoutError, mfa::MfaError,
+    validation::ValidationErrors,
+};
+
+#[derive(Debug, Error)]
+pub enum EmailLoginError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Link or code is invalid, expired or was requested on another device")]
+    InvalidToken,
+    #[error("Too many failed attempts. Please try again later")]
+    TooManyAttempts,
+    #[error("This account has been disabled. Please contact support")]
+    AccountDisabled,
+    #[error("This account uses two-factor authentication. Please sign in with your password")]
+    MfaRequired,
+    #[error(transparent)]
+    ActionToken(#[from] ActionTokenError),
+    #[error(transparent)]
+    Lockout(#[from] LockoutError),
+    #[error(transparent)]
+    Mfa(#[from] MfaError),
+    #[error("Failed to look up the account")]
+    UserLookupFailure(#[from] diesel::result::Error),
+}
@@ -0,0 +1,21 @@
+use thiserror::Error;
+
+use super::{jwt::JWTCreationError, validation::ValidationErrors};
+
+#[derive(Debug, Error)]
+pub enum ImpersonationError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("User not found")]
+    UserNotFound,
+    #[error("You cannot impersonate yourself")]
+    SelfImpersonation,
+    #[error("Users who can impersonate others cannot be impersonated")]
+    ProtectedUser,
+    #[error("Scope is not available when impersonating: {0}")]
+    InvalidScope(String),
+    #[error(transparent)]
+    TokenCreation(#[from] JWTCreationError),
+    #[error("Failed to read or record impersonations")]
+    Database(#[from] diesel::result::Error),
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+use super::passwords::PasswordError;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Could not read record: {0}")]
+    UnreadableRecord(String),
+    #[error("Email is missing or invalid")]
+    InvalidEmail,
+    #[error(transparent)]
+    UnsupportedHash(#[from] PasswordError),
+    #[error("Role does not exist: {0}")]
+    UnknownRole(String),
+    #[error("A user with this email already exists")]
+    EmailTaken,
+    #[error("Failed to insert user")]
+    UserInsertionFailure(#[from] diesel::result::Error),
+}
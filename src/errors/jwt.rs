@@ -28,6 +28,14 @@ pub enum JWTValidationError {
     DuplicateToken,
     #[error("Refresh token was not found on the database")]
     TokenNotFound,
+    #[error("Authorization header is missing or malformed")]
+    MissingToken,
+    #[error("Invalid verification key")]
+    InvalidVerificationKey,
+    #[error("A more recent or stronger sign in is required")]
+    InsufficientAuthentication,
+    #[error("Tokens for acting on behalf of another user cannot be used here")]
+    DelegatedToken,
 }
 
 #[derive(Debug, Error)]
@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LockoutError {
+    #[error("Failed to check or record failed login attempts")]
+    TrackingFailure(#[from] diesel::result::Error),
+}
@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+use super::{action_tokens::ActionTokenError, validation::ValidationErrors};
+
+#[derive(Debug, Error)]
+pub enum LoginHistoryError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error(transparent)]
+    ActionToken(#[from] ActionTokenError),
+    #[error("Failed to read or record sign ins")]
+    Database(#[from] diesel::result::Error),
+}
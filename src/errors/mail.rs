@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("Failed to deliver email: {0}")]
+    DeliveryFailure(String),
+}
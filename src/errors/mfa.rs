@@ -0,0 +1,40 @@
+use thiserror::Error;
+
+use super::{
+    action_tokens::ActionTokenError, crypto::CryptoError, lockout::LockoutError,
+    passwords::PasswordError, validation::ValidationErrors,
+};
+
+#[derive(Debug, Error)]
+pub enum MfaError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Two-factor authentication is already set up")]
+    AlreadyEnrolled,
+    #[error("Two-factor authentication setup has not been started")]
+    NotEnrolled,
+    #[error("Code is invalid or has already been used")]
+    InvalidCode,
+    #[error("Sign-in challenge is invalid or has expired. Please sign in again")]
+    InvalidChallenge,
+    #[error("Too many failed attempts. Please try again later")]
+    TooManyAttempts,
+    #[error("Password is incorrect")]
+    IncorrectPassword,
+    #[error("This account has been suspended. Please contact support")]
+    AccountSuspended,
+    #[error("This account has been disabled. Please contact support")]
+    AccountDisabled,
+    #[error("This account has been deleted")]
+    AccountDeleted,
+    #[error(transparent)]
+    ActionToken(#[from] ActionTokenError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Lockout(#[from] LockoutError),
+    #[error(transparent)]
+    PasswordHashing(#[from] PasswordError),
+    #[error("Failed to read or update two-factor authentication")]
+    CredentialUpdateFailure(#[from] diesel::result::Error),
+}
@@ -1,2 +1,24 @@
+pub mod action_tokens;
+pub mod admin;
+pub mod audit;
+pub mod caregivers;
+pub mod crypto;
+pub mod delegations;
+pub mod dependents;
+pub mod email_login;
+pub mod impersonations;
+pub mod import;
 pub mod jwt;
+pub mod lockout;
+pub mod logins;
+pub mod mail;
+pub mod mfa;
+pub mod passwords;
+pub mod phone_login;
+pub mod profiles;
+pub mod rate_limit;
+pub mod roles;
+pub mod sms;
 pub mod users;
+pub mod validation;
+pub mod webauthn;
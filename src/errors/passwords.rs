@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PasswordError {
+    #[error("Password hash is not in a supported format")]
+    UnsupportedFormat,
+    #[error("Password hash is malformed")]
+    MalformedHash,
+    #[error("Password hashing failed: {0}")]
+    HashingFailure(#[from] password_hash::Error),
+}
+
+// argon2 errors are not `std::error::Error` without its `std` feature
+impl From<argon2::Error> for PasswordError {
+    fn from(err: argon2::Error) -> Self {
+        PasswordError::HashingFailure(err.into())
+    }
+}
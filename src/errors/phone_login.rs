@@ -0,0 +1,31 @@
+use thiserror::Error;
+
+use super::{mfa::MfaError, sms::SmsError, validation::ValidationErrors};
+
+#[derive(Debug, Error)]
+pub enum PhoneLoginError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Code is invalid or has expired")]
+    InvalidCode,
+    #[error("Too many wrong codes. Please request a new one")]
+    TooManyAttempts,
+    #[error(
+        "A code was sent recently. Please wait {retry_after_seconds} seconds before asking again"
+    )]
+    ResendCooldown { retry_after_seconds: i64 },
+    #[error("This phone number is already registered. Please sign in instead")]
+    PhoneNumberTaken,
+    #[error("No account uses this phone number. Please register first")]
+    AccountNotFound,
+    #[error("This account has been disabled. Please contact support")]
+    AccountDisabled,
+    #[error("This account uses two-factor authentication, which SMS sign in does not support")]
+    MfaRequired,
+    #[error(transparent)]
+    Sms(#[from] SmsError),
+    #[error(transparent)]
+    Mfa(#[from] MfaError),
+    #[error("Failed to read or update phone sign in")]
+    Database(#[from] diesel::result::Error),
+}
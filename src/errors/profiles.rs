@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+use super::{crypto::CryptoError, validation::ValidationErrors};
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Failed to fetch profile")]
+    ProfileFetchingFailure,
+    #[error("Failed to update profile")]
+    ProfileUpdateFailure,
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
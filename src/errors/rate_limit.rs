@@ -0,0 +1,9 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RateLimitError {
+    #[error("Rate limit store is unavailable: {0}")]
+    StoreUnavailable(#[from] r2d2::Error),
+    #[error("Failed to update rate limit bucket")]
+    BucketUpdateFailure(#[from] diesel::result::Error),
+}
@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RoleError {
+    #[error("Role does not exist")]
+    RoleNotFound,
+    #[error("User not found")]
+    UserNotFound,
+    #[error("User does not have this role")]
+    RoleNotAssigned,
+    #[error("Administrators cannot revoke their own admin role")]
+    SelfRevocation,
+    #[error("Failed to update role assignments")]
+    RoleAssignmentFailure,
+}
@@ -0,0 +1,7 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SmsError {
+    #[error("Failed to deliver SMS: {0}")]
+    DeliveryFailure(String),
+}
@@ -1,7 +1,11 @@
 use thiserror::Error;
 
+use super::{action_tokens::ActionTokenError, lockout::LockoutError, validation::ValidationErrors};
+
 #[derive(Debug, Error)]
 pub enum UserCreationError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
     #[error("Password hashing failed")]
     PasswordHashError,
     #[error("User insertion failed")]
@@ -10,10 +14,46 @@ pub enum UserCreationError {
 
 #[derive(Debug, Error)]
 pub enum UserValidationError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
     #[error("Email or password is invalid")]
     InvalidCredentials,
     #[error("A database integrity error has occurred. Please contact site administrator")]
     InvalidPasswordFormat,
     #[error("User not found")]
     UserNotFound,
+    #[error("This account has been suspended. Please contact support")]
+    AccountSuspended,
+    #[error("This account has been disabled. Please contact support")]
+    AccountDisabled,
+    #[error("This account has been deleted")]
+    AccountDeleted,
+    #[error("A password reset is required. Please check your email for a reset link")]
+    PasswordResetRequired,
+    #[error(transparent)]
+    LockoutTracking(#[from] LockoutError),
+}
+
+#[derive(Debug, Error)]
+pub enum PasswordResetError {
+    #[error(transparent)]
+    InvalidToken(#[from] ActionTokenError),
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Password hashing failed")]
+    PasswordHashError,
+    #[error("Failed to update password")]
+    PasswordUpdateFailure(#[from] diesel::result::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum PasswordChangeError {
+    #[error("User not found")]
+    UserNotFound,
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Password hashing failed")]
+    PasswordHashError,
+    #[error("Failed to update password")]
+    PasswordUpdateFailure(#[from] diesel::result::Error),
 }
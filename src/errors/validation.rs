@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use thiserror::Error;
+
+/// Problems with individual request fields, sent back as
+/// `422 Unprocessable Entity` with a body like
+/// `{ "errors": { "password": ["must be at least 8 characters long"] } }`.
+#[derive(Debug, Default, Error, Serialize)]
+#[error("Some fields are invalid")]
+pub struct ValidationErrors {
+    pub errors: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.errors
+            .entry(field.to_string())
+            .or_default()
+            .push(message.into());
+    }
+
+    pub fn single(field: &str, message: impl Into<String>) -> Self {
+        let mut errors = ValidationErrors::default();
+        errors.add(field, message);
+        errors
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// `Ok` when no errors were added
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl From<validator::ValidationErrors> for ValidationErrors {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut result = ValidationErrors::default();
+        result.add_all("", &errors);
+        result
+    }
+}
+
+impl ValidationErrors {
+    // Fields of nested structs get paths like `caregiver.documents[0].url`
+    fn add_all(&mut self, prefix: &str, errors: &validator::ValidationErrors) {
+        use validator::ValidationErrorsKind;
+
+        for (field, kind) in errors.errors() {
+            let path = if prefix.is_empty() {
+                field.to_string()
+            } else {
+                format!("{}.{}", prefix, field)
+            };
+
+            match kind {
+                ValidationErrorsKind::Field(field_errors) => {
+                    for error in field_errors {
+                        let message = match &error.message {
+                            Some(message) => message.to_string(),
+                            None => format!("is invalid ({})", error.code),
+                        };
+                        self.add(&path, message);
+                    }
+                }
+                ValidationErrorsKind::Struct(nested) => self.add_all(&path, nested),
+                ValidationErrorsKind::List(items) => {
+                    for (index, nested) in items {
+                        self.add_all(&format!("{}[{}]", path, index), nested);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use thiserror::Error;
+
+use super::validation::ValidationErrors;
+
+#[derive(Debug, Error)]
+pub enum WebAuthnError {
+    #[error(transparent)]
+    InvalidFields(#[from] ValidationErrors),
+    #[error("Challenge is invalid, expired or has already been used")]
+    InvalidChallenge,
+    #[error("Client data is malformed or is not for this ceremony")]
+    InvalidClientData,
+    #[error("Request did not come from an allowed origin")]
+    OriginMismatch,
+    #[error("Authenticator data is malformed")]
+    InvalidAuthenticatorData,
+    #[error("Credential is for another site")]
+    RelyingPartyMismatch,
+    #[error("User presence was not confirmed on the authenticator")]
+    UserNotPresent,
+    #[error("User verification was not performed on the authenticator")]
+    UserNotVerified,
+    #[error("Only attestation format \"none\" is supported")]
+    UnsupportedAttestation,
+    #[error("Only ES256 (P-256) credentials are supported")]
+    UnsupportedAlgorithm,
+    #[error("This credential is already registered")]
+    CredentialAlreadyRegistered,
+    #[error("Credential not found")]
+    CredentialNotFound,
+    #[error("Signature is invalid")]
+    InvalidSignature,
+    #[error("Signature counter went backwards. The authenticator may have been cloned")]
+    CounterRegression,
+    #[error("This account has been disabled. Please contact support")]
+    AccountDisabled,
+    #[error("Failed to read or update WebAuthn credentials")]
+    CredentialUpdateFailure(#[from] diesel::result::Error),
+}
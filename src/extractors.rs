@@ -0,0 +1,156 @@
+use std::{
+    future::{ready, Ready},
+    net::SocketAddr,
+};
+
+use actix_web::{
+    dev::Payload,
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized, InternalError},
+    http::header::{AUTHORIZATION, USER_AGENT, WWW_AUTHENTICATE},
+    web, FromRequest, HttpRequest, HttpResponse,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    config,
+    errors::{jwt::JWTValidationError, validation::ValidationErrors},
+    models::audit::ClientInfo,
+    services::jwt::{acr, decode_access_token, Claims, TokenKeys},
+};
+
+/// The user behind a valid `Authorization: Bearer <access token>` header.
+/// Delegated tokens are refused, so that a delegate cannot manage the account
+/// of the patient they act for.
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub claims: Claims,
+}
+
+impl AuthenticatedUser {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.claims.permissions.iter().any(|p| p == permission)
+    }
+
+    /// Refuses tokens from a sign in weaker than `minimum_acr` or older than
+    /// `max_age`. The response tells the client what to step up to
+    /// (RFC 9470), e.g. through `POST /api/token/step-up`.
+    pub fn require_authentication(
+        &self,
+        minimum_acr: &str,
+        max_age: Option<Duration>,
+    ) -> Result<(), actix_web::Error> {
+        let strong_enough = acr::satisfies(&self.claims.acr, minimum_acr);
+        let recent_enough = max_age.is_none_or(|max_age| {
+            Utc::now().timestamp() - self.claims.auth_time as i64 <= max_age.num_seconds()
+        });
+
+        if strong_enough && recent_enough {
+            return Ok(());
+        }
+
+        let error = JWTValidationError::InsufficientAuthentication.to_string();
+        let mut challenge = format!(
+            "Bearer error=\"insufficient_user_authentication\", \
+             error_description=\"{}\", acr_values=\"{}\"",
+            error, minimum_acr
+        );
+        if let Some(max_age) = max_age {
+            challenge.push_str(&format!(", max_age={}", max_age.num_seconds()));
+        }
+
+        let response = HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, challenge))
+            .body(error.clone());
+
+        Err(InternalError::from_response(error, response).into())
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
+
+fn authenticate(req: &HttpRequest) -> Result<AuthenticatedUser, actix_web::Error> {
+    let token_keys = req
+        .app_data::<web::Data<TokenKeys>>()
+        .ok_or_else(|| ErrorInternalServerError("Signing key is not configured"))?;
+
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| ErrorUnauthorized(JWTValidationError::MissingToken.to_string()))?;
+
+    let claims = decode_access_token(token_keys, token.trim()).map_err(|err| match err {
+        JWTValidationError::InvalidVerificationKey => ErrorInternalServerError(err.to_string()),
+        _ => ErrorUnauthorized(err.to_string()),
+    })?;
+
+    if claims.act.is_some() {
+        return Err(ErrorForbidden(
+            JWTValidationError::DelegatedToken.to_string(),
+        ));
+    }
+
+    let user_id = Uuid::parse_str(&claims.user_id)
+        .map_err(|_err| ErrorUnauthorized(JWTValidationError::TokenInvalid.to_string()))?;
+
+    Ok(AuthenticatedUser { user_id, claims })
+}
+
+impl FromRequest for ClientInfo {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let ip_address = client_ip(req);
+
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        ready(Ok(ClientInfo {
+            ip_address,
+            user_agent,
+        }))
+    }
+}
+
+/// The address the request came from, taken from proxy headers only when
+/// they are trusted.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    if config::get().trust_proxy_headers {
+        req.connection_info().realip_remote_addr().map(|addr| {
+            // Falls back to the peer address, which carries a port
+            addr.parse::<SocketAddr>()
+                .map(|socket_addr| socket_addr.ip().to_string())
+                .unwrap_or_else(|_err| addr.to_string())
+        })
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+}
+
+// Passwords and emails are small, so anything bigger is not a real request
+const JSON_BODY_LIMIT: usize = 16 * 1024;
+
+/// JSON bodies that cannot be read, e.g. with a missing field or a wrong
+/// type, get the same `422` field error response as invalid values.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(JSON_BODY_LIMIT)
+        .error_handler(|err, _req| {
+            let response = HttpResponse::UnprocessableEntity()
+                .json(ValidationErrors::single("body", err.to_string()));
+            InternalError::from_response(err, response).into()
+        })
+}
@@ -3,20 +3,58 @@ use actix_web::{get, post, web, HttpResponse, Responder};
 use serde::Serialize;
 use uuid::Uuid;
 
+mod admin;
+mod audit_log;
+mod caregivers;
+mod delegations;
+mod dependents;
+mod email_login;
+mod impersonations;
+mod logins;
+mod mfa;
+mod phone_login;
+mod profiles;
+mod webauthn;
+
+pub use admin::*;
+pub use audit_log::*;
+pub use caregivers::*;
+pub use delegations::*;
+pub use dependents::*;
+pub use email_login::*;
+pub use impersonations::*;
+pub use logins::*;
+pub use mfa::*;
+pub use phone_login::*;
+pub use profiles::*;
+pub use webauthn::*;
+
 use crate::{
-    db,
-    models::users::{InsertableUser, LoginFields},
-    repository::{jwt::revoke_refresh_token, users::get_user_by_id},
+    config, db,
+    errors::{
+        jwt::JWTError,
+        users::{PasswordChangeError, PasswordResetError, UserCreationError, UserValidationError},
+    },
+    extractors::AuthenticatedUser,
+    models::{
+        audit::ClientInfo,
+        users::{LoginFields, PasswordChangeFields, PasswordResetFields, RegistrationFields},
+    },
+    repository::users::get_user_by_id,
     services::{
         self,
-        jwt::{RefreshInfo, RevocationInfo},
+        audit::{self, AuditEntry},
+        jwt::{amr, RefreshInfo, RevocationInfo, TokenKeys},
+        mail::MailSender,
     },
 };
 
 #[post("/token/obtain")]
 async fn obtain(
     pool: web::Data<db::DbPool>,
-    secret_key: web::Data<String>,
+    token_keys: web::Data<TokenKeys>,
+    mailer: web::Data<dyn MailSender>,
+    client: ClientInfo,
     req_body: web::Json<LoginFields>,
 ) -> impl Responder {
     let login_fields = req_body.into_inner();
@@ -26,23 +64,66 @@ async fn obtain(
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
 
-    let user = match services::users::validate_user(&mut conn, login_fields) {
+    let result = services::users::validate_user(&mut conn, mailer.get_ref(), login_fields, &client);
+    let user_id = result.as_ref().ok().map(|user| user.id);
+
+    let user = match result {
         Ok(user) => user,
-        Err(e) => return HttpResponse::Unauthorized().body(e.to_string()),
+        Err(UserValidationError::InvalidFields(errors)) => {
+            return HttpResponse::UnprocessableEntity().json(errors)
+        }
+        Err(e @ UserValidationError::LockoutTracking(_)) => {
+            return HttpResponse::InternalServerError().body(e.to_string())
+        }
+        Err(e) => {
+            audit::record(
+                &mut conn,
+                &client,
+                AuditEntry::failure("user.login", None, None, &e),
+            );
+            return HttpResponse::Unauthorized().body(e.to_string());
+        }
     };
 
-    let jwt = match services::jwt::generate_jwt(&mut conn, secret_key.get_ref().clone(), user) {
-        Ok(e) => e,
+    match services::mfa::start_login(&mut conn, &user, &config::get().mfa.required_roles) {
+        Ok(Some(challenge)) => {
+            audit::record(
+                &mut conn,
+                &client,
+                AuditEntry::success("user.login", user_id, user_id)
+                    .with_detail("second factor required"),
+            );
+            return HttpResponse::Ok().json(challenge);
+        }
+        Ok(None) => {}
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
-    };
+    }
 
-    HttpResponse::Ok().json(jwt)
+    let result =
+        services::jwt::generate_jwt(&mut conn, &token_keys, user.clone(), &[amr::PASSWORD]);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result("user.login", user_id, user_id, &result),
+    );
+
+    if result.is_ok() {
+        services::logins::record_login(&mut conn, mailer.get_ref(), &user, &client);
+    }
+
+    match result {
+        Ok(jwt) => HttpResponse::Ok().json(jwt),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
 }
 
 #[post("/register")]
 async fn register(
     pool: web::Data<db::DbPool>,
-    req_body: web::Json<InsertableUser>,
+    mailer: web::Data<dyn MailSender>,
+    client: ClientInfo,
+    req_body: web::Json<RegistrationFields>,
 ) -> impl Responder {
     let user_details = req_body.into_inner();
 
@@ -51,18 +132,22 @@ async fn register(
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
 
-    let _user = match services::users::create_user(&mut conn, user_details) {
-        Ok(u) => u,
-        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
-    };
-
-    HttpResponse::Created().body("User created successfully")
+    // The same response whether or not the email was already registered
+    match services::users::create_user(&mut conn, mailer.get_ref(), &client, user_details) {
+        Ok(()) => HttpResponse::Accepted()
+            .body("Registration received. Please check your email to continue"),
+        Err(UserCreationError::InvalidFields(errors)) => {
+            HttpResponse::UnprocessableEntity().json(errors)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
 }
 
 #[post("/token/refresh")]
 async fn refresh(
     pool: web::Data<db::DbPool>,
-    secret_key: web::Data<String>,
+    token_keys: web::Data<TokenKeys>,
+    client: ClientInfo,
     req_body: web::Json<RefreshInfo>,
 ) -> impl Responder {
     let refresh_info = req_body.into_inner();
@@ -74,10 +159,18 @@ async fn refresh(
 
     let refreshed_tokens = match services::jwt::refresh_token(
         &mut conn,
-        secret_key.get_ref().clone(),
+        &token_keys,
         &refresh_info.refresh_token,
+        &client,
     ) {
         Ok(jwt) => jwt,
+        Err(
+            e @ JWTError::UserValidation(
+                UserValidationError::AccountSuspended
+                | UserValidationError::AccountDisabled
+                | UserValidationError::AccountDeleted,
+            ),
+        ) => return HttpResponse::Forbidden().body(e.to_string()),
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
 
@@ -87,6 +180,7 @@ async fn refresh(
 #[post("/token/revoke")]
 async fn revoke(
     pool: web::Data<db::DbPool>,
+    client: ClientInfo,
     req_body: web::Json<RevocationInfo>,
 ) -> impl Responder {
     let revocation_info = req_body.into_inner();
@@ -96,13 +190,86 @@ async fn revoke(
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
 
-    if let Err(err) = revoke_refresh_token(&mut conn, &revocation_info.refresh_token) {
+    if let Err(err) =
+        services::jwt::revoke_token(&mut conn, &revocation_info.refresh_token, &client)
+    {
         return HttpResponse::NotModified().body(err.to_string());
     };
 
     HttpResponse::Ok().body("Token successfully revoked")
 }
 
+#[post("/password/reset")]
+async fn reset_password(
+    pool: web::Data<db::DbPool>,
+    client: ClientInfo,
+    req_body: web::Json<PasswordResetFields>,
+) -> impl Responder {
+    let reset_fields = req_body.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::users::reset_password(&mut conn, reset_fields);
+    let user_id = result.as_ref().ok().map(|user| user.id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result("user.password_reset", user_id, user_id, &result),
+    );
+
+    match result {
+        Ok(_user) => HttpResponse::Ok().body("Password successfully reset"),
+        Err(e @ PasswordResetError::InvalidToken(_)) => {
+            HttpResponse::BadRequest().body(e.to_string())
+        }
+        Err(PasswordResetError::InvalidFields(errors)) => {
+            HttpResponse::UnprocessableEntity().json(errors)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[post("/me/password")]
+async fn change_password(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<PasswordChangeFields>,
+) -> impl Responder {
+    let change_fields = req_body.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::users::change_password(&mut conn, auth.user_id, change_fields);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "user.password_changed",
+            Some(auth.user_id),
+            Some(auth.user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(()) => HttpResponse::Ok().body("Password successfully changed"),
+        Err(PasswordChangeError::InvalidFields(errors)) => {
+            HttpResponse::UnprocessableEntity().json(errors)
+        }
+        Err(e @ PasswordChangeError::UserNotFound) => HttpResponse::NotFound().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
 #[get("/jwks.json")]
 async fn get_jwks() -> actix_web::Result<NamedFile> {
     Ok(NamedFile::open("jwks/jwks.json")?)
@@ -111,6 +278,7 @@ async fn get_jwks() -> actix_web::Result<NamedFile> {
 #[get("/email/{user_id}")]
 async fn get_email_by_user_id(
     pool: web::Data<db::DbPool>,
+    client: ClientInfo,
     user_id: web::Path<String>,
 ) -> HttpResponse {
     let mut conn = match pool.get() {
@@ -123,15 +291,27 @@ async fn get_email_by_user_id(
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
 
-    let user = match get_user_by_id(&mut conn, user_id) {
+    let result = get_user_by_id(&mut conn, user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result("user.email_looked_up", None, Some(user_id), &result),
+    );
+
+    let user = match result {
         Ok(user) => user,
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
     };
 
+    let Some(email) = user.email else {
+        return HttpResponse::NotFound().body("User has no email address");
+    };
+
     #[derive(Serialize)]
     struct EmailResponse {
         email: String,
     }
 
-    HttpResponse::Ok().json(EmailResponse { email: user.email })
+    HttpResponse::Ok().json(EmailResponse { email })
 }
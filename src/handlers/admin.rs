@@ -0,0 +1,474 @@
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use uuid::Uuid;
+
+use crate::{
+    db,
+    errors::{admin::AdminError, roles::RoleError},
+    extractors::AuthenticatedUser,
+    models::{
+        admin::{UserSearchQuery, UserStatusFields},
+        audit::ClientInfo,
+        roles::RoleGrant,
+        users::UserStatus,
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        mail::MailSender,
+    },
+};
+
+const ROLES_MANAGE: &str = "roles:manage";
+const USERS_READ: &str = "users:read";
+const USERS_MANAGE: &str = "users:manage";
+
+#[get("/admin/users")]
+async fn list_users(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    query: web::Query<UserSearchQuery>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_READ) {
+        return HttpResponse::Forbidden().body("Missing permission: users:read");
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::list_users(&mut conn, query.into_inner());
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result("admin.users.listed", Some(auth.user_id), None, &result),
+    );
+
+    match result {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[get("/admin/users/{user_id}")]
+async fn get_user(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_READ) {
+        return HttpResponse::Forbidden().body("Missing permission: users:read");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::get_user(&mut conn, user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.viewed",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[get("/admin/users/{user_id}/sessions")]
+async fn get_user_sessions(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_READ) {
+        return HttpResponse::Forbidden().body("Missing permission: users:read");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::get_user_sessions(&mut conn, user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.sessions_viewed",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(sessions) => HttpResponse::Ok().json(sessions),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[post("/admin/users/{user_id}/disable")]
+async fn disable_user(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: users:manage");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::set_status(&mut conn, user_id, UserStatus::Disabled);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.disabled",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[post("/admin/users/{user_id}/enable")]
+async fn enable_user(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: users:manage");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::set_status(&mut conn, user_id, UserStatus::Active);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.enabled",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+/// Suspends, disables, deletes or reactivates an account
+#[put("/admin/users/{user_id}/status")]
+async fn set_user_status(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+    req_body: web::Json<UserStatusFields>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: users:manage");
+    }
+
+    let user_id = user_id.into_inner();
+    let status = req_body.into_inner().status;
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::set_status(&mut conn, user_id, status);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.status_changed",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        )
+        .with_detail(format!("status={}", status.as_str())),
+    );
+
+    match result {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[post("/admin/users/{user_id}/password-reset")]
+async fn force_password_reset(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: users:manage");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::force_password_reset(&mut conn, mailer.get_ref(), user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.password_reset_forced",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(()) => HttpResponse::Ok().body("Password reset email sent"),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[post("/admin/users/{user_id}/revoke-tokens")]
+async fn revoke_user_tokens(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: users:manage");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::revoke_all_tokens(&mut conn, user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.tokens_revoked",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(count) => HttpResponse::Ok().body(format!("{} tokens successfully revoked", count)),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[post("/admin/users/{user_id}/unlock")]
+async fn unlock_user(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: users:manage");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::admin::unlock_user(&mut conn, user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.unlocked",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(()) => HttpResponse::Ok().body("Account successfully unlocked"),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+#[get("/admin/users/{user_id}/roles")]
+async fn get_user_roles(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    user_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(ROLES_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: roles:manage");
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::roles::get_user_roles(&mut conn, user_id.into_inner()) {
+        Ok(roles) => HttpResponse::Ok().json(roles),
+        Err(e) => role_error_response(e),
+    }
+}
+
+#[post("/admin/users/{user_id}/roles")]
+async fn grant_user_role(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+    req_body: web::Json<RoleGrant>,
+) -> impl Responder {
+    if !auth.has_permission(ROLES_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: roles:manage");
+    }
+
+    let user_id = user_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result =
+        services::roles::grant_role(&mut conn, Some(auth.user_id), user_id, &req_body.role);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.role.granted",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        )
+        .with_detail(format!("role={}", req_body.role)),
+    );
+
+    match result {
+        Ok(()) => HttpResponse::Ok().body("Role successfully granted"),
+        Err(e) => role_error_response(e),
+    }
+}
+
+#[delete("/admin/users/{user_id}/roles/{role}")]
+async fn revoke_user_role(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    path: web::Path<(Uuid, String)>,
+) -> impl Responder {
+    if !auth.has_permission(ROLES_MANAGE) {
+        return HttpResponse::Forbidden().body("Missing permission: roles:manage");
+    }
+
+    let (user_id, role) = path.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::roles::revoke_role(&mut conn, auth.user_id, user_id, &role);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.role.revoked",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        )
+        .with_detail(format!("role={}", role)),
+    );
+
+    match result {
+        Ok(()) => HttpResponse::Ok().body("Role successfully revoked"),
+        Err(e) => role_error_response(e),
+    }
+}
+
+fn admin_error_response(err: AdminError) -> HttpResponse {
+    match err {
+        AdminError::UserNotFound => HttpResponse::NotFound().body(err.to_string()),
+        AdminError::InvalidCursor => HttpResponse::BadRequest().body(err.to_string()),
+        AdminError::NoEmailAddress => HttpResponse::Conflict().body(err.to_string()),
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+fn role_error_response(err: RoleError) -> HttpResponse {
+    match err {
+        RoleError::RoleNotFound | RoleError::UserNotFound | RoleError::RoleNotAssigned => {
+            HttpResponse::NotFound().body(err.to_string())
+        }
+        RoleError::SelfRevocation => HttpResponse::BadRequest().body(err.to_string()),
+        RoleError::RoleAssignmentFailure => {
+            HttpResponse::InternalServerError().body(err.to_string())
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use actix_web::{
+    error::ErrorInternalServerError, get, http::header, web, web::Bytes, HttpResponse, Responder,
+};
+use futures_util::stream;
+
+use crate::{
+    db,
+    errors::audit::AuditError,
+    extractors::AuthenticatedUser,
+    models::audit::{AuditEventQuery, ClientInfo},
+    services::audit::{self, AuditEntry, AuditExport},
+};
+
+const AUDIT_READ: &str = "audit:read";
+
+#[get("/admin/audit-events")]
+async fn list_audit_events(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    query: web::Query<AuditEventQuery>,
+) -> impl Responder {
+    if !auth.has_permission(AUDIT_READ) {
+        return HttpResponse::Forbidden().body("Missing permission: audit:read");
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = audit::list_events(&mut conn, query.into_inner());
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.audit_events.listed",
+            Some(auth.user_id),
+            None,
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => audit_error_response(e),
+    }
+}
+
+#[get("/admin/audit-events/export")]
+async fn export_audit_events(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    query: web::Query<AuditEventQuery>,
+) -> impl Responder {
+    if !auth.has_permission(AUDIT_READ) {
+        return HttpResponse::Forbidden().body("Missing permission: audit:read");
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = AuditExport::new(query.into_inner());
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.audit_events.exported",
+            Some(auth.user_id),
+            None,
+            &result,
+        ),
+    );
+    drop(conn);
+
+    let export = match result {
+        Ok(export) => export,
+        Err(e) => return audit_error_response(e),
+    };
+
+    // Each batch borrows a connection only while it is fetched. A failure
+    // part way through can no longer change the status, so it cuts the
+    // response short instead.
+    let batches = stream::try_unfold(export, move |mut export| {
+        let pool = pool.clone();
+        async move {
+            let mut conn = pool.get().map_err(ErrorInternalServerError)?;
+            let batch = export
+                .next_batch(&mut conn)
+                .map_err(ErrorInternalServerError)?;
+
+            Ok::<_, actix_web::Error>(batch.map(|lines| (Bytes::from(lines), export)))
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"audit-events.jsonl\"",
+        ))
+        .streaming(batches)
+}
+
+fn audit_error_response(err: AuditError) -> HttpResponse {
+    match err {
+        AuditError::InvalidCursor => HttpResponse::BadRequest().body(err.to_string()),
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
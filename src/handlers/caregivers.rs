@@ -0,0 +1,240 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use uuid::Uuid;
+
+use crate::{
+    db,
+    errors::caregivers::CaregiverError,
+    extractors::AuthenticatedUser,
+    models::{
+        audit::ClientInfo,
+        caregivers::{ApplicationQuery, CaregiverApplicationFields, RejectionFields},
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        mail::MailSender,
+    },
+};
+
+const CAREGIVERS_VERIFY: &str = "caregivers:verify";
+
+#[post("/me/caregiver-application")]
+async fn submit_caregiver_application(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<CaregiverApplicationFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::caregivers::submit_application(
+        &mut conn,
+        mailer.get_ref(),
+        auth.user_id,
+        req_body.into_inner(),
+    );
+
+    let mut entry = AuditEntry::from_result(
+        "caregiver.application_submitted",
+        Some(auth.user_id),
+        Some(auth.user_id),
+        &result,
+    );
+    if let Ok(details) = &result {
+        entry = entry.with_detail(format!("application_id={}", details.application.id));
+    }
+    audit::record(&mut conn, &client, entry);
+
+    match result {
+        Ok(details) => HttpResponse::Created().json(details),
+        Err(e) => caregiver_error_response(e),
+    }
+}
+
+#[get("/me/caregiver-application")]
+async fn get_own_caregiver_application(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::caregivers::get_own_application(&mut conn, auth.user_id) {
+        Ok(details) => HttpResponse::Ok().json(details),
+        Err(e) => caregiver_error_response(e),
+    }
+}
+
+#[get("/admin/caregiver-applications")]
+async fn list_caregiver_applications(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    query: web::Query<ApplicationQuery>,
+) -> impl Responder {
+    if !auth.has_permission(CAREGIVERS_VERIFY) {
+        return HttpResponse::Forbidden().body("Missing permission: caregivers:verify");
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::caregivers::list_applications(&mut conn, query.into_inner()) {
+        Ok(applications) => HttpResponse::Ok().json(applications),
+        Err(e) => caregiver_error_response(e),
+    }
+}
+
+#[get("/admin/caregiver-applications/{application_id}")]
+async fn get_caregiver_application(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    application_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(CAREGIVERS_VERIFY) {
+        return HttpResponse::Forbidden().body("Missing permission: caregivers:verify");
+    }
+
+    let application_id = application_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::caregivers::get_application(&mut conn, application_id);
+    let applicant_id = result
+        .as_ref()
+        .ok()
+        .map(|details| details.application.user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.caregiver_application.viewed",
+            Some(auth.user_id),
+            applicant_id,
+            &result,
+        )
+        .with_detail(format!("application_id={}", application_id)),
+    );
+
+    match result {
+        Ok(details) => HttpResponse::Ok().json(details),
+        Err(e) => caregiver_error_response(e),
+    }
+}
+
+#[post("/admin/caregiver-applications/{application_id}/approve")]
+async fn approve_caregiver_application(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    application_id: web::Path<Uuid>,
+) -> impl Responder {
+    if !auth.has_permission(CAREGIVERS_VERIFY) {
+        return HttpResponse::Forbidden().body("Missing permission: caregivers:verify");
+    }
+
+    let application_id = application_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::caregivers::approve_application(
+        &mut conn,
+        mailer.get_ref(),
+        auth.user_id,
+        application_id,
+    );
+    let applicant_id = result.as_ref().ok().map(|application| application.user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.caregiver_application.approved",
+            Some(auth.user_id),
+            applicant_id,
+            &result,
+        )
+        .with_detail(format!("application_id={}", application_id)),
+    );
+
+    match result {
+        Ok(application) => HttpResponse::Ok().json(application),
+        Err(e) => caregiver_error_response(e),
+    }
+}
+
+#[post("/admin/caregiver-applications/{application_id}/reject")]
+async fn reject_caregiver_application(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    application_id: web::Path<Uuid>,
+    req_body: web::Json<RejectionFields>,
+) -> impl Responder {
+    if !auth.has_permission(CAREGIVERS_VERIFY) {
+        return HttpResponse::Forbidden().body("Missing permission: caregivers:verify");
+    }
+
+    let application_id = application_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::caregivers::reject_application(
+        &mut conn,
+        mailer.get_ref(),
+        auth.user_id,
+        application_id,
+        req_body.into_inner(),
+    );
+    let applicant_id = result.as_ref().ok().map(|application| application.user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.caregiver_application.rejected",
+            Some(auth.user_id),
+            applicant_id,
+            &result,
+        )
+        .with_detail(format!("application_id={}", application_id)),
+    );
+
+    match result {
+        Ok(application) => HttpResponse::Ok().json(application),
+        Err(e) => caregiver_error_response(e),
+    }
+}
+
+fn caregiver_error_response(err: CaregiverError) -> HttpResponse {
+    match err {
+        CaregiverError::InvalidFields(errors) => HttpResponse::UnprocessableEntity().json(errors),
+        CaregiverError::ApplicationNotFound => HttpResponse::NotFound().body(err.to_string()),
+        CaregiverError::ApplicationPending
+        | CaregiverError::AlreadyReviewed
+        | CaregiverError::AlreadyCaregiver
+        | CaregiverError::NoEmailAddress => HttpResponse::Conflict().body(err.to_string()),
+        CaregiverError::SelfReview => HttpResponse::Forbidden().body(err.to_string()),
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
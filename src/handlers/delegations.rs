@@ -0,0 +1,203 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use uuid::Uuid;
+
+use crate::{
+    db,
+    errors::delegations::DelegationError,
+    extractors::AuthenticatedUser,
+    models::{
+        audit::ClientInfo,
+        delegations::{
+            DelegationAcceptanceFields, DelegationInvitationFields, TokenExchangeFields,
+        },
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        jwt::TokenKeys,
+        mail::MailSender,
+    },
+};
+
+#[post("/me/delegations")]
+async fn invite_delegate(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<DelegationInvitationFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::delegations::invite(
+        &mut conn,
+        mailer.get_ref(),
+        auth.user_id,
+        req_body.into_inner(),
+    );
+
+    let mut entry = AuditEntry::from_result(
+        "delegation.invited",
+        Some(auth.user_id),
+        Some(auth.user_id),
+        &result,
+    );
+    if let Ok(delegation) = &result {
+        entry = entry.with_detail(format!(
+            "delegation_id={} scopes={}",
+            delegation.id, delegation.scopes
+        ));
+    }
+    audit::record(&mut conn, &client, entry);
+
+    match result {
+        Ok(delegation) => HttpResponse::Created().json(delegation),
+        Err(e) => delegation_error_response(e),
+    }
+}
+
+#[get("/me/delegations")]
+async fn list_delegations(pool: web::Data<db::DbPool>, auth: AuthenticatedUser) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::delegations::list(&mut conn, auth.user_id) {
+        Ok(delegations) => HttpResponse::Ok().json(delegations),
+        Err(e) => delegation_error_response(e),
+    }
+}
+
+#[delete("/me/delegations/{delegation_id}")]
+async fn revoke_delegation(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    delegation_id: web::Path<Uuid>,
+) -> impl Responder {
+    let delegation_id = delegation_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::delegations::revoke(&mut conn, auth.user_id, delegation_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "delegation.revoked",
+            Some(auth.user_id),
+            Some(auth.user_id),
+            &result,
+        )
+        .with_detail(format!("delegation_id={}", delegation_id)),
+    );
+
+    match result {
+        Ok(delegation) => HttpResponse::Ok().json(delegation),
+        Err(e) => delegation_error_response(e),
+    }
+}
+
+#[post("/delegations/accept")]
+async fn accept_delegation(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<DelegationAcceptanceFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::delegations::accept(&mut conn, auth.user_id, req_body.into_inner());
+    let subject_id = result.as_ref().ok().map(|delegation| delegation.subject_id);
+
+    let mut entry = AuditEntry::from_result(
+        "delegation.accepted",
+        Some(auth.user_id),
+        subject_id,
+        &result,
+    );
+    if let Ok(delegation) = &result {
+        entry = entry.with_detail(format!("delegation_id={}", delegation.id));
+    }
+    audit::record(&mut conn, &client, entry);
+
+    match result {
+        Ok(delegation) => HttpResponse::Ok().json(delegation),
+        Err(e) => delegation_error_response(e),
+    }
+}
+
+#[post("/token/exchange")]
+async fn exchange_token(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<TokenExchangeFields>,
+) -> impl Responder {
+    let fields = req_body.into_inner();
+    let subject_id = fields.subject_id;
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result =
+        services::delegations::exchange(&mut conn, &token_keys, &auth.claims, auth.user_id, fields);
+
+    let mut entry = AuditEntry::from_result(
+        "delegation.token_exchanged",
+        Some(auth.user_id),
+        Some(subject_id),
+        &result,
+    );
+    match &result {
+        Ok((Some(delegation), token)) => {
+            entry = entry.with_detail(format!(
+                "delegation_id={} scopes={}",
+                delegation.id, token.scope
+            ));
+        }
+        Ok((None, token)) => {
+            entry = entry.with_detail(format!("guardian=true scopes={}", token.scope));
+        }
+        Err(_) => {}
+    }
+    audit::record(&mut conn, &client, entry);
+
+    match result {
+        Ok((_, token)) => HttpResponse::Ok().json(token),
+        Err(e) => delegation_error_response(e),
+    }
+}
+
+fn delegation_error_response(err: DelegationError) -> HttpResponse {
+    match err {
+        DelegationError::InvalidFields(errors) => HttpResponse::UnprocessableEntity().json(errors),
+        DelegationError::SelfDelegation | DelegationError::InvalidScope(_) => {
+            HttpResponse::BadRequest().body(err.to_string())
+        }
+        DelegationError::InvalidInvitation | DelegationError::DelegationNotFound => {
+            HttpResponse::NotFound().body(err.to_string())
+        }
+        DelegationError::WrongInvitee
+        | DelegationError::NotDelegated
+        | DelegationError::AccountDisabled => HttpResponse::Forbidden().body(err.to_string()),
+        DelegationError::AlreadyDelegated | DelegationError::AlreadyRevoked => {
+            HttpResponse::Conflict().body(err.to_string())
+        }
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -0,0 +1,169 @@
+use actix_web::{get, patch, post, web, HttpResponse, Responder};
+use chrono::Duration;
+use uuid::Uuid;
+
+use crate::{
+    config, db,
+    errors::{dependents::DependentError, profiles::ProfileError},
+    extractors::AuthenticatedUser,
+    models::{
+        audit::ClientInfo,
+        dependents::{DependentConversionFields, DependentFields},
+        profiles::ProfileUpdate,
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        crypto::DataKey,
+        jwt::acr,
+        mail::MailSender,
+    },
+};
+
+#[post("/me/dependents")]
+async fn create_dependent(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<DependentFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::dependents::create_dependent(
+        &mut conn,
+        &data_key,
+        auth.user_id,
+        req_body.into_inner(),
+    );
+    let dependent_id = result.as_ref().ok().map(|dependent| dependent.id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "dependent.created",
+            Some(auth.user_id),
+            dependent_id,
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(dependent) => HttpResponse::Created().json(dependent),
+        Err(e) => dependent_error_response(e),
+    }
+}
+
+#[get("/me/dependents")]
+async fn list_dependents(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    // Dependents come back with their NIK, as from `get_profile`
+    let max_age = Duration::minutes(config::get().sensitive_data_max_auth_age_minutes);
+    if let Err(e) = auth.require_authentication(acr::SINGLE_FACTOR, Some(max_age)) {
+        return HttpResponse::from_error(e);
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::dependents::list_dependents(&mut conn, &data_key, auth.user_id) {
+        Ok(dependents) => HttpResponse::Ok().json(dependents),
+        Err(e) => dependent_error_response(e),
+    }
+}
+
+#[patch("/me/dependents/{dependent_id}")]
+async fn update_dependent(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+    dependent_id: web::Path<Uuid>,
+    req_body: web::Json<ProfileUpdate>,
+) -> impl Responder {
+    let max_age = Duration::minutes(config::get().sensitive_data_max_auth_age_minutes);
+    if let Err(e) = auth.require_authentication(acr::SINGLE_FACTOR, Some(max_age)) {
+        return HttpResponse::from_error(e);
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::dependents::update_dependent_profile(
+        &mut conn,
+        &data_key,
+        auth.user_id,
+        dependent_id.into_inner(),
+        req_body.into_inner(),
+    ) {
+        Ok(dependent) => HttpResponse::Ok().json(dependent),
+        Err(e) => dependent_error_response(e),
+    }
+}
+
+#[post("/me/dependents/{dependent_id}/convert")]
+async fn convert_dependent(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    dependent_id: web::Path<Uuid>,
+    req_body: web::Json<DependentConversionFields>,
+) -> impl Responder {
+    let dependent_id = dependent_id.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::dependents::convert_to_account(
+        &mut conn,
+        mailer.get_ref(),
+        &data_key,
+        auth.user_id,
+        dependent_id,
+        req_body.into_inner(),
+    );
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "dependent.converted",
+            Some(auth.user_id),
+            Some(dependent_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(_user) => HttpResponse::NoContent().finish(),
+        Err(e) => dependent_error_response(e),
+    }
+}
+
+fn dependent_error_response(err: DependentError) -> HttpResponse {
+    match err {
+        DependentError::InvalidFields(errors) => HttpResponse::UnprocessableEntity().json(errors),
+        DependentError::InvalidProfile(ProfileError::InvalidFields(errors)) => {
+            HttpResponse::UnprocessableEntity().json(errors)
+        }
+        DependentError::DependentNotFound => HttpResponse::NotFound().body(err.to_string()),
+        DependentError::NotOfAge | DependentError::EmailTaken => {
+            HttpResponse::Conflict().body(err.to_string())
+        }
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -0,0 +1,124 @@
+use actix_web::{post, web, HttpResponse, Responder};
+
+use crate::{
+    db,
+    errors::email_login::EmailLoginError,
+    models::{
+        audit::ClientInfo,
+        users::{EmailCodeFields, EmailLinkFields, EmailLoginFields, User},
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        jwt::{amr, TokenKeys},
+        mail::MailSender,
+    },
+};
+
+#[post("/login/email-link")]
+async fn request_login_link(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    req_body: web::Json<EmailLoginFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::email_login::send_login_link(&mut conn, mailer.get_ref(), req_body.into_inner())
+    {
+        Ok(started) => HttpResponse::Accepted().json(started),
+        Err(e) => email_login_error_response(e),
+    }
+}
+
+#[post("/login/email-otp")]
+async fn request_login_code(
+    pool: web::Data<db::DbPool>,
+    mailer: web::Data<dyn MailSender>,
+    req_body: web::Json<EmailLoginFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::email_login::send_login_code(&mut conn, mailer.get_ref(), req_body.into_inner())
+    {
+        Ok(started) => HttpResponse::Accepted().json(started),
+        Err(e) => email_login_error_response(e),
+    }
+}
+
+#[post("/login/email-link/redeem")]
+async fn redeem_login_link(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    client: ClientInfo,
+    req_body: web::Json<EmailLinkFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::email_login::redeem_login_link(&mut conn, req_body.into_inner());
+
+    issue_tokens(&mut conn, &token_keys, &client, result, "method=link")
+}
+
+#[post("/login/email-otp/verify")]
+async fn verify_login_code(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    client: ClientInfo,
+    req_body: web::Json<EmailCodeFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::email_login::verify_login_code(&mut conn, req_body.into_inner());
+
+    issue_tokens(&mut conn, &token_keys, &client, result, "method=code")
+}
+
+fn issue_tokens(
+    conn: &mut db::Connection,
+    token_keys: &TokenKeys,
+    client: &ClientInfo,
+    result: Result<User, EmailLoginError>,
+    method: &str,
+) -> HttpResponse {
+    let user_id = result.as_ref().ok().map(|user| user.id);
+
+    audit::record(
+        conn,
+        client,
+        AuditEntry::from_result("user.email_login", user_id, user_id, &result).with_detail(method),
+    );
+
+    let user = match result {
+        Ok(user) => user,
+        Err(e) => return email_login_error_response(e),
+    };
+
+    match services::jwt::generate_jwt(conn, token_keys, user, &[amr::OTP]) {
+        Ok(jwt) => HttpResponse::Ok().json(jwt),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+fn email_login_error_response(err: EmailLoginError) -> HttpResponse {
+    match err {
+        EmailLoginError::InvalidFields(errors) => HttpResponse::UnprocessableEntity().json(errors),
+        EmailLoginError::InvalidToken | EmailLoginError::AccountDisabled => {
+            HttpResponse::Unauthorized().body(err.to_string())
+        }
+        EmailLoginError::MfaRequired => HttpResponse::Forbidden().body(err.to_string()),
+        EmailLoginError::TooManyAttempts => HttpResponse::TooManyRequests().body(err.to_string()),
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
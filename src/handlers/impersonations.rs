@@ -0,0 +1,108 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::Duration;
+use uuid::Uuid;
+
+use crate::{
+    config, db,
+    errors::impersonations::ImpersonationError,
+    extractors::AuthenticatedUser,
+    models::{audit::ClientInfo, impersonations::ImpersonationFields},
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        impersonations::USERS_IMPERSONATE,
+        jwt::{acr, TokenKeys},
+    },
+};
+
+#[post("/admin/impersonate/{user_id}")]
+async fn impersonate_user(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    user_id: web::Path<Uuid>,
+    req_body: web::Json<ImpersonationFields>,
+) -> impl Responder {
+    if !auth.has_permission(USERS_IMPERSONATE) {
+        return HttpResponse::Forbidden().body("Missing permission: users:impersonate");
+    }
+
+    // Seeing a user's data takes a recent sign in, as it does for the user
+    let max_age = Duration::minutes(config::get().sensitive_data_max_auth_age_minutes);
+    if let Err(e) = auth.require_authentication(acr::SINGLE_FACTOR, Some(max_age)) {
+        return HttpResponse::from_error(e);
+    }
+
+    let user_id = user_id.into_inner();
+    let fields = req_body.into_inner();
+    let reason = fields.reason.clone();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::impersonations::impersonate(
+        &mut conn,
+        &token_keys,
+        &auth.claims,
+        auth.user_id,
+        user_id,
+        fields,
+    );
+
+    let detail = match &result {
+        Ok(grant) => format!(
+            "impersonation_id={} scopes={} reason={:?}",
+            grant.impersonation.id, grant.token.scope, grant.impersonation.reason
+        ),
+        Err(_) => format!("reason={:?}", reason.trim()),
+    };
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "admin.user.impersonated",
+            Some(auth.user_id),
+            Some(user_id),
+            &result,
+        )
+        .with_detail(detail),
+    );
+
+    match result {
+        Ok(grant) => HttpResponse::Ok().json(grant),
+        Err(e) => impersonation_error_response(e),
+    }
+}
+
+#[get("/me/impersonations")]
+async fn list_own_impersonations(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::impersonations::list_for_user(&mut conn, auth.user_id) {
+        Ok(impersonations) => HttpResponse::Ok().json(impersonations),
+        Err(e) => impersonation_error_response(e),
+    }
+}
+
+fn impersonation_error_response(err: ImpersonationError) -> HttpResponse {
+    match err {
+        ImpersonationError::InvalidFields(errors) => {
+            HttpResponse::UnprocessableEntity().json(errors)
+        }
+        ImpersonationError::UserNotFound => HttpResponse::NotFound().body(err.to_string()),
+        ImpersonationError::SelfImpersonation | ImpersonationError::InvalidScope(_) => {
+            HttpResponse::BadRequest().body(err.to_string())
+        }
+        ImpersonationError::ProtectedUser => HttpResponse::Forbidden().body(err.to_string()),
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
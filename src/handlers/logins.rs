@@ -0,0 +1,64 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+
+use crate::{
+    db,
+    errors::{action_tokens::ActionTokenError, logins::LoginHistoryError},
+    extractors::AuthenticatedUser,
+    models::{audit::ClientInfo, logins::LoginDisownFields},
+    services::{
+        self,
+        audit::{self, AuditEntry},
+    },
+};
+
+#[get("/me/logins")]
+async fn list_logins(pool: web::Data<db::DbPool>, auth: AuthenticatedUser) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::logins::list_logins(&mut conn, auth.user_id) {
+        Ok(logins) => HttpResponse::Ok().json(logins),
+        Err(e) => login_history_error_response(e),
+    }
+}
+
+/// The "this wasn't me" link of a new sign in email
+#[post("/logins/disown")]
+async fn disown_login(
+    pool: web::Data<db::DbPool>,
+    client: ClientInfo,
+    req_body: web::Json<LoginDisownFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::logins::disown_login(&mut conn, req_body.into_inner());
+    let user_id = result.as_ref().ok().map(|(user_id, _)| *user_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result("user.login_disowned", user_id, user_id, &result),
+    );
+
+    match result {
+        Ok((_, count)) => HttpResponse::Ok().body(format!("{} tokens successfully revoked", count)),
+        Err(e) => login_history_error_response(e),
+    }
+}
+
+fn login_history_error_response(err: LoginHistoryError) -> HttpResponse {
+    match err {
+        LoginHistoryError::InvalidFields(errors) => {
+            HttpResponse::UnprocessableEntity().json(errors)
+        }
+        LoginHistoryError::ActionToken(ActionTokenError::InvalidToken) => {
+            HttpResponse::BadRequest().body(err.to_string())
+        }
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
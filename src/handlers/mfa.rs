@@ -0,0 +1,255 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+
+use crate::{
+    config, db,
+    errors::mfa::MfaError,
+    extractors::AuthenticatedUser,
+    models::{
+        audit::ClientInfo,
+        mfa::{
+            MfaTokenFields, MfaTokenResponse, MfaVerificationFields,
+            RecoveryCodeRegenerationFields, StepUpFields, TotpCodeFields,
+        },
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        crypto::DataKey,
+        jwt::{amr, TokenKeys},
+        mail::MailSender,
+    },
+};
+
+#[post("/me/mfa/totp")]
+async fn begin_totp_enrollment(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::mfa::begin_enrollment(&mut conn, &data_key, auth.user_id) {
+        Ok(enrollment) => HttpResponse::Ok().json(enrollment),
+        Err(e) => mfa_error_response(e),
+    }
+}
+
+#[post("/me/mfa/totp/confirm")]
+async fn confirm_totp_enrollment(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<TotpCodeFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::mfa::confirm_enrollment(
+        &mut conn,
+        &data_key,
+        auth.user_id,
+        req_body.into_inner(),
+    );
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "user.mfa_enabled",
+            Some(auth.user_id),
+            Some(auth.user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(recovery_codes) => HttpResponse::Ok().json(recovery_codes),
+        Err(e) => mfa_error_response(e),
+    }
+}
+
+#[post("/token/mfa/enroll")]
+async fn begin_challenge_totp_enrollment(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    req_body: web::Json<MfaTokenFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::mfa::begin_challenge_enrollment(&mut conn, &data_key, req_body.into_inner()) {
+        Ok(enrollment) => HttpResponse::Ok().json(enrollment),
+        Err(e) => mfa_error_response(e),
+    }
+}
+
+#[post("/token/mfa")]
+async fn verify_mfa(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    data_key: web::Data<DataKey>,
+    mailer: web::Data<dyn MailSender>,
+    client: ClientInfo,
+    req_body: web::Json<MfaVerificationFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let fields = req_body.into_inner();
+    let used_recovery_code = fields.recovery_code.is_some();
+
+    let result = services::mfa::verify_challenge(&mut conn, mailer.get_ref(), &data_key, fields);
+    let user_id = result
+        .as_ref()
+        .ok()
+        .map(|verification| verification.user.id);
+
+    let mut entry = AuditEntry::from_result("user.mfa_verified", user_id, user_id, &result);
+    if used_recovery_code {
+        entry = entry.with_detail("method=recovery_code");
+    }
+    audit::record(&mut conn, &client, entry);
+
+    let verification = match result {
+        Ok(verification) => verification,
+        Err(e) => return mfa_error_response(e),
+    };
+
+    let tokens = match services::jwt::generate_jwt(
+        &mut conn,
+        &token_keys,
+        verification.user.clone(),
+        &[amr::PASSWORD, amr::OTP, amr::MULTI_FACTOR],
+    ) {
+        Ok(jwt) => jwt,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    // The password sign in this second factor finishes
+    services::logins::record_login(&mut conn, mailer.get_ref(), &verification.user, &client);
+
+    HttpResponse::Ok().json(MfaTokenResponse {
+        tokens,
+        recovery_codes: verification.recovery_codes,
+        recovery_codes_remaining: verification.recovery_codes_remaining,
+    })
+}
+
+#[get("/me/mfa/recovery-codes")]
+async fn get_recovery_code_status(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::mfa::recovery_code_status(&mut conn, auth.user_id) {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => mfa_error_response(e),
+    }
+}
+
+#[post("/me/mfa/recovery-codes")]
+async fn regenerate_recovery_codes(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<RecoveryCodeRegenerationFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result =
+        services::mfa::regenerate_recovery_codes(&mut conn, auth.user_id, req_body.into_inner());
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "user.recovery_codes_regenerated",
+            Some(auth.user_id),
+            Some(auth.user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(recovery_codes) => HttpResponse::Ok().json(recovery_codes),
+        Err(e) => mfa_error_response(e),
+    }
+}
+
+#[post("/token/step-up")]
+async fn step_up(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<StepUpFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::mfa::step_up(
+        &mut conn,
+        &data_key,
+        auth.user_id,
+        req_body.into_inner(),
+        &config::get().mfa.required_roles,
+    );
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "user.step_up",
+            Some(auth.user_id),
+            Some(auth.user_id),
+            &result,
+        ),
+    );
+
+    let step_up = match result {
+        Ok(step_up) => step_up,
+        Err(e) => return mfa_error_response(e),
+    };
+
+    match services::jwt::generate_jwt(&mut conn, &token_keys, step_up.user, &step_up.methods) {
+        Ok(jwt) => HttpResponse::Ok().json(jwt),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+fn mfa_error_response(err: MfaError) -> HttpResponse {
+    match err {
+        MfaError::InvalidFields(errors) => HttpResponse::UnprocessableEntity().json(errors),
+        MfaError::AlreadyEnrolled | MfaError::NotEnrolled => {
+            HttpResponse::Conflict().body(err.to_string())
+        }
+        MfaError::InvalidCode
+        | MfaError::InvalidChallenge
+        | MfaError::IncorrectPassword
+        | MfaError::AccountSuspended
+        | MfaError::AccountDisabled
+        | MfaError::AccountDeleted => HttpResponse::Unauthorized().body(err.to_string()),
+        MfaError::TooManyAttempts => HttpResponse::TooManyRequests().body(err.to_string()),
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -0,0 +1,118 @@
+use actix_web::{http::header::RETRY_AFTER, post, web, HttpResponse, Responder};
+
+use crate::{
+    db,
+    errors::phone_login::PhoneLoginError,
+    models::{
+        audit::ClientInfo,
+        users::{PhoneCodeFields, PhoneLoginFields, PhoneRegistrationFields, User},
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        jwt::{amr, TokenKeys},
+        sms::SmsSender,
+    },
+};
+
+#[post("/phone/code")]
+async fn request_phone_code(
+    pool: web::Data<db::DbPool>,
+    sms_sender: web::Data<dyn SmsSender>,
+    req_body: web::Json<PhoneCodeFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::phone_login::send_code(&mut conn, sms_sender.get_ref(), req_body.into_inner()) {
+        Ok(sent) => HttpResponse::Accepted().json(sent),
+        Err(e) => phone_login_error_response(e),
+    }
+}
+
+#[post("/phone/register")]
+async fn register_with_phone(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    client: ClientInfo,
+    req_body: web::Json<PhoneRegistrationFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::phone_login::register(&mut conn, req_body.into_inner());
+
+    issue_tokens(
+        &mut conn,
+        &token_keys,
+        &client,
+        result,
+        "user.phone_registered",
+    )
+}
+
+#[post("/phone/login")]
+async fn login_with_phone(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    client: ClientInfo,
+    req_body: web::Json<PhoneLoginFields>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::phone_login::login(&mut conn, req_body.into_inner());
+
+    issue_tokens(&mut conn, &token_keys, &client, result, "user.phone_login")
+}
+
+fn issue_tokens(
+    conn: &mut db::Connection,
+    token_keys: &TokenKeys,
+    client: &ClientInfo,
+    result: Result<User, PhoneLoginError>,
+    event_type: &'static str,
+) -> HttpResponse {
+    let user_id = result.as_ref().ok().map(|user| user.id);
+
+    audit::record(
+        conn,
+        client,
+        AuditEntry::from_result(event_type, user_id, user_id, &result),
+    );
+
+    let user = match result {
+        Ok(user) => user,
+        Err(e) => return phone_login_error_response(e),
+    };
+
+    match services::jwt::generate_jwt(conn, token_keys, user, &[amr::SMS]) {
+        Ok(jwt) => HttpResponse::Ok().json(jwt),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+fn phone_login_error_response(err: PhoneLoginError) -> HttpResponse {
+    match err {
+        PhoneLoginError::InvalidFields(errors) => HttpResponse::UnprocessableEntity().json(errors),
+        PhoneLoginError::InvalidCode | PhoneLoginError::AccountDisabled => {
+            HttpResponse::Unauthorized().body(err.to_string())
+        }
+        PhoneLoginError::TooManyAttempts => HttpResponse::TooManyRequests().body(err.to_string()),
+        PhoneLoginError::ResendCooldown {
+            retry_after_seconds,
+        } => HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, retry_after_seconds.to_string()))
+            .body(err.to_string()),
+        PhoneLoginError::PhoneNumberTaken => HttpResponse::Conflict().body(err.to_string()),
+        PhoneLoginError::AccountNotFound => HttpResponse::NotFound().body(err.to_string()),
+        PhoneLoginError::MfaRequired => HttpResponse::Forbidden().body(err.to_string()),
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
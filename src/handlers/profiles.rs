@@ -0,0 +1,63 @@
+use actix_web::{get, patch, web, HttpResponse, Responder};
+
+use chrono::Duration;
+
+use crate::{
+    config, db,
+    errors::profiles::ProfileError,
+    extractors::AuthenticatedUser,
+    models::profiles::ProfileUpdate,
+    services::{self, crypto::DataKey, jwt::acr},
+};
+
+#[get("/me/profile")]
+async fn get_profile(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    // The profile holds the NIK, so a long lived session is not enough
+    let max_age = Duration::minutes(config::get().sensitive_data_max_auth_age_minutes);
+    if let Err(e) = auth.require_authentication(acr::SINGLE_FACTOR, Some(max_age)) {
+        return HttpResponse::from_error(e);
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::profiles::get_profile(&mut conn, &data_key, auth.user_id) {
+        Ok(profile) => HttpResponse::Ok().json(profile),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[patch("/me/profile")]
+async fn update_profile(
+    pool: web::Data<db::DbPool>,
+    data_key: web::Data<DataKey>,
+    auth: AuthenticatedUser,
+    req_body: web::Json<ProfileUpdate>,
+) -> impl Responder {
+    // The updated profile is sent back with the NIK, as from `get_profile`
+    let max_age = Duration::minutes(config::get().sensitive_data_max_auth_age_minutes);
+    if let Err(e) = auth.require_authentication(acr::SINGLE_FACTOR, Some(max_age)) {
+        return HttpResponse::from_error(e);
+    }
+
+    let update = req_body.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::profiles::update_profile(&mut conn, &data_key, auth.user_id, update) {
+        Ok(profile) => HttpResponse::Ok().json(profile),
+        Err(ProfileError::InvalidFields(errors)) => {
+            HttpResponse::UnprocessableEntity().json(errors)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
@@ -0,0 +1,188 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+
+use crate::{
+    db,
+    errors::webauthn::WebAuthnError,
+    extractors::AuthenticatedUser,
+    models::{
+        audit::ClientInfo,
+        webauthn::{AuthenticationCredential, RegistrationCredential},
+    },
+    services::{
+        self,
+        audit::{self, AuditEntry},
+        jwt::{amr, TokenKeys},
+    },
+};
+
+#[post("/me/webauthn/register/options")]
+async fn begin_webauthn_registration(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::webauthn::start_registration(&mut conn, auth.user_id) {
+        Ok(options) => HttpResponse::Ok().json(options),
+        Err(e) => webauthn_error_response(e),
+    }
+}
+
+#[post("/me/webauthn/register")]
+async fn finish_webauthn_registration(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    req_body: web::Json<RegistrationCredential>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result =
+        services::webauthn::finish_registration(&mut conn, auth.user_id, req_body.into_inner());
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "user.webauthn_registered",
+            Some(auth.user_id),
+            Some(auth.user_id),
+            &result,
+        ),
+    );
+
+    match result {
+        Ok(credential) => HttpResponse::Created().json(credential),
+        Err(e) => webauthn_error_response(e),
+    }
+}
+
+#[get("/me/webauthn/credentials")]
+async fn list_webauthn_credentials(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::webauthn::list_credentials(&mut conn, auth.user_id) {
+        Ok(credentials) => HttpResponse::Ok().json(credentials),
+        Err(e) => webauthn_error_response(e),
+    }
+}
+
+#[delete("/me/webauthn/credentials/{credential_id}")]
+async fn delete_webauthn_credential(
+    pool: web::Data<db::DbPool>,
+    auth: AuthenticatedUser,
+    client: ClientInfo,
+    path: web::Path<String>,
+) -> impl Responder {
+    let credential_id = path.into_inner();
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::webauthn::delete_credential(&mut conn, auth.user_id, &credential_id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result(
+            "user.webauthn_removed",
+            Some(auth.user_id),
+            Some(auth.user_id),
+            &result,
+        )
+        .with_detail(format!("credential_id={}", credential_id)),
+    );
+
+    match result {
+        Ok(()) => HttpResponse::Ok().body("Passkey successfully removed"),
+        Err(e) => webauthn_error_response(e),
+    }
+}
+
+#[post("/webauthn/login/options")]
+async fn begin_webauthn_login(pool: web::Data<db::DbPool>) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match services::webauthn::start_authentication(&mut conn) {
+        Ok(options) => HttpResponse::Ok().json(options),
+        Err(e) => webauthn_error_response(e),
+    }
+}
+
+#[post("/webauthn/login")]
+async fn finish_webauthn_login(
+    pool: web::Data<db::DbPool>,
+    token_keys: web::Data<TokenKeys>,
+    client: ClientInfo,
+    req_body: web::Json<AuthenticationCredential>,
+) -> impl Responder {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let result = services::webauthn::finish_authentication(&mut conn, req_body.into_inner());
+    let user_id = result.as_ref().ok().map(|user| user.id);
+
+    audit::record(
+        &mut conn,
+        &client,
+        AuditEntry::from_result("user.webauthn_login", user_id, user_id, &result),
+    );
+
+    let user = match result {
+        Ok(user) => user,
+        Err(e) => return webauthn_error_response(e),
+    };
+
+    // The authenticator verified the user, so the passkey counts as two factors
+    match services::jwt::generate_jwt(
+        &mut conn,
+        &token_keys,
+        user,
+        &[amr::HARDWARE_KEY, amr::MULTI_FACTOR],
+    ) {
+        Ok(jwt) => HttpResponse::Ok().json(jwt),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+fn webauthn_error_response(err: WebAuthnError) -> HttpResponse {
+    match err {
+        WebAuthnError::InvalidFields(errors) => HttpResponse::UnprocessableEntity().json(errors),
+        WebAuthnError::InvalidClientData
+        | WebAuthnError::InvalidAuthenticatorData
+        | WebAuthnError::UnsupportedAttestation
+        | WebAuthnError::UnsupportedAlgorithm => HttpResponse::BadRequest().body(err.to_string()),
+        WebAuthnError::InvalidChallenge
+        | WebAuthnError::OriginMismatch
+        | WebAuthnError::RelyingPartyMismatch
+        | WebAuthnError::UserNotPresent
+        | WebAuthnError::UserNotVerified
+        | WebAuthnError::InvalidSignature
+        | WebAuthnError::CounterRegression
+        | WebAuthnError::AccountDisabled => HttpResponse::Unauthorized().body(err.to_string()),
+        WebAuthnError::CredentialNotFound => HttpResponse::NotFound().body(err.to_string()),
+        WebAuthnError::CredentialAlreadyRegistered => {
+            HttpResponse::Conflict().body(err.to_string())
+        }
+        _ => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
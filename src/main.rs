@@ -1,13 +1,19 @@
 use std::fs;
 use std::io::{Error, ErrorKind};
 use std::net::{Ipv4Addr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
 
 use actix_web::{web, App, HttpServer};
 use dotenvy::dotenv;
 
+mod cli;
+mod config;
 mod db;
 mod errors;
+mod extractors;
 mod handlers;
+mod middleware;
 mod models;
 mod repository;
 mod schema;
@@ -16,7 +22,14 @@ mod services;
 #[cfg(test)]
 mod tests;
 
+use crate::config::RateLimitStoreKind;
 use crate::handlers::*;
+use crate::middleware::rate_limit::RateLimiter;
+use crate::services::crypto::DataKey;
+use crate::services::jwt::TokenKeys;
+use crate::services::mail::{FileMailSender, LogMailSender, MailSender};
+use crate::services::rate_limit::{MemoryRateLimitStore, PostgresRateLimitStore, RateLimitStore};
+use crate::services::sms::{FileSmsSender, LogSmsSender, SmsSender};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -35,9 +48,96 @@ async fn main() -> std::io::Result<()> {
         .filter_level(log::LevelFilter::Debug)
         .init();
 
+    // `pandacare-auth <command> [args...]` runs a maintenance command and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some((command, command_args)) = args.split_first() {
+        return cli::run(&pool, command, command_args);
+    }
+
     let secret_key: String = fs::read_to_string("keys/rsa-private.pem")
         .map_err(|err| Error::new(ErrorKind::NotFound, err.to_string()))?;
 
+    let token_keys = web::Data::new(
+        TokenKeys::new(&secret_key)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?,
+    );
+
+    let data_key = std::env::var("DATA_ENCRYPTION_KEY")
+        .map_err(|err| Error::new(ErrorKind::NotFound, err.to_string()))
+        .and_then(|key| {
+            DataKey::from_base64(&key)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))
+        })?;
+
+    let mailer: Arc<dyn MailSender> = match std::env::var("MAIL_OUTBOX_PATH") {
+        Ok(path) => Arc::new(FileMailSender::new(path)),
+        Err(_err) => {
+            log::warn!("MAIL_OUTBOX_PATH is not set, so email will not be delivered");
+            Arc::new(LogMailSender)
+        }
+    };
+
+    let sms_sender: Arc<dyn SmsSender> = match std::env::var("SMS_OUTBOX_PATH") {
+        Ok(path) => Arc::new(FileSmsSender::new(path)),
+        Err(_err) => {
+            log::warn!("SMS_OUTBOX_PATH is not set, so text messages will not be sent");
+            Arc::new(LogSmsSender)
+        }
+    };
+
+    // Created once so that every worker draws from the same buckets
+    let rate_limit_store: Arc<dyn RateLimitStore> = match config::get().rate_limit.store {
+        RateLimitStoreKind::Memory => Arc::new(MemoryRateLimitStore::default()),
+        RateLimitStoreKind::Postgres => Arc::new(PostgresRateLimitStore::new(pool.clone())),
+    };
+
+    // Forgets full buckets now and then, since callers choose the keys and
+    // could otherwise grow the store without bound
+    let prune_store = rate_limit_store.clone();
+    actix_web::rt::spawn(async move {
+        let seconds = config::get().rate_limit.prune_interval_seconds.max(1);
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(seconds));
+
+        loop {
+            interval.tick().await;
+
+            let store = prune_store.clone();
+            match web::block(move || store.prune()).await {
+                Ok(Ok(_pruned)) => {}
+                Ok(Err(err)) => log::error!("Failed to prune rate limit buckets: {}", err),
+                Err(err) => log::error!("Failed to prune rate limit buckets: {}", err),
+            }
+        }
+    });
+
+    // Signs the audit log now and then, so that it cannot be rewritten
+    // without the signing key
+    let checkpoint_pool = pool.clone();
+    let checkpoint_key = secret_key.clone();
+    actix_web::rt::spawn(async move {
+        let minutes = config::get().audit_checkpoint_interval_minutes.max(1);
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(minutes * 60));
+
+        loop {
+            interval.tick().await;
+
+            let pool = checkpoint_pool.clone();
+            let secret_key = checkpoint_key.clone();
+            let result = web::block(move || {
+                let mut conn = pool.get().map_err(|err| err.to_string())?;
+                services::audit::create_checkpoint(&mut conn, &secret_key)
+                    .map_err(|err| err.to_string())
+            })
+            .await;
+
+            match result {
+                Ok(Ok(_checkpoint)) => {}
+                Ok(Err(err)) => log::error!("Failed to create audit checkpoint: {}", err),
+                Err(err) => log::error!("Failed to create audit checkpoint: {}", err),
+            }
+        }
+    });
+
     let port = std::env::var("PORT")
         .unwrap_or_else(|_| "8080".to_string())
         .parse::<u16>()
@@ -45,16 +145,78 @@ async fn main() -> std::io::Result<()> {
 
     HttpServer::new(move || {
         App::new()
+            .wrap(RateLimiter::for_auth_endpoints(rate_limit_store.clone()))
             .wrap(Logger::default())
-            .app_data(web::Data::new(secret_key.clone()))
+            .app_data(token_keys.clone())
             .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(data_key.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(web::Data::from(sms_sender.clone()))
+            .app_data(extractors::json_config())
             .service(
                 web::scope("/api")
                     .service(obtain)
                     .service(register)
                     .service(refresh)
                     .service(revoke)
-                    .service(get_email_by_user_id),
+                    .service(reset_password)
+                    .service(change_password)
+                    .service(request_login_link)
+                    .service(request_login_code)
+                    .service(redeem_login_link)
+                    .service(verify_login_code)
+                    .service(request_phone_code)
+                    .service(register_with_phone)
+                    .service(login_with_phone)
+                    .service(verify_mfa)
+                    .service(step_up)
+                    .service(begin_challenge_totp_enrollment)
+                    .service(begin_totp_enrollment)
+                    .service(confirm_totp_enrollment)
+                    .service(get_recovery_code_status)
+                    .service(regenerate_recovery_codes)
+                    .service(begin_webauthn_login)
+                    .service(finish_webauthn_login)
+                    .service(begin_webauthn_registration)
+                    .service(finish_webauthn_registration)
+                    .service(list_webauthn_credentials)
+                    .service(delete_webauthn_credential)
+                    .service(get_email_by_user_id)
+                    .service(get_profile)
+                    .service(update_profile)
+                    .service(list_users)
+                    .service(get_user)
+                    .service(get_user_sessions)
+                    .service(disable_user)
+                    .service(enable_user)
+                    .service(set_user_status)
+                    .service(force_password_reset)
+                    .service(revoke_user_tokens)
+                    .service(unlock_user)
+                    .service(get_user_roles)
+                    .service(grant_user_role)
+                    .service(revoke_user_role)
+                    .service(impersonate_user)
+                    .service(list_audit_events)
+                    .service(export_audit_events)
+                    .service(list_own_impersonations)
+                    .service(list_logins)
+                    .service(disown_login)
+                    .service(submit_caregiver_application)
+                    .service(get_own_caregiver_application)
+                    .service(list_caregiver_applications)
+                    .service(get_caregiver_application)
+                    .service(approve_caregiver_application)
+                    .service(reject_caregiver_application)
+                    .service(invite_delegate)
+                    .service(list_delegations)
+                    .service(revoke_delegation)
+                    .service(accept_delegation)
+                    .service(exchange_token)
+                    .service(create_dependent)
+                    .service(list_dependents)
+                    .service(update_dependent)
+                    .service(convert_dependent),
             )
             .service(web::scope("/.well-known").service(get_jwks))
     })
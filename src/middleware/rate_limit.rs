@@ -0,0 +1,452 @@
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{HeaderName, HeaderValue, AUTHORIZATION, RETRY_AFTER},
+        Method,
+    },
+    web, Error, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use serde::{de::DeserializeOwned, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config,
+    extractors::client_ip,
+    models::rate_limit::{Quota, RateLimitDecision},
+    services::{
+        jwt::{decode_access_token, TokenKeys},
+        rate_limit::RateLimitStore,
+        users::{normalize_email, normalize_phone_number},
+    },
+};
+
+/// What a rule counts requests by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitKey {
+    /// The client IP address
+    Ip,
+    /// The user behind a valid bearer token, else the IP address. Nothing else
+    /// the caller sends is trusted, since it could change it on every request.
+    UserOrIp,
+    /// The `email` field of a JSON request body, so that attempts against one
+    /// account are limited however many addresses they come from
+    EmailInBody,
+    /// The `phone_number` field of a JSON request body
+    PhoneNumberInBody,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitRule {
+    /// Keeps the buckets of different rules apart
+    pub name: &'static str,
+    pub method: Method,
+    /// Full request path; `{name}` segments match any value
+    pub path: &'static str,
+    pub key: RateLimitKey,
+    pub quota: Quota,
+}
+
+impl RateLimitRule {
+    pub fn new(
+        name: &'static str,
+        method: Method,
+        path: &'static str,
+        key: RateLimitKey,
+        quota: Quota,
+    ) -> Self {
+        RateLimitRule {
+            name,
+            method,
+            path,
+            key,
+            quota,
+        }
+    }
+
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        if *method != self.method {
+            return false;
+        }
+
+        let mut pattern = self.path.trim_end_matches('/').split('/');
+        let mut segments = path.trim_end_matches('/').split('/');
+
+        loop {
+            match (pattern.next(), segments.next()) {
+                (None, None) => return true,
+                (Some(expected), Some(segment)) => {
+                    let is_placeholder = expected.starts_with('{') && expected.ends_with('}');
+                    let segment_matches = if is_placeholder {
+                        !segment.is_empty()
+                    } else {
+                        expected == segment
+                    };
+
+                    if !segment_matches {
+                        return false;
+                    }
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// Token bucket rate limiting for the routes matched by its rules. Requests
+/// over a limit get `429 Too Many Requests` with a `Retry-After` header;
+/// limited routes also carry `RateLimit-Limit`, `RateLimit-Remaining` and
+/// `RateLimit-Reset` headers for the tightest matching rule.
+///
+/// If the store fails the request is let through, so that an outage of the
+/// store does not take authentication down with it.
+#[derive(Clone)]
+pub struct RateLimiter {
+    rules: Rc<Vec<RateLimitRule>>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>) -> Self {
+        RateLimiter {
+            rules: Rc::new(Vec::new()),
+            store,
+        }
+    }
+
+    pub fn rule(mut self, rule: RateLimitRule) -> Self {
+        Rc::make_mut(&mut self.rules).push(rule);
+        self
+    }
+
+    /// The limits on the authentication endpoints, with quotas from the config
+    pub fn for_auth_endpoints(store: Arc<dyn RateLimitStore>) -> Self {
+        let quotas = &config::get().rate_limit;
+
+        RateLimiter::new(store)
+            .rule(RateLimitRule::new(
+                "obtain_ip",
+                Method::POST,
+                "/api/token/obtain",
+                RateLimitKey::Ip,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "obtain_email",
+                Method::POST,
+                "/api/token/obtain",
+                RateLimitKey::EmailInBody,
+                quotas.obtain_per_email,
+            ))
+            .rule(RateLimitRule::new(
+                "mfa_ip",
+                Method::POST,
+                "/api/token/mfa",
+                RateLimitKey::Ip,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "step_up_user",
+                Method::POST,
+                "/api/token/step-up",
+                RateLimitKey::UserOrIp,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "token_exchange_user",
+                Method::POST,
+                "/api/token/exchange",
+                RateLimitKey::UserOrIp,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "delegation_invite_user",
+                Method::POST,
+                "/api/me/delegations",
+                RateLimitKey::UserOrIp,
+                quotas.email_login_per_email,
+            ))
+            .rule(RateLimitRule::new(
+                "dependent_convert_user",
+                Method::POST,
+                "/api/me/dependents/{dependent_id}/convert",
+                RateLimitKey::UserOrIp,
+                quotas.email_login_per_email,
+            ))
+            .rule(RateLimitRule::new(
+                "email_link_email",
+                Method::POST,
+                "/api/login/email-link",
+                RateLimitKey::EmailInBody,
+                quotas.email_login_per_email,
+            ))
+            .rule(RateLimitRule::new(
+                "email_otp_email",
+                Method::POST,
+                "/api/login/email-otp",
+                RateLimitKey::EmailInBody,
+                quotas.email_login_per_email,
+            ))
+            .rule(RateLimitRule::new(
+                "email_otp_verify_ip",
+                Method::POST,
+                "/api/login/email-otp/verify",
+                RateLimitKey::Ip,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "sms_code_number",
+                Method::POST,
+                "/api/phone/code",
+                RateLimitKey::PhoneNumberInBody,
+                quotas.sms_code_per_number,
+            ))
+            .rule(RateLimitRule::new(
+                "sms_code_ip",
+                Method::POST,
+                "/api/phone/code",
+                RateLimitKey::Ip,
+                quotas.sms_code_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "phone_login_ip",
+                Method::POST,
+                "/api/phone/login",
+                RateLimitKey::Ip,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "phone_register_ip",
+                Method::POST,
+                "/api/phone/register",
+                RateLimitKey::Ip,
+                quotas.register_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "webauthn_options_ip",
+                Method::POST,
+                "/api/webauthn/login/options",
+                RateLimitKey::Ip,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "webauthn_login_ip",
+                Method::POST,
+                "/api/webauthn/login",
+                RateLimitKey::Ip,
+                quotas.obtain_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "register_ip",
+                Method::POST,
+                "/api/register",
+                RateLimitKey::Ip,
+                quotas.register_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "refresh_ip",
+                Method::POST,
+                "/api/token/refresh",
+                RateLimitKey::Ip,
+                quotas.refresh_per_ip,
+            ))
+            .rule(RateLimitRule::new(
+                "email_lookup",
+                Method::GET,
+                "/api/email/{user_id}",
+                RateLimitKey::UserOrIp,
+                quotas.email_lookup_per_client,
+            ))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimiterMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+            rules: Rc::clone(&self.rules),
+            store: Arc::clone(&self.store),
+        }))
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    rules: Rc<Vec<RateLimitRule>>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let rules = Rc::clone(&self.rules);
+        let store = Arc::clone(&self.store);
+
+        Box::pin(async move {
+            let mut tightest: Option<RateLimitDecision> = None;
+
+            for rule in rules.iter() {
+                if !rule.matches(req.method(), req.path()) {
+                    continue;
+                }
+
+                let Some(key) = request_key(&mut req, rule.key).await else {
+                    continue;
+                };
+
+                // Keys come from the request body before it is validated, so
+                // they are hashed to a fixed length that any store can keep
+                let bucket_key = format!("{}:{:x}", rule.name, Sha256::digest(key.as_bytes()));
+                let quota = rule.quota;
+                let store = Arc::clone(&store);
+
+                // These limits guard sign in, so an unusable store refuses the
+                // request rather than letting it through unchecked
+                let decision = match web::block(move || store.take(&bucket_key, quota)).await {
+                    Ok(Ok(decision)) => decision,
+                    Ok(Err(e)) => {
+                        log::error!("Rate limit {} could not be checked: {}", rule.name, e);
+                        return Ok(req
+                            .into_response(store_unavailable_response())
+                            .map_into_right_body());
+                    }
+                    Err(e) => {
+                        log::error!("Rate limit {} could not be checked: {}", rule.name, e);
+                        return Ok(req
+                            .into_response(store_unavailable_response())
+                            .map_into_right_body());
+                    }
+                };
+
+                tightest = Some(match tightest {
+                    Some(current) if !is_tighter(&decision, &current) => current,
+                    _ => decision,
+                });
+            }
+
+            let Some(decision) = tightest else {
+                return service
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body);
+            };
+
+            if !decision.allowed {
+                let mut response = HttpResponse::TooManyRequests()
+                    .insert_header((RETRY_AFTER, decision.retry_after_seconds))
+                    .body("Too many requests. Please try again later");
+                insert_rate_limit_headers(&mut response, &decision);
+
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            let mut res = service.call(req).await?;
+            insert_rate_limit_headers(res.response_mut(), &decision);
+
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+// A denial is tighter than any allowance, then the longer wait or the fewer requests left
+fn is_tighter(candidate: &RateLimitDecision, current: &RateLimitDecision) -> bool {
+    match (candidate.allowed, current.allowed) {
+        (false, true) => true,
+        (true, false) => false,
+        (false, false) => candidate.retry_after_seconds > current.retry_after_seconds,
+        (true, true) => candidate.remaining < current.remaining,
+    }
+}
+
+fn store_unavailable_response() -> HttpResponse {
+    HttpResponse::ServiceUnavailable().body("Rate limiting is unavailable. Please try again later")
+}
+
+fn insert_rate_limit_headers<B>(response: &mut HttpResponse<B>, decision: &RateLimitDecision) {
+    let headers = response.headers_mut();
+
+    for (name, value) in [
+        ("ratelimit-limit", decision.limit as u64),
+        ("ratelimit-remaining", decision.remaining as u64),
+        ("ratelimit-reset", decision.reset_seconds),
+    ] {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+}
+
+async fn request_key(req: &mut ServiceRequest, key: RateLimitKey) -> Option<String> {
+    match key {
+        RateLimitKey::Ip => client_ip(req.request()).map(|ip| format!("ip:{}", ip)),
+        RateLimitKey::UserOrIp => user_or_ip_key(req),
+        RateLimitKey::EmailInBody => body_field::<EmailField>(req)
+            .await
+            .map(|field| format!("email:{}", normalize_email(&field.email))),
+        RateLimitKey::PhoneNumberInBody => body_field::<PhoneNumberField>(req)
+            .await
+            .and_then(|field| normalize_phone_number(&field.phone_number))
+            .map(|phone_number| format!("phone:{}", phone_number)),
+    }
+}
+
+fn user_or_ip_key(req: &ServiceRequest) -> Option<String> {
+    let bearer_token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    let token_keys = req.app_data::<web::Data<TokenKeys>>();
+
+    if let (Some(token), Some(token_keys)) = (bearer_token, token_keys) {
+        if let Ok(claims) = decode_access_token(token_keys, token.trim()) {
+            return Some(format!("user:{}", claims.user_id));
+        }
+    }
+
+    client_ip(req.request()).map(|ip| format!("ip:{}", ip))
+}
+
+#[derive(Deserialize)]
+struct EmailField {
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct PhoneNumberField {
+    phone_number: String,
+}
+
+// Reads the body to find the field, then puts it back for the handler
+async fn body_field<T: DeserializeOwned>(req: &mut ServiceRequest) -> Option<T> {
+    let body = req.extract::<web::Bytes>().await.ok()?;
+    req.set_payload(Payload::from(body.clone()));
+
+    serde_json::from_slice::<T>(&body).ok()
+}
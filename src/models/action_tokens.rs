@@ -0,0 +1,12 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::action_tokens)]
+pub struct NewActionToken {
+    pub token_hash: String,
+    pub user_id: Uuid,
+    pub purpose: String,
+    pub expired_at: NaiveDateTime,
+}
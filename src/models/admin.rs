@@ -0,0 +1,65 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::users::UserStatus;
+
+#[derive(Deserialize)]
+pub struct UserSearchQuery {
+    pub role: Option<String>,
+    pub email_prefix: Option<String>,
+    pub verified: Option<bool>,
+    pub status: Option<UserStatus>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Search filters after the opaque cursor has been decoded
+pub struct UserFilter {
+    pub role: Option<String>,
+    pub email_prefix: Option<String>,
+    pub verified: Option<bool>,
+    pub status: Option<UserStatus>,
+    pub after: Option<UserCursor>,
+    pub limit: i64,
+}
+
+/// The last user of a page. Users sort by email, or by phone number if they
+/// have no email, then by id; those with neither, such as dependents, come last.
+#[derive(Serialize, Deserialize)]
+pub struct UserCursor {
+    pub login_name: Option<String>,
+    pub id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserView {
+    pub id: Uuid,
+    pub email: Option<String>,
+    pub phone_number: Option<String>,
+    pub guardian_id: Option<Uuid>,
+    pub roles: Vec<String>,
+    pub is_verified: bool,
+    pub status: UserStatus,
+    pub must_reset_password: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize)]
+pub struct UserStatusFields {
+    pub status: UserStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserPage {
+    pub users: Vec<AdminUserView>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionView {
+    pub issued_at: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+}
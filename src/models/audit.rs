@@ -0,0 +1,111 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::audit_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: NaiveDateTime,
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub event_type: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    pub reason: Option<String>,
+    /// Hash of the entry before this one in the chain
+    pub previous_hash: Option<String>,
+    /// Hash of this entry and the one before it. Entries written before the
+    /// chain existed have none.
+    pub entry_hash: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::audit_events)]
+pub struct NewAuditEvent {
+    pub occurred_at: NaiveDateTime,
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub event_type: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    pub reason: Option<String>,
+    pub previous_hash: Option<String>,
+    pub entry_hash: Option<String>,
+}
+
+/// The hash of the chain up to an event, signed with the service's key
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::audit_checkpoints)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuditCheckpoint {
+    pub id: i64,
+    pub last_event_id: i64,
+    pub entry_hash: String,
+    pub signature: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::audit_checkpoints)]
+pub struct NewAuditCheckpoint {
+    pub last_event_id: i64,
+    pub entry_hash: String,
+    pub signature: String,
+}
+
+/// What a walk over an intact audit chain found
+#[derive(Debug, Default)]
+pub struct AuditChainSummary {
+    pub chained_events: u64,
+    /// Events written before the chain existed
+    pub unchained_events: u64,
+    pub checkpoints: u64,
+    /// Entries after this were written since the last checkpoint and are only
+    /// protected by the chain itself
+    pub last_checkpoint_at: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize)]
+pub struct AuditEventQuery {
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub event_type: Option<String>,
+    pub outcome: Option<String>,
+    /// Events at or after this time (UTC)
+    pub from: Option<NaiveDateTime>,
+    /// Events before this time (UTC)
+    pub to: Option<NaiveDateTime>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Search filters after the opaque cursor has been decoded
+pub struct AuditEventFilter {
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub event_type: Option<String>,
+    pub outcome: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    /// Events sort newest first, by ID
+    pub before_id: Option<i64>,
+    pub limit: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEventPage {
+    pub events: Vec<AuditEvent>,
+    pub next_cursor: Option<String>,
+}
+
+/// Where a request came from, as recorded in the audit log.
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
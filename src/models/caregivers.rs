@@ -0,0 +1,162 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidateUrl, ValidationError};
+
+use crate::models::users::trimmed;
+
+/// Where a caregiver application is in its review
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplicationStatus {
+    PendingVerification,
+    Approved,
+    Rejected,
+}
+
+impl ApplicationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingVerification => "pending_verification",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+/// Kinds of supporting documents a caregiver can submit
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentType {
+    /// Surat Tanda Registrasi, the registration certificate behind the STR number
+    StrCertificate,
+    /// Surat Izin Praktik, the permit to practise at a facility
+    PracticePermit,
+    Diploma,
+    IdentityCard,
+}
+
+impl DocumentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StrCertificate => "str_certificate",
+            Self::PracticePermit => "practice_permit",
+            Self::Diploma => "diploma",
+            Self::IdentityCard => "identity_card",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::caregiver_applications)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CaregiverApplication {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub str_number: String,
+    /// One of the values of [`ApplicationStatus`]
+    pub status: String,
+    pub rejection_reason: Option<String>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<NaiveDateTime>,
+    pub submitted_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::caregiver_applications)]
+pub struct NewCaregiverApplication {
+    pub user_id: Uuid,
+    pub str_number: String,
+}
+
+#[derive(Clone, Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::caregiver_documents)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CaregiverDocument {
+    pub id: i64,
+    pub document_type: String,
+    pub url: String,
+    pub submitted_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::caregiver_documents)]
+pub struct NewCaregiverDocument {
+    pub application_id: Uuid,
+    pub document_type: String,
+    pub url: String,
+}
+
+/// An application with the documents submitted for it
+#[derive(Debug, Serialize)]
+pub struct CaregiverApplicationDetails {
+    #[serde(flatten)]
+    pub application: CaregiverApplication,
+    pub documents: Vec<CaregiverDocument>,
+}
+
+/// What a caregiver submits for review, either when registering or later
+#[derive(Deserialize, Clone, Validate)]
+pub struct CaregiverApplicationFields {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(custom(
+        function = "valid_str_number",
+        message = "must be 8 to 32 letters, digits, dots, dashes or slashes"
+    ))]
+    pub str_number: String,
+    #[validate(
+        length(min = 1, max = 10, message = "must have between 1 and 10 documents"),
+        nested
+    )]
+    pub documents: Vec<CaregiverDocumentFields>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Validate)]
+pub struct CaregiverDocumentFields {
+    pub document_type: DocumentType,
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(
+        custom(function = "valid_document_url", message = "must be an https URL"),
+        length(max = 2048, message = "must be at most 2048 characters long")
+    )]
+    pub url: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RejectionFields {
+    /// Sent to the applicant, so that they know what to fix
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(length(
+        min = 1,
+        max = 1000,
+        message = "must be between 1 and 1000 characters long"
+    ))]
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct ApplicationQuery {
+    pub status: Option<ApplicationStatus>,
+}
+
+fn valid_str_number(str_number: &str) -> Result<(), ValidationError> {
+    let valid = (8..=32).contains(&str_number.len())
+        && str_number
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "./-".contains(character));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("str_number"))
+    }
+}
+
+fn valid_document_url(url: &str) -> Result<(), ValidationError> {
+    if url.starts_with("https://") && url.validate_url() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("url"))
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::users::{trimmed, valid_email_address};
+
+/// What a delegate may do for the patient. Services that accept delegated
+/// tokens check these in the `scope` claim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DelegationScope {
+    #[serde(rename = "appointments:read")]
+    AppointmentsRead,
+    #[serde(rename = "appointments:write")]
+    AppointmentsWrite,
+    #[serde(rename = "records:read")]
+    RecordsRead,
+    #[serde(rename = "prescriptions:read")]
+    PrescriptionsRead,
+}
+
+impl DelegationScope {
+    pub const ALL: [Self; 4] = [
+        Self::AppointmentsRead,
+        Self::AppointmentsWrite,
+        Self::RecordsRead,
+        Self::PrescriptionsRead,
+    ];
+
+    /// Scopes that only let the holder look, which is all an impersonating
+    /// administrator gets
+    pub fn is_read_only(&self) -> bool {
+        !matches!(self, Self::AppointmentsWrite)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AppointmentsRead => "appointments:read",
+            Self::AppointmentsWrite => "appointments:write",
+            Self::RecordsRead => "records:read",
+            Self::PrescriptionsRead => "prescriptions:read",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelegationStatus {
+    /// Invited, waiting for the delegate to accept
+    Pending,
+    Active,
+    Revoked,
+}
+
+impl DelegationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Active => "active",
+            Self::Revoked => "revoked",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::delegations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Delegation {
+    pub id: Uuid,
+    /// The patient being acted for
+    pub subject_id: Uuid,
+    /// The delegate, once they accept
+    pub actor_id: Option<Uuid>,
+    pub invited_email: String,
+    /// Space separated [`DelegationScope`]s
+    pub scopes: String,
+    /// One of the values of [`DelegationStatus`]
+    pub status: String,
+    pub invitation_expired_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub accepted_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::delegations)]
+pub struct NewDelegation {
+    pub subject_id: Uuid,
+    pub invited_email: String,
+    pub scopes: String,
+    pub invitation_token_hash: String,
+    pub invitation_expired_at: NaiveDateTime,
+}
+
+/// Delegations a user gave to others and received from others
+#[derive(Debug, Serialize)]
+pub struct DelegationList {
+    pub granted: Vec<Delegation>,
+    pub received: Vec<Delegation>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct DelegationInvitationFields {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(
+        custom(
+            function = "valid_email_address",
+            message = "must be a valid email address"
+        ),
+        length(max = 255, message = "must be at most 255 characters long")
+    )]
+    pub email: String,
+    #[validate(length(min = 1, message = "must have at least one scope"))]
+    pub scopes: Vec<DelegationScope>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct DelegationAcceptanceFields {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub token: String,
+}
+
+/// A delegate asking for a token to act for a patient (RFC 8693 style)
+#[derive(Deserialize)]
+pub struct TokenExchangeFields {
+    pub subject_id: Uuid,
+    /// Space separated scopes to narrow the token to. Defaults to every
+    /// scope of the delegation.
+    pub scope: Option<String>,
+}
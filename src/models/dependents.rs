@@ -0,0 +1,40 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{
+    profiles::ProfileResponse,
+    users::{trimmed, valid_email_address},
+};
+
+/// A dependent as their guardian sees them
+#[derive(Debug, Serialize)]
+pub struct DependentView {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub profile: ProfileResponse,
+}
+
+/// A guardian registering a dependent, e.g. their child. The date of birth
+/// decides when the dependent can be converted into a full account.
+#[derive(Deserialize)]
+pub struct DependentFields {
+    pub full_name: String,
+    pub date_of_birth: NaiveDate,
+    pub nik: Option<String>,
+}
+
+/// The email address a dependent who came of age signs in with from then on
+#[derive(Deserialize, Validate)]
+pub struct DependentConversionFields {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(
+        custom(
+            function = "valid_email_address",
+            message = "must be a valid email address"
+        ),
+        length(max = 255, message = "must be at most 255 characters long")
+    )]
+    pub email: String,
+}
@@ -0,0 +1,56 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{models::users::trimmed, services::jwt::ExchangedToken};
+
+#[derive(Clone, Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::impersonations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Impersonation {
+    pub id: Uuid,
+    /// `None` once the administrator's account is deleted
+    pub admin_id: Option<Uuid>,
+    pub user_id: Uuid,
+    pub reason: String,
+    /// Space separated [`DelegationScope`](super::delegations::DelegationScope)s
+    pub scopes: String,
+    pub issued_at: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::impersonations)]
+pub struct NewImpersonation {
+    pub admin_id: Uuid,
+    pub user_id: Uuid,
+    pub reason: String,
+    pub scopes: String,
+    pub expired_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ImpersonationFields {
+    /// Why the administrator needs to act as the user, e.g. a support ticket.
+    /// The user can read it.
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(length(
+        min = 1,
+        max = 500,
+        message = "must be between 1 and 500 characters long"
+    ))]
+    pub reason: String,
+    /// Space separated read-only scopes to narrow the token to. Defaults to
+    /// all of them.
+    pub scope: Option<String>,
+}
+
+/// The token for an impersonation, with the record the user will see
+#[derive(Serialize)]
+pub struct ImpersonationGrant {
+    pub impersonation: Impersonation,
+    #[serde(flatten)]
+    pub token: ExchangedToken,
+}
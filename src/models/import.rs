@@ -0,0 +1,49 @@
+use serde::Deserialize;
+
+/// One user from an export of another system.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ImportRecord {
+    pub email: String,
+    pub password_hash: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// A CSV row, where roles are a single `;`-separated column.
+#[derive(Debug, Deserialize)]
+pub struct CsvImportRecord {
+    pub email: String,
+    pub password_hash: String,
+    #[serde(default)]
+    pub roles: String,
+}
+
+impl From<CsvImportRecord> for ImportRecord {
+    fn from(record: CsvImportRecord) -> Self {
+        ImportRecord {
+            email: record.email,
+            password_hash: record.password_hash,
+            roles: record
+                .roles
+                .split(';')
+                .map(str::trim)
+                .filter(|role| !role.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ImportOutcome {
+    Imported,
+    AlreadyExists,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub already_existing: usize,
+    /// Position (from 1) and reason for every record that could not be imported
+    pub failed: Vec<(usize, String)>,
+}
@@ -1,19 +1,26 @@
 use chrono::NaiveDateTime;
 use diesel::{Insertable, Queryable};
+use uuid::Uuid;
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::refresh_tokens)]
 pub struct RefreshTokenCreationDTO {
     pub token_str: String,
-    pub user_id: String,
+    pub user_id: Uuid,
     pub expired_at: NaiveDateTime,
+    pub amr: String,
+    pub auth_time: NaiveDateTime,
 }
 
 #[derive(Queryable)]
 pub struct RefreshTokenDTO {
     pub token: String,
-    pub user_id: String,
+    pub user_id: Uuid,
     pub expired_at: NaiveDateTime,
     pub revoked: bool,
     pub issued_at: NaiveDateTime,
+    /// Space separated, see `services::jwt::Claims::amr`
+    pub amr: String,
+    /// When the user signed in, carried over from token to token on refresh
+    pub auth_time: NaiveDateTime,
 }
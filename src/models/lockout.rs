@@ -0,0 +1,13 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::login_failures)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LoginFailure {
+    pub scope: String,
+    pub subject: String,
+    pub failure_count: i32,
+    pub last_failed_at: NaiveDateTime,
+    pub blocked_until: Option<NaiveDateTime>,
+}
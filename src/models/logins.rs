@@ -0,0 +1,46 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Clone, Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::login_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Login {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub country_code: Option<String>,
+    pub city: Option<String>,
+    /// No earlier sign in came from this user agent
+    pub new_device: bool,
+    /// No earlier sign in came from this country and city
+    pub new_location: bool,
+    pub logged_in_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::login_history)]
+pub struct NewLogin {
+    pub user_id: Uuid,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub country_code: Option<String>,
+    pub city: Option<String>,
+    pub new_device: bool,
+    pub new_location: bool,
+}
+
+/// Token from the link in a new sign in email
+#[derive(Deserialize, Validate)]
+pub struct LoginDisownFields {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub token: String,
+}
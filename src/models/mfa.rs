@@ -0,0 +1,149 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{models::users::User, services::jwt::Jwt};
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::totp_credentials)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TotpCredential {
+    pub secret_encrypted: String,
+    /// Unset until the user has proven their authenticator works
+    pub confirmed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::totp_credentials)]
+pub struct NewTotpCredential {
+    pub user_id: Uuid,
+    pub secret_encrypted: String,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::recovery_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RecoveryCode {
+    pub id: i64,
+    pub code_hash: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::recovery_codes)]
+pub struct NewRecoveryCode {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
+
+/// What an authenticator app needs to generate codes. The URI is usually
+/// shown as a QR code; the secret is for typing in by hand.
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Shown once, right after they are generated
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodeStatus {
+    pub remaining: i64,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RecoveryCodeRegenerationFields {
+    /// Asked for again, so that a stolen session cannot replace the codes
+    #[validate(length(
+        min = 1,
+        max = 1024,
+        message = "must be between 1 and 1024 characters long"
+    ))]
+    pub password: String,
+}
+
+/// The outcome of a successful second factor check
+pub struct MfaVerification {
+    pub user: User,
+    /// New recovery codes, when this sign in also finished setting up TOTP
+    pub recovery_codes: Option<Vec<String>>,
+    /// Recovery codes left, when one was used for this sign in
+    pub recovery_codes_remaining: Option<i64>,
+}
+
+/// The token pair from `POST /api/token/mfa`, with anything the user needs
+/// to see about their recovery codes
+#[derive(Serialize)]
+pub struct MfaTokenResponse {
+    #[serde(flatten)]
+    pub tokens: Jwt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes_remaining: Option<i64>,
+}
+
+/// Returned by `obtain` instead of tokens when the password alone is not enough.
+#[derive(Debug, Serialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    /// Set when the user must set up TOTP before they can finish signing in
+    pub enrollment_required: bool,
+    pub mfa_token: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct TotpCodeFields {
+    #[validate(length(equal = 6, message = "must be 6 digits"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct MfaTokenFields {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub mfa_token: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct MfaVerificationFields {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub mfa_token: String,
+    /// Either a TOTP code or one of the user's recovery codes is needed
+    #[validate(length(equal = 6, message = "must be 6 digits"))]
+    pub code: Option<String>,
+    #[validate(length(max = 32, message = "must be at most 32 characters long"))]
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct StepUpFields {
+    #[validate(length(
+        min = 1,
+        max = 1024,
+        message = "must be between 1 and 1024 characters long"
+    ))]
+    pub password: String,
+    /// A TOTP code, needed from users who sign in with a second factor
+    #[validate(length(equal = 6, message = "must be 6 digits"))]
+    pub code: Option<String>,
+}
+
+/// A signed in user who just proved who they are again
+pub struct StepUp {
+    pub user: User,
+    /// The methods used, for the `amr` claim of the new tokens
+    pub methods: Vec<&'static str>,
+}
@@ -1,2 +1,18 @@
+pub mod action_tokens;
+pub mod admin;
+pub mod audit;
+pub mod caregivers;
+pub mod delegations;
+pub mod dependents;
+pub mod impersonations;
+pub mod import;
 pub mod jwt;
+pub mod lockout;
+pub mod logins;
+pub mod mfa;
+pub mod profiles;
+pub mod rate_limit;
+pub mod roles;
+pub mod sms;
 pub mod users;
+pub mod webauthn;
@@ -0,0 +1,43 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::profiles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Profile {
+    pub full_name: Option<String>,
+    pub nik_encrypted: Option<String>,
+    pub phone_number: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+    pub updated_at: NaiveDateTime,
+}
+
+// Fields left as `None` are not touched when the row already exists
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::profiles)]
+pub struct ProfileChangeset {
+    pub user_id: Uuid,
+    pub full_name: Option<String>,
+    pub nik_encrypted: Option<String>,
+    pub phone_number: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+pub struct ProfileUpdate {
+    pub full_name: Option<String>,
+    pub nik: Option<String>,
+    pub phone_number: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ProfileResponse {
+    pub full_name: Option<String>,
+    pub nik: Option<String>,
+    pub phone_number: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+    pub updated_at: Option<NaiveDateTime>,
+}
@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::rate_limit_buckets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RateLimitBucket {
+    pub bucket_key: String,
+    pub tokens: f64,
+    pub updated_at: NaiveDateTime,
+    /// When the bucket will have refilled completely, after which it can be
+    /// forgotten
+    pub full_at: NaiveDateTime,
+}
+
+/// Allows `capacity` requests in a burst, refilled evenly over `period_seconds`.
+/// Parsed from `<capacity>/<period_seconds>`, e.g. `10/60`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    pub capacity: u32,
+    pub period_seconds: u32,
+}
+
+impl Quota {
+    pub const fn new(capacity: u32, period_seconds: u32) -> Self {
+        Quota {
+            capacity,
+            period_seconds,
+        }
+    }
+
+    /// Tokens added back per second
+    pub fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period_seconds as f64
+    }
+}
+
+impl FromStr for Quota {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (capacity, period_seconds) = value
+            .split_once('/')
+            .ok_or_else(|| format!("Expected <capacity>/<seconds>, got {}", value))?;
+
+        let capacity = capacity.trim().parse::<u32>().map_err(|e| e.to_string())?;
+        let period_seconds = period_seconds
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| e.to_string())?;
+
+        if capacity == 0 || period_seconds == 0 {
+            return Err("Rate limit capacity and period must be positive".to_string());
+        }
+
+        Ok(Quota::new(capacity, period_seconds))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_seconds: u64,
+    /// Seconds until the next request would be allowed; zero when allowed
+    pub retry_after_seconds: u64,
+}
@@ -0,0 +1,33 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::roles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RoleRecord {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::user_roles)]
+pub struct NewUserRole {
+    pub user_id: Uuid,
+    pub role_id: i32,
+    pub granted_by: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Queryable)]
+pub struct RoleAssignment {
+    pub name: String,
+    pub granted_by: Option<Uuid>,
+    pub granted_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct RoleGrant {
+    pub role: String,
+}
@@ -0,0 +1,11 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::sms_codes)]
+pub struct NewSmsCode {
+    pub phone_number: String,
+    pub code_hash: String,
+    pub sent_at: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+}
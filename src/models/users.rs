@@ -1,27 +1,88 @@
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt::Display;
 use uuid::Uuid;
+use validator::{Validate, ValidateEmail, ValidationError};
+
+use crate::{
+    models::caregivers::CaregiverApplicationFields,
+    services::users::{normalize_email, normalize_phone_number},
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize, Queryable, Selectable)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct User {
     pub id: Uuid,
-    pub email: String,
-    pub password: String,
-    pub role: Role,
+    /// `None` for users who signed up with a phone number
+    pub email: Option<String>,
+    /// `None` for users who sign in without a password
+    pub password: Option<String>,
+    pub is_verified: bool,
+    pub must_reset_password: bool,
+    /// In E.164 form, see `services::users::normalize_phone_number`
+    pub phone_number: Option<String>,
+    /// The guardian of a dependent, who has no credentials of their own
+    pub guardian_id: Option<Uuid>,
+    pub status: UserStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// When the account was deleted. The row stays, so that what points at
+    /// it keeps working.
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// Where an account stands. Only active accounts can sign in or refresh
+/// their tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, DbEnum)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::UserStatus"]
+pub enum UserStatus {
+    Active,
+    /// Blocked for a while, e.g. during an investigation
+    Suspended,
+    Disabled,
+    /// Soft-deleted
+    Deleted,
+}
+
+impl UserStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Suspended => "suspended",
+            Self::Disabled => "disabled",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+impl User {
+    pub fn is_active(&self) -> bool {
+        self.status == UserStatus::Active
+    }
+
+    /// The email address, or the phone number of users without one
+    pub fn login_name(&self) -> &str {
+        self.email
+            .as_deref()
+            .or(self.phone_number.as_deref())
+            .unwrap_or_default()
+    }
 }
 
 impl Display for User {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{{},{}}}", self.id, self.email)
+        write!(f, "{{{},{}}}", self.id, self.login_name())
     }
 }
 
-#[derive(Clone, Debug, Deserialize, diesel_derive_enum::DbEnum)]
+// The roles a user may pick for themselves when registering. Every other role
+// is granted by an administrator through the `roles` table.
+#[derive(Clone, Debug, Deserialize)]
 #[serde(field_identifier, rename_all = "lowercase")]
-#[ExistingTypePath = "crate::schema::sql_types::Role"]
 pub enum Role {
     Pacilian,
     Caregiver,
@@ -52,16 +113,213 @@ impl Serialize for Role {
     }
 }
 
-#[derive(Deserialize, Insertable, Clone)]
+#[derive(Insertable, Clone)]
 #[diesel(table_name = crate::schema::users)]
 pub struct InsertableUser {
+    pub email: Option<String>,
+    /// See `services::users::normalize_email`
+    pub email_normalized: Option<String>,
+    pub password: Option<String>,
+    pub phone_number: Option<String>,
+}
+
+/// A user without credentials, managed by their guardian
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::users)]
+pub struct NewDependentUser {
+    pub guardian_id: Uuid,
+}
+
+#[derive(Deserialize, Clone, Validate)]
+pub struct RegistrationFields {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(
+        custom(
+            function = "valid_email_address",
+            message = "must be a valid email address"
+        ),
+        length(max = 255, message = "must be at most 255 characters long")
+    )]
     pub email: String,
+    #[validate(length(max = 1024, message = "must be at most 1024 characters long"))]
     pub password: String,
     pub role: Role,
+    /// Required with the caregiver role, whose registrations wait for review
+    #[validate(nested)]
+    pub caregiver: Option<CaregiverApplicationFields>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct LoginFields {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(
+        custom(
+            function = "valid_email_address",
+            message = "must be a valid email address"
+        ),
+        length(max = 255, message = "must be at most 255 characters long")
+    )]
     pub email: String,
+    #[validate(length(
+        min = 1,
+        max = 1024,
+        message = "must be between 1 and 1024 characters long"
+    ))]
     pub password: String,
 }
+
+#[derive(Deserialize, Validate)]
+pub struct PasswordResetFields {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub token: String,
+    #[validate(length(max = 1024, message = "must be at most 1024 characters long"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct PasswordChangeFields {
+    #[validate(length(
+        min = 1,
+        max = 1024,
+        message = "must be between 1 and 1024 characters long"
+    ))]
+    pub current_password: String,
+    #[validate(length(max = 1024, message = "must be at most 1024 characters long"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct EmailLoginFields {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(
+        custom(
+            function = "valid_email_address",
+            message = "must be a valid email address"
+        ),
+        length(max = 255, message = "must be at most 255 characters long")
+    )]
+    pub email: String,
+}
+
+/// Returned when a sign in link or code is requested, whether or not the
+/// email belongs to an account. The device keeps the token and sends it back
+/// with the link or code, which are useless anywhere else.
+#[derive(Serialize)]
+pub struct EmailLoginStarted {
+    pub device_token: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct EmailLinkFields {
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub token: String,
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub device_token: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct EmailCodeFields {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(
+        custom(
+            function = "valid_email_address",
+            message = "must be a valid email address"
+        ),
+        length(max = 255, message = "must be at most 255 characters long")
+    )]
+    pub email: String,
+    #[validate(length(
+        min = 1,
+        max = 16,
+        message = "must be between 1 and 16 characters long"
+    ))]
+    pub code: String,
+    #[validate(length(
+        min = 1,
+        max = 128,
+        message = "must be between 1 and 128 characters long"
+    ))]
+    pub device_token: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct PhoneCodeFields {
+    #[validate(custom(
+        function = "valid_phone_number",
+        message = "must be a valid phone number"
+    ))]
+    pub phone_number: String,
+}
+
+/// Returned when an SMS code has been sent
+#[derive(Serialize)]
+pub struct PhoneCodeSent {
+    pub expires_in_seconds: i64,
+    pub resend_after_seconds: i64,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct PhoneRegistrationFields {
+    #[validate(custom(
+        function = "valid_phone_number",
+        message = "must be a valid phone number"
+    ))]
+    pub phone_number: String,
+    #[validate(length(
+        min = 1,
+        max = 16,
+        message = "must be between 1 and 16 characters long"
+    ))]
+    pub code: String,
+    pub role: Role,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct PhoneLoginFields {
+    #[validate(custom(
+        function = "valid_phone_number",
+        message = "must be a valid phone number"
+    ))]
+    pub phone_number: String,
+    #[validate(length(
+        min = 1,
+        max = 16,
+        message = "must be between 1 and 16 characters long"
+    ))]
+    pub code: String,
+}
+
+// Whitespace around an email address, or a similar single value, is never part of it
+pub(crate) fn trimmed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    String::deserialize(deserializer).map(|value| value.trim().to_string())
+}
+
+// Checked after normalization, so that compatibility forms such as fullwidth
+// letters are judged by the address they stand for
+pub(crate) fn valid_email_address(email: &str) -> Result<(), ValidationError> {
+    if normalize_email(email).validate_email() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("email"))
+    }
+}
+
+fn valid_phone_number(phone_number: &str) -> Result<(), ValidationError> {
+    if normalize_phone_number(phone_number).is_some() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("phone_number"))
+    }
+}
@@ -0,0 +1,163 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::webauthn_credentials)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebAuthnCredential {
+    /// Base64url, as the browser reports it
+    pub credential_id: String,
+    pub user_id: Uuid,
+    /// Uncompressed SEC1 point of the ES256 public key
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    pub name: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::webauthn_credentials)]
+pub struct NewWebAuthnCredential {
+    pub credential_id: String,
+    pub user_id: Uuid,
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    pub name: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::webauthn_challenges)]
+pub struct NewWebAuthnChallenge {
+    pub challenge: String,
+    pub user_id: Option<Uuid>,
+    pub ceremony: String,
+    pub expired_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialSummary {
+    pub credential_id: String,
+    pub name: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+impl From<WebAuthnCredential> for CredentialSummary {
+    fn from(credential: WebAuthnCredential) -> Self {
+        CredentialSummary {
+            credential_id: credential.credential_id,
+            name: credential.name,
+            created_at: credential.created_at,
+            last_used_at: credential.last_used_at,
+        }
+    }
+}
+
+// --- Ceremony options, passed as they are to `navigator.credentials` ---
+
+#[derive(Debug, Serialize)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    /// Base64url of the user's UUID bytes
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: &'static str,
+    pub user_verification: &'static str,
+}
+
+/// `PublicKeyCredentialCreationOptions`, with binary fields in base64url
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingParty,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: u64,
+    pub attestation: &'static str,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+}
+
+/// `PublicKeyCredentialRequestOptions`, with binary fields in base64url
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: u64,
+    pub user_verification: &'static str,
+}
+
+// --- Ceremony results, as sent back by the browser with binary fields in base64url ---
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+    /// A label for the user to tell their passkeys apart, e.g. "Work laptop"
+    #[validate(length(max = 255, message = "must be at most 255 characters long"))]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+#[derive(Deserialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+/// The parts of `CollectedClientData` that are checked
+#[derive(Deserialize)]
+pub struct CollectedClientData {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub challenge: String,
+    pub origin: String,
+}
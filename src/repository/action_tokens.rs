@@ -0,0 +1,53 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{db::Connection, models::action_tokens::NewActionToken};
+
+pub fn insert_action_token(conn: &mut Connection, new_token: NewActionToken) -> QueryResult<usize> {
+    use crate::schema::action_tokens::dsl::*;
+
+    diesel::insert_into(action_tokens)
+        .values(new_token)
+        .execute(conn)
+}
+
+// Marks a live token as used in a single statement so it can only be redeemed once
+pub fn consume_action_token(
+    conn: &mut Connection,
+    hash: &str,
+    token_purpose: &str,
+) -> QueryResult<Option<Uuid>> {
+    use crate::schema::action_tokens::dsl::*;
+
+    let now = Utc::now().naive_utc();
+
+    diesel::update(
+        action_tokens
+            .filter(token_hash.eq(hash))
+            .filter(purpose.eq(token_purpose))
+            .filter(consumed_at.is_null())
+            .filter(expired_at.gt(now)),
+    )
+    .set(consumed_at.eq(now))
+    .returning(user_id)
+    .get_result::<Uuid>(conn)
+    .optional()
+}
+
+pub fn get_live_action_token_owner(
+    conn: &mut Connection,
+    hash: &str,
+    token_purpose: &str,
+) -> QueryResult<Option<Uuid>> {
+    use crate::schema::action_tokens::dsl::*;
+
+    action_tokens
+        .filter(token_hash.eq(hash))
+        .filter(purpose.eq(token_purpose))
+        .filter(consumed_at.is_null())
+        .filter(expired_at.gt(Utc::now().naive_utc()))
+        .select(user_id)
+        .first::<Uuid>(conn)
+        .optional()
+}
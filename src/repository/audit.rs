@@ -0,0 +1,134 @@
+use diesel::prelude::*;
+
+use crate::{
+    db::Connection,
+    models::audit::{
+        AuditCheckpoint, AuditEvent, AuditEventFilter, NewAuditCheckpoint, NewAuditEvent,
+    },
+};
+
+// Any constant works, as long as nothing else takes the same advisory lock
+const AUDIT_CHAIN_LOCK_ID: i64 = 0x6175_6469_7400;
+
+/// Keeps other writers of the audit trail waiting until the transaction
+/// ends, so that each entry is chained to the one written right before it.
+/// Only the chain head is serialized, so readers and the table itself are
+/// never locked.
+pub fn lock_audit_chain(conn: &mut Connection) -> QueryResult<()> {
+    diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+        .bind::<diesel::sql_types::BigInt, _>(AUDIT_CHAIN_LOCK_ID)
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// The most recent event that is part of the hash chain
+pub fn get_last_chained_audit_event(conn: &mut Connection) -> QueryResult<Option<AuditEvent>> {
+    use crate::schema::audit_events::dsl::*;
+
+    audit_events
+        .filter(entry_hash.is_not_null())
+        .order(id.desc())
+        .select(AuditEvent::as_select())
+        .first(conn)
+        .optional()
+}
+
+pub fn insert_audit_event(conn: &mut Connection, event: NewAuditEvent) -> QueryResult<usize> {
+    use crate::schema::audit_events::dsl::*;
+
+    diesel::insert_into(audit_events)
+        .values(event)
+        .execute(conn)
+}
+
+// Newest first
+pub fn search_audit_events(
+    conn: &mut Connection,
+    filter: &AuditEventFilter,
+) -> QueryResult<Vec<AuditEvent>> {
+    use crate::schema::audit_events::dsl::*;
+
+    let mut query = audit_events.into_boxed();
+
+    if let Some(actor) = filter.actor_id {
+        query = query.filter(actor_id.eq(actor));
+    }
+
+    if let Some(subject) = filter.subject_id {
+        query = query.filter(subject_id.eq(subject));
+    }
+
+    if let Some(event) = &filter.event_type {
+        query = query.filter(event_type.eq(event));
+    }
+
+    if let Some(result) = &filter.outcome {
+        query = query.filter(outcome.eq(result));
+    }
+
+    if let Some(from) = filter.from {
+        query = query.filter(occurred_at.ge(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(occurred_at.lt(to));
+    }
+
+    if let Some(before_id) = filter.before_id {
+        query = query.filter(id.lt(before_id));
+    }
+
+    query
+        .order(id.desc())
+        .limit(filter.limit)
+        .select(AuditEvent::as_select())
+        .load(conn)
+}
+
+// Oldest first, for walking the chain
+pub fn get_audit_events_after(
+    conn: &mut Connection,
+    after_id: i64,
+    limit: i64,
+) -> QueryResult<Vec<AuditEvent>> {
+    use crate::schema::audit_events::dsl::*;
+
+    audit_events
+        .filter(id.gt(after_id))
+        .order(id.asc())
+        .limit(limit)
+        .select(AuditEvent::as_select())
+        .load(conn)
+}
+
+pub fn insert_audit_checkpoint(
+    conn: &mut Connection,
+    checkpoint: NewAuditCheckpoint,
+) -> QueryResult<AuditCheckpoint> {
+    use crate::schema::audit_checkpoints::dsl::*;
+
+    diesel::insert_into(audit_checkpoints)
+        .values(checkpoint)
+        .returning(AuditCheckpoint::as_returning())
+        .get_result(conn)
+}
+
+pub fn get_latest_audit_checkpoint(conn: &mut Connection) -> QueryResult<Option<AuditCheckpoint>> {
+    use crate::schema::audit_checkpoints::dsl::*;
+
+    audit_checkpoints
+        .order(id.desc())
+        .select(AuditCheckpoint::as_select())
+        .first(conn)
+        .optional()
+}
+
+// In the order of the events they sign
+pub fn get_audit_checkpoints(conn: &mut Connection) -> QueryResult<Vec<AuditCheckpoint>> {
+    use crate::schema::audit_checkpoints::dsl::*;
+
+    audit_checkpoints
+        .order((last_event_id.asc(), id.asc()))
+        .select(AuditCheckpoint::as_select())
+        .load(conn)
+}
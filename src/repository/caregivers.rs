@@ -0,0 +1,130 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::caregivers::{
+        ApplicationStatus, CaregiverApplication, CaregiverDocument, NewCaregiverApplication,
+        NewCaregiverDocument,
+    },
+};
+
+pub fn insert_caregiver_application(
+    conn: &mut Connection,
+    new_application: NewCaregiverApplication,
+) -> QueryResult<CaregiverApplication> {
+    use crate::schema::caregiver_applications::dsl::*;
+
+    diesel::insert_into(caregiver_applications)
+        .values(new_application)
+        .returning(CaregiverApplication::as_returning())
+        .get_result(conn)
+}
+
+pub fn insert_caregiver_documents(
+    conn: &mut Connection,
+    new_documents: Vec<NewCaregiverDocument>,
+) -> QueryResult<Vec<CaregiverDocument>> {
+    use crate::schema::caregiver_documents::dsl::*;
+
+    diesel::insert_into(caregiver_documents)
+        .values(new_documents)
+        .returning(CaregiverDocument::as_returning())
+        .get_results(conn)
+}
+
+pub fn get_caregiver_application(
+    conn: &mut Connection,
+    application_id: Uuid,
+) -> QueryResult<Option<CaregiverApplication>> {
+    use crate::schema::caregiver_applications::dsl::*;
+
+    caregiver_applications
+        .find(application_id)
+        .select(CaregiverApplication::as_select())
+        .first(conn)
+        .optional()
+}
+
+// Locks the application until the end of the transaction, so that two
+// reviewers cannot decide on it at once
+pub fn get_caregiver_application_for_update(
+    conn: &mut Connection,
+    application_id: Uuid,
+) -> QueryResult<Option<CaregiverApplication>> {
+    use crate::schema::caregiver_applications::dsl::*;
+
+    caregiver_applications
+        .find(application_id)
+        .select(CaregiverApplication::as_select())
+        .for_update()
+        .first(conn)
+        .optional()
+}
+
+pub fn get_latest_caregiver_application_for_user(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Option<CaregiverApplication>> {
+    use crate::schema::caregiver_applications::dsl::*;
+
+    caregiver_applications
+        .filter(user_id.eq(owner_id))
+        .order(submitted_at.desc())
+        .select(CaregiverApplication::as_select())
+        .first(conn)
+        .optional()
+}
+
+// Oldest first, so that reviewers work through them in order
+pub fn list_caregiver_applications(
+    conn: &mut Connection,
+    with_status: Option<ApplicationStatus>,
+) -> QueryResult<Vec<CaregiverApplication>> {
+    use crate::schema::caregiver_applications::dsl::*;
+
+    let mut query = caregiver_applications
+        .select(CaregiverApplication::as_select())
+        .order(submitted_at.asc())
+        .into_boxed();
+
+    if let Some(with_status) = with_status {
+        query = query.filter(status.eq(with_status.as_str()));
+    }
+
+    query.load(conn)
+}
+
+pub fn get_caregiver_documents(
+    conn: &mut Connection,
+    for_application_id: Uuid,
+) -> QueryResult<Vec<CaregiverDocument>> {
+    use crate::schema::caregiver_documents::dsl::*;
+
+    caregiver_documents
+        .filter(application_id.eq(for_application_id))
+        .order(id.asc())
+        .select(CaregiverDocument::as_select())
+        .load(conn)
+}
+
+pub fn set_caregiver_application_review(
+    conn: &mut Connection,
+    application_id: Uuid,
+    new_status: ApplicationStatus,
+    reviewer_id: Uuid,
+    reason: Option<String>,
+) -> QueryResult<CaregiverApplication> {
+    use crate::schema::caregiver_applications::dsl::*;
+
+    diesel::update(caregiver_applications.find(application_id))
+        .set((
+            status.eq(new_status.as_str()),
+            rejection_reason.eq(reason),
+            reviewed_by.eq(reviewer_id),
+            reviewed_at.eq(Utc::now().naive_utc()),
+        ))
+        .returning(CaregiverApplication::as_returning())
+        .get_result(conn)
+}
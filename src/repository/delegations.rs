@@ -0,0 +1,126 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::delegations::{Delegation, DelegationStatus, NewDelegation},
+};
+
+pub fn insert_delegation(
+    conn: &mut Connection,
+    new_delegation: NewDelegation,
+) -> QueryResult<Delegation> {
+    use crate::schema::delegations::dsl::*;
+
+    diesel::insert_into(delegations)
+        .values(new_delegation)
+        .returning(Delegation::as_returning())
+        .get_result(conn)
+}
+
+// A pending invitation that has not expired, locked until the end of the
+// transaction so that it can only be accepted once
+pub fn get_open_invitation_for_update(
+    conn: &mut Connection,
+    token_hash: &str,
+) -> QueryResult<Option<Delegation>> {
+    use crate::schema::delegations::dsl::*;
+
+    delegations
+        .filter(invitation_token_hash.eq(token_hash))
+        .filter(status.eq(DelegationStatus::Pending.as_str()))
+        .filter(invitation_expired_at.gt(Utc::now().naive_utc()))
+        .select(Delegation::as_select())
+        .for_update()
+        .first(conn)
+        .optional()
+}
+
+pub fn activate_delegation(
+    conn: &mut Connection,
+    delegation_id: Uuid,
+    delegate_id: Uuid,
+) -> QueryResult<Delegation> {
+    use crate::schema::delegations::dsl::*;
+
+    diesel::update(delegations.find(delegation_id))
+        .set((
+            actor_id.eq(delegate_id),
+            status.eq(DelegationStatus::Active.as_str()),
+            accepted_at.eq(Utc::now().naive_utc()),
+            invitation_token_hash.eq(None::<String>),
+        ))
+        .returning(Delegation::as_returning())
+        .get_result(conn)
+}
+
+pub fn revoke_delegation(conn: &mut Connection, delegation_id: Uuid) -> QueryResult<Delegation> {
+    use crate::schema::delegations::dsl::*;
+
+    diesel::update(delegations.find(delegation_id))
+        .set((
+            status.eq(DelegationStatus::Revoked.as_str()),
+            revoked_at.eq(Utc::now().naive_utc()),
+            invitation_token_hash.eq(None::<String>),
+        ))
+        .returning(Delegation::as_returning())
+        .get_result(conn)
+}
+
+pub fn get_delegation(
+    conn: &mut Connection,
+    delegation_id: Uuid,
+) -> QueryResult<Option<Delegation>> {
+    use crate::schema::delegations::dsl::*;
+
+    delegations
+        .find(delegation_id)
+        .select(Delegation::as_select())
+        .first(conn)
+        .optional()
+}
+
+pub fn get_active_delegation(
+    conn: &mut Connection,
+    for_subject_id: Uuid,
+    for_actor_id: Uuid,
+) -> QueryResult<Option<Delegation>> {
+    use crate::schema::delegations::dsl::*;
+
+    delegations
+        .filter(subject_id.eq(for_subject_id))
+        .filter(actor_id.eq(for_actor_id))
+        .filter(status.eq(DelegationStatus::Active.as_str()))
+        .select(Delegation::as_select())
+        .first(conn)
+        .optional()
+}
+
+// Newest first
+pub fn get_delegations_by_subject(
+    conn: &mut Connection,
+    for_subject_id: Uuid,
+) -> QueryResult<Vec<Delegation>> {
+    use crate::schema::delegations::dsl::*;
+
+    delegations
+        .filter(subject_id.eq(for_subject_id))
+        .order(created_at.desc())
+        .select(Delegation::as_select())
+        .load(conn)
+}
+
+// Newest first
+pub fn get_delegations_by_actor(
+    conn: &mut Connection,
+    for_actor_id: Uuid,
+) -> QueryResult<Vec<Delegation>> {
+    use crate::schema::delegations::dsl::*;
+
+    delegations
+        .filter(actor_id.eq(for_actor_id))
+        .order(created_at.desc())
+        .select(Delegation::as_select())
+        .load(conn)
+}
@@ -0,0 +1,33 @@
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::impersonations::{Impersonation, NewImpersonation},
+};
+
+pub fn insert_impersonation(
+    conn: &mut Connection,
+    new_impersonation: NewImpersonation,
+) -> QueryResult<Impersonation> {
+    use crate::schema::impersonations::dsl::*;
+
+    diesel::insert_into(impersonations)
+        .values(new_impersonation)
+        .returning(Impersonation::as_returning())
+        .get_result(conn)
+}
+
+// Newest first
+pub fn get_impersonations_for_user(
+    conn: &mut Connection,
+    for_user_id: Uuid,
+) -> QueryResult<Vec<Impersonation>> {
+    use crate::schema::impersonations::dsl::*;
+
+    impersonations
+        .filter(user_id.eq(for_user_id))
+        .order(issued_at.desc())
+        .select(Impersonation::as_select())
+        .load(conn)
+}
@@ -5,20 +5,31 @@ use crate::{
         users::User,
     },
 };
-use chrono::{Duration, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 use diesel::{
-    expression_methods::ExpressionMethods, insert_into, query_dsl::methods::FilterDsl, result,
-    update, QueryResult, RunQueryDsl,
+    expression_methods::ExpressionMethods,
+    insert_into,
+    query_dsl::methods::{FilterDsl, OrderDsl},
+    result, update, QueryResult, RunQueryDsl,
 };
+use uuid::Uuid;
 
-pub fn create_refresh_token(conn: &mut Connection, user: User, token: &str) -> QueryResult<String> {
+pub fn create_refresh_token(
+    conn: &mut Connection,
+    user: User,
+    token: &str,
+    methods: &str,
+    authenticated_at: NaiveDateTime,
+) -> QueryResult<String> {
     use crate::schema::refresh_tokens::dsl::*;
 
     loop {
         let new_token = RefreshTokenCreationDTO {
             token_str: token.to_string(),
-            user_id: user.id.to_string(),
+            user_id: user.id,
             expired_at: Utc::now().naive_utc() + Duration::minutes(30),
+            amr: methods.to_string(),
+            auth_time: authenticated_at,
         };
 
         let created_token = insert_into(refresh_tokens)
@@ -56,3 +67,32 @@ pub fn revoke_refresh_token(conn: &mut Connection, token: &str) -> QueryResult<(
         .execute(conn)
         .map(|_| Ok(()))?
 }
+
+// Refresh tokens that can still be used, newest first
+pub fn get_active_refresh_tokens_for_user(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Vec<RefreshTokenDTO>> {
+    use crate::schema::refresh_tokens::dsl::*;
+
+    refresh_tokens
+        .filter(user_id.eq(owner_id))
+        .filter(is_revoked.eq(false))
+        .filter(expired_at.gt(Utc::now().naive_utc()))
+        .order(issued_at.desc())
+        .get_results::<RefreshTokenDTO>(conn)
+}
+
+// Revokes every outstanding refresh token of a user, returning how many were revoked
+pub fn revoke_all_refresh_tokens_for_user(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<usize> {
+    use crate::schema::refresh_tokens::dsl::*;
+
+    update(refresh_tokens)
+        .filter(user_id.eq(owner_id))
+        .filter(is_revoked.eq(false))
+        .set(is_revoked.eq(true))
+        .execute(conn)
+}
@@ -0,0 +1,58 @@
+use diesel::prelude::*;
+
+use crate::{db::Connection, models::lockout::LoginFailure};
+
+pub fn get_login_failure(
+    conn: &mut Connection,
+    failure_scope: &str,
+    failure_subject: &str,
+) -> QueryResult<Option<LoginFailure>> {
+    use crate::schema::login_failures::dsl::*;
+
+    login_failures
+        .find((failure_scope, failure_subject))
+        .select(LoginFailure::as_select())
+        .first::<LoginFailure>(conn)
+        .optional()
+}
+
+// Locks the row so concurrent failures for the same subject are counted one after another
+pub fn get_login_failure_for_update(
+    conn: &mut Connection,
+    failure_scope: &str,
+    failure_subject: &str,
+) -> QueryResult<Option<LoginFailure>> {
+    use crate::schema::login_failures::dsl::*;
+
+    login_failures
+        .find((failure_scope, failure_subject))
+        .select(LoginFailure::as_select())
+        .for_update()
+        .first::<LoginFailure>(conn)
+        .optional()
+}
+
+pub fn upsert_login_failure(conn: &mut Connection, failure: LoginFailure) -> QueryResult<usize> {
+    use crate::schema::login_failures::dsl::*;
+
+    diesel::insert_into(login_failures)
+        .values(&failure)
+        .on_conflict((scope, subject))
+        .do_update()
+        .set((
+            failure_count.eq(failure.failure_count),
+            last_failed_at.eq(failure.last_failed_at),
+            blocked_until.eq(failure.blocked_until),
+        ))
+        .execute(conn)
+}
+
+pub fn delete_login_failure(
+    conn: &mut Connection,
+    failure_scope: &str,
+    failure_subject: &str,
+) -> QueryResult<usize> {
+    use crate::schema::login_failures::dsl::*;
+
+    diesel::delete(login_failures.find((failure_scope, failure_subject))).execute(conn)
+}
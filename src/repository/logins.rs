@@ -0,0 +1,66 @@
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::logins::{Login, NewLogin},
+};
+
+pub fn insert_login(conn: &mut Connection, login: NewLogin) -> QueryResult<Login> {
+    use crate::schema::login_history::dsl::*;
+
+    diesel::insert_into(login_history)
+        .values(login)
+        .returning(Login::as_returning())
+        .get_result(conn)
+}
+
+pub fn has_logins(conn: &mut Connection, user: Uuid) -> QueryResult<bool> {
+    use crate::schema::login_history::dsl::*;
+
+    diesel::select(diesel::dsl::exists(login_history.filter(user_id.eq(user)))).get_result(conn)
+}
+
+pub fn has_login_from_device(conn: &mut Connection, user: Uuid, agent: &str) -> QueryResult<bool> {
+    use crate::schema::login_history::dsl::*;
+
+    diesel::select(diesel::dsl::exists(
+        login_history
+            .filter(user_id.eq(user))
+            .filter(user_agent.eq(agent)),
+    ))
+    .get_result(conn)
+}
+
+pub fn has_login_from_location(
+    conn: &mut Connection,
+    user: Uuid,
+    country: &str,
+    in_city: Option<&str>,
+) -> QueryResult<bool> {
+    use crate::schema::login_history::dsl::*;
+
+    diesel::select(diesel::dsl::exists(
+        login_history
+            .filter(user_id.eq(user))
+            .filter(country_code.eq(country))
+            .filter(city.is_not_distinct_from(in_city)),
+    ))
+    .get_result(conn)
+}
+
+// Newest first
+pub fn get_logins_for_user(
+    conn: &mut Connection,
+    user: Uuid,
+    limit: i64,
+) -> QueryResult<Vec<Login>> {
+    use crate::schema::login_history::dsl::*;
+
+    login_history
+        .filter(user_id.eq(user))
+        .order(logged_in_at.desc())
+        .limit(limit)
+        .select(Login::as_select())
+        .load(conn)
+}
@@ -0,0 +1,131 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{Nullable, Timestamp},
+};
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::mfa::{NewRecoveryCode, NewTotpCredential, RecoveryCode, TotpCredential},
+};
+
+pub fn get_totp_credential(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Option<TotpCredential>> {
+    use crate::schema::totp_credentials::dsl::*;
+
+    totp_credentials
+        .filter(user_id.eq(owner_id))
+        .select(TotpCredential::as_select())
+        .first::<TotpCredential>(conn)
+        .optional()
+}
+
+pub fn get_totp_credential_for_update(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Option<TotpCredential>> {
+    use crate::schema::totp_credentials::dsl::*;
+
+    totp_credentials
+        .filter(user_id.eq(owner_id))
+        .select(TotpCredential::as_select())
+        .for_update()
+        .first::<TotpCredential>(conn)
+        .optional()
+}
+
+// Starts over with a new secret; check that the existing credential is not
+// confirmed before calling this
+pub fn upsert_pending_totp_credential(
+    conn: &mut Connection,
+    new_credential: NewTotpCredential,
+) -> QueryResult<usize> {
+    use crate::schema::totp_credentials::dsl::*;
+
+    diesel::insert_into(totp_credentials)
+        .values(&new_credential)
+        .on_conflict(user_id)
+        .do_update()
+        .set((
+            secret_encrypted.eq(&new_credential.secret_encrypted),
+            confirmed_at.eq(None::<NaiveDateTime>),
+            last_used_step.eq(None::<i64>),
+            created_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+}
+
+// Records a code as used, confirming the credential if it was not yet. Only
+// later time steps are accepted, so that a code works once; returns the
+// number of rows updated, which is zero for a replayed code.
+pub fn use_totp_step(conn: &mut Connection, owner_id: Uuid, step: i64) -> QueryResult<usize> {
+    use crate::schema::totp_credentials::dsl::*;
+
+    diesel::update(
+        totp_credentials
+            .filter(user_id.eq(owner_id))
+            .filter(last_used_step.is_null().or(last_used_step.lt(step))),
+    )
+    .set((
+        last_used_step.eq(step),
+        confirmed_at.eq(sql::<Nullable<Timestamp>>("COALESCE(confirmed_at, NOW())")),
+    ))
+    .execute(conn)
+}
+
+pub fn insert_recovery_codes(
+    conn: &mut Connection,
+    new_codes: Vec<NewRecoveryCode>,
+) -> QueryResult<usize> {
+    use crate::schema::recovery_codes::dsl::*;
+
+    diesel::insert_into(recovery_codes)
+        .values(new_codes)
+        .execute(conn)
+}
+
+pub fn delete_recovery_codes(conn: &mut Connection, owner_id: Uuid) -> QueryResult<usize> {
+    use crate::schema::recovery_codes::dsl::*;
+
+    diesel::delete(recovery_codes.filter(user_id.eq(owner_id))).execute(conn)
+}
+
+pub fn get_unused_recovery_codes(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Vec<RecoveryCode>> {
+    use crate::schema::recovery_codes::dsl::*;
+
+    recovery_codes
+        .filter(user_id.eq(owner_id))
+        .filter(used_at.is_null())
+        .select(RecoveryCode::as_select())
+        .load::<RecoveryCode>(conn)
+}
+
+pub fn count_unused_recovery_codes(conn: &mut Connection, owner_id: Uuid) -> QueryResult<i64> {
+    use crate::schema::recovery_codes::dsl::*;
+
+    recovery_codes
+        .filter(user_id.eq(owner_id))
+        .filter(used_at.is_null())
+        .count()
+        .get_result::<i64>(conn)
+}
+
+// Returns the number of rows updated, which is zero if the code was already used
+pub fn use_recovery_code(conn: &mut Connection, code_id: i64) -> QueryResult<usize> {
+    use crate::schema::recovery_codes::dsl::*;
+
+    diesel::update(
+        recovery_codes
+            .filter(id.eq(code_id))
+            .filter(used_at.is_null()),
+    )
+    .set(used_at.eq(Utc::now().naive_utc()))
+    .execute(conn)
+}
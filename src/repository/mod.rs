@@ -1,2 +1,15 @@
+pub mod action_tokens;
+pub mod audit;
+pub mod caregivers;
+pub mod delegations;
+pub mod impersonations;
 pub mod jwt;
+pub mod lockout;
+pub mod logins;
+pub mod mfa;
+pub mod profiles;
+pub mod rate_limit;
+pub mod roles;
+pub mod sms;
 pub mod users;
+pub mod webauthn;
@@ -0,0 +1,36 @@
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::profiles::{Profile, ProfileChangeset},
+};
+
+pub fn get_profile_by_user_id(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Option<Profile>> {
+    use crate::schema::profiles::dsl::*;
+
+    let profile = profiles
+        .filter(user_id.eq(owner_id))
+        .select(Profile::as_select())
+        .first::<Profile>(conn)
+        .optional()?;
+
+    Ok(profile)
+}
+
+pub fn upsert_profile(conn: &mut Connection, changes: ProfileChangeset) -> QueryResult<Profile> {
+    use crate::schema::profiles::dsl::*;
+
+    let profile = diesel::insert_into(profiles)
+        .values(&changes)
+        .on_conflict(user_id)
+        .do_update()
+        .set(&changes)
+        .returning(Profile::as_returning())
+        .get_result::<Profile>(conn)?;
+
+    Ok(profile)
+}
@@ -0,0 +1,46 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::{db::Connection, models::rate_limit::RateLimitBucket};
+
+// Locks the row so concurrent requests draw from the bucket one after another
+pub fn get_rate_limit_bucket_for_update(
+    conn: &mut Connection,
+    key: &str,
+) -> QueryResult<Option<RateLimitBucket>> {
+    use crate::schema::rate_limit_buckets::dsl::*;
+
+    rate_limit_buckets
+        .find(key)
+        .select(RateLimitBucket::as_select())
+        .for_update()
+        .first::<RateLimitBucket>(conn)
+        .optional()
+}
+
+pub fn upsert_rate_limit_bucket(
+    conn: &mut Connection,
+    bucket: RateLimitBucket,
+) -> QueryResult<usize> {
+    use crate::schema::rate_limit_buckets::dsl::*;
+
+    diesel::insert_into(rate_limit_buckets)
+        .values(&bucket)
+        .on_conflict(bucket_key)
+        .do_update()
+        .set((
+            tokens.eq(bucket.tokens),
+            updated_at.eq(bucket.updated_at),
+            full_at.eq(bucket.full_at),
+        ))
+        .execute(conn)
+}
+
+pub fn delete_full_rate_limit_buckets(
+    conn: &mut Connection,
+    now: NaiveDateTime,
+) -> QueryResult<usize> {
+    use crate::schema::rate_limit_buckets::dsl::*;
+
+    diesel::delete(rate_limit_buckets.filter(full_at.le(now))).execute(conn)
+}
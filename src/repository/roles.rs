@@ -0,0 +1,103 @@
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::roles::{NewUserRole, RoleAssignment, RoleRecord},
+};
+
+pub fn get_role_by_name(conn: &mut Connection, role_name: &str) -> QueryResult<RoleRecord> {
+    use crate::schema::roles::dsl::*;
+
+    let role = roles
+        .filter(name.eq(role_name))
+        .select(RoleRecord::as_select())
+        .first::<RoleRecord>(conn)?;
+
+    Ok(role)
+}
+
+pub fn get_role_names_for_user(conn: &mut Connection, owner_id: Uuid) -> QueryResult<Vec<String>> {
+    use crate::schema::{roles, user_roles};
+
+    let role_names = user_roles::table
+        .inner_join(roles::table)
+        .filter(user_roles::user_id.eq(owner_id))
+        .select(roles::name)
+        .order(roles::name)
+        .load::<String>(conn)?;
+
+    Ok(role_names)
+}
+
+// Returns (user id, role name) pairs for every user in `owner_ids`
+pub fn get_role_names_for_users(
+    conn: &mut Connection,
+    owner_ids: &[Uuid],
+) -> QueryResult<Vec<(Uuid, String)>> {
+    use crate::schema::{roles, user_roles};
+
+    let role_names = user_roles::table
+        .inner_join(roles::table)
+        .filter(user_roles::user_id.eq_any(owner_ids))
+        .select((user_roles::user_id, roles::name))
+        .order(roles::name)
+        .load::<(Uuid, String)>(conn)?;
+
+    Ok(role_names)
+}
+
+pub fn get_role_assignments_for_user(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Vec<RoleAssignment>> {
+    use crate::schema::{roles, user_roles};
+
+    let assignments = user_roles::table
+        .inner_join(roles::table)
+        .filter(user_roles::user_id.eq(owner_id))
+        .select((roles::name, user_roles::granted_by, user_roles::granted_at))
+        .order(roles::name)
+        .load::<RoleAssignment>(conn)?;
+
+    Ok(assignments)
+}
+
+pub fn get_permission_names_for_user(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Vec<String>> {
+    use crate::schema::{permissions, role_permissions, user_roles};
+
+    let permission_names = user_roles::table
+        .inner_join(role_permissions::table.on(role_permissions::role_id.eq(user_roles::role_id)))
+        .inner_join(permissions::table.on(permissions::id.eq(role_permissions::permission_id)))
+        .filter(user_roles::user_id.eq(owner_id))
+        .select(permissions::name)
+        .distinct()
+        .order(permissions::name)
+        .load::<String>(conn)?;
+
+    Ok(permission_names)
+}
+
+// Returns the number of rows inserted, which is zero if the user already had the role
+pub fn insert_user_role(conn: &mut Connection, new_user_role: NewUserRole) -> QueryResult<usize> {
+    use crate::schema::user_roles::dsl::*;
+
+    diesel::insert_into(user_roles)
+        .values(new_user_role)
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+pub fn delete_user_role(
+    conn: &mut Connection,
+    owner_id: Uuid,
+    revoked_role_id: i32,
+) -> QueryResult<usize> {
+    use crate::schema::user_roles::dsl::*;
+
+    diesel::delete(user_roles.filter(user_id.eq(owner_id).and(role_id.eq(revoked_role_id))))
+        .execute(conn)
+}
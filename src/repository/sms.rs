@@ -0,0 +1,69 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::{db::Connection, models::sms::NewSmsCode};
+
+// When the last code was sent to the number, to enforce the resend cooldown
+pub fn get_sms_code_sent_at_for_update(
+    conn: &mut Connection,
+    number: &str,
+) -> QueryResult<Option<NaiveDateTime>> {
+    use crate::schema::sms_codes::dsl::*;
+
+    sms_codes
+        .filter(phone_number.eq(number))
+        .select(sent_at)
+        .for_update()
+        .first::<NaiveDateTime>(conn)
+        .optional()
+}
+
+// Replaces any earlier code for the number, with a fresh attempt count
+pub fn upsert_sms_code(conn: &mut Connection, new_code: NewSmsCode) -> QueryResult<usize> {
+    use crate::schema::sms_codes::dsl::*;
+
+    diesel::insert_into(sms_codes)
+        .values(&new_code)
+        .on_conflict(phone_number)
+        .do_update()
+        .set((
+            code_hash.eq(&new_code.code_hash),
+            failed_attempts.eq(0),
+            sent_at.eq(new_code.sent_at),
+            expired_at.eq(new_code.expired_at),
+        ))
+        .execute(conn)
+}
+
+/// Uses up the code if it matches, has not expired and has not been guessed
+/// at too often. Returns whether it was used up.
+pub fn consume_sms_code(
+    conn: &mut Connection,
+    number: &str,
+    checked_hash: &str,
+    max_attempts: i32,
+) -> QueryResult<bool> {
+    use crate::schema::sms_codes::dsl::*;
+
+    let deleted = diesel::delete(
+        sms_codes
+            .filter(phone_number.eq(number))
+            .filter(code_hash.eq(checked_hash))
+            .filter(expired_at.gt(Utc::now().naive_utc()))
+            .filter(failed_attempts.lt(max_attempts)),
+    )
+    .execute(conn)?;
+
+    Ok(deleted > 0)
+}
+
+// Returns the failed attempts so far, or `None` if no code was sent to the number
+pub fn record_failed_sms_attempt(conn: &mut Connection, number: &str) -> QueryResult<Option<i32>> {
+    use crate::schema::sms_codes::dsl::*;
+
+    diesel::update(sms_codes.filter(phone_number.eq(number)))
+        .set(failed_attempts.eq(failed_attempts + 1))
+        .returning(failed_attempts)
+        .get_result::<i32>(conn)
+        .optional()
+}
@@ -1,23 +1,48 @@
-use diesel::prelude::*;
+use chrono::Utc;
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{Nullable, Text},
+};
 use uuid::Uuid;
 
 use crate::{
     db::Connection,
-    models::users::{InsertableUser, User},
+    models::{
+        admin::UserFilter,
+        users::{InsertableUser, NewDependentUser, User, UserStatus},
+    },
 };
 
-pub fn get_user_by_email(conn: &mut Connection, user_email: &str) -> QueryResult<User> {
+// Expects the email in normalized form, see `services::users::normalize_email`
+pub fn get_user_by_email(conn: &mut Connection, normalized_email: &str) -> QueryResult<User> {
     use crate::schema::users::dsl::*;
 
-    let user = users.filter(email.eq(user_email)).first::<User>(conn)?;
+    let user = users
+        .filter(email_normalized.eq(normalized_email))
+        .select(User::as_select())
+        .first::<User>(conn)?;
 
     Ok(user)
 }
 
+// Expects the number in E.164 form, see `services::users::normalize_phone_number`
+pub fn get_user_by_phone_number(conn: &mut Connection, number: &str) -> QueryResult<User> {
+    use crate::schema::users::dsl::*;
+
+    users
+        .filter(phone_number.eq(number))
+        .select(User::as_select())
+        .first::<User>(conn)
+}
+
 pub fn get_user_by_id(conn: &mut Connection, user_id: Uuid) -> QueryResult<User> {
     use crate::schema::users::dsl::*;
 
-    let user = users.filter(id.eq(user_id)).first::<User>(conn)?;
+    let user = users
+        .filter(id.eq(user_id))
+        .select(User::as_select())
+        .first::<User>(conn)?;
 
     Ok(user)
 }
@@ -27,7 +52,161 @@ pub fn insert_new_user(conn: &mut Connection, new_user: InsertableUser) -> Query
 
     let user = diesel::insert_into(users)
         .values(new_user)
+        .returning(User::as_returning())
         .get_result::<User>(conn)?;
 
     Ok(user)
 }
+
+pub fn insert_dependent_user(
+    conn: &mut Connection,
+    new_dependent: NewDependentUser,
+) -> QueryResult<User> {
+    use crate::schema::users::dsl::*;
+
+    diesel::insert_into(users)
+        .values(new_dependent)
+        .returning(User::as_returning())
+        .get_result::<User>(conn)
+}
+
+pub fn get_dependents(conn: &mut Connection, for_guardian_id: Uuid) -> QueryResult<Vec<User>> {
+    use crate::schema::users::dsl::*;
+
+    users
+        .filter(guardian_id.eq(for_guardian_id))
+        .order(id)
+        .select(User::as_select())
+        .load::<User>(conn)
+}
+
+// Gives a dependent an email address of their own, which ends the guardianship
+pub fn convert_dependent(
+    conn: &mut Connection,
+    dependent_id: Uuid,
+    new_email: &str,
+    new_email_normalized: &str,
+) -> QueryResult<User> {
+    use crate::schema::users::dsl::*;
+
+    diesel::update(users.filter(id.eq(dependent_id)))
+        .set((
+            email.eq(new_email),
+            email_normalized.eq(new_email_normalized),
+            guardian_id.eq(None::<Uuid>),
+        ))
+        .returning(User::as_returning())
+        .get_result::<User>(conn)
+}
+
+pub fn search_users(conn: &mut Connection, filter: &UserFilter) -> QueryResult<Vec<User>> {
+    use crate::schema::{roles, user_roles, users};
+
+    let mut query = users::table.into_boxed();
+
+    if let Some(role_name) = &filter.role {
+        let users_with_role = user_roles::table
+            .inner_join(roles::table)
+            .filter(roles::name.eq(role_name.clone()))
+            .select(user_roles::user_id);
+
+        query = query.filter(users::id.eq_any(users_with_role));
+    }
+
+    if let Some(prefix) = &filter.email_prefix {
+        let escaped = prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+
+        query = query.filter(users::email.ilike(format!("{}%", escaped)));
+    }
+
+    if let Some(verified) = filter.verified {
+        query = query.filter(users::is_verified.eq(verified));
+    }
+
+    if let Some(status) = filter.status {
+        query = query.filter(users::status.eq(status));
+    }
+
+    let login_name = sql::<Nullable<Text>>("COALESCE(users.email, users.phone_number)");
+
+    // Keyset on (login name, id), with the users who have no login name last
+    if let Some(after) = &filter.after {
+        query = match &after.login_name {
+            Some(after_login_name) => query.filter(
+                login_name
+                    .clone()
+                    .gt(after_login_name.clone())
+                    .or(login_name
+                        .clone()
+                        .eq(after_login_name.clone())
+                        .and(users::id.gt(after.id)))
+                    .or(login_name.clone().is_null()),
+            ),
+            None => query.filter(login_name.clone().is_null().and(users::id.gt(after.id))),
+        };
+    }
+
+    query
+        .order((login_name.asc().nulls_last(), users::id.asc()))
+        .limit(filter.limit)
+        .select(User::as_select())
+        .load::<User>(conn)
+}
+
+/// Deleting stamps `deleted_at`, and any other status clears it
+pub fn set_user_status(
+    conn: &mut Connection,
+    user_id: Uuid,
+    new_status: UserStatus,
+) -> QueryResult<User> {
+    use crate::schema::users::dsl::*;
+
+    let deleted = (new_status == UserStatus::Deleted).then(|| Utc::now().naive_utc());
+
+    diesel::update(users.filter(id.eq(user_id)))
+        .set((status.eq(new_status), deleted_at.eq(deleted)))
+        .returning(User::as_returning())
+        .get_result::<User>(conn)
+}
+
+pub fn set_must_reset_password(
+    conn: &mut Connection,
+    user_id: Uuid,
+    must_reset: bool,
+) -> QueryResult<User> {
+    use crate::schema::users::dsl::*;
+
+    diesel::update(users.filter(id.eq(user_id)))
+        .set(must_reset_password.eq(must_reset))
+        .returning(User::as_returning())
+        .get_result::<User>(conn)
+}
+
+// Replaces the hash of an unchanged password, e.g. to upgrade its parameters
+pub fn set_password_hash(
+    conn: &mut Connection,
+    user_id: Uuid,
+    password_hash: &str,
+) -> QueryResult<usize> {
+    use crate::schema::users::dsl::*;
+
+    diesel::update(users.filter(id.eq(user_id)))
+        .set(password.eq(password_hash))
+        .execute(conn)
+}
+
+pub fn update_password(
+    conn: &mut Connection,
+    user_id: Uuid,
+    password_hash: &str,
+) -> QueryResult<User> {
+    use crate::schema::users::dsl::*;
+
+    diesel::update(users.filter(id.eq(user_id)))
+        .set((password.eq(password_hash), must_reset_password.eq(false)))
+        .returning(User::as_returning())
+        .get_result::<User>(conn)
+}
@@ -0,0 +1,121 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    models::webauthn::{NewWebAuthnChallenge, NewWebAuthnCredential, WebAuthnCredential},
+};
+
+pub fn insert_webauthn_challenge(
+    conn: &mut Connection,
+    new_challenge: NewWebAuthnChallenge,
+) -> QueryResult<usize> {
+    use crate::schema::webauthn_challenges::dsl::*;
+
+    // Abandoned ceremonies would otherwise pile up
+    diesel::delete(webauthn_challenges.filter(expired_at.lt(Utc::now().naive_utc())))
+        .execute(conn)?;
+
+    diesel::insert_into(webauthn_challenges)
+        .values(new_challenge)
+        .execute(conn)
+}
+
+// Deletes a live challenge in a single statement so it can only be answered
+// once. Returns `None` if there was no such challenge, else the user it was
+// issued for, if any.
+pub fn consume_webauthn_challenge(
+    conn: &mut Connection,
+    issued_challenge: &str,
+    challenge_ceremony: &str,
+) -> QueryResult<Option<Option<Uuid>>> {
+    use crate::schema::webauthn_challenges::dsl::*;
+
+    diesel::delete(
+        webauthn_challenges
+            .filter(challenge.eq(issued_challenge))
+            .filter(ceremony.eq(challenge_ceremony))
+            .filter(expired_at.gt(Utc::now().naive_utc())),
+    )
+    .returning(user_id)
+    .get_result::<Option<Uuid>>(conn)
+    .optional()
+}
+
+pub fn insert_webauthn_credential(
+    conn: &mut Connection,
+    new_credential: NewWebAuthnCredential,
+) -> QueryResult<WebAuthnCredential> {
+    use crate::schema::webauthn_credentials::dsl::*;
+
+    diesel::insert_into(webauthn_credentials)
+        .values(new_credential)
+        .returning(WebAuthnCredential::as_returning())
+        .get_result(conn)
+}
+
+pub fn get_webauthn_credential(
+    conn: &mut Connection,
+    id: &str,
+) -> QueryResult<Option<WebAuthnCredential>> {
+    use crate::schema::webauthn_credentials::dsl::*;
+
+    webauthn_credentials
+        .filter(credential_id.eq(id))
+        .select(WebAuthnCredential::as_select())
+        .first::<WebAuthnCredential>(conn)
+        .optional()
+}
+
+// Oldest first
+pub fn get_webauthn_credentials_for_user(
+    conn: &mut Connection,
+    owner_id: Uuid,
+) -> QueryResult<Vec<WebAuthnCredential>> {
+    use crate::schema::webauthn_credentials::dsl::*;
+
+    webauthn_credentials
+        .filter(user_id.eq(owner_id))
+        .order(created_at.asc())
+        .select(WebAuthnCredential::as_select())
+        .load::<WebAuthnCredential>(conn)
+}
+
+// Only updates if the count is still the one that was checked, so that two
+// concurrent sign-ins with the same counter cannot both succeed. Returns the
+// number of rows updated.
+pub fn update_webauthn_sign_count(
+    conn: &mut Connection,
+    id: &str,
+    checked_count: i64,
+    new_count: i64,
+) -> QueryResult<usize> {
+    use crate::schema::webauthn_credentials::dsl::*;
+
+    diesel::update(
+        webauthn_credentials
+            .filter(credential_id.eq(id))
+            .filter(sign_count.eq(checked_count)),
+    )
+    .set((
+        sign_count.eq(new_count),
+        last_used_at.eq(Utc::now().naive_utc()),
+    ))
+    .execute(conn)
+}
+
+pub fn delete_webauthn_credential(
+    conn: &mut Connection,
+    owner_id: Uuid,
+    id: &str,
+) -> QueryResult<usize> {
+    use crate::schema::webauthn_credentials::dsl::*;
+
+    diesel::delete(
+        webauthn_credentials
+            .filter(user_id.eq(owner_id))
+            .filter(credential_id.eq(id)),
+    )
+    .execute(conn)
+}
@@ -2,37 +2,335 @@
 
 pub mod sql_types {
     #[derive(diesel::query_builder::QueryId, Debug, diesel::sql_types::SqlType)]
-    #[diesel(postgres_type(name = "role"))]
-    pub struct Role;
+    #[diesel(postgres_type(name = "user_status"))]
+    pub struct UserStatus;
+}
+
+diesel::table! {
+    action_tokens (token_hash) {
+        #[max_length = 64]
+        token_hash -> Varchar,
+        user_id -> Uuid,
+        #[max_length = 32]
+        purpose -> Varchar,
+        expired_at -> Timestamp,
+        consumed_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    audit_checkpoints (id) {
+        id -> Int8,
+        last_event_id -> Int8,
+        #[max_length = 64]
+        entry_hash -> Varchar,
+        signature -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    audit_events (id) {
+        id -> Int8,
+        occurred_at -> Timestamp,
+        actor_id -> Nullable<Uuid>,
+        subject_id -> Nullable<Uuid>,
+        #[max_length = 64]
+        event_type -> Varchar,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        #[max_length = 16]
+        outcome -> Varchar,
+        reason -> Nullable<Text>,
+        #[max_length = 64]
+        previous_hash -> Nullable<Varchar>,
+        #[max_length = 64]
+        entry_hash -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    caregiver_applications (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 32]
+        str_number -> Varchar,
+        #[max_length = 32]
+        status -> Varchar,
+        rejection_reason -> Nullable<Text>,
+        reviewed_by -> Nullable<Uuid>,
+        reviewed_at -> Nullable<Timestamp>,
+        submitted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    caregiver_documents (id) {
+        id -> Int8,
+        application_id -> Uuid,
+        #[max_length = 32]
+        document_type -> Varchar,
+        #[max_length = 2048]
+        url -> Varchar,
+        submitted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    delegations (id) {
+        id -> Uuid,
+        subject_id -> Uuid,
+        actor_id -> Nullable<Uuid>,
+        #[max_length = 255]
+        invited_email -> Varchar,
+        #[max_length = 255]
+        scopes -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        #[max_length = 64]
+        invitation_token_hash -> Nullable<Varchar>,
+        invitation_expired_at -> Timestamp,
+        created_at -> Timestamp,
+        accepted_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    impersonations (id) {
+        id -> Uuid,
+        admin_id -> Nullable<Uuid>,
+        user_id -> Uuid,
+        reason -> Text,
+        #[max_length = 255]
+        scopes -> Varchar,
+        issued_at -> Timestamp,
+        expired_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    login_failures (scope, subject) {
+        #[max_length = 16]
+        scope -> Varchar,
+        #[max_length = 255]
+        subject -> Varchar,
+        failure_count -> Int4,
+        last_failed_at -> Timestamp,
+        blocked_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    login_history (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        #[max_length = 2]
+        country_code -> Nullable<Varchar>,
+        #[max_length = 255]
+        city -> Nullable<Varchar>,
+        new_device -> Bool,
+        new_location -> Bool,
+        logged_in_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    permissions (id) {
+        id -> Int4,
+        #[max_length = 128]
+        name -> Varchar,
+        #[max_length = 255]
+        description -> Varchar,
+    }
+}
+
+diesel::table! {
+    profiles (user_id) {
+        user_id -> Uuid,
+        #[max_length = 255]
+        full_name -> Nullable<Varchar>,
+        nik_encrypted -> Nullable<Text>,
+        #[max_length = 16]
+        phone_number -> Nullable<Varchar>,
+        date_of_birth -> Nullable<Date>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    rate_limit_buckets (bucket_key) {
+        #[max_length = 255]
+        bucket_key -> Varchar,
+        tokens -> Float8,
+        updated_at -> Timestamp,
+        full_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Int8,
+        user_id -> Uuid,
+        #[max_length = 255]
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
 }
 
 diesel::table! {
     refresh_tokens (token_str) {
         #[max_length = 255]
         token_str -> Varchar,
-        #[max_length = 255]
-        user_id -> Varchar,
+        user_id -> Uuid,
         expired_at -> Timestamp,
         is_revoked -> Bool,
         issued_at -> Timestamp,
+        #[max_length = 64]
+        amr -> Varchar,
+        auth_time -> Timestamp,
+    }
+}
+
+diesel::table! {
+    role_permissions (role_id, permission_id) {
+        role_id -> Int4,
+        permission_id -> Int4,
+    }
+}
+
+diesel::table! {
+    roles (id) {
+        id -> Int4,
+        #[max_length = 64]
+        name -> Varchar,
+        #[max_length = 255]
+        description -> Varchar,
+    }
+}
+
+diesel::table! {
+    sms_codes (phone_number) {
+        #[max_length = 16]
+        phone_number -> Varchar,
+        #[max_length = 64]
+        code_hash -> Varchar,
+        failed_attempts -> Int4,
+        sent_at -> Timestamp,
+        expired_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    totp_credentials (user_id) {
+        user_id -> Uuid,
+        secret_encrypted -> Text,
+        confirmed_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_roles (user_id, role_id) {
+        user_id -> Uuid,
+        role_id -> Int4,
+        granted_by -> Nullable<Uuid>,
+        granted_at -> Timestamp,
     }
 }
 
 diesel::table! {
     use diesel::sql_types::*;
-    use super::sql_types::Role;
+    use super::sql_types::UserStatus;
 
     users (id) {
         id -> Uuid,
         #[max_length = 255]
-        email -> Varchar,
+        email -> Nullable<Varchar>,
         #[max_length = 255]
-        password -> Varchar,
-        role -> Role,
+        password -> Nullable<Varchar>,
+        is_verified -> Bool,
+        must_reset_password -> Bool,
+        #[max_length = 255]
+        email_normalized -> Nullable<Varchar>,
+        #[max_length = 16]
+        phone_number -> Nullable<Varchar>,
+        guardian_id -> Nullable<Uuid>,
+        status -> UserStatus,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    webauthn_challenges (challenge) {
+        #[max_length = 64]
+        challenge -> Varchar,
+        user_id -> Nullable<Uuid>,
+        #[max_length = 16]
+        ceremony -> Varchar,
+        expired_at -> Timestamp,
+        created_at -> Timestamp,
     }
 }
 
+diesel::table! {
+    webauthn_credentials (credential_id) {
+        #[max_length = 1024]
+        credential_id -> Varchar,
+        user_id -> Uuid,
+        public_key -> Bytea,
+        sign_count -> Int8,
+        #[max_length = 255]
+        name -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::joinable!(action_tokens -> users (user_id));
+diesel::joinable!(audit_checkpoints -> audit_events (last_event_id));
+diesel::joinable!(caregiver_documents -> caregiver_applications (application_id));
+diesel::joinable!(login_history -> users (user_id));
+diesel::joinable!(profiles -> users (user_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(role_permissions -> permissions (permission_id));
+diesel::joinable!(role_permissions -> roles (role_id));
+diesel::joinable!(totp_credentials -> users (user_id));
+diesel::joinable!(user_roles -> roles (role_id));
+diesel::joinable!(webauthn_challenges -> users (user_id));
+diesel::joinable!(webauthn_credentials -> users (user_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    action_tokens,
+    audit_checkpoints,
+    audit_events,
+    caregiver_applications,
+    caregiver_documents,
+    delegations,
+    impersonations,
+    login_failures,
+    login_history,
+    permissions,
+    profiles,
+    rate_limit_buckets,
+    recovery_codes,
     refresh_tokens,
+    role_permissions,
+    roles,
+    sms_codes,
+    totp_credentials,
+    user_roles,
     users,
+    webauthn_challenges,
+    webauthn_credentials,
 );
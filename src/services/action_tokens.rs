@@ -0,0 +1,148 @@
+use chrono::{Duration, Utc};
+use rand::{distr::Alphanumeric, rng, Rng};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    errors::action_tokens::ActionTokenError,
+    models::action_tokens::NewActionToken,
+    repository::action_tokens::{
+        consume_action_token, get_live_action_token_owner, insert_action_token,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActionTokenPurpose {
+    PasswordReset,
+    /// Finishes a sign in once the second factor is checked
+    MfaChallenge,
+    /// Like `MfaChallenge`, for a user who must set up TOTP first
+    MfaEnrollment,
+    /// Passwordless sign in with a link sent by email
+    EmailLink,
+    /// Passwordless sign in with a code sent by email
+    EmailCode,
+    /// Signs the user out everywhere from a new sign in email
+    LoginDisowned,
+}
+
+impl ActionTokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PasswordReset => "password_reset",
+            Self::MfaChallenge => "mfa_challenge",
+            Self::MfaEnrollment => "mfa_enrollment",
+            Self::EmailLink => "email_link",
+            Self::EmailCode => "email_code",
+            Self::LoginDisowned => "login_disowned",
+        }
+    }
+}
+
+/// Creates a single-use token and returns it in plain text. Only its hash is stored.
+pub fn issue_action_token(
+    conn: &mut Connection,
+    user_id: Uuid,
+    purpose: ActionTokenPurpose,
+    valid_for: Duration,
+) -> Result<String, ActionTokenError> {
+    let token = random_token();
+
+    store_action_token(conn, user_id, purpose, valid_for, hash_token(&token))?;
+
+    Ok(token)
+}
+
+/// Stores a token that only redeems together with `binding`, a secret kept
+/// by the device that asked for it. This lets the token itself be short,
+/// like a code typed in from an email.
+pub fn issue_bound_action_token(
+    conn: &mut Connection,
+    user_id: Uuid,
+    purpose: ActionTokenPurpose,
+    valid_for: Duration,
+    token: &str,
+    binding: &str,
+) -> Result<(), ActionTokenError> {
+    store_action_token(
+        conn,
+        user_id,
+        purpose,
+        valid_for,
+        hash_bound_token(token, binding),
+    )
+}
+
+/// Redeems a token, returning the user it was issued to.
+pub fn redeem_action_token(
+    conn: &mut Connection,
+    token: &str,
+    purpose: ActionTokenPurpose,
+) -> Result<Uuid, ActionTokenError> {
+    consume_action_token(conn, &hash_token(token), purpose.as_str())
+        .map_err(|_err| ActionTokenError::TokenRedemptionFailure)?
+        .ok_or(ActionTokenError::InvalidToken)
+}
+
+/// Redeems a token stored with `issue_bound_action_token`, returning the user
+/// it was issued to.
+pub fn redeem_bound_action_token(
+    conn: &mut Connection,
+    token: &str,
+    binding: &str,
+    purpose: ActionTokenPurpose,
+) -> Result<Uuid, ActionTokenError> {
+    consume_action_token(conn, &hash_bound_token(token, binding), purpose.as_str())
+        .map_err(|_err| ActionTokenError::TokenRedemptionFailure)?
+        .ok_or(ActionTokenError::InvalidToken)
+}
+
+/// Looks up the user a live token was issued to without using it up, for
+/// tokens that survive failed attempts until redeemed.
+pub fn find_action_token(
+    conn: &mut Connection,
+    token: &str,
+    purpose: ActionTokenPurpose,
+) -> Result<Uuid, ActionTokenError> {
+    get_live_action_token_owner(conn, &hash_token(token), purpose.as_str())
+        .map_err(|_err| ActionTokenError::TokenRedemptionFailure)?
+        .ok_or(ActionTokenError::InvalidToken)
+}
+
+/// 64 random alphanumeric characters
+pub fn random_token() -> String {
+    rng()
+        .sample_iter(Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+fn store_action_token(
+    conn: &mut Connection,
+    user_id: Uuid,
+    purpose: ActionTokenPurpose,
+    valid_for: Duration,
+    token_hash: String,
+) -> Result<(), ActionTokenError> {
+    let new_token = NewActionToken {
+        token_hash,
+        user_id,
+        purpose: purpose.as_str().to_string(),
+        expired_at: Utc::now().naive_utc() + valid_for,
+    };
+
+    insert_action_token(conn, new_token).map_err(|_err| ActionTokenError::TokenCreationFailure)?;
+
+    Ok(())
+}
+
+fn hash_bound_token(token: &str, binding: &str) -> String {
+    hash_token(&format!("{}:{}", binding, token))
+}
+
+/// The SHA-256 hash tokens are stored by
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
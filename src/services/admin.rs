@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Duration;
+use diesel::{result::Error as DieselError, Connection as _};
+use uuid::Uuid;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::admin::AdminError,
+    models::{
+        admin::{AdminUserView, SessionView, UserCursor, UserFilter, UserPage, UserSearchQuery},
+        users::{User, UserStatus},
+    },
+    repository::{
+        jwt::{get_active_refresh_tokens_for_user, revoke_all_refresh_tokens_for_user},
+        roles::get_role_names_for_users,
+        users::{get_user_by_id, search_users, set_must_reset_password, set_user_status},
+    },
+    services::{
+        action_tokens::{issue_action_token, ActionTokenPurpose},
+        lockout::{self, LockoutScope},
+        mail::{MailMessage, MailSender},
+    },
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+pub fn list_users(conn: &mut Connection, query: UserSearchQuery) -> Result<UserPage, AdminError> {
+    let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    // Fetch one extra row to find out whether there is a next page
+    let filter = UserFilter {
+        role: query.role,
+        email_prefix: query.email_prefix,
+        verified: query.verified,
+        status: query.status,
+        after,
+        limit: limit + 1,
+    };
+
+    let mut users = search_users(conn, &filter).map_err(|_err| AdminError::QueryFailure)?;
+
+    let next_cursor = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|user| {
+            encode_cursor(&UserCursor {
+                login_name: user.email.clone().or_else(|| user.phone_number.clone()),
+                id: user.id,
+            })
+        })
+    } else {
+        None
+    };
+
+    Ok(UserPage {
+        users: to_views(conn, users)?,
+        next_cursor,
+    })
+}
+
+pub fn get_user(conn: &mut Connection, user_id: Uuid) -> Result<AdminUserView, AdminError> {
+    let user = find_user(conn, user_id)?;
+
+    to_views(conn, vec![user])?
+        .pop()
+        .ok_or(AdminError::UserNotFound)
+}
+
+pub fn get_user_sessions(
+    conn: &mut Connection,
+    user_id: Uuid,
+) -> Result<Vec<SessionView>, AdminError> {
+    find_user(conn, user_id)?;
+
+    let sessions = get_active_refresh_tokens_for_user(conn, user_id)
+        .map_err(|_err| AdminError::QueryFailure)?
+        .into_iter()
+        .map(|token| SessionView {
+            issued_at: token.issued_at,
+            expired_at: token.expired_at,
+        })
+        .collect();
+
+    Ok(sessions)
+}
+
+/// Moves the account to another status. Any change signs the user out of
+/// every session, so that the new status applies from the next refresh.
+pub fn set_status(
+    conn: &mut Connection,
+    user_id: Uuid,
+    status: UserStatus,
+) -> Result<AdminUserView, AdminError> {
+    let user = find_user(conn, user_id)?;
+
+    let user = if user.status == status {
+        user
+    } else {
+        // Both or neither, so that a new status never leaves sessions alive
+        conn.transaction(|conn| {
+            let user = set_user_status(conn, user_id, status)?;
+            revoke_all_refresh_tokens_for_user(conn, user_id)?;
+            Ok(user)
+        })
+        .map_err(|err| match err {
+            DieselError::NotFound => AdminError::UserNotFound,
+            _ => AdminError::UserUpdateFailure,
+        })?
+    };
+
+    to_views(conn, vec![user])?
+        .pop()
+        .ok_or(AdminError::UserNotFound)
+}
+
+/// Signs the user out everywhere and emails them a password reset link.
+/// They cannot log in with their old password until the reset is done.
+pub fn force_password_reset(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    user_id: Uuid,
+) -> Result<(), AdminError> {
+    let email = find_user(conn, user_id)?
+        .email
+        .ok_or(AdminError::NoEmailAddress)?;
+
+    set_must_reset_password(conn, user_id, true).map_err(|err| match err {
+        DieselError::NotFound => AdminError::UserNotFound,
+        _ => AdminError::UserUpdateFailure,
+    })?;
+
+    revoke_all_refresh_tokens_for_user(conn, user_id)
+        .map_err(|_err| AdminError::TokenRevocationFailure)?;
+
+    let valid_for = Duration::minutes(config::get().password_reset_ttl_minutes);
+    let token = issue_action_token(conn, user_id, ActionTokenPurpose::PasswordReset, valid_for)?;
+
+    mailer.send(MailMessage {
+        to: email,
+        subject: "Reset your PandaCare password".to_string(),
+        body: format!(
+            "For your security, please choose a new password for your PandaCare account.\n\n\
+             {}/reset-password?token={}\n\n\
+             This link expires in {} minutes.",
+            config::get().frontend_url,
+            token,
+            valid_for.num_minutes()
+        ),
+    })?;
+
+    Ok(())
+}
+
+pub fn revoke_all_tokens(conn: &mut Connection, user_id: Uuid) -> Result<usize, AdminError> {
+    find_user(conn, user_id)?;
+
+    revoke_all_refresh_tokens_for_user(conn, user_id)
+        .map_err(|_err| AdminError::TokenRevocationFailure)
+}
+
+/// Lifts a lockout or login delay on the account. Blocks on the IP addresses
+/// it was attacked from stay in place.
+pub fn unlock_user(conn: &mut Connection, user_id: Uuid) -> Result<(), AdminError> {
+    find_user(conn, user_id)?;
+
+    for scope in [
+        LockoutScope::Account,
+        LockoutScope::Mfa,
+        LockoutScope::EmailCode,
+    ] {
+        lockout::clear_failures(conn, scope, &user_id.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn find_user(conn: &mut Connection, user_id: Uuid) -> Result<User, AdminError> {
+    get_user_by_id(conn, user_id).map_err(|err| match err {
+        DieselError::NotFound => AdminError::UserNotFound,
+        _ => AdminError::QueryFailure,
+    })
+}
+
+fn to_views(conn: &mut Connection, users: Vec<User>) -> Result<Vec<AdminUserView>, AdminError> {
+    let user_ids: Vec<Uuid> = users.iter().map(|user| user.id).collect();
+
+    let mut roles_by_user: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (user_id, role_name) in
+        get_role_names_for_users(conn, &user_ids).map_err(|_err| AdminError::QueryFailure)?
+    {
+        roles_by_user.entry(user_id).or_default().push(role_name);
+    }
+
+    let views = users
+        .into_iter()
+        .map(|user| AdminUserView {
+            roles: roles_by_user.remove(&user.id).unwrap_or_default(),
+            id: user.id,
+            email: user.email,
+            phone_number: user.phone_number,
+            guardian_id: user.guardian_id,
+            is_verified: user.is_verified,
+            status: user.status,
+            must_reset_password: user.must_reset_password,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            deleted_at: user.deleted_at,
+        })
+        .collect();
+
+    Ok(views)
+}
+
+// Cursors are the last email (or phone number, for users without one) of the
+// previous page, encoded so clients treat them as opaque
+fn encode_cursor(cursor: &UserCursor) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).unwrap_or_default())
+}
+
+fn decode_cursor(cursor: &str) -> Result<UserCursor, AdminError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or(AdminError::InvalidCursor)
+}
@@ -0,0 +1,487 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use chrono::{NaiveDateTime, SubsecRound, Utc};
+use diesel::Connection as _;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    errors::audit::{AuditError, ChainBreak},
+    models::audit::{
+        AuditChainSummary, AuditCheckpoint, AuditEvent, AuditEventFilter, AuditEventPage,
+        AuditEventQuery, ClientInfo, NewAuditCheckpoint, NewAuditEvent,
+    },
+    repository::audit::{
+        get_audit_checkpoints, get_audit_events_after, get_last_chained_audit_event,
+        get_latest_audit_checkpoint, insert_audit_checkpoint, insert_audit_event, lock_audit_chain,
+        search_audit_events,
+    },
+    services::jwt::signing::rs256::{RS256Signer, RS256Verifier},
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+// Rows fetched at a time while exporting
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+pub struct AuditEntry {
+    pub event_type: &'static str,
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub outcome: AuditOutcome,
+    pub reason: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn success(
+        event_type: &'static str,
+        actor_id: Option<Uuid>,
+        subject_id: Option<Uuid>,
+    ) -> Self {
+        AuditEntry {
+            event_type,
+            actor_id,
+            subject_id,
+            outcome: AuditOutcome::Success,
+            reason: None,
+        }
+    }
+
+    pub fn failure(
+        event_type: &'static str,
+        actor_id: Option<Uuid>,
+        subject_id: Option<Uuid>,
+        reason: impl ToString,
+    ) -> Self {
+        AuditEntry {
+            event_type,
+            actor_id,
+            subject_id,
+            outcome: AuditOutcome::Failure,
+            reason: Some(reason.to_string()),
+        }
+    }
+}
+
+impl AuditEntry {
+    /// Records a failure with the error as the reason when `result` is an error
+    pub fn from_result<T, E: Display>(
+        event_type: &'static str,
+        actor_id: Option<Uuid>,
+        subject_id: Option<Uuid>,
+        result: &Result<T, E>,
+    ) -> Self {
+        match result {
+            Ok(_) => Self::success(event_type, actor_id, subject_id),
+            Err(err) => Self::failure(event_type, actor_id, subject_id, err),
+        }
+    }
+
+    /// Adds details about what was acted upon, e.g. the role that was granted
+    pub fn with_detail(self, detail: impl Display) -> Self {
+        let reason = match self.reason {
+            Some(reason) => format!("{}: {}", detail, reason),
+            None => detail.to_string(),
+        };
+
+        AuditEntry {
+            reason: Some(reason),
+            ..self
+        }
+    }
+}
+
+/// Appends an entry to the audit log, chained to the entry before it.
+///
+/// Failing to write the trail must not turn a completed action into an error
+/// response, so failures are only logged.
+pub fn record(conn: &mut Connection, client: &ClientInfo, entry: AuditEntry) {
+    let result = conn.transaction(|conn| {
+        lock_audit_chain(conn)?;
+
+        let previous_hash =
+            get_last_chained_audit_event(conn)?.and_then(|previous| previous.entry_hash);
+
+        let mut event = NewAuditEvent {
+            // Postgres keeps microseconds, and the hash has to match what is
+            // read back
+            occurred_at: Utc::now().naive_utc().trunc_subsecs(6),
+            actor_id: entry.actor_id,
+            subject_id: entry.subject_id,
+            event_type: entry.event_type.to_string(),
+            ip_address: client.ip_address.clone(),
+            user_agent: client.user_agent.clone(),
+            outcome: entry.outcome.as_str().to_string(),
+            reason: entry.reason,
+            previous_hash,
+            entry_hash: None,
+        };
+        event.entry_hash = Some(HashedFields::from(&event).hash());
+
+        insert_audit_event(conn, event)
+    });
+
+    if let Err(err) = result {
+        log::error!("Failed to record audit event {}: {}", entry.event_type, err);
+    }
+}
+
+/// Signs the hash of the newest entry, unless nothing was written since the
+/// last checkpoint
+pub fn create_checkpoint(
+    conn: &mut Connection,
+    secret_key: &str,
+) -> Result<Option<AuditCheckpoint>, AuditError> {
+    let Some(AuditEvent {
+        id: last_event_id,
+        entry_hash: Some(entry_hash),
+        ..
+    }) = get_last_chained_audit_event(conn)?
+    else {
+        return Ok(None);
+    };
+
+    if get_latest_audit_checkpoint(conn)?
+        .is_some_and(|checkpoint| checkpoint.last_event_id == last_event_id)
+    {
+        return Ok(None);
+    }
+
+    let signature = RS256Signer::new(secret_key)?
+        .sign_message(checkpoint_message(last_event_id, &entry_hash).as_bytes())?;
+
+    let checkpoint = insert_audit_checkpoint(
+        conn,
+        NewAuditCheckpoint {
+            last_event_id,
+            entry_hash,
+            signature,
+        },
+    )?;
+
+    Ok(Some(checkpoint))
+}
+
+/// Walks the whole trail from the oldest entry, recomputing every hash and
+/// checking every checkpoint against the key. Fails with the first break.
+pub fn verify_chain(
+    conn: &mut Connection,
+    secret_key: &str,
+) -> Result<AuditChainSummary, AuditError> {
+    let verifier = RS256Verifier::new(secret_key)?;
+    let mut chain = ChainVerifier::new(&verifier, get_audit_checkpoints(conn)?);
+    let mut after_id = 0;
+
+    loop {
+        let events = get_audit_events_after(conn, after_id, EXPORT_BATCH_SIZE)?;
+
+        for event in &events {
+            chain.check(event)?;
+        }
+
+        match events.last() {
+            Some(last) if events.len() as i64 == EXPORT_BATCH_SIZE => after_id = last.id,
+            _ => break,
+        }
+    }
+
+    Ok(chain.finish()?)
+}
+
+/// Checks events one at a time, in the order they were written
+pub struct ChainVerifier<'a> {
+    verifier: &'a RS256Verifier,
+    // Sorted by the event they sign
+    checkpoints: VecDeque<AuditCheckpoint>,
+    previous_hash: Option<String>,
+    summary: AuditChainSummary,
+}
+
+impl<'a> ChainVerifier<'a> {
+    pub fn new(verifier: &'a RS256Verifier, checkpoints: Vec<AuditCheckpoint>) -> Self {
+        ChainVerifier {
+            verifier,
+            checkpoints: checkpoints.into(),
+            previous_hash: None,
+            summary: AuditChainSummary::default(),
+        }
+    }
+
+    pub fn check(&mut self, event: &AuditEvent) -> Result<(), ChainBreak> {
+        // Checkpoints for events that should have come by now
+        if let Some(checkpoint) = self
+            .checkpoints
+            .front()
+            .filter(|checkpoint| checkpoint.last_event_id < event.id)
+        {
+            return Err(ChainBreak::MissingEvent {
+                checkpoint_id: checkpoint.id,
+                event_id: checkpoint.last_event_id,
+            });
+        }
+
+        let Some(entry_hash) = &event.entry_hash else {
+            if self.previous_hash.is_some() {
+                return Err(ChainBreak::MissingHash(event.id));
+            }
+
+            self.summary.unchained_events += 1;
+            return Ok(());
+        };
+
+        if event.previous_hash != self.previous_hash {
+            return Err(ChainBreak::BrokenLink(event.id));
+        }
+
+        if HashedFields::from(event).hash() != *entry_hash {
+            return Err(ChainBreak::HashMismatch(event.id));
+        }
+
+        while self
+            .checkpoints
+            .front()
+            .is_some_and(|checkpoint| checkpoint.last_event_id == event.id)
+        {
+            let Some(checkpoint) = self.checkpoints.pop_front() else {
+                break;
+            };
+            let message = checkpoint_message(checkpoint.last_event_id, &checkpoint.entry_hash);
+
+            if !self
+                .verifier
+                .verify_message(message.as_bytes(), &checkpoint.signature)
+            {
+                return Err(ChainBreak::InvalidSignature(checkpoint.id));
+            }
+
+            if checkpoint.entry_hash != *entry_hash {
+                return Err(ChainBreak::CheckpointMismatch {
+                    checkpoint_id: checkpoint.id,
+                    event_id: event.id,
+                });
+            }
+
+            self.summary.checkpoints += 1;
+            self.summary.last_checkpoint_at = Some(checkpoint.created_at);
+        }
+
+        self.previous_hash = Some(entry_hash.clone());
+        self.summary.chained_events += 1;
+
+        Ok(())
+    }
+
+    /// Fails if checkpoints are left over for events past the end of the trail
+    pub fn finish(self) -> Result<AuditChainSummary, ChainBreak> {
+        match self.checkpoints.front() {
+            Some(checkpoint) => Err(ChainBreak::MissingEvent {
+                checkpoint_id: checkpoint.id,
+                event_id: checkpoint.last_event_id,
+            }),
+            None => Ok(self.summary),
+        }
+    }
+}
+
+fn checkpoint_message(last_event_id: i64, entry_hash: &str) -> String {
+    format!(
+        "pandacare-audit-checkpoint:{}:{}",
+        last_event_id, entry_hash
+    )
+}
+
+// What an entry's hash covers: every field written by `record`
+struct HashedFields<'a> {
+    previous_hash: Option<&'a str>,
+    occurred_at: NaiveDateTime,
+    actor_id: Option<Uuid>,
+    subject_id: Option<Uuid>,
+    event_type: &'a str,
+    ip_address: Option<&'a str>,
+    user_agent: Option<&'a str>,
+    outcome: &'a str,
+    reason: Option<&'a str>,
+}
+
+impl HashedFields<'_> {
+    // Each field is prefixed with its length, so that no two different
+    // entries feed the same bytes to the hash
+    fn hash(&self) -> String {
+        let occurred_at = self.occurred_at.format("%Y-%m-%dT%H:%M:%S%.6f").to_string();
+        let actor_id = self.actor_id.map(|id| id.to_string());
+        let subject_id = self.subject_id.map(|id| id.to_string());
+
+        let fields = [
+            self.previous_hash,
+            Some(occurred_at.as_str()),
+            actor_id.as_deref(),
+            subject_id.as_deref(),
+            Some(self.event_type),
+            self.ip_address,
+            self.user_agent,
+            Some(self.outcome),
+            self.reason,
+        ];
+
+        let mut hasher = Sha256::new();
+
+        for field in fields {
+            match field {
+                Some(value) => {
+                    hasher.update((value.len() as u64).to_be_bytes());
+                    hasher.update(value.as_bytes());
+                }
+                None => hasher.update(u64::MAX.to_be_bytes()),
+            }
+        }
+
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+impl<'a> From<&'a NewAuditEvent> for HashedFields<'a> {
+    fn from(event: &'a NewAuditEvent) -> Self {
+        HashedFields {
+            previous_hash: event.previous_hash.as_deref(),
+            occurred_at: event.occurred_at,
+            actor_id: event.actor_id,
+            subject_id: event.subject_id,
+            event_type: &event.event_type,
+            ip_address: event.ip_address.as_deref(),
+            user_agent: event.user_agent.as_deref(),
+            outcome: &event.outcome,
+            reason: event.reason.as_deref(),
+        }
+    }
+}
+
+impl<'a> From<&'a AuditEvent> for HashedFields<'a> {
+    fn from(event: &'a AuditEvent) -> Self {
+        HashedFields {
+            previous_hash: event.previous_hash.as_deref(),
+            occurred_at: event.occurred_at,
+            actor_id: event.actor_id,
+            subject_id: event.subject_id,
+            event_type: &event.event_type,
+            ip_address: event.ip_address.as_deref(),
+            user_agent: event.user_agent.as_deref(),
+            outcome: &event.outcome,
+            reason: event.reason.as_deref(),
+        }
+    }
+}
+
+/// A page of events matching the query, newest first
+pub fn list_events(
+    conn: &mut Connection,
+    query: AuditEventQuery,
+) -> Result<AuditEventPage, AuditError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    // Fetch one extra row to find out whether there is a next page
+    let mut filter = to_filter(query)?;
+    filter.limit = limit + 1;
+
+    let mut events = search_audit_events(conn, &filter)?;
+
+    let next_cursor = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|event| event.id.to_string())
+    } else {
+        None
+    };
+
+    Ok(AuditEventPage {
+        events,
+        next_cursor,
+    })
+}
+
+/// Every event matching the query, newest first, as JSON Lines. The events
+/// are fetched a batch at a time, so that a large log never sits in memory
+/// whole.
+pub struct AuditExport {
+    filter: AuditEventFilter,
+    done: bool,
+}
+
+impl AuditExport {
+    /// The limit is ignored, while a cursor starts the export after the page
+    /// it came from
+    pub fn new(query: AuditEventQuery) -> Result<Self, AuditError> {
+        let mut filter = to_filter(query)?;
+        filter.limit = EXPORT_BATCH_SIZE;
+
+        Ok(AuditExport {
+            filter,
+            done: false,
+        })
+    }
+
+    /// The next batch of lines, or `None` once every event has been returned
+    pub fn next_batch(&mut self, conn: &mut Connection) -> Result<Option<String>, AuditError> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let events = search_audit_events(conn, &self.filter)?;
+
+        match events.last() {
+            Some(last) if events.len() as i64 == EXPORT_BATCH_SIZE => {
+                self.filter.before_id = Some(last.id)
+            }
+            _ => self.done = true,
+        }
+
+        if events.is_empty() {
+            return Ok(None);
+        }
+
+        let mut lines = String::new();
+        for event in &events {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+
+        Ok(Some(lines))
+    }
+}
+
+// Cursors are the ID of the last event of the previous page
+fn to_filter(query: AuditEventQuery) -> Result<AuditEventFilter, AuditError> {
+    let before_id = query
+        .cursor
+        .as_deref()
+        .map(|cursor| cursor.parse().map_err(|_err| AuditError::InvalidCursor))
+        .transpose()?;
+
+    Ok(AuditEventFilter {
+        actor_id: query.actor_id,
+        subject_id: query.subject_id,
+        event_type: query.event_type,
+        outcome: query.outcome,
+        from: query.from,
+        to: query.to,
+        before_id,
+        limit: DEFAULT_PAGE_SIZE,
+    })
+}
@@ -0,0 +1,100 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use sha1::{Digest, Sha1};
+
+/// Looks a password up in a local copy of a breached password corpus, such as
+/// the Pwned Passwords SHA-1 dump. The password itself never leaves the process.
+///
+/// Two layouts are supported:
+/// - a single file of uppercase SHA-1 hashes sorted in ascending order, one
+///   per line and optionally followed by `:<count>`, searched in place;
+/// - a directory of k-anonymity range files named after the first five hex
+///   digits of the hash (e.g. `5BAA6.txt`), each listing the remaining 35
+///   digits the same way.
+pub fn is_breached(corpus: &Path, password: &str) -> io::Result<bool> {
+    let hash = hex_upper(&Sha1::digest(password.as_bytes()));
+
+    if corpus.is_dir() {
+        let (prefix, suffix) = hash.split_at(5);
+        let range_file = corpus.join(format!("{}.txt", prefix));
+
+        if !range_file.exists() {
+            return Ok(false);
+        }
+
+        let lines = BufReader::new(File::open(range_file)?).lines();
+        for line in lines {
+            if hash_of(&line?).eq_ignore_ascii_case(suffix) {
+                return Ok(true);
+            }
+        }
+
+        return Ok(false);
+    }
+
+    sorted_file_contains(File::open(corpus)?, &hash)
+}
+
+// Binary search over byte offsets for the start of the line holding the
+// hash. Each probe reads the first line starting at or after its offset.
+fn sorted_file_contains(mut file: File, hash: &str) -> io::Result<bool> {
+    let mut low = 0;
+    let mut high = file.metadata()?.len();
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+
+        let Some((line, next_line_start)) = line_from(&mut file, middle)? else {
+            high = middle;
+            continue;
+        };
+
+        match hash_of(&line).to_ascii_uppercase().as_str().cmp(hash) {
+            Ordering::Equal => return Ok(true),
+            Ordering::Less => low = next_line_start,
+            Ordering::Greater => high = middle,
+        }
+    }
+
+    Ok(false)
+}
+
+// Returns the first line starting at or after `offset`, and where the line
+// after it starts
+fn line_from(file: &mut File, offset: u64) -> io::Result<Option<(String, u64)>> {
+    // Step back one byte so that a line starting exactly at `offset` is kept
+    let seek_to = offset.saturating_sub(1);
+    file.seek(SeekFrom::Start(seek_to))?;
+
+    let mut reader = BufReader::new(file.by_ref());
+    let mut position = seek_to;
+
+    if offset > 0 {
+        let mut skipped = Vec::new();
+        position += reader.read_until(b'\n', &mut skipped)? as u64;
+    }
+
+    let mut line = String::new();
+    let length = reader.read_line(&mut line)?;
+
+    if length == 0 {
+        return Ok(None);
+    }
+
+    let line = line.trim_end_matches(['\r', '\n']).to_string();
+
+    Ok(Some((line, position + length as u64)))
+}
+
+fn hash_of(line: &str) -> &str {
+    line.split(':').next().unwrap_or_default().trim()
+}
+
+fn hex_upper(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
@@ -0,0 +1,276 @@
+use diesel::{
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection as _, QueryResult,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{caregivers::CaregiverError, validation::ValidationErrors},
+    models::{
+        caregivers::{
+            ApplicationQuery, ApplicationStatus, CaregiverApplication, CaregiverApplicationDetails,
+            CaregiverApplicationFields, NewCaregiverApplication, NewCaregiverDocument,
+            RejectionFields,
+        },
+        roles::NewUserRole,
+        users::Role,
+    },
+    repository::{
+        caregivers::{
+            get_caregiver_application, get_caregiver_application_for_update,
+            get_caregiver_documents, get_latest_caregiver_application_for_user,
+            insert_caregiver_application, insert_caregiver_documents, list_caregiver_applications,
+            set_caregiver_application_review,
+        },
+        roles::{get_role_by_name, get_role_names_for_user, insert_user_role},
+        users::get_user_by_id,
+    },
+    services::mail::{MailMessage, MailSender},
+};
+
+/// Records an application and its documents. The caller checks the fields
+/// and decides whether the user may apply.
+pub fn store_application(
+    conn: &mut Connection,
+    user_id: Uuid,
+    fields: CaregiverApplicationFields,
+) -> QueryResult<CaregiverApplicationDetails> {
+    let application = insert_caregiver_application(
+        conn,
+        NewCaregiverApplication {
+            user_id,
+            str_number: fields.str_number,
+        },
+    )?;
+
+    let documents = insert_caregiver_documents(
+        conn,
+        fields
+            .documents
+            .into_iter()
+            .map(|document| NewCaregiverDocument {
+                application_id: application.id,
+                document_type: document.document_type.as_str().to_string(),
+                url: document.url,
+            })
+            .collect(),
+    )?;
+
+    Ok(CaregiverApplicationDetails {
+        application,
+        documents,
+    })
+}
+
+/// Applies for the caregiver role as a signed in user, e.g. after an
+/// earlier application was rejected.
+pub fn submit_application(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    user_id: Uuid,
+    fields: CaregiverApplicationFields,
+) -> Result<CaregiverApplicationDetails, CaregiverError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let user = get_user_by_id(conn, user_id)?;
+
+    // The outcome of the review is sent by email
+    let Some(email) = user.email else {
+        return Err(CaregiverError::NoEmailAddress);
+    };
+
+    let roles = get_role_names_for_user(conn, user_id)?;
+
+    if roles.contains(&Role::Caregiver.to_string()) {
+        return Err(CaregiverError::AlreadyCaregiver);
+    }
+
+    let details = conn
+        .transaction(|conn| store_application(conn, user_id, fields))
+        .map_err(|err| match err {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                CaregiverError::ApplicationPending
+            }
+            _ => err.into(),
+        })?;
+
+    notify(
+        mailer,
+        email,
+        "We received your caregiver application",
+        "Thank you for applying to work as a caregiver on PandaCare. We will check your \
+         STR and documents and email you once we have decided. Until then, you can keep \
+         using PandaCare as a pacilian."
+            .to_string(),
+    );
+
+    Ok(details)
+}
+
+/// The user's most recent application
+pub fn get_own_application(
+    conn: &mut Connection,
+    user_id: Uuid,
+) -> Result<CaregiverApplicationDetails, CaregiverError> {
+    let application = get_latest_caregiver_application_for_user(conn, user_id)?
+        .ok_or(CaregiverError::ApplicationNotFound)?;
+
+    with_documents(conn, application)
+}
+
+pub fn list_applications(
+    conn: &mut Connection,
+    query: ApplicationQuery,
+) -> Result<Vec<CaregiverApplication>, CaregiverError> {
+    Ok(list_caregiver_applications(conn, query.status)?)
+}
+
+pub fn get_application(
+    conn: &mut Connection,
+    application_id: Uuid,
+) -> Result<CaregiverApplicationDetails, CaregiverError> {
+    let application = get_caregiver_application(conn, application_id)?
+        .ok_or(CaregiverError::ApplicationNotFound)?;
+
+    with_documents(conn, application)
+}
+
+/// Approves a pending application and grants the applicant the caregiver
+/// role. Tokens issued from then on, including on refresh, carry it.
+pub fn approve_application(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    reviewer_id: Uuid,
+    application_id: Uuid,
+) -> Result<CaregiverApplication, CaregiverError> {
+    let application = conn.transaction(|conn| {
+        let application = review(
+            conn,
+            reviewer_id,
+            application_id,
+            ApplicationStatus::Approved,
+            None,
+        )?;
+        let role = get_role_by_name(conn, &Role::Caregiver.to_string())?;
+
+        insert_user_role(
+            conn,
+            NewUserRole {
+                user_id: application.user_id,
+                role_id: role.id,
+                granted_by: Some(reviewer_id),
+            },
+        )?;
+
+        Ok::<_, CaregiverError>(application)
+    })?;
+
+    if let Some(email) = get_user_by_id(conn, application.user_id)?.email {
+        notify(
+            mailer,
+            email,
+            "Your caregiver application has been approved",
+            format!(
+                "Your PandaCare caregiver application has been approved. Sign in again at \
+                 {}/login to start working as a caregiver.",
+                config::get().frontend_url
+            ),
+        );
+    }
+
+    Ok(application)
+}
+
+/// Rejects a pending application, telling the applicant why. They keep
+/// their pacilian access and can apply again.
+pub fn reject_application(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    reviewer_id: Uuid,
+    application_id: Uuid,
+    fields: RejectionFields,
+) -> Result<CaregiverApplication, CaregiverError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let application = conn.transaction(|conn| {
+        review(
+            conn,
+            reviewer_id,
+            application_id,
+            ApplicationStatus::Rejected,
+            Some(fields.reason.clone()),
+        )
+    })?;
+
+    if let Some(email) = get_user_by_id(conn, application.user_id)?.email {
+        notify(
+            mailer,
+            email,
+            "Your caregiver application was not approved",
+            format!(
+                "We could not approve your PandaCare caregiver application:\n\n{}\n\n\
+                 You can send a new application from your account at {}.",
+                fields.reason,
+                config::get().frontend_url
+            ),
+        );
+    }
+
+    Ok(application)
+}
+
+// Decides on an application that is still pending, within a transaction
+fn review(
+    conn: &mut Connection,
+    reviewer_id: Uuid,
+    application_id: Uuid,
+    status: ApplicationStatus,
+    reason: Option<String>,
+) -> Result<CaregiverApplication, CaregiverError> {
+    let application = get_caregiver_application_for_update(conn, application_id)?
+        .ok_or(CaregiverError::ApplicationNotFound)?;
+
+    if application.user_id == reviewer_id {
+        return Err(CaregiverError::SelfReview);
+    }
+
+    if application.status != ApplicationStatus::PendingVerification.as_str() {
+        return Err(CaregiverError::AlreadyReviewed);
+    }
+
+    Ok(set_caregiver_application_review(
+        conn,
+        application_id,
+        status,
+        reviewer_id,
+        reason,
+    )?)
+}
+
+fn with_documents(
+    conn: &mut Connection,
+    application: CaregiverApplication,
+) -> Result<CaregiverApplicationDetails, CaregiverError> {
+    let documents = get_caregiver_documents(conn, application.id)?;
+
+    Ok(CaregiverApplicationDetails {
+        application,
+        documents,
+    })
+}
+
+// Best effort: the decision stands even if the email does not go out
+fn notify(mailer: &dyn MailSender, to: String, subject: &str, body: String) {
+    let message = MailMessage {
+        to,
+        subject: subject.to_string(),
+        body,
+    };
+
+    if let Err(e) = mailer.send(message) {
+        log::error!("Failed to send caregiver application notification: {}", e);
+    }
+}
@@ -0,0 +1,69 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::errors::crypto::CryptoError;
+
+const NONCE_LENGTH: usize = 12;
+
+/// AES-256-GCM key used to encrypt sensitive columns at rest.
+///
+/// Ciphertexts are stored as base64 of the random nonce followed by the
+/// sealed data, so the same plaintext never encrypts to the same value twice.
+#[derive(Clone)]
+pub struct DataKey {
+    cipher: Aes256Gcm,
+}
+
+impl DataKey {
+    pub fn from_base64(base64_key: &str) -> Result<Self, CryptoError> {
+        let key_bytes = STANDARD
+            .decode(base64_key.trim())
+            .map_err(|_err| CryptoError::InvalidKey)?;
+
+        if key_bytes.len() != 32 {
+            return Err(CryptoError::InvalidKey);
+        }
+
+        let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(key),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, CryptoError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_err| CryptoError::EncryptionFailure)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(STANDARD.encode(sealed))
+    }
+
+    pub fn decrypt(&self, encoded: &str) -> Result<String, CryptoError> {
+        let sealed = STANDARD
+            .decode(encoded)
+            .map_err(|_err| CryptoError::DecryptionFailure)?;
+
+        if sealed.len() < NONCE_LENGTH {
+            return Err(CryptoError::DecryptionFailure);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_err| CryptoError::DecryptionFailure)?;
+
+        String::from_utf8(plaintext).map_err(|_err| CryptoError::DecryptionFailure)
+    }
+}
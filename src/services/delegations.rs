@@ -0,0 +1,201 @@
+use chrono::{Duration, Utc};
+use diesel::{
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection as _,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{delegations::DelegationError, validation::ValidationErrors},
+    models::delegations::{
+        Delegation, DelegationAcceptanceFields, DelegationInvitationFields, DelegationList,
+        DelegationScope, DelegationStatus, NewDelegation, TokenExchangeFields,
+    },
+    repository::{
+        delegations::{
+            activate_delegation, get_active_delegation, get_delegation, get_delegations_by_actor,
+            get_delegations_by_subject, get_open_invitation_for_update, insert_delegation,
+            revoke_delegation,
+        },
+        users::get_user_by_id,
+    },
+    services::{
+        action_tokens::{hash_token, random_token},
+        jwt::{generate_delegated_token, Claims, ExchangedToken, TokenKeys},
+        mail::{MailMessage, MailSender},
+        users::normalize_email,
+    },
+};
+
+/// Invites someone, by email, to act for the patient with the given scopes.
+/// The delegation only becomes active once they accept while signed in
+/// with that address.
+pub fn invite(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    subject_id: Uuid,
+    fields: DelegationInvitationFields,
+) -> Result<Delegation, DelegationError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let subject = get_user_by_id(conn, subject_id)?;
+    let invited_email = normalize_email(&fields.email);
+
+    if subject.email.as_deref().map(normalize_email) == Some(invited_email.clone()) {
+        return Err(DelegationError::SelfDelegation);
+    }
+
+    let mut scopes: Vec<&str> = fields.scopes.iter().map(|scope| scope.as_str()).collect();
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    let token = random_token();
+    let ttl_hours = config::get().delegation_invitation_ttl_hours;
+
+    // Only keep the invitation if the email with its link went out
+    conn.transaction(|conn| {
+        let delegation = insert_delegation(
+            conn,
+            NewDelegation {
+                subject_id,
+                invited_email,
+                scopes: scopes.join(" "),
+                invitation_token_hash: hash_token(&token),
+                invitation_expired_at: (Utc::now() + Duration::hours(ttl_hours)).naive_utc(),
+            },
+        )?;
+
+        mailer.send(MailMessage {
+            to: fields.email,
+            subject: "You have been invited to help with a PandaCare account".to_string(),
+            body: format!(
+                "{} has invited you to help manage their PandaCare account ({}).\n\n\
+                 Sign in or create an account with this email address, then accept the \
+                 invitation at {}/delegations/accept?token={}\n\n\
+                 The invitation expires in {} hours. If you were not expecting it, you can \
+                 ignore this email.",
+                subject.login_name(),
+                scopes.join(", "),
+                config::get().frontend_url,
+                token,
+                ttl_hours
+            ),
+        })?;
+
+        Ok(delegation)
+    })
+}
+
+/// Accepts an invitation as the signed in user it was sent to
+pub fn accept(
+    conn: &mut Connection,
+    actor_id: Uuid,
+    fields: DelegationAcceptanceFields,
+) -> Result<Delegation, DelegationError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let actor = get_user_by_id(conn, actor_id)?;
+
+    conn.transaction(|conn| {
+        let invitation = get_open_invitation_for_update(conn, &hash_token(&fields.token))?
+            .ok_or(DelegationError::InvalidInvitation)?;
+
+        if invitation.subject_id == actor_id {
+            return Err(DelegationError::SelfDelegation);
+        }
+
+        if actor.email.as_deref().map(normalize_email) != Some(invitation.invited_email) {
+            return Err(DelegationError::WrongInvitee);
+        }
+
+        activate_delegation(conn, invitation.id, actor_id).map_err(|err| match err {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                DelegationError::AlreadyDelegated
+            }
+            _ => err.into(),
+        })
+    })
+}
+
+pub fn list(conn: &mut Connection, user_id: Uuid) -> Result<DelegationList, DelegationError> {
+    Ok(DelegationList {
+        granted: get_delegations_by_subject(conn, user_id)?,
+        received: get_delegations_by_actor(conn, user_id)?,
+    })
+}
+
+/// Revokes a delegation, or withdraws an invitation, given by the patient.
+/// Tokens already exchanged stay valid until they expire a few minutes
+/// later.
+pub fn revoke(
+    conn: &mut Connection,
+    subject_id: Uuid,
+    delegation_id: Uuid,
+) -> Result<Delegation, DelegationError> {
+    let delegation = get_delegation(conn, delegation_id)?
+        .filter(|delegation| delegation.subject_id == subject_id)
+        .ok_or(DelegationError::DelegationNotFound)?;
+
+    if delegation.status == DelegationStatus::Revoked.as_str() {
+        return Err(DelegationError::AlreadyRevoked);
+    }
+
+    Ok(revoke_delegation(conn, delegation_id)?)
+}
+
+/// Exchanges the delegate's own access token for one to act for the
+/// patient, limited to the requested scopes. Guardians can do this for their
+/// dependents without a delegation, with every scope.
+pub fn exchange(
+    conn: &mut Connection,
+    token_keys: &TokenKeys,
+    actor_claims: &Claims,
+    actor_id: Uuid,
+    fields: TokenExchangeFields,
+) -> Result<(Option<Delegation>, ExchangedToken), DelegationError> {
+    let subject = match get_user_by_id(conn, fields.subject_id) {
+        Ok(subject) => subject,
+        Err(DieselError::NotFound) => return Err(DelegationError::NotDelegated),
+        Err(err) => return Err(err.into()),
+    };
+
+    let delegation = if subject.guardian_id == Some(actor_id) {
+        None
+    } else {
+        Some(
+            get_active_delegation(conn, subject.id, actor_id)?
+                .ok_or(DelegationError::NotDelegated)?,
+        )
+    };
+
+    let granted: Vec<&str> = match &delegation {
+        Some(delegation) => delegation.scopes.split(' ').collect(),
+        None => DelegationScope::ALL
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect(),
+    };
+    let scopes: Vec<&str> = match &fields.scope {
+        Some(requested) if !requested.trim().is_empty() => {
+            let requested: Vec<&str> = requested.split_whitespace().collect();
+
+            if let Some(scope) = requested.iter().find(|scope| !granted.contains(scope)) {
+                return Err(DelegationError::InvalidScope(scope.to_string()));
+            }
+
+            requested
+        }
+        _ => granted,
+    };
+
+    if !subject.is_active() || !get_user_by_id(conn, actor_id)?.is_active() {
+        return Err(DelegationError::AccountDisabled);
+    }
+
+    let token = generate_delegated_token(token_keys, subject.id, actor_claims, &scopes)?;
+
+    Ok((delegation, token))
+}
@@ -0,0 +1,193 @@
+use chrono::{Duration, Months, NaiveDate, Utc};
+use diesel::{
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection as _,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{dependents::DependentError, validation::ValidationErrors},
+    models::{
+        dependents::{DependentConversionFields, DependentFields, DependentView},
+        profiles::ProfileUpdate,
+        roles::NewUserRole,
+        users::{NewDependentUser, Role, User},
+    },
+    repository::{
+        roles::{get_role_by_name, insert_user_role},
+        users::{convert_dependent, get_dependents, get_user_by_id, insert_dependent_user},
+    },
+    services::{
+        action_tokens::{issue_action_token, ActionTokenPurpose},
+        crypto::DataKey,
+        mail::{MailMessage, MailSender},
+        profiles,
+        users::normalize_email,
+    },
+};
+
+/// Registers a minor who signs in through their guardian until they come of
+/// age. Guardians act for them with tokens from the token exchange.
+pub fn create_dependent(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    guardian_id: Uuid,
+    fields: DependentFields,
+) -> Result<DependentView, DependentError> {
+    if has_come_of_age(fields.date_of_birth) {
+        return Err(ValidationErrors::single(
+            "date_of_birth",
+            "must be of someone who has not come of age, who registers on their own",
+        )
+        .into());
+    }
+
+    conn.transaction(|conn| {
+        let dependent = insert_dependent_user(conn, NewDependentUser { guardian_id })?;
+        let role = get_role_by_name(conn, &Role::Pacilian.to_string())?;
+
+        insert_user_role(
+            conn,
+            NewUserRole {
+                user_id: dependent.id,
+                role_id: role.id,
+                granted_by: Some(guardian_id),
+            },
+        )?;
+
+        let profile = profiles::update_profile(
+            conn,
+            data_key,
+            dependent.id,
+            ProfileUpdate {
+                full_name: Some(fields.full_name),
+                nik: fields.nik,
+                phone_number: None,
+                date_of_birth: Some(fields.date_of_birth),
+            },
+        )?;
+
+        Ok(DependentView {
+            id: dependent.id,
+            profile,
+        })
+    })
+}
+
+pub fn list_dependents(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    guardian_id: Uuid,
+) -> Result<Vec<DependentView>, DependentError> {
+    get_dependents(conn, guardian_id)?
+        .into_iter()
+        .map(|dependent| {
+            Ok(DependentView {
+                id: dependent.id,
+                profile: profiles::get_profile(conn, data_key, dependent.id)?,
+            })
+        })
+        .collect()
+}
+
+pub fn update_dependent_profile(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    guardian_id: Uuid,
+    dependent_id: Uuid,
+    update: ProfileUpdate,
+) -> Result<DependentView, DependentError> {
+    get_dependent(conn, guardian_id, dependent_id)?;
+
+    Ok(DependentView {
+        id: dependent_id,
+        profile: profiles::update_profile(conn, data_key, dependent_id, update)?,
+    })
+}
+
+/// Turns a dependent who has come of age into a full account with their own
+/// email address. They choose a password through the link sent to it, so
+/// that their former guardian, who can no longer act for them, never knows
+/// it.
+pub fn convert_to_account(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    data_key: &DataKey,
+    guardian_id: Uuid,
+    dependent_id: Uuid,
+    fields: DependentConversionFields,
+) -> Result<User, DependentError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let dependent = get_dependent(conn, guardian_id, dependent_id)?;
+    let profile = profiles::get_profile(conn, data_key, dependent.id)?;
+
+    if !profile.date_of_birth.is_some_and(has_come_of_age) {
+        return Err(DependentError::NotOfAge);
+    }
+
+    let email_normalized = normalize_email(&fields.email);
+
+    if email_normalized.chars().count() > 255 {
+        return Err(
+            ValidationErrors::single("email", "must be at most 255 characters long").into(),
+        );
+    }
+
+    let valid_for = Duration::minutes(config::get().password_reset_ttl_minutes);
+
+    // Only converted if the link went out, as the account has no other way in
+    conn.transaction(|conn| {
+        let user = convert_dependent(conn, dependent_id, &fields.email, &email_normalized)
+            .map_err(|err| match err {
+                DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    DependentError::EmailTaken
+                }
+                _ => err.into(),
+            })?;
+
+        let token =
+            issue_action_token(conn, user.id, ActionTokenPurpose::PasswordReset, valid_for)?;
+
+        mailer.send(MailMessage {
+            to: fields.email,
+            subject: "Your PandaCare account is ready".to_string(),
+            body: format!(
+                "Your guardian has handed your PandaCare account over to you. Choose a \
+                 password to start signing in with this email address:\n\n\
+                 {}/reset-password?token={}\n\n\
+                 This link expires in {} minutes. You can ask for a new one from the sign \
+                 in page.",
+                config::get().frontend_url,
+                token,
+                valid_for.num_minutes()
+            ),
+        })?;
+
+        Ok(user)
+    })
+}
+
+/// The dependent with this ID, if it belongs to the guardian
+pub fn get_dependent(
+    conn: &mut Connection,
+    guardian_id: Uuid,
+    dependent_id: Uuid,
+) -> Result<User, DependentError> {
+    match get_user_by_id(conn, dependent_id) {
+        Ok(user) if user.guardian_id == Some(guardian_id) => Ok(user),
+        Ok(_) | Err(DieselError::NotFound) => Err(DependentError::DependentNotFound),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn has_come_of_age(date_of_birth: NaiveDate) -> bool {
+    let years = config::get().age_of_majority_years;
+
+    date_of_birth
+        .checked_add_months(Months::new(years * 12))
+        .is_some_and(|majority| majority <= Utc::now().date_naive())
+}
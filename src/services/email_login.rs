@@ -0,0 +1,194 @@
+use chrono::Duration;
+use rand::{rng, Rng};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{
+        action_tokens::ActionTokenError, email_login::EmailLoginError, validation::ValidationErrors,
+    },
+    models::users::{EmailCodeFields, EmailLinkFields, EmailLoginFields, EmailLoginStarted, User},
+    repository::users::{get_user_by_email, get_user_by_id},
+    services::{
+        action_tokens::{
+            issue_bound_action_token, random_token, redeem_bound_action_token, ActionTokenPurpose,
+        },
+        lockout::{self, LockoutScope},
+        mail::{MailMessage, MailSender},
+        mfa,
+        users::normalize_email,
+    },
+};
+
+/// Emails a single-use sign in link. It only works on the device that asked
+/// for it, which identifies itself with the returned device token.
+pub fn send_login_link(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    fields: EmailLoginFields,
+) -> Result<EmailLoginStarted, EmailLoginError> {
+    send_login_email(conn, mailer, fields, ActionTokenPurpose::EmailLink)
+}
+
+/// Emails a single-use 6-digit sign in code, bound to the requesting device
+/// like a link.
+pub fn send_login_code(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    fields: EmailLoginFields,
+) -> Result<EmailLoginStarted, EmailLoginError> {
+    send_login_email(conn, mailer, fields, ActionTokenPurpose::EmailCode)
+}
+
+/// Signs in with the token from a sign in link, returning the user.
+pub fn redeem_login_link(
+    conn: &mut Connection,
+    fields: EmailLinkFields,
+) -> Result<User, EmailLoginError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let user_id = redeem_bound_action_token(
+        conn,
+        &fields.token,
+        &fields.device_token,
+        ActionTokenPurpose::EmailLink,
+    )
+    .map_err(invalid_token)?;
+
+    finish_login(conn, user_id)
+}
+
+/// Signs in with a code from a sign in email, returning the user. Wrong codes
+/// count towards a lockout of code sign in for the account, since six digits
+/// alone are easy to guess.
+pub fn verify_login_code(
+    conn: &mut Connection,
+    fields: EmailCodeFields,
+) -> Result<User, EmailLoginError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let user = get_user_by_email(conn, &normalize_email(&fields.email))
+        .map_err(|_err| EmailLoginError::InvalidToken)?;
+    let subject = user.id.to_string();
+
+    if lockout::is_blocked(conn, LockoutScope::EmailCode, &subject)? {
+        return Err(EmailLoginError::TooManyAttempts);
+    }
+
+    let redeemed = redeem_bound_action_token(
+        conn,
+        fields.code.trim(),
+        &fields.device_token,
+        ActionTokenPurpose::EmailCode,
+    );
+
+    match redeemed {
+        Ok(owner_id) if owner_id == user.id => {}
+        Ok(_) | Err(ActionTokenError::InvalidToken) => {
+            lockout::record_failure(conn, LockoutScope::EmailCode, &subject)?;
+            return Err(EmailLoginError::InvalidToken);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    lockout::clear_failures(conn, LockoutScope::EmailCode, &subject)?;
+
+    finish_login(conn, user.id)
+}
+
+// Responds the same whether or not the address belongs to an account, and
+// sends nothing if it does not
+fn send_login_email(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    fields: EmailLoginFields,
+    purpose: ActionTokenPurpose,
+) -> Result<EmailLoginStarted, EmailLoginError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let device_token = random_token();
+
+    let user = get_user_by_email(conn, &normalize_email(&fields.email))
+        .ok()
+        .filter(User::is_active);
+
+    let Some(User {
+        id: user_id,
+        email: Some(email),
+        ..
+    }) = user
+    else {
+        return Ok(EmailLoginStarted { device_token });
+    };
+
+    let valid_for = Duration::minutes(config::get().email_login_ttl_minutes);
+
+    let message = match purpose {
+        ActionTokenPurpose::EmailCode => {
+            let code = format!("{:06}", rng().random_range(0..1_000_000));
+            issue_bound_action_token(conn, user_id, purpose, valid_for, &code, &device_token)?;
+
+            MailMessage {
+                to: email,
+                subject: format!("Your PandaCare sign in code is {}", code),
+                body: format!(
+                    "Enter this code to sign in to PandaCare:\n\n{}\n\n\
+                     The code expires in {} minutes and only works in the browser or app \
+                     where you asked for it. If you didn't ask for it, you can ignore this email.",
+                    code,
+                    valid_for.num_minutes()
+                ),
+            }
+        }
+        _ => {
+            let token = random_token();
+            issue_bound_action_token(conn, user_id, purpose, valid_for, &token, &device_token)?;
+
+            MailMessage {
+                to: email,
+                subject: "Sign in to PandaCare".to_string(),
+                body: format!(
+                    "Open this link to sign in to PandaCare:\n\n\
+                     {}/login/email-link?token={}\n\n\
+                     The link expires in {} minutes and only works in the browser or app \
+                     where you asked for it. If you didn't ask for it, you can ignore this email.",
+                    config::get().frontend_url,
+                    token,
+                    valid_for.num_minutes()
+                ),
+            }
+        }
+    };
+
+    // Best effort: a delivery failure must not reveal that the account exists
+    if let Err(e) = mailer.send(message) {
+        log::error!("Failed to send sign in email: {}", e);
+    }
+
+    Ok(EmailLoginStarted { device_token })
+}
+
+// An emailed link or code stands in for the password only. Accounts that
+// need a second factor keep signing in with both.
+fn finish_login(conn: &mut Connection, user_id: Uuid) -> Result<User, EmailLoginError> {
+    let user = get_user_by_id(conn, user_id)?;
+
+    if !user.is_active() {
+        return Err(EmailLoginError::AccountDisabled);
+    }
+
+    if mfa::is_required(conn, &user, &config::get().mfa.required_roles)? {
+        return Err(EmailLoginError::MfaRequired);
+    }
+
+    Ok(user)
+}
+
+fn invalid_token(err: ActionTokenError) -> EmailLoginError {
+    match err {
+        ActionTokenError::InvalidToken => EmailLoginError::InvalidToken,
+        _ => err.into(),
+    }
+}
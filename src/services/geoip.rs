@@ -0,0 +1,134 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    net::{IpAddr, Ipv6Addr},
+    path::Path,
+    sync::LazyLock,
+};
+
+use serde::Deserialize;
+
+use crate::config;
+
+static DATABASE: LazyLock<Option<GeoIpDatabase>> = LazyLock::new(|| {
+    let path = config::get().geoip_database_path.as_ref()?;
+
+    match GeoIpDatabase::open(Path::new(path)) {
+        Ok(database) => Some(database),
+        // Sign ins go on without locations rather than failing
+        Err(e) => {
+            log::error!("Failed to load GeoIP database {}: {}", path, e);
+            None
+        }
+    }
+});
+
+/// Where an address is, as precisely as the database knows
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    /// ISO 3166-1 alpha-2, e.g. `ID`
+    pub country_code: String,
+    pub city: Option<String>,
+}
+
+/// A local copy of an IP geolocation database, such as GeoLite2, held in memory.
+///
+/// The file is a CSV with a `network,country_code,city` header and one row
+/// per network in CIDR notation, e.g. `203.0.113.0/24,ID,Depok`. The city may
+/// be empty. Networks must not overlap, as in the GeoLite2 blocks files, whose
+/// rows can be joined with the locations file to produce it.
+pub struct GeoIpDatabase {
+    // First and last address of each network, IPv4 ones mapped into IPv6,
+    // sorted by the first address
+    networks: Vec<(u128, u128, Location)>,
+}
+
+#[derive(Deserialize)]
+struct NetworkRecord {
+    network: String,
+    country_code: String,
+    city: Option<String>,
+}
+
+impl GeoIpDatabase {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    pub fn from_reader(reader: impl Read) -> io::Result<Self> {
+        let mut networks = Vec::new();
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+
+        for record in reader.deserialize::<NetworkRecord>() {
+            let record = record?;
+            let (first, last) = parse_network(&record.network).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid network: {}", record.network),
+                )
+            })?;
+
+            networks.push((
+                first,
+                last,
+                Location {
+                    country_code: record.country_code.to_ascii_uppercase(),
+                    city: record.city.filter(|city| !city.is_empty()),
+                },
+            ));
+        }
+
+        networks.sort_unstable_by_key(|(first, _, _)| *first);
+
+        Ok(GeoIpDatabase { networks })
+    }
+
+    pub fn locate(&self, ip_address: IpAddr) -> Option<&Location> {
+        let address = to_bits(ip_address);
+
+        // The last network starting at or before the address
+        let index = self
+            .networks
+            .partition_point(|(first, _, _)| *first <= address);
+        let (_, last, location) = self.networks.get(index.checked_sub(1)?)?;
+
+        (address <= *last).then_some(location)
+    }
+}
+
+/// Looks an address up in the configured database. Gives nothing when there
+/// is no database or it does not know the address.
+pub fn locate(ip_address: &str) -> Option<Location> {
+    let ip_address = ip_address.parse().ok()?;
+
+    DATABASE.as_ref()?.locate(ip_address).cloned()
+}
+
+fn parse_network(network: &str) -> Option<(u128, u128)> {
+    let (address, prefix) = network.split_once('/')?;
+    let address: IpAddr = address.parse().ok()?;
+    let prefix: u32 = prefix.parse().ok()?;
+
+    let prefix = match address {
+        IpAddr::V4(_) if prefix <= 32 => prefix + 96,
+        IpAddr::V6(_) if prefix <= 128 => prefix,
+        _ => return None,
+    };
+
+    let host_mask = u128::MAX.checked_shr(prefix).unwrap_or(0);
+    let first = to_bits(address) & !host_mask;
+
+    Some((first, first | host_mask))
+}
+
+fn to_bits(ip_address: IpAddr) -> u128 {
+    let ip_address: Ipv6Addr = match ip_address {
+        IpAddr::V4(ip_address) => ip_address.to_ipv6_mapped(),
+        IpAddr::V6(ip_address) => ip_address,
+    };
+
+    ip_address.to_bits()
+}
@@ -0,0 +1,100 @@
+use chrono::{Duration, Utc};
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::Connection,
+    errors::{impersonations::ImpersonationError, validation::ValidationErrors},
+    models::{
+        delegations::DelegationScope,
+        impersonations::{
+            Impersonation, ImpersonationFields, ImpersonationGrant, NewImpersonation,
+        },
+    },
+    repository::{
+        impersonations::{get_impersonations_for_user, insert_impersonation},
+        roles::get_permission_names_for_user,
+        users::get_user_by_id,
+    },
+    services::jwt::{generate_impersonation_token, Claims, TokenKeys},
+};
+
+/// Held by those who may impersonate, who cannot be impersonated themselves
+pub const USERS_IMPERSONATE: &str = "users:impersonate";
+
+/// Issues an administrator a read-only access token as `user_id`. It cannot
+/// be refreshed, and the impersonation is recorded for the user to see.
+pub fn impersonate(
+    conn: &mut Connection,
+    token_keys: &TokenKeys,
+    admin_claims: &Claims,
+    admin_id: Uuid,
+    user_id: Uuid,
+    fields: ImpersonationFields,
+) -> Result<ImpersonationGrant, ImpersonationError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    if user_id == admin_id {
+        return Err(ImpersonationError::SelfImpersonation);
+    }
+
+    match get_user_by_id(conn, user_id) {
+        Ok(_user) => {}
+        Err(DieselError::NotFound) => return Err(ImpersonationError::UserNotFound),
+        Err(err) => return Err(err.into()),
+    }
+
+    // Otherwise one administrator could borrow another's permissions
+    if get_permission_names_for_user(conn, user_id)?
+        .iter()
+        .any(|permission| permission == USERS_IMPERSONATE)
+    {
+        return Err(ImpersonationError::ProtectedUser);
+    }
+
+    let allowed: Vec<&str> = DelegationScope::ALL
+        .iter()
+        .filter(|scope| scope.is_read_only())
+        .map(|scope| scope.as_str())
+        .collect();
+    let scopes: Vec<&str> = match &fields.scope {
+        Some(requested) if !requested.trim().is_empty() => {
+            let requested: Vec<&str> = requested.split_whitespace().collect();
+
+            if let Some(scope) = requested.iter().find(|scope| !allowed.contains(scope)) {
+                return Err(ImpersonationError::InvalidScope(scope.to_string()));
+            }
+
+            requested
+        }
+        _ => allowed,
+    };
+
+    let token = generate_impersonation_token(token_keys, user_id, admin_claims, &scopes)?;
+
+    // The token is only handed out once the impersonation is on record
+    let impersonation = insert_impersonation(
+        conn,
+        NewImpersonation {
+            admin_id,
+            user_id,
+            reason: fields.reason,
+            scopes: token.scope.clone(),
+            expired_at: (Utc::now() + Duration::seconds(token.expires_in)).naive_utc(),
+        },
+    )?;
+
+    Ok(ImpersonationGrant {
+        impersonation,
+        token,
+    })
+}
+
+/// Every time an administrator acted as the user
+pub fn list_for_user(
+    conn: &mut Connection,
+    user_id: Uuid,
+) -> Result<Vec<Impersonation>, ImpersonationError> {
+    Ok(get_impersonations_for_user(conn, user_id)?)
+}
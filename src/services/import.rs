@@ -0,0 +1,133 @@
+use std::io::{BufRead, BufReader, Read};
+
+use diesel::{
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection as _,
+};
+use validator::ValidateEmail;
+
+use crate::{
+    db::Connection,
+    errors::import::ImportError,
+    models::{
+        import::{CsvImportRecord, ImportOutcome, ImportRecord, ImportSummary},
+        roles::NewUserRole,
+        users::InsertableUser,
+    },
+    repository::{
+        roles::{get_role_by_name, insert_user_role},
+        users::insert_new_user,
+    },
+    services::{passwords::HashScheme, users::normalize_email},
+};
+
+// Imported users without roles are patients
+const DEFAULT_ROLE: &str = "pacilian";
+
+/// Reads records from a CSV export with an `email,password_hash,roles` header.
+pub fn read_csv(reader: impl Read) -> impl Iterator<Item = Result<ImportRecord, ImportError>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .into_deserialize::<CsvImportRecord>()
+        .map(|record| {
+            record
+                .map(ImportRecord::from)
+                .map_err(|err| ImportError::UnreadableRecord(err.to_string()))
+        })
+}
+
+/// Reads records from a JSON Lines export, one object per line. Blank lines
+/// are skipped.
+pub fn read_jsonl(reader: impl Read) -> impl Iterator<Item = Result<ImportRecord, ImportError>> {
+    BufReader::new(reader)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| {
+            let line = line.map_err(|err| ImportError::UnreadableRecord(err.to_string()))?;
+
+            serde_json::from_str::<ImportRecord>(&line)
+                .map_err(|err| ImportError::UnreadableRecord(err.to_string()))
+        })
+}
+
+/// Imports every record, carrying on past the ones that fail.
+pub fn import_users(
+    conn: &mut Connection,
+    records: impl Iterator<Item = Result<ImportRecord, ImportError>>,
+) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+
+    for (index, record) in records.enumerate() {
+        match record.and_then(|record| import_user(conn, record)) {
+            Ok(ImportOutcome::Imported) => summary.imported += 1,
+            Ok(ImportOutcome::AlreadyExists) => summary.already_existing += 1,
+            Err(e) => summary.failed.push((index + 1, e.to_string())),
+        }
+    }
+
+    summary
+}
+
+/// Creates a user with a password hash from another system. The hash is kept
+/// as it is and replaced with an Argon2id hash on the user's first login.
+pub fn import_user(
+    conn: &mut Connection,
+    record: ImportRecord,
+) -> Result<ImportOutcome, ImportError> {
+    let email = record.email.trim().to_string();
+
+    let email_normalized = normalize_email(&email);
+
+    if !email_normalized.validate_email() {
+        return Err(ImportError::InvalidEmail);
+    }
+
+    HashScheme::detect(&record.password_hash)?;
+
+    let roles = if record.roles.is_empty() {
+        vec![DEFAULT_ROLE.to_string()]
+    } else {
+        record.roles
+    };
+
+    let new_user = InsertableUser {
+        email: Some(email),
+        email_normalized: Some(email_normalized),
+        password: Some(record.password_hash),
+        phone_number: None,
+    };
+
+    let result = conn.transaction(|conn| {
+        let user = insert_new_user(conn, new_user).map_err(|err| match err {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ImportError::EmailTaken
+            }
+            _ => ImportError::UserInsertionFailure(err),
+        })?;
+
+        for role_name in &roles {
+            let role = get_role_by_name(conn, role_name).map_err(|err| match err {
+                DieselError::NotFound => ImportError::UnknownRole(role_name.clone()),
+                _ => ImportError::UserInsertionFailure(err),
+            })?;
+
+            insert_user_role(
+                conn,
+                NewUserRole {
+                    user_id: user.id,
+                    role_id: role.id,
+                    granted_by: None,
+                },
+            )?;
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => Ok(ImportOutcome::Imported),
+        Err(ImportError::EmailTaken) => Ok(ImportOutcome::AlreadyExists),
+        Err(e) => Err(e),
+    }
+}
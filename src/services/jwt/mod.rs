@@ -1,27 +1,37 @@
-use std::{fs, io};
-
 use crate::{
+    config,
     db::Connection,
     errors::jwt::{JWTCreationError, JWTError, JWTValidationError},
-    models::{jwt::RefreshTokenDTO, users::User},
+    models::{audit::ClientInfo, jwt::RefreshTokenDTO, users::User},
     repository::{
         jwt::{create_refresh_token, get_refresh_token, revoke_refresh_token},
+        profiles::get_profile_by_user_id,
+        roles::{get_permission_names_for_user, get_role_names_for_user},
         users::get_user_by_id,
     },
+    services::{
+        audit::{self, AuditEntry},
+        users::check_active,
+    },
 };
 
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use rand::{distr::Alphanumeric, rng, Rng};
 use serde::{Deserialize, Serialize};
-use signing::{rs256::RS256Signer, TokenSigner};
+pub use signing::TokenKeys;
+use signing::{TokenSigner, TokenVerifier};
 use uuid::{self, Uuid};
 
-mod signing;
+pub mod signing;
+
+const ISSUER: &str = "Pandacare";
+const ACCESS_TOKEN_LIFETIME_SECONDS: i64 = 300;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisteredClaims {
     pub iss: String, // Issuer
-    // pub sub: String, // Subject
+    #[serde(default)]
+    pub sub: String, // Subject, the user the token is about
     // pub aud: String, // Audience
     pub exp: usize,  // Expiration time (as UTC timestamp)
     pub nbf: usize,  // Not before (as UTC timestamp)
@@ -30,11 +40,12 @@ pub struct RegisteredClaims {
 }
 
 impl RegisteredClaims {
-    pub fn new(iss: &str, seconds_to_expiration: i64) -> Self {
+    pub fn new(iss: &str, sub: &str, seconds_to_expiration: i64) -> Self {
         let now: DateTime<Utc> = Utc::now();
         let exp: DateTime<Utc> = now + Duration::seconds(seconds_to_expiration);
         RegisteredClaims {
             iss: iss.to_string(),
+            sub: sub.to_string(),
             exp: exp.timestamp() as usize,
             nbf: now.timestamp() as usize,
             iat: now.timestamp() as usize,
@@ -44,11 +55,85 @@ impl RegisteredClaims {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Claims {
+pub struct Claims {
     #[serde(flatten)]
     pub registered_claims: RegisteredClaims,
     pub user_id: String,
     pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// How the user proved who they are (RFC 8176), e.g. `["pwd"]` for a
+    /// password alone or `["pwd", "otp", "mfa"]` with a TOTP code
+    #[serde(default)]
+    pub amr: Vec<String>,
+    /// When the user signed in (as UTC timestamp). Refreshing keeps it, so
+    /// it tells how long ago the user last proved who they are.
+    #[serde(default)]
+    pub auth_time: usize,
+    /// How strong that sign in was, one of the levels in [`acr`]
+    #[serde(default)]
+    pub acr: String,
+    /// The delegate or administrator acting for the user in `sub`, on tokens
+    /// from [`generate_delegated_token`] and [`generate_impersonation_token`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// Space separated scopes a delegated token is limited to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Set when an administrator is acting as the user, so that services can
+    /// show it and refuse anything the user would not want done for them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub impersonated: bool,
+}
+
+/// The `act` claim (RFC 8693)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+}
+
+/// Authentication method references (RFC 8176) used in the `amr` claim
+pub mod amr {
+    pub const PASSWORD: &str = "pwd";
+    pub const OTP: &str = "otp";
+    pub const SMS: &str = "sms";
+    pub const MULTI_FACTOR: &str = "mfa";
+    pub const HARDWARE_KEY: &str = "hwk";
+}
+
+/// Authentication context classes used in the `acr` claim
+pub mod acr {
+    use super::amr;
+
+    /// One factor, such as a password or a code sent by email or SMS
+    pub const SINGLE_FACTOR: &str = "1";
+    /// Two factors, e.g. a password and a TOTP code, or a verified passkey
+    pub const MULTI_FACTOR: &str = "2";
+
+    // Weakest first
+    const LEVELS: [&str; 2] = [SINGLE_FACTOR, MULTI_FACTOR];
+
+    /// The level reached by signing in with the given methods
+    pub fn from_methods(methods: &[&str]) -> &'static str {
+        if methods.contains(&amr::MULTI_FACTOR) {
+            MULTI_FACTOR
+        } else {
+            SINGLE_FACTOR
+        }
+    }
+
+    /// Whether `acr` is at least as strong as `minimum`. Unknown values are
+    /// weaker than every level.
+    pub fn satisfies(acr: &str, minimum: &str) -> bool {
+        let rank = |value: &str| LEVELS.iter().position(|level| *level == value);
+
+        match (rank(acr), rank(minimum)) {
+            (Some(acr), Some(minimum)) => acr >= minimum,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -57,6 +142,18 @@ pub struct Jwt {
     pub refresh: String,
 }
 
+/// A token exchange response (RFC 8693). There is no refresh token, so the
+/// delegate exchanges again once it expires, and a revoked delegation stops
+/// them from getting new tokens.
+#[derive(Serialize)]
+pub struct ExchangedToken {
+    pub access_token: String,
+    pub issued_token_type: &'static str,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    pub scope: String,
+}
+
 #[derive(Deserialize)]
 pub struct RefreshInfo {
     pub refresh_token: String,
@@ -64,22 +161,55 @@ pub struct RefreshInfo {
 
 pub type RevocationInfo = RefreshInfo;
 
+/// Issues tokens for a user who just signed in with the given methods
 pub fn generate_jwt(
     conn: &mut Connection,
-    secret_key: String,
+    token_keys: &TokenKeys,
+    user: User,
+    methods: &[&str],
+) -> Result<Jwt, JWTCreationError> {
+    issue_jwt(conn, token_keys, user, methods, Utc::now().naive_utc())
+}
+
+fn issue_jwt(
+    conn: &mut Connection,
+    token_keys: &TokenKeys,
     user: User,
+    methods: &[&str],
+    auth_time: NaiveDateTime,
 ) -> Result<Jwt, JWTCreationError> {
-    let registered_claims = RegisteredClaims::new("Pandacare", 300);
+    let registered_claims =
+        RegisteredClaims::new(ISSUER, &user.id.to_string(), ACCESS_TOKEN_LIFETIME_SECONDS);
+
+    let name = if config::get().include_name_in_tokens {
+        get_profile_by_user_id(conn, user.id)
+            .map_err(|err| JWTCreationError::InvalidTokenData(err.to_string()))?
+            .and_then(|profile| profile.full_name)
+    } else {
+        None
+    };
+
+    let roles = get_role_names_for_user(conn, user.id)
+        .map_err(|err| JWTCreationError::InvalidTokenData(err.to_string()))?;
+
+    let permissions = get_permission_names_for_user(conn, user.id)
+        .map_err(|err| JWTCreationError::InvalidTokenData(err.to_string()))?;
 
     let claims = Claims {
         registered_claims,
         user_id: user.id.to_string(),
-        roles: vec![user.role.to_string()],
+        roles,
+        permissions,
+        name,
+        amr: methods.iter().map(|method| method.to_string()).collect(),
+        auth_time: auth_time.and_utc().timestamp() as usize,
+        acr: acr::from_methods(methods).to_string(),
+        act: None,
+        scope: None,
+        impersonated: false,
     };
 
-    let signer: RS256Signer = RS256Signer::new(&secret_key)?;
-
-    let access_token = signer
+    let access_token = token_keys
         .sign(claims)
         .map_err(|_err| JWTCreationError::TokenEncodingFailure)?;
 
@@ -89,8 +219,9 @@ pub fn generate_jwt(
         .map(char::from)
         .collect();
 
-    let refresh_token = create_refresh_token(conn, user, &random_str)
-        .map_err(|_err| JWTCreationError::RefreshTokenGenerationFailure)?;
+    let refresh_token =
+        create_refresh_token(conn, user, &random_str, &methods.join(" "), auth_time)
+            .map_err(|_err| JWTCreationError::RefreshTokenGenerationFailure)?;
 
     Ok(Jwt {
         access: access_token,
@@ -98,28 +229,146 @@ pub fn generate_jwt(
     })
 }
 
+/// Issues an access token to act for `subject_id`, for the delegate who
+/// authenticated with `actor_claims`. It carries no roles or permissions of
+/// its own, only the delegated scopes, and keeps the delegate's sign in
+/// details since that is who authenticated.
+pub fn generate_delegated_token(
+    token_keys: &TokenKeys,
+    subject_id: Uuid,
+    actor_claims: &Claims,
+    scopes: &[&str],
+) -> Result<ExchangedToken, JWTCreationError> {
+    issue_acting_token(token_keys, subject_id, actor_claims, scopes, false)
+}
+
+/// Like [`generate_delegated_token`], for an administrator troubleshooting
+/// the account of `user_id`. The token is flagged as `impersonated`.
+pub fn generate_impersonation_token(
+    token_keys: &TokenKeys,
+    user_id: Uuid,
+    admin_claims: &Claims,
+    scopes: &[&str],
+) -> Result<ExchangedToken, JWTCreationError> {
+    issue_acting_token(token_keys, user_id, admin_claims, scopes, true)
+}
+
+fn issue_acting_token(
+    token_keys: &TokenKeys,
+    subject_id: Uuid,
+    actor_claims: &Claims,
+    scopes: &[&str],
+    impersonated: bool,
+) -> Result<ExchangedToken, JWTCreationError> {
+    let scope = scopes.join(" ");
+
+    let claims = Claims {
+        registered_claims: RegisteredClaims::new(
+            ISSUER,
+            &subject_id.to_string(),
+            ACCESS_TOKEN_LIFETIME_SECONDS,
+        ),
+        user_id: subject_id.to_string(),
+        roles: Vec::new(),
+        permissions: Vec::new(),
+        name: None,
+        amr: actor_claims.amr.clone(),
+        auth_time: actor_claims.auth_time,
+        acr: actor_claims.acr.clone(),
+        act: Some(Actor {
+            sub: actor_claims.user_id.clone(),
+        }),
+        scope: Some(scope.clone()),
+        impersonated,
+    };
+
+    let access_token = token_keys
+        .sign(claims)
+        .map_err(|_err| JWTCreationError::TokenEncodingFailure)?;
+
+    Ok(ExchangedToken {
+        access_token,
+        issued_token_type: "urn:ietf:params:oauth:token-type:access_token",
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_LIFETIME_SECONDS,
+        scope,
+    })
+}
+
+pub fn decode_access_token(
+    token_keys: &TokenKeys,
+    token: &str,
+) -> Result<Claims, JWTValidationError> {
+    token_keys.verify::<Claims>(token)
+}
+
+/// Exchanges a refresh token for new tokens, revoking it. Attempts are
+/// audited, including those with a revoked token, which may have been stolen.
 pub fn refresh_token(
     conn: &mut Connection,
-    secret_key: String,
+    token_keys: &TokenKeys,
     token_str: &str,
+    client: &ClientInfo,
 ) -> Result<Jwt, JWTError> {
-    use crate::errors::users::UserValidationError;
+    let refresh_token = find_refresh_token(conn, token_str);
+    let user_id = refresh_token.as_ref().ok().map(|token| token.user_id);
+
+    let result = refresh_token.and_then(|token| rotate_refresh_token(conn, token_keys, &token));
+
+    audit::record(
+        conn,
+        client,
+        AuditEntry::from_result("token.refreshed", user_id, user_id, &result),
+    );
 
-    let refresh_token: Vec<RefreshTokenDTO> = get_refresh_token(conn, token_str)
+    result
+}
+
+/// Revokes a refresh token, e.g. when signing out
+pub fn revoke_token(
+    conn: &mut Connection,
+    token_str: &str,
+    client: &ClientInfo,
+) -> diesel::QueryResult<()> {
+    let user_id = find_refresh_token(conn, token_str)
+        .ok()
+        .map(|token| token.user_id);
+
+    let result = revoke_refresh_token(conn, token_str);
+
+    audit::record(
+        conn,
+        client,
+        AuditEntry::from_result("token.revoked", user_id, user_id, &result),
+    );
+
+    result
+}
+
+fn find_refresh_token(conn: &mut Connection, token_str: &str) -> Result<RefreshTokenDTO, JWTError> {
+    let mut refresh_tokens: Vec<RefreshTokenDTO> = get_refresh_token(conn, token_str)
         .map_err(|_err| JWTError::JWTValidation(JWTValidationError::TokenFetchingFailure))?;
 
-    let refresh_token: &RefreshTokenDTO = match refresh_token.as_slice() {
-        [only] => only,
-        [] => return Err(JWTError::JWTValidation(JWTValidationError::TokenNotFound)),
-        _ => return Err(JWTError::JWTValidation(JWTValidationError::DuplicateToken)),
-    };
+    match refresh_tokens.len() {
+        1 => Ok(refresh_tokens.remove(0)),
+        0 => Err(JWTError::JWTValidation(JWTValidationError::TokenNotFound)),
+        _ => Err(JWTError::JWTValidation(JWTValidationError::DuplicateToken)),
+    }
+}
 
-    let user_id = Uuid::parse_str(&refresh_token.user_id)
-        .map_err(|_err| JWTError::JWTValidation(JWTValidationError::TokenInvalid))?;
+fn rotate_refresh_token(
+    conn: &mut Connection,
+    token_keys: &TokenKeys,
+    refresh_token: &RefreshTokenDTO,
+) -> Result<Jwt, JWTError> {
+    use crate::errors::users::UserValidationError;
 
-    let user = get_user_by_id(conn, user_id)
+    let user = get_user_by_id(conn, refresh_token.user_id)
         .map_err(|_err| JWTError::UserValidation(UserValidationError::UserNotFound))?;
 
+    // Checked first, since changing the status also revokes the user's tokens
+    check_active(&user)?;
+
     if refresh_token.revoked {
         Err(JWTError::JWTValidation(JWTValidationError::TokenRevoked))
     } else if Utc::now().naive_utc() > refresh_token.expired_at {
@@ -128,7 +377,11 @@ pub fn refresh_token(
         revoke_refresh_token(conn, &refresh_token.token)
             .map_err(|_err| JWTError::JWTValidation(JWTValidationError::TokenNotFound))?;
 
-        let jwt = generate_jwt(conn, secret_key, user).map_err(JWTError::JWTCreation)?;
+        // Refreshing does not authenticate again, so the original sign in carries over
+        let methods: Vec<&str> = refresh_token.amr.split_whitespace().collect();
+
+        let jwt = issue_jwt(conn, token_keys, user, &methods, refresh_token.auth_time)
+            .map_err(JWTError::JWTCreation)?;
         Ok(jwt)
     }
 }
@@ -1,4 +1,8 @@
-use crate::errors::jwt::JWTCreationError;
+use serde::de::DeserializeOwned;
+
+use crate::errors::jwt::{JWTCreationError, JWTValidationError};
+
+use rs256::{RS256Signer, RS256Verifier};
 
 pub mod es256;
 pub mod hs256;
@@ -7,3 +11,36 @@ pub mod rs256;
 pub trait TokenSigner {
     fn sign(&self, claims: impl serde::Serialize) -> Result<String, JWTCreationError>;
 }
+
+pub trait TokenVerifier {
+    fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, JWTValidationError>;
+}
+
+/// Signs and verifies access tokens. Parsing the key is expensive, so this is
+/// built once at startup and shared.
+pub struct TokenKeys {
+    signer: RS256Signer,
+    verifier: RS256Verifier,
+}
+
+impl TokenKeys {
+    pub fn new(secret_key: &str) -> Result<Self, JWTCreationError> {
+        Ok(Self {
+            signer: RS256Signer::new(secret_key)?,
+            verifier: RS256Verifier::new(secret_key)
+                .map_err(|_err| JWTCreationError::InvalidPrivateKey)?,
+        })
+    }
+}
+
+impl TokenSigner for TokenKeys {
+    fn sign(&self, claims: impl serde::Serialize) -> Result<String, JWTCreationError> {
+        self.signer.sign(claims)
+    }
+}
+
+impl TokenVerifier for TokenKeys {
+    fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, JWTValidationError> {
+        self.verifier.verify(token)
+    }
+}
@@ -1,10 +1,18 @@
 use std::fs;
 
-use jsonwebtoken::{encode, jwk::JwkSet, EncodingKey, Header};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    crypto, decode, encode, errors::ErrorKind, jwk::JwkSet, Algorithm, DecodingKey, EncodingKey,
+    Header, Validation,
+};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey, pkcs8::DecodePrivateKey, traits::PublicKeyParts, RsaPrivateKey,
+};
+use serde::de::DeserializeOwned;
 
-use crate::errors::jwt::JWTCreationError;
+use crate::errors::jwt::{JWTCreationError, JWTValidationError};
 
-use super::TokenSigner;
+use super::{TokenSigner, TokenVerifier};
 
 pub struct RS256Signer {
     secret_key: EncodingKey,
@@ -16,6 +24,13 @@ impl RS256Signer {
             .map_err(|_err| JWTCreationError::InvalidPrivateKey)?;
         Ok(Self { secret_key })
     }
+
+    /// Signs arbitrary bytes rather than claims, returning the signature in
+    /// base64url like the last part of a JWT
+    pub fn sign_message(&self, message: &[u8]) -> Result<String, JWTCreationError> {
+        crypto::sign(message, &self.secret_key, Algorithm::RS256)
+            .map_err(|_err| JWTCreationError::TokenEncodingFailure)
+    }
 }
 
 impl TokenSigner for RS256Signer {
@@ -52,3 +67,43 @@ impl TokenSigner for RS256Signer {
         .map_err(|_err| JWTCreationError::TokenEncodingFailure)
     }
 }
+
+pub struct RS256Verifier {
+    public_key: DecodingKey,
+}
+
+impl RS256Verifier {
+    // The public half is derived from the signing key so the two can never drift apart
+    pub fn new(secret_key: &str) -> Result<Self, JWTValidationError> {
+        let private_key = RsaPrivateKey::from_pkcs8_pem(secret_key)
+            .or_else(|_err| RsaPrivateKey::from_pkcs1_pem(secret_key))
+            .map_err(|_err| JWTValidationError::InvalidVerificationKey)?;
+
+        let modulus = URL_SAFE_NO_PAD.encode(private_key.n().to_bytes_be());
+        let exponent = URL_SAFE_NO_PAD.encode(private_key.e().to_bytes_be());
+
+        let public_key = DecodingKey::from_rsa_components(&modulus, &exponent)
+            .map_err(|_err| JWTValidationError::InvalidVerificationKey)?;
+
+        Ok(Self { public_key })
+    }
+
+    /// Checks a signature made by [`RS256Signer::sign_message`]
+    pub fn verify_message(&self, message: &[u8], signature: &str) -> bool {
+        crypto::verify(signature, message, &self.public_key, Algorithm::RS256).unwrap_or(false)
+    }
+}
+
+impl TokenVerifier for RS256Verifier {
+    fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, JWTValidationError> {
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_issuer(&["Pandacare"]);
+
+        decode::<T>(token, &self.public_key, &validation)
+            .map(|token_data| token_data.claims)
+            .map_err(|err| match err.kind() {
+                ErrorKind::ExpiredSignature => JWTValidationError::TokenExpired,
+                _ => JWTValidationError::TokenInvalid,
+            })
+    }
+}
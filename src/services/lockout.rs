@@ -0,0 +1,129 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::Connection as _;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::lockout::LockoutError,
+    models::lockout::LoginFailure,
+    repository::lockout::{
+        delete_login_failure, get_login_failure, get_login_failure_for_update, upsert_login_failure,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockoutScope {
+    Account,
+    Ip,
+    /// Second factor codes of an account. Kept apart from password failures,
+    /// which a correct password clears, so that knowing the password does not
+    /// allow unlimited guesses at the code.
+    Mfa,
+    /// Codes of passwordless sign in by email
+    EmailCode,
+}
+
+impl LockoutScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Ip => "ip",
+            Self::Mfa => "mfa",
+            Self::EmailCode => "email_code",
+        }
+    }
+
+    fn lockout_threshold(&self) -> i32 {
+        let lockout = &config::get().lockout;
+
+        match self {
+            Self::Account | Self::Mfa | Self::EmailCode => lockout.account_lockout_threshold,
+            Self::Ip => lockout.ip_lockout_threshold,
+        }
+    }
+}
+
+pub struct FailureOutcome {
+    /// Set when this failure is the one that locked the subject out
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+/// Whether attempts for this subject must currently be refused
+pub fn is_blocked(
+    conn: &mut Connection,
+    scope: LockoutScope,
+    subject: &str,
+) -> Result<bool, LockoutError> {
+    let failure = get_login_failure(conn, scope.as_str(), subject)?;
+    let now = Utc::now().naive_utc();
+
+    Ok(failure
+        .and_then(|failure| failure.blocked_until)
+        .is_some_and(|blocked_until| blocked_until > now))
+}
+
+pub fn record_failure(
+    conn: &mut Connection,
+    scope: LockoutScope,
+    subject: &str,
+) -> Result<FailureOutcome, LockoutError> {
+    let lockout = &config::get().lockout;
+    let now = Utc::now().naive_utc();
+    let window_start = now - Duration::minutes(lockout.failure_window_minutes);
+
+    conn.transaction(|conn| {
+        let previous = get_login_failure_for_update(conn, scope.as_str(), subject)?;
+
+        let failure_count = match previous {
+            Some(failure) if failure.last_failed_at > window_start => failure.failure_count + 1,
+            _ => 1,
+        };
+
+        let blocked_until = block_duration(scope, failure_count).map(|duration| now + duration);
+
+        upsert_login_failure(
+            conn,
+            LoginFailure {
+                scope: scope.as_str().to_string(),
+                subject: subject.to_string(),
+                failure_count,
+                last_failed_at: now,
+                blocked_until,
+            },
+        )?;
+
+        Ok(FailureOutcome {
+            locked_until: blocked_until.filter(|_| failure_count == scope.lockout_threshold()),
+        })
+    })
+}
+
+pub fn clear_failures(
+    conn: &mut Connection,
+    scope: LockoutScope,
+    subject: &str,
+) -> Result<(), LockoutError> {
+    delete_login_failure(conn, scope.as_str(), subject)?;
+
+    Ok(())
+}
+
+// Past the backoff threshold each failure doubles the wait before the next
+// attempt, until the lockout threshold blocks the subject for the full period
+fn block_duration(scope: LockoutScope, failure_count: i32) -> Option<Duration> {
+    let lockout = &config::get().lockout;
+    let lockout_duration = Duration::minutes(lockout.lockout_minutes);
+
+    if failure_count >= scope.lockout_threshold() {
+        return Some(lockout_duration);
+    }
+
+    if failure_count <= lockout.backoff_threshold {
+        return None;
+    }
+
+    let exponent = (failure_count - lockout.backoff_threshold - 1).min(30) as u32;
+    let delay = Duration::seconds(lockout.backoff_base_seconds.saturating_mul(1 << exponent));
+
+    Some(delay.min(lockout_duration))
+}
@@ -0,0 +1,166 @@
+use chrono::Duration;
+use diesel::Connection as _;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{logins::LoginHistoryError, validation::ValidationErrors},
+    models::{
+        audit::ClientInfo,
+        logins::{Login, LoginDisownFields, NewLogin},
+        users::User,
+    },
+    repository::{
+        jwt::revoke_all_refresh_tokens_for_user,
+        logins::{
+            get_logins_for_user, has_login_from_device, has_login_from_location, has_logins,
+            insert_login,
+        },
+    },
+    services::{
+        action_tokens::{issue_action_token, redeem_action_token, ActionTokenPurpose},
+        geoip,
+        mail::{MailMessage, MailSender},
+    },
+};
+
+// Sign ins shown to the user
+const HISTORY_LIMIT: i64 = 100;
+
+/// Adds a successful sign in to the user's history, emailing them when it
+/// came from a device or place their account was never used from before.
+///
+/// Like the audit trail, failing to record must not fail the sign in, so
+/// errors are only logged.
+pub fn record_login(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    user: &User,
+    client: &ClientInfo,
+) {
+    if let Err(e) = try_record_login(conn, mailer, user, client) {
+        log::error!("Failed to record sign in of user {}: {}", user.id, e);
+    }
+}
+
+pub fn list_logins(conn: &mut Connection, user_id: Uuid) -> Result<Vec<Login>, LoginHistoryError> {
+    Ok(get_logins_for_user(conn, user_id, HISTORY_LIMIT)?)
+}
+
+/// Signs the user out of every session with the token from a new sign in
+/// email, returning who they are and how many sessions ended.
+pub fn disown_login(
+    conn: &mut Connection,
+    fields: LoginDisownFields,
+) -> Result<(Uuid, usize), LoginHistoryError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    conn.transaction(|conn| {
+        let user_id = redeem_action_token(conn, &fields.token, ActionTokenPurpose::LoginDisowned)?;
+        let revoked = revoke_all_refresh_tokens_for_user(conn, user_id)?;
+
+        Ok((user_id, revoked))
+    })
+}
+
+fn try_record_login(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    user: &User,
+    client: &ClientInfo,
+) -> Result<(), LoginHistoryError> {
+    let location = client.ip_address.as_deref().and_then(geoip::locate);
+
+    // Nothing is new about the very first sign in
+    let known_user = has_logins(conn, user.id)?;
+
+    let new_device = match &client.user_agent {
+        Some(user_agent) if known_user => !has_login_from_device(conn, user.id, user_agent)?,
+        _ => false,
+    };
+
+    let new_location = match &location {
+        Some(location) if known_user => !has_login_from_location(
+            conn,
+            user.id,
+            &location.country_code,
+            location.city.as_deref(),
+        )?,
+        _ => false,
+    };
+
+    let login = insert_login(
+        conn,
+        NewLogin {
+            user_id: user.id,
+            ip_address: client.ip_address.clone(),
+            user_agent: client.user_agent.clone(),
+            country_code: location
+                .as_ref()
+                .map(|location| location.country_code.clone()),
+            city: location.and_then(|location| location.city),
+            new_device,
+            new_location,
+        },
+    )?;
+
+    if let (true, Some(email)) = (new_device || new_location, &user.email) {
+        alert(conn, mailer, email, &login)?;
+    }
+
+    Ok(())
+}
+
+fn alert(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    email: &str,
+    login: &Login,
+) -> Result<(), LoginHistoryError> {
+    let valid_for = Duration::hours(config::get().login_alert_ttl_hours);
+    let token = issue_action_token(
+        conn,
+        login.user_id,
+        ActionTokenPurpose::LoginDisowned,
+        valid_for,
+    )?;
+
+    let location = match (&login.city, &login.country_code) {
+        (Some(city), Some(country_code)) => format!("{}, {}", city, country_code),
+        (None, Some(country_code)) => country_code.clone(),
+        _ => "Unknown".to_string(),
+    };
+
+    let message = MailMessage {
+        to: email.to_string(),
+        subject: "New sign in to your PandaCare account".to_string(),
+        body: format!(
+            "Your PandaCare account was signed in to from a {} we have not seen before.\n\n\
+             Time: {} UTC\nDevice: {}\nLocation: {}\nIP address: {}\n\n\
+             If this was you, you can ignore this email. If it wasn't, sign out of every \
+             session with the link below and then change your password:\n\n\
+             {}/this-wasnt-me?token={}\n\nThe link expires in {} hours.",
+            if login.new_device {
+                "device"
+            } else {
+                "location"
+            },
+            login.logged_in_at.format("%Y-%m-%d %H:%M"),
+            login.user_agent.as_deref().unwrap_or("Unknown"),
+            location,
+            login.ip_address.as_deref().unwrap_or("Unknown"),
+            config::get().frontend_url,
+            token,
+            valid_for.num_hours()
+        ),
+    };
+
+    // Best effort: the sign in stands even if the email does not go out
+    if let Err(e) = mailer.send(message) {
+        log::error!("Failed to send new sign in notification: {}", e);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf, sync::Mutex};
+
+use crate::errors::mail::MailError;
+
+#[derive(Clone, Debug)]
+pub struct MailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers transactional email. Handlers receive it as `web::Data<dyn MailSender>`
+/// so deployments and tests can plug in their own transport.
+pub trait MailSender: Send + Sync {
+    fn send(&self, message: MailMessage) -> Result<(), MailError>;
+}
+
+/// Notes outgoing mail in the application log instead of delivering it.
+/// Bodies carry sign in links, codes and reset tokens, so only the recipient
+/// and subject are logged; use [`FileMailSender`] to read them locally.
+pub struct LogMailSender;
+
+impl MailSender for LogMailSender {
+    fn send(&self, message: MailMessage) -> Result<(), MailError> {
+        log::info!(
+            "Email to {} with subject {:?} was not delivered",
+            message.to,
+            message.subject
+        );
+
+        Ok(())
+    }
+}
+
+/// Appends outgoing mail to a local mbox-style file so it can be inspected
+/// without a mail server.
+pub struct FileMailSender {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileMailSender {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl MailSender for FileMailSender {
+    fn send(&self, message: MailMessage) -> Result<(), MailError> {
+        let _guard = self
+            .lock
+            .lock()
+            .map_err(|err| MailError::DeliveryFailure(err.to_string()))?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| MailError::DeliveryFailure(err.to_string()))?;
+
+        writeln!(
+            file,
+            "To: {}\nSubject: {}\n\n{}\n",
+            message.to, message.subject, message.body
+        )
+        .map_err(|err| MailError::DeliveryFailure(err.to_string()))
+    }
+}
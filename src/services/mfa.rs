@@ -0,0 +1,538 @@
+use chrono::{Duration, Utc};
+use diesel::Connection as _;
+use rand::{rng, Rng, RngCore};
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{
+        crypto::CryptoError, mfa::MfaError, passwords::PasswordError, users::UserValidationError,
+        validation::ValidationErrors,
+    },
+    models::{
+        mfa::{
+            MfaChallenge, MfaTokenFields, MfaVerification, MfaVerificationFields, NewRecoveryCode,
+            NewTotpCredential, RecoveryCodeRegenerationFields, RecoveryCodeStatus, RecoveryCodes,
+            StepUp, StepUpFields, TotpCodeFields, TotpCredential, TotpEnrollment,
+        },
+        users::User,
+    },
+    repository::{
+        mfa::{
+            count_unused_recovery_codes, delete_recovery_codes, get_totp_credential,
+            get_totp_credential_for_update, get_unused_recovery_codes, insert_recovery_codes,
+            upsert_pending_totp_credential, use_recovery_code, use_totp_step,
+        },
+        roles::get_role_names_for_user,
+        users::get_user_by_id,
+    },
+    services::{
+        action_tokens::{
+            find_action_token, issue_action_token, redeem_action_token, ActionTokenPurpose,
+        },
+        crypto::DataKey,
+        jwt::amr,
+        lockout::{self, LockoutScope},
+        mail::{MailMessage, MailSender},
+        passwords::{hash_random_secret, verify_password},
+        users::check_active,
+    },
+};
+
+// RFC 6238 defaults, which is what authenticator apps expect
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECONDS: u64 = 30;
+// Codes from one step either side are accepted to allow for clock drift
+const TOTP_SKEW_STEPS: u64 = 1;
+const TOTP_SECRET_BYTES: usize = 20;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+// Lowercase letters and digits, leaving out look-alikes such as `l` and `1`
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Decides whether a user who gave the right password still needs a second
+/// factor. Returns a challenge to send back instead of tokens, or `None` if
+/// the password is enough.
+pub fn start_login(
+    conn: &mut Connection,
+    user: &User,
+    required_roles: &[String],
+) -> Result<Option<MfaChallenge>, MfaError> {
+    let Some(purpose) = challenge_purpose(conn, user, required_roles)? else {
+        return Ok(None);
+    };
+
+    let valid_for = Duration::minutes(config::get().mfa.challenge_ttl_minutes);
+    let mfa_token = issue_action_token(conn, user.id, purpose, valid_for)?;
+
+    Ok(Some(MfaChallenge {
+        mfa_required: true,
+        enrollment_required: purpose == ActionTokenPurpose::MfaEnrollment,
+        mfa_token,
+    }))
+}
+
+/// Whether the user has to give a second factor to sign in, either because
+/// they set one up or because one of their roles requires it.
+pub fn is_required(
+    conn: &mut Connection,
+    user: &User,
+    required_roles: &[String],
+) -> Result<bool, MfaError> {
+    Ok(challenge_purpose(conn, user, required_roles)?.is_some())
+}
+
+/// Creates a new TOTP secret for the user. It only takes effect once
+/// confirmed with a code from the authenticator; until then, starting over
+/// replaces it.
+pub fn begin_enrollment(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    user_id: Uuid,
+) -> Result<TotpEnrollment, MfaError> {
+    let user = get_user_by_id(conn, user_id)?;
+
+    create_pending_secret(conn, data_key, &user)
+}
+
+/// Turns TOTP on for a signed in user with the first code from their
+/// authenticator, and returns their recovery codes.
+pub fn confirm_enrollment(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    user_id: Uuid,
+    fields: TotpCodeFields,
+) -> Result<RecoveryCodes, MfaError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let user = get_user_by_id(conn, user_id)?;
+    let credential = get_totp_credential(conn, user_id)?.ok_or(MfaError::NotEnrolled)?;
+
+    if credential.confirmed_at.is_some() {
+        return Err(MfaError::AlreadyEnrolled);
+    }
+
+    check_code(conn, data_key, &user, &credential, &fields.code)?;
+
+    Ok(RecoveryCodes {
+        recovery_codes: replace_recovery_codes(conn, user_id)?,
+    })
+}
+
+/// Starts TOTP setup for a user whose sign in is waiting on it, using the
+/// token from their `obtain` response instead of an access token.
+pub fn begin_challenge_enrollment(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    fields: MfaTokenFields,
+) -> Result<TotpEnrollment, MfaError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let user_id = find_action_token(conn, &fields.mfa_token, ActionTokenPurpose::MfaEnrollment)
+        .map_err(|_err| MfaError::InvalidChallenge)?;
+    let user = get_user_by_id(conn, user_id)?;
+
+    create_pending_secret(conn, data_key, &user)
+}
+
+/// Finishes a sign in with a TOTP code or a recovery code. For a user
+/// setting up TOTP while signing in, the code also confirms their new
+/// authenticator and recovery codes are generated for them.
+///
+/// A wrong code leaves the challenge usable, but counts towards a lockout of
+/// the account's second factor.
+pub fn verify_challenge(
+    conn: &mut Connection,
+    mailer: &dyn MailSender,
+    data_key: &DataKey,
+    fields: MfaVerificationFields,
+) -> Result<MfaVerification, MfaError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let (user_id, purpose) = [
+        ActionTokenPurpose::MfaChallenge,
+        ActionTokenPurpose::MfaEnrollment,
+    ]
+    .into_iter()
+    .find_map(|purpose| {
+        find_action_token(conn, &fields.mfa_token, purpose)
+            .ok()
+            .map(|user_id| (user_id, purpose))
+    })
+    .ok_or(MfaError::InvalidChallenge)?;
+
+    let user = get_user_by_id(conn, user_id)?;
+
+    // The account may have been blocked since the password was checked
+    check_account_active(&user)?;
+
+    let credential = get_totp_credential(conn, user_id)?.ok_or(MfaError::NotEnrolled)?;
+
+    if purpose == ActionTokenPurpose::MfaChallenge && credential.confirmed_at.is_none() {
+        return Err(MfaError::InvalidChallenge);
+    }
+
+    let mut verification = MfaVerification {
+        user,
+        recovery_codes: None,
+        recovery_codes_remaining: None,
+    };
+
+    match (&fields.code, &fields.recovery_code) {
+        (Some(code), None) => {
+            check_code(conn, data_key, &verification.user, &credential, code)?;
+        }
+        (None, Some(recovery_code)) if purpose == ActionTokenPurpose::MfaChallenge => {
+            let remaining = check_recovery_code(conn, &verification.user, recovery_code)?;
+            notify_recovery_code_used(mailer, &verification.user, remaining);
+            verification.recovery_codes_remaining = Some(remaining);
+        }
+        (None, Some(_recovery_code)) => return Err(MfaError::InvalidCode),
+        _ => {
+            return Err(ValidationErrors::single(
+                "code",
+                "either a code or a recovery code is required",
+            )
+            .into())
+        }
+    }
+
+    redeem_action_token(conn, &fields.mfa_token, purpose)
+        .map_err(|_err| MfaError::InvalidChallenge)?;
+
+    if purpose == ActionTokenPurpose::MfaEnrollment {
+        verification.recovery_codes = Some(replace_recovery_codes(conn, user_id)?);
+    }
+
+    Ok(verification)
+}
+
+/// How many unused recovery codes the user has left
+pub fn recovery_code_status(
+    conn: &mut Connection,
+    user_id: Uuid,
+) -> Result<RecoveryCodeStatus, MfaError> {
+    Ok(RecoveryCodeStatus {
+        remaining: count_unused_recovery_codes(conn, user_id)?,
+    })
+}
+
+/// Replaces all of the user's recovery codes with new ones. Needs the
+/// password again, since anyone holding the codes can get past TOTP.
+pub fn regenerate_recovery_codes(
+    conn: &mut Connection,
+    user_id: Uuid,
+    fields: RecoveryCodeRegenerationFields,
+) -> Result<RecoveryCodes, MfaError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let user = get_user_by_id(conn, user_id)?;
+
+    let password_matches = match &user.password {
+        Some(password_hash) => verify_password(&fields.password, password_hash)?,
+        None => false,
+    };
+
+    if !password_matches {
+        return Err(ValidationErrors::single("password", "is incorrect").into());
+    }
+
+    let enrolled = get_totp_credential(conn, user_id)?
+        .is_some_and(|credential| credential.confirmed_at.is_some());
+
+    if !enrolled {
+        return Err(MfaError::NotEnrolled);
+    }
+
+    Ok(RecoveryCodes {
+        recovery_codes: replace_recovery_codes(conn, user_id)?,
+    })
+}
+
+/// Has a signed in user prove who they are again, for tokens with a fresh
+/// `auth_time`. Takes the password, plus a TOTP code from users who need a
+/// second factor to sign in, which also raises the tokens' `acr`.
+///
+/// Wrong passwords count towards the account lockout of password sign in.
+pub fn step_up(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    user_id: Uuid,
+    fields: StepUpFields,
+    required_roles: &[String],
+) -> Result<StepUp, MfaError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let user = get_user_by_id(conn, user_id)?;
+
+    check_account_active(&user)?;
+
+    let account = user.id.to_string();
+
+    if lockout::is_blocked(conn, LockoutScope::Account, &account)? {
+        return Err(MfaError::TooManyAttempts);
+    }
+
+    let password_matches = match &user.password {
+        Some(password_hash) => verify_password(&fields.password, password_hash)?,
+        None => false,
+    };
+
+    if !password_matches {
+        lockout::record_failure(conn, LockoutScope::Account, &account)?;
+        return Err(MfaError::IncorrectPassword);
+    }
+
+    lockout::clear_failures(conn, LockoutScope::Account, &account)?;
+
+    if !is_required(conn, &user, required_roles)? {
+        return Ok(StepUp {
+            user,
+            methods: vec![amr::PASSWORD],
+        });
+    }
+
+    let credential = get_totp_credential(conn, user.id)?
+        .filter(|credential| credential.confirmed_at.is_some())
+        .ok_or(MfaError::NotEnrolled)?;
+
+    let Some(code) = &fields.code else {
+        return Err(ValidationErrors::single(
+            "code",
+            "is required for accounts with two-factor authentication",
+        )
+        .into());
+    };
+
+    check_code(conn, data_key, &user, &credential, code)?;
+
+    Ok(StepUp {
+        user,
+        methods: vec![amr::PASSWORD, amr::OTP, amr::MULTI_FACTOR],
+    })
+}
+
+fn create_pending_secret(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    user: &User,
+) -> Result<TotpEnrollment, MfaError> {
+    let mut secret = vec![0u8; TOTP_SECRET_BYTES];
+    rng().fill_bytes(&mut secret);
+
+    let totp = build_totp(secret, user.login_name());
+    let secret_base32 = totp.get_secret_base32();
+    let secret_encrypted = data_key.encrypt(&secret_base32)?;
+
+    conn.transaction(|conn| {
+        let existing = get_totp_credential_for_update(conn, user.id)?;
+
+        if existing.is_some_and(|credential| credential.confirmed_at.is_some()) {
+            return Err(MfaError::AlreadyEnrolled);
+        }
+
+        upsert_pending_totp_credential(
+            conn,
+            NewTotpCredential {
+                user_id: user.id,
+                secret_encrypted,
+            },
+        )?;
+
+        Ok(())
+    })?;
+
+    Ok(TotpEnrollment {
+        secret: secret_base32,
+        otpauth_uri: totp.get_url(),
+    })
+}
+
+// Accepts a code once, within the allowed clock drift, and tracks failures
+fn check_code(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    user: &User,
+    credential: &TotpCredential,
+    code: &str,
+) -> Result<(), MfaError> {
+    let secret_base32 = data_key.decrypt(&credential.secret_encrypted)?;
+    let secret = Secret::Encoded(secret_base32)
+        .to_bytes()
+        .map_err(|_err| CryptoError::DecryptionFailure)?;
+    let totp = build_totp(secret, user.login_name());
+
+    let current_step = Utc::now().timestamp().max(0) as u64 / TOTP_STEP_SECONDS;
+    let matched_step = (current_step.saturating_sub(TOTP_SKEW_STEPS)
+        ..=current_step + TOTP_SKEW_STEPS)
+        .find(|step| {
+            let expected = totp.generate(step * TOTP_STEP_SECONDS);
+            bool::from(expected.as_bytes().ct_eq(code.as_bytes()))
+        });
+
+    track_attempt(conn, user.id, |conn| {
+        // A code that was already used counts as wrong, so it cannot be replayed
+        match matched_step {
+            Some(step) => Ok(use_totp_step(conn, user.id, step as i64)? > 0),
+            None => Ok(false),
+        }
+    })
+}
+
+// Uses up a matching recovery code and returns how many are left
+fn check_recovery_code(
+    conn: &mut Connection,
+    user: &User,
+    recovery_code: &str,
+) -> Result<i64, MfaError> {
+    let normalized = normalize_recovery_code(recovery_code);
+
+    track_attempt(conn, user.id, |conn| {
+        for code in get_unused_recovery_codes(conn, user.id)? {
+            if verify_password(&normalized, &code.code_hash)? {
+                return Ok(use_recovery_code(conn, code.id)? > 0);
+            }
+        }
+
+        Ok(false)
+    })?;
+
+    Ok(count_unused_recovery_codes(conn, user.id)?)
+}
+
+fn challenge_purpose(
+    conn: &mut Connection,
+    user: &User,
+    required_roles: &[String],
+) -> Result<Option<ActionTokenPurpose>, MfaError> {
+    let enrolled = get_totp_credential(conn, user.id)?
+        .is_some_and(|credential| credential.confirmed_at.is_some());
+
+    if enrolled {
+        return Ok(Some(ActionTokenPurpose::MfaChallenge));
+    }
+
+    let roles = get_role_names_for_user(conn, user.id)?;
+
+    if roles.iter().any(|role| required_roles.contains(role)) {
+        Ok(Some(ActionTokenPurpose::MfaEnrollment))
+    } else {
+        Ok(None)
+    }
+}
+
+// Says why an account cannot sign in, like password sign in does
+fn check_account_active(user: &User) -> Result<(), MfaError> {
+    check_active(user).map_err(|err| match err {
+        UserValidationError::AccountSuspended => MfaError::AccountSuspended,
+        UserValidationError::AccountDeleted => MfaError::AccountDeleted,
+        _ => MfaError::AccountDisabled,
+    })
+}
+
+// Refuses attempts while the second factor is locked, and counts failures
+// towards that lock
+fn track_attempt(
+    conn: &mut Connection,
+    user_id: Uuid,
+    attempt: impl FnOnce(&mut Connection) -> Result<bool, MfaError>,
+) -> Result<(), MfaError> {
+    let subject = user_id.to_string();
+
+    if lockout::is_blocked(conn, LockoutScope::Mfa, &subject)? {
+        return Err(MfaError::TooManyAttempts);
+    }
+
+    if !attempt(conn)? {
+        lockout::record_failure(conn, LockoutScope::Mfa, &subject)?;
+        return Err(MfaError::InvalidCode);
+    }
+
+    lockout::clear_failures(conn, LockoutScope::Mfa, &subject)?;
+
+    Ok(())
+}
+
+// Returns the new codes in plain text; only their hashes are kept
+fn replace_recovery_codes(conn: &mut Connection, user_id: Uuid) -> Result<Vec<String>, MfaError> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| new_recovery_code())
+        .collect();
+
+    let new_codes = codes
+        .iter()
+        .map(|code| {
+            Ok(NewRecoveryCode {
+                user_id,
+                code_hash: hash_random_secret(&normalize_recovery_code(code))?,
+            })
+        })
+        .collect::<Result<Vec<_>, PasswordError>>()?;
+
+    conn.transaction(|conn| {
+        delete_recovery_codes(conn, user_id)?;
+        insert_recovery_codes(conn, new_codes)
+    })?;
+
+    Ok(codes)
+}
+
+// Two groups of five, e.g. `k7m2p-xq9rt`
+fn new_recovery_code() -> String {
+    let mut rng = rng();
+    let characters: String = (0..RECOVERY_CODE_LENGTH)
+        .map(|_| RECOVERY_CODE_ALPHABET[rng.random_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+        .collect();
+
+    format!(
+        "{}-{}",
+        &characters[..RECOVERY_CODE_LENGTH / 2],
+        &characters[RECOVERY_CODE_LENGTH / 2..]
+    )
+}
+
+// Codes are accepted with or without the dash, in any case
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|character| character.to_ascii_lowercase())
+        .collect()
+}
+
+fn notify_recovery_code_used(mailer: &dyn MailSender, user: &User, remaining: i64) {
+    let Some(email) = user.email.clone() else {
+        return;
+    };
+
+    let message = MailMessage {
+        to: email,
+        subject: "A recovery code was used to sign in to PandaCare".to_string(),
+        body: format!(
+            "One of your recovery codes was just used to sign in to your PandaCare account. \
+             You have {} recovery codes left.\n\n\
+             If this wasn't you, change your password and generate new recovery codes right away.",
+            remaining
+        ),
+    };
+
+    if let Err(e) = mailer.send(message) {
+        log::error!("Failed to send recovery code notification: {}", e);
+    }
+}
+
+fn build_totp(secret: Vec<u8>, account_name: &str) -> TOTP {
+    TOTP::new_unchecked(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        TOTP_SKEW_STEPS as u8,
+        TOTP_STEP_SECONDS,
+        secret,
+        Some(config::get().mfa.totp_issuer.clone()),
+        account_name.to_string(),
+    )
+}
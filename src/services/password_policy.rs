@@ -0,0 +1,338 @@
+use std::path::Path;
+
+use crate::{config, errors::validation::ValidationErrors, services::breached_passwords};
+
+// Ranked by how common they are as passwords, most common first
+const COMMON_WORDS: &[&str] = &[
+    "password",
+    "123456",
+    "qwerty",
+    "iloveyou",
+    "admin",
+    "welcome",
+    "letmein",
+    "monkey",
+    "dragon",
+    "sunshine",
+    "princess",
+    "football",
+    "baseball",
+    "master",
+    "shadow",
+    "superman",
+    "batman",
+    "trustno",
+    "starwars",
+    "freedom",
+    "whatever",
+    "hello",
+    "login",
+    "secret",
+    "love",
+    "charlie",
+    "michael",
+    "jennifer",
+    "jessica",
+    "michelle",
+    "daniel",
+    "andrew",
+    "joshua",
+    "thomas",
+    "robert",
+    "jordan",
+    "hunter",
+    "ranger",
+    "soccer",
+    "hockey",
+    "killer",
+    "pepper",
+    "cheese",
+    "buster",
+    "maggie",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "flower",
+    "computer",
+    "internet",
+    "matrix",
+    "google",
+    "samsung",
+    "apple",
+    "windows",
+    "linux",
+    "changeme",
+    "default",
+    "guest",
+    "root",
+    "test",
+    "pass",
+    "user",
+    "money",
+    "access",
+    "indonesia",
+    "jakarta",
+    "bismillah",
+    "sayang",
+    "rahasia",
+    "cinta",
+    "pandacare",
+    "panda",
+    "doctor",
+    "health",
+    "care",
+];
+
+const KEYBOARD_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Checks a new password against the configured policy. `personal_info` holds
+/// things like the user's email and name, which the password must not contain.
+/// Problems are reported under `field`.
+pub fn check_password_policy(
+    field: &str,
+    password: &str,
+    personal_info: &[&str],
+) -> Result<(), ValidationErrors> {
+    let policy = &config::get().password_policy;
+    let mut errors = ValidationErrors::default();
+    let length = password.chars().count();
+
+    if length < policy.min_length {
+        errors.add(
+            field,
+            format!("must be at least {} characters long", policy.min_length),
+        );
+    }
+
+    if length > policy.max_length {
+        errors.add(
+            field,
+            format!("must be at most {} characters long", policy.max_length),
+        );
+    }
+
+    let lowercase = password.to_lowercase();
+    let personal_words = personal_words(personal_info);
+
+    if personal_words
+        .iter()
+        .any(|word| lowercase.contains(word.as_str()))
+    {
+        errors.add(field, "must not contain your email address or name");
+    }
+
+    if strength_score(password, &personal_words) < policy.min_strength_score {
+        errors.add(
+            field,
+            "is too easy to guess. Try a longer password or a few unrelated words",
+        );
+    }
+
+    if let Some(corpus) = &policy.breached_passwords_path {
+        match breached_passwords::is_breached(Path::new(corpus), password) {
+            Ok(true) => errors.add(
+                field,
+                "has appeared in a data breach. Please choose a different password",
+            ),
+            Ok(false) => {}
+            // Fail open: an unreadable corpus must not block every password change
+            Err(e) => log::error!("Failed to check breached passwords: {}", e),
+        }
+    }
+
+    errors.into_result()
+}
+
+// Words taken from the email address and name, e.g. `siti.rahma@example.com`
+// gives `siti.rahma`, `siti` and `rahma`
+fn personal_words(personal_info: &[&str]) -> Vec<String> {
+    let mut words = Vec::new();
+
+    for info in personal_info {
+        let info = info.trim().to_lowercase();
+        let local_part = info.split('@').next().unwrap_or_default().to_string();
+
+        words.extend(
+            local_part
+                .split(|c: char| !c.is_alphanumeric())
+                .chain(info.split_whitespace())
+                .map(str::to_string),
+        );
+        words.push(local_part);
+    }
+
+    words.retain(|word| word.chars().count() >= 3);
+    words.sort();
+    words.dedup();
+    words
+}
+
+/// Scores a password from 0 (trivial) to 4 (very hard to guess), in the style
+/// of zxcvbn: it estimates the guesses needed by an attacker who tries common
+/// words, repeats, sequences, keyboard runs and years before brute force, and
+/// looks for the cheapest way to split the password into such pieces.
+pub fn strength_score(password: &str, personal_words: &[String]) -> u8 {
+    let log10_guesses = estimate_log10_guesses(password, personal_words);
+
+    match log10_guesses {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+fn estimate_log10_guesses(password: &str, personal_words: &[String]) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let n = chars.len();
+
+    // cheapest[j] is the lowest cost of guessing the first j characters
+    let mut cheapest = vec![f64::INFINITY; n + 1];
+    cheapest[0] = 0.0;
+
+    for end in 1..=n {
+        let brute_force = cheapest[end - 1] + cardinality(chars[end - 1]).log10();
+        cheapest[end] = cheapest[end].min(brute_force);
+
+        for start in 0..end.saturating_sub(2) {
+            if let Some(guesses) =
+                pattern_guesses(&chars[start..end], &lower[start..end], personal_words)
+            {
+                cheapest[end] = cheapest[end].min(cheapest[start] + guesses.log10());
+            }
+        }
+    }
+
+    cheapest[n]
+}
+
+// Guesses needed for a piece of at least three characters matching a known
+// pattern, or `None` if it matches none
+fn pattern_guesses(original: &[char], lower: &[char], personal_words: &[String]) -> Option<f64> {
+    let len = original.len() as f64;
+    let word: String = lower.iter().collect();
+    let unleeted: String = lower.iter().map(|c| unleet(*c)).collect();
+
+    // Substituting symbols for letters (`p@ssw0rd`) only doubles the guesses
+    for (candidate, leet_factor) in [(&word, 1.0), (&unleeted, 2.0)] {
+        let rank = if personal_words.contains(candidate) {
+            Some(1)
+        } else {
+            COMMON_WORDS
+                .iter()
+                .position(|common| common == candidate)
+                .map(|index| index + 2)
+        };
+
+        if let Some(rank) = rank {
+            return Some(rank as f64 * case_variations(original) * leet_factor);
+        }
+    }
+
+    if original.iter().all(|c| *c == original[0]) {
+        return Some(cardinality(original[0]) * len);
+    }
+
+    if let Some(descending) = sequence_direction(original) {
+        let first = original[0].to_ascii_lowercase();
+        let start_guesses = if "az09".contains(first) {
+            4.0
+        } else if first.is_ascii_digit() {
+            10.0
+        } else {
+            26.0
+        };
+
+        return Some(start_guesses * len * if descending { 2.0 } else { 1.0 });
+    }
+
+    if original.len() >= 4 && is_keyboard_run(lower) {
+        return Some(40.0 * len);
+    }
+
+    if original.len() == 4 {
+        let year: String = original.iter().collect();
+        if year
+            .parse::<u32>()
+            .is_ok_and(|year| (1900..=2039).contains(&year))
+        {
+            return Some(140.0);
+        }
+    }
+
+    None
+}
+
+// `Some(true)` for a run like `cba` or `321`, `Some(false)` for `abc` or `123`
+fn sequence_direction(chars: &[char]) -> Option<bool> {
+    let same_class = chars.iter().all(|c| c.is_ascii_digit())
+        || chars.iter().all(|c| c.is_ascii_lowercase())
+        || chars.iter().all(|c| c.is_ascii_uppercase());
+
+    if !same_class {
+        return None;
+    }
+
+    let steps: Vec<i32> = chars
+        .windows(2)
+        .map(|pair| pair[1] as i32 - pair[0] as i32)
+        .collect();
+
+    if steps.iter().all(|step| *step == 1) {
+        Some(false)
+    } else if steps.iter().all(|step| *step == -1) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+fn is_keyboard_run(chars: &[char]) -> bool {
+    let run: String = chars.iter().collect();
+    let reversed: String = chars.iter().rev().collect();
+
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| row.contains(&run) || row.contains(&reversed))
+}
+
+// Lowercase, Capitalized and UPPERCASE are tried first; other mixes cost more
+fn case_variations(chars: &[char]) -> f64 {
+    let upper = chars.iter().filter(|c| c.is_uppercase()).count();
+
+    if upper == 0 {
+        1.0
+    } else if upper == chars.len() || (upper == 1 && chars[0].is_uppercase()) {
+        2.0
+    } else {
+        (chars.len() as f64).powi(2)
+    }
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '@' | '4' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        _ => c,
+    }
+}
+
+fn cardinality(c: char) -> f64 {
+    if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
+        26.0
+    } else if c.is_ascii_digit() {
+        10.0
+    } else {
+        33.0
+    }
+}
@@ -0,0 +1,240 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, KeyId, Params, ParamsBuilder, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use pbkdf2::{pbkdf2_hmac, Pbkdf2};
+use scrypt::Scrypt;
+use sha2::{Digest, Sha256};
+
+use crate::{config, errors::passwords::PasswordError};
+
+const RANDOM_SECRET_MEMORY_KIB: u32 = 1024;
+const RANDOM_SECRET_ITERATIONS: u32 = 1;
+
+/// The algorithms stored hashes may use. Only Argon2id is used for new
+/// hashes; the others come from accounts imported from older systems and are
+/// upgraded when their owners log in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashScheme {
+    /// PHC string, `$argon2id$...` (or the older `argon2i`/`argon2d`)
+    Argon2,
+    /// Modular crypt format, `$2a$`, `$2b$` or `$2y$`
+    Bcrypt,
+    /// PHC string `$pbkdf2-sha256$i=<rounds>,l=<length>$...`, or the passlib
+    /// form `$pbkdf2-sha256$<rounds>$<salt>$<hash>`
+    Pbkdf2Sha256,
+    /// PHC string, `$scrypt$...`
+    Scrypt,
+}
+
+impl HashScheme {
+    /// Works out the scheme of a stored hash from its prefix and checks that
+    /// the rest of it is well formed, without verifying anything against it.
+    pub fn detect(stored_hash: &str) -> Result<Self, PasswordError> {
+        let scheme = if stored_hash.starts_with("$argon2") {
+            HashScheme::Argon2
+        } else if ["$2a$", "$2b$", "$2y$"]
+            .iter()
+            .any(|prefix| stored_hash.starts_with(prefix))
+        {
+            HashScheme::Bcrypt
+        } else if stored_hash.starts_with("$pbkdf2-sha256$") {
+            HashScheme::Pbkdf2Sha256
+        } else if stored_hash.starts_with("$scrypt$") {
+            HashScheme::Scrypt
+        } else {
+            return Err(PasswordError::UnsupportedFormat);
+        };
+
+        let well_formed = match scheme {
+            // Cost, 22 characters of salt and 31 of hash
+            HashScheme::Bcrypt => stored_hash.len() == 60,
+            HashScheme::Pbkdf2Sha256 => {
+                PasswordHash::new(stored_hash).is_ok()
+                    || parse_passlib_pbkdf2(stored_hash).is_some()
+            }
+            HashScheme::Argon2 | HashScheme::Scrypt => PasswordHash::new(stored_hash).is_ok(),
+        };
+
+        if !well_formed {
+            return Err(PasswordError::MalformedHash);
+        }
+
+        Ok(scheme)
+    }
+}
+
+/// Hashes a password with the configured Argon2id settings and pepper.
+pub fn hash_password(password: &str) -> Result<String, PasswordError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(current_hasher()?
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Hashes a randomly generated secret, such as a recovery code, to be checked
+/// later with [`verify_password`]. Random secrets cannot be guessed from a
+/// word list, so they get a far cheaper hash than passwords.
+pub fn hash_random_secret(secret: &str) -> Result<String, PasswordError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let params = params_with(RANDOM_SECRET_MEMORY_KIB, RANDOM_SECRET_ITERATIONS, 1)?;
+
+    Ok(hasher_with(params)?
+        .hash_password(secret.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Checks a password against a stored hash, using whatever algorithm and
+/// settings the hash was made with. Fails only if the stored hash cannot be
+/// parsed.
+pub fn verify_password(password: &str, stored_hash: &str) -> Result<bool, PasswordError> {
+    match HashScheme::detect(stored_hash)? {
+        HashScheme::Argon2 => verify_argon2(password, stored_hash),
+        HashScheme::Bcrypt => {
+            bcrypt::verify(password, stored_hash).map_err(|_err| PasswordError::MalformedHash)
+        }
+        HashScheme::Pbkdf2Sha256 => verify_pbkdf2_sha256(password, stored_hash),
+        HashScheme::Scrypt => {
+            let parsed_hash = PasswordHash::new(stored_hash)?;
+            Ok(Scrypt
+                .verify_password(password.as_bytes(), &parsed_hash)
+                .is_ok())
+        }
+    }
+}
+
+fn verify_argon2(password: &str, stored_hash: &str) -> Result<bool, PasswordError> {
+    let parsed_hash = PasswordHash::new(stored_hash)?;
+    let params = Params::try_from(&parsed_hash)?;
+
+    // Hashes made before the pepper was configured carry no key id. Those
+    // made with another pepper can no longer be checked.
+    let hasher = if params.keyid().is_empty() {
+        Argon2::default()
+    } else if params.keyid() == current_params()?.keyid() {
+        current_hasher()?
+    } else {
+        return Ok(false);
+    };
+
+    Ok(hasher
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok())
+}
+
+/// Whether a stored hash was made with another algorithm or other settings
+/// than the current ones, and should be replaced the next time the password
+/// is known.
+pub fn needs_rehash(stored_hash: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(stored_hash) else {
+        return true;
+    };
+    let Ok(params) = Params::try_from(&parsed_hash) else {
+        return true;
+    };
+    let Ok(current) = current_params() else {
+        return false;
+    };
+
+    parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+        || params.m_cost() != current.m_cost()
+        || params.t_cost() != current.t_cost()
+        || params.p_cost() != current.p_cost()
+        || params.keyid() != current.keyid()
+}
+
+fn verify_pbkdf2_sha256(password: &str, stored_hash: &str) -> Result<bool, PasswordError> {
+    if let Ok(parsed_hash) = PasswordHash::new(stored_hash) {
+        return Ok(Pbkdf2
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok());
+    }
+
+    let (rounds, salt, expected) =
+        parse_passlib_pbkdf2(stored_hash).ok_or(PasswordError::MalformedHash)?;
+
+    let mut derived = vec![0u8; expected.len()];
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, rounds, &mut derived);
+
+    // Compare every byte so that the time taken does not depend on where they differ
+    let difference = derived
+        .iter()
+        .zip(&expected)
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+
+    Ok(difference == 0)
+}
+
+// Passlib writes `$pbkdf2-sha256$<rounds>$<salt>$<hash>`, with salt and hash in
+// base64 using `.` in place of `+` and without padding
+fn parse_passlib_pbkdf2(stored_hash: &str) -> Option<(u32, Vec<u8>, Vec<u8>)> {
+    let mut fields = stored_hash.strip_prefix("$pbkdf2-sha256$")?.split('$');
+
+    let rounds = fields.next()?.parse::<u32>().ok()?;
+    let decode = |field: &str| STANDARD_NO_PAD.decode(field.replace('.', "+")).ok();
+    let salt = decode(fields.next()?)?;
+    let hash = decode(fields.next()?)?;
+
+    if fields.next().is_some() || rounds == 0 || hash.is_empty() {
+        return None;
+    }
+
+    Some((rounds, salt, hash))
+}
+
+fn pepper() -> Option<&'static str> {
+    config::get().password_hashing.pepper.as_deref()
+}
+
+fn current_params() -> Result<Params, PasswordError> {
+    let settings = &config::get().password_hashing;
+
+    params_with(
+        settings.memory_kib,
+        settings.iterations,
+        settings.parallelism,
+    )
+}
+
+fn params_with(
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Result<Params, PasswordError> {
+    let mut builder = ParamsBuilder::new();
+
+    builder
+        .m_cost(memory_kib)
+        .t_cost(iterations)
+        .p_cost(parallelism);
+
+    // The key id marks peppered hashes and records which pepper they used
+    if let Some(pepper) = pepper() {
+        let digest = Sha256::digest(pepper.as_bytes());
+        builder.keyid(KeyId::new(&digest[..Params::MAX_KEYID_LEN])?);
+    }
+
+    Ok(builder.build()?)
+}
+
+fn current_hasher() -> Result<Argon2<'static>, PasswordError> {
+    hasher_with(current_params()?)
+}
+
+fn hasher_with(params: Params) -> Result<Argon2<'static>, PasswordError> {
+    let hasher = match pepper() {
+        Some(pepper) => Argon2::new_with_secret(
+            pepper.as_bytes(),
+            Algorithm::Argon2id,
+            Version::V0x13,
+            params,
+        )?,
+        None => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+    };
+
+    Ok(hasher)
+}
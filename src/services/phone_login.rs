@@ -0,0 +1,201 @@
+use chrono::{Duration, Utc};
+use diesel::{
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection as _,
+};
+use rand::{rng, Rng};
+use sha2::{Digest, Sha256};
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{phone_login::PhoneLoginError, validation::ValidationErrors},
+    models::{
+        roles::NewUserRole,
+        sms::NewSmsCode,
+        users::{
+            InsertableUser, PhoneCodeFields, PhoneCodeSent, PhoneLoginFields,
+            PhoneRegistrationFields, Role, User,
+        },
+    },
+    repository::{
+        roles::{get_role_by_name, insert_user_role},
+        sms::{
+            consume_sms_code, get_sms_code_sent_at_for_update, record_failed_sms_attempt,
+            upsert_sms_code,
+        },
+        users::{get_user_by_phone_number, insert_new_user},
+    },
+    services::{
+        mfa,
+        sms::{SmsMessage, SmsSender},
+        users::normalize_phone_number,
+    },
+};
+
+/// Texts a 6-digit code to the number, for signing in or signing up with
+/// it. Any number gets a code, so the response does not reveal whether it
+/// has an account. A number has to wait a while between codes.
+pub fn send_code(
+    conn: &mut Connection,
+    sms_sender: &dyn SmsSender,
+    fields: PhoneCodeFields,
+) -> Result<PhoneCodeSent, PhoneLoginError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let settings = &config::get().sms;
+    let phone_number = phone_number(&fields.phone_number)?;
+    let code = format!("{:06}", rng().random_range(0..1_000_000));
+    let now = Utc::now().naive_utc();
+    let cooldown = Duration::seconds(settings.resend_cooldown_seconds);
+    let valid_for = Duration::minutes(settings.code_ttl_minutes);
+
+    conn.transaction(|conn| {
+        if let Some(sent_at) = get_sms_code_sent_at_for_update(conn, &phone_number)? {
+            let retry_after_seconds = (sent_at + cooldown - now).num_seconds();
+
+            if retry_after_seconds > 0 {
+                return Err(PhoneLoginError::ResendCooldown {
+                    retry_after_seconds,
+                });
+            }
+        }
+
+        upsert_sms_code(
+            conn,
+            NewSmsCode {
+                phone_number: phone_number.clone(),
+                code_hash: hash_code(&phone_number, &code),
+                sent_at: now,
+                expired_at: now + valid_for,
+            },
+        )?;
+
+        // Sent inside the transaction so that a failed delivery does not start a cooldown
+        sms_sender.send(SmsMessage {
+            to: phone_number.clone(),
+            body: format!(
+                "{} is your PandaCare code. It expires in {} minutes. Never share it with anyone.",
+                code,
+                valid_for.num_minutes()
+            ),
+        })?;
+
+        Ok(PhoneCodeSent {
+            expires_in_seconds: valid_for.num_seconds(),
+            resend_after_seconds: cooldown.num_seconds(),
+        })
+    })
+}
+
+/// Creates an account for the number once its code checks out. The account
+/// has no email or password and signs in with SMS codes.
+pub fn register(
+    conn: &mut Connection,
+    fields: PhoneRegistrationFields,
+) -> Result<User, PhoneLoginError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    // Caregiver applications are reviewed by email, see `services::caregivers`
+    if let Role::Caregiver = fields.role {
+        return Err(ValidationErrors::single(
+            "role",
+            "caregivers have to register with an email address",
+        )
+        .into());
+    }
+
+    let phone_number = phone_number(&fields.phone_number)?;
+    check_code(conn, &phone_number, &fields.code)?;
+
+    let new_user = InsertableUser {
+        email: None,
+        email_normalized: None,
+        password: None,
+        phone_number: Some(phone_number),
+    };
+
+    let result = conn.transaction(|conn| {
+        let user = insert_new_user(conn, new_user)?;
+        let role = get_role_by_name(conn, &fields.role.to_string())?;
+
+        insert_user_role(
+            conn,
+            NewUserRole {
+                user_id: user.id,
+                role_id: role.id,
+                granted_by: None,
+            },
+        )?;
+
+        Ok(user)
+    });
+
+    match result {
+        Ok(user) => Ok(user),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(PhoneLoginError::PhoneNumberTaken)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Signs in with a code sent to the number, returning the user.
+pub fn login(conn: &mut Connection, fields: PhoneLoginFields) -> Result<User, PhoneLoginError> {
+    fields.validate().map_err(ValidationErrors::from)?;
+
+    let phone_number = phone_number(&fields.phone_number)?;
+    check_code(conn, &phone_number, &fields.code)?;
+
+    let user = match get_user_by_phone_number(conn, &phone_number) {
+        Ok(user) => user,
+        Err(DieselError::NotFound) => return Err(PhoneLoginError::AccountNotFound),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !user.is_active() {
+        return Err(PhoneLoginError::AccountDisabled);
+    }
+
+    if mfa::is_required(conn, &user, &config::get().mfa.required_roles)? {
+        return Err(PhoneLoginError::MfaRequired);
+    }
+
+    Ok(user)
+}
+
+// Uses up the code of the number if it matches. Each wrong guess counts, and
+// once the limit is reached the code stops working even if right.
+fn check_code(
+    conn: &mut Connection,
+    phone_number: &str,
+    code: &str,
+) -> Result<(), PhoneLoginError> {
+    let max_attempts = config::get().sms.max_code_attempts;
+    let code_hash = hash_code(phone_number, code.trim());
+
+    if consume_sms_code(conn, phone_number, &code_hash, max_attempts)? {
+        return Ok(());
+    }
+
+    match record_failed_sms_attempt(conn, phone_number)? {
+        Some(failed_attempts) if failed_attempts >= max_attempts => {
+            Err(PhoneLoginError::TooManyAttempts)
+        }
+        _ => Err(PhoneLoginError::InvalidCode),
+    }
+}
+
+fn phone_number(input: &str) -> Result<String, PhoneLoginError> {
+    normalize_phone_number(input).ok_or_else(|| {
+        ValidationErrors::single("phone_number", "must be a valid phone number").into()
+    })
+}
+
+fn hash_code(phone_number: &str, code: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(format!("{}:{}", phone_number, code).as_bytes())
+    )
+}
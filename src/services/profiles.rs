@@ -0,0 +1,141 @@
+use chrono::{Datelike, NaiveDate, Utc};
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    errors::{profiles::ProfileError, validation::ValidationErrors},
+    models::profiles::{Profile, ProfileChangeset, ProfileResponse, ProfileUpdate},
+    repository::profiles::{get_profile_by_user_id, upsert_profile},
+    services::{crypto::DataKey, users::normalize_phone_number},
+};
+
+pub fn get_profile(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    user_id: Uuid,
+) -> Result<ProfileResponse, ProfileError> {
+    let profile = get_profile_by_user_id(conn, user_id)
+        .map_err(|_err| ProfileError::ProfileFetchingFailure)?;
+
+    match profile {
+        Some(profile) => to_response(data_key, profile),
+        None => Ok(ProfileResponse::default()),
+    }
+}
+
+pub fn update_profile(
+    conn: &mut Connection,
+    data_key: &DataKey,
+    user_id: Uuid,
+    update: ProfileUpdate,
+) -> Result<ProfileResponse, ProfileError> {
+    let mut errors = ValidationErrors::default();
+
+    let full_name = update.full_name.and_then(|full_name| {
+        let full_name = validate_full_name(&full_name);
+        if full_name.is_none() {
+            errors.add("full_name", "must be between 1 and 255 characters long");
+        }
+        full_name
+    });
+
+    let nik = update.nik.and_then(|nik| {
+        let nik = validate_nik(&nik);
+        if nik.is_none() {
+            errors.add("nik", "must be a valid 16-digit Indonesian identity number");
+        }
+        nik
+    });
+
+    // Stored in the same form as the phone numbers people sign in with
+    let phone_number = update.phone_number.and_then(|phone_number| {
+        let phone_number = normalize_phone_number(&phone_number);
+        if phone_number.is_none() {
+            errors.add("phone_number", "must be a valid phone number");
+        }
+        phone_number
+    });
+
+    if let Some(date_of_birth) = update.date_of_birth {
+        if date_of_birth > Utc::now().date_naive() {
+            errors.add("date_of_birth", "cannot be in the future");
+        }
+    }
+
+    errors.into_result()?;
+
+    let nik_encrypted = nik.map(|nik| data_key.encrypt(&nik)).transpose()?;
+
+    let changes = ProfileChangeset {
+        user_id,
+        full_name,
+        nik_encrypted,
+        phone_number,
+        date_of_birth: update.date_of_birth,
+    };
+
+    let profile =
+        upsert_profile(conn, changes).map_err(|_err| ProfileError::ProfileUpdateFailure)?;
+
+    to_response(data_key, profile)
+}
+
+fn to_response(data_key: &DataKey, profile: Profile) -> Result<ProfileResponse, ProfileError> {
+    let nik = profile
+        .nik_encrypted
+        .map(|nik| data_key.decrypt(&nik))
+        .transpose()?;
+
+    Ok(ProfileResponse {
+        full_name: profile.full_name,
+        nik,
+        phone_number: profile.phone_number,
+        date_of_birth: profile.date_of_birth,
+        updated_at: Some(profile.updated_at),
+    })
+}
+
+fn validate_full_name(full_name: &str) -> Option<String> {
+    let full_name = full_name.trim();
+
+    if full_name.is_empty() || full_name.chars().count() > 255 {
+        return None;
+    }
+
+    Some(full_name.to_string())
+}
+
+/// Checks the structure of a NIK (Nomor Induk Kependudukan).
+///
+/// A NIK is 16 digits: a 6-digit region code starting with a province code,
+/// the holder's birth date as DDMMYY (with 40 added to the day for women),
+/// and a 4-digit serial number.
+pub fn validate_nik(nik: &str) -> Option<String> {
+    let nik = nik.trim();
+
+    if nik.len() != 16 || !nik.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let field = |range: std::ops::Range<usize>| nik[range].parse::<u32>().unwrap_or_default();
+
+    let province = field(0..2);
+    let day = field(6..8);
+    let month = field(8..10);
+    let year = field(10..12);
+    let serial = field(12..16);
+
+    let day = if day > 40 { day - 40 } else { day };
+
+    // The two-digit year is ambiguous, so accept the date if it exists in either century
+    let century = Utc::now().year() as u32 / 100 * 100;
+    let date_exists = [century, century - 100]
+        .iter()
+        .any(|c| NaiveDate::from_ymd_opt((c + year) as i32, month, day).is_some());
+
+    if !(11..=94).contains(&province) || !date_exists || serial == 0 {
+        return None;
+    }
+
+    Some(nik.to_string())
+}
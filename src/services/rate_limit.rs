@@ -0,0 +1,173 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::Connection as _;
+
+use crate::{
+    db::DbPool,
+    errors::rate_limit::RateLimitError,
+    models::rate_limit::{Quota, RateLimitBucket, RateLimitDecision},
+    repository::rate_limit::{
+        delete_full_rate_limit_buckets, get_rate_limit_bucket_for_update, upsert_rate_limit_bucket,
+    },
+};
+
+/// Keeps the token buckets behind the rate limiting middleware.
+pub trait RateLimitStore: Send + Sync {
+    /// Takes one token from the bucket under `key`, creating it full if needed
+    fn take(&self, key: &str, quota: Quota) -> Result<RateLimitDecision, RateLimitError>;
+
+    /// Forgets the buckets that have refilled completely, which behave just
+    /// like missing ones. Callers pick the keys, so this has to run now and
+    /// then to keep the store from growing without bound.
+    fn prune(&self) -> Result<usize, RateLimitError>;
+}
+
+// Past this, room for a new bucket is only made by forgetting full ones. Live
+// buckets are never dropped, or flooding the store with keys would reset them.
+pub(crate) const MEMORY_STORE_MAX_BUCKETS: usize = 100_000;
+
+struct MemoryBucket {
+    tokens: f64,
+    updated_at: NaiveDateTime,
+    full_at: NaiveDateTime,
+}
+
+/// Keeps buckets in process memory. Each instance counts separately, so use
+/// [`PostgresRateLimitStore`] when running more than one.
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, MemoryBucket>>,
+}
+
+impl RateLimitStore for MemoryRateLimitStore {
+    fn take(&self, key: &str, quota: Quota) -> Result<RateLimitDecision, RateLimitError> {
+        let now = Utc::now().naive_utc();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+
+        if buckets.len() >= MEMORY_STORE_MAX_BUCKETS && !buckets.contains_key(key) {
+            buckets.retain(|_key, bucket| bucket.full_at > now);
+
+            // A new key is treated like an empty bucket until there is room
+            if buckets.len() >= MEMORY_STORE_MAX_BUCKETS {
+                let (_tokens, decision) = take_token(Some((0.0, now)), quota, now);
+                return Ok(decision);
+            }
+        }
+
+        let previous = buckets
+            .get(key)
+            .map(|bucket| (bucket.tokens, bucket.updated_at));
+        let (tokens, decision) = take_token(previous, quota, now);
+
+        buckets.insert(
+            key.to_string(),
+            MemoryBucket {
+                tokens,
+                updated_at: now,
+                full_at: full_at(now, &decision),
+            },
+        );
+
+        Ok(decision)
+    }
+
+    fn prune(&self) -> Result<usize, RateLimitError> {
+        let now = Utc::now().naive_utc();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+
+        let before = buckets.len();
+        buckets.retain(|_key, bucket| bucket.full_at > now);
+
+        Ok(before - buckets.len())
+    }
+}
+
+/// Keeps buckets in the `rate_limit_buckets` table so that every instance
+/// shares the same limits.
+pub struct PostgresRateLimitStore {
+    pool: DbPool,
+}
+
+impl PostgresRateLimitStore {
+    pub fn new(pool: DbPool) -> Self {
+        PostgresRateLimitStore { pool }
+    }
+}
+
+impl RateLimitStore for PostgresRateLimitStore {
+    fn take(&self, key: &str, quota: Quota) -> Result<RateLimitDecision, RateLimitError> {
+        let mut conn = self.pool.get()?;
+
+        conn.transaction(|conn| {
+            let now = Utc::now().naive_utc();
+            let previous = get_rate_limit_bucket_for_update(conn, key)?
+                .map(|bucket| (bucket.tokens, bucket.updated_at));
+
+            let (tokens, decision) = take_token(previous, quota, now);
+
+            upsert_rate_limit_bucket(
+                conn,
+                RateLimitBucket {
+                    bucket_key: key.to_string(),
+                    tokens,
+                    updated_at: now,
+                    full_at: full_at(now, &decision),
+                },
+            )?;
+
+            Ok(decision)
+        })
+    }
+
+    fn prune(&self) -> Result<usize, RateLimitError> {
+        let mut conn = self.pool.get()?;
+
+        Ok(delete_full_rate_limit_buckets(
+            &mut conn,
+            Utc::now().naive_utc(),
+        )?)
+    }
+}
+
+fn full_at(now: NaiveDateTime, decision: &RateLimitDecision) -> NaiveDateTime {
+    now + Duration::seconds(decision.reset_seconds as i64)
+}
+
+/// Refills a bucket for the time since it was last used and takes a token
+/// from it if one is available. Returns the tokens left and the decision.
+fn take_token(
+    previous: Option<(f64, NaiveDateTime)>,
+    quota: Quota,
+    now: NaiveDateTime,
+) -> (f64, RateLimitDecision) {
+    let capacity = quota.capacity as f64;
+    let refill_rate = quota.refill_rate();
+
+    let available = match previous {
+        Some((tokens, updated_at)) => {
+            let elapsed = (now - updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+            (tokens + elapsed * refill_rate).min(capacity)
+        }
+        None => capacity,
+    };
+
+    let allowed = available >= 1.0;
+    let tokens = if allowed { available - 1.0 } else { available };
+
+    let retry_after_seconds = if allowed {
+        0
+    } else {
+        ((1.0 - tokens) / refill_rate).ceil() as u64
+    };
+
+    let decision = RateLimitDecision {
+        allowed,
+        limit: quota.capacity,
+        remaining: tokens.floor() as u32,
+        reset_seconds: ((capacity - tokens) / refill_rate).ceil() as u64,
+        retry_after_seconds,
+    };
+
+    (tokens, decision)
+}
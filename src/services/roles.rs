@@ -0,0 +1,74 @@
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+use crate::{
+    db::Connection,
+    errors::roles::RoleError,
+    models::roles::{NewUserRole, RoleAssignment},
+    repository::{
+        roles::{
+            delete_user_role, get_role_assignments_for_user, get_role_by_name, insert_user_role,
+        },
+        users::get_user_by_id,
+    },
+};
+
+pub const ADMIN_ROLE: &str = "admin";
+
+pub fn get_user_roles(
+    conn: &mut Connection,
+    user_id: Uuid,
+) -> Result<Vec<RoleAssignment>, RoleError> {
+    get_user_by_id(conn, user_id).map_err(|_err| RoleError::UserNotFound)?;
+
+    get_role_assignments_for_user(conn, user_id).map_err(|_err| RoleError::RoleAssignmentFailure)
+}
+
+pub fn grant_role(
+    conn: &mut Connection,
+    granted_by: Option<Uuid>,
+    user_id: Uuid,
+    role_name: &str,
+) -> Result<(), RoleError> {
+    get_user_by_id(conn, user_id).map_err(|_err| RoleError::UserNotFound)?;
+
+    let role = get_role_by_name(conn, role_name).map_err(|err| match err {
+        DieselError::NotFound => RoleError::RoleNotFound,
+        _ => RoleError::RoleAssignmentFailure,
+    })?;
+
+    let new_user_role = NewUserRole {
+        user_id,
+        role_id: role.id,
+        granted_by,
+    };
+
+    insert_user_role(conn, new_user_role).map_err(|_err| RoleError::RoleAssignmentFailure)?;
+
+    Ok(())
+}
+
+pub fn revoke_role(
+    conn: &mut Connection,
+    revoked_by: Uuid,
+    user_id: Uuid,
+    role_name: &str,
+) -> Result<(), RoleError> {
+    if revoked_by == user_id && role_name == ADMIN_ROLE {
+        return Err(RoleError::SelfRevocation);
+    }
+
+    let role = get_role_by_name(conn, role_name).map_err(|err| match err {
+        DieselError::NotFound => RoleError::RoleNotFound,
+        _ => RoleError::RoleAssignmentFailure,
+    })?;
+
+    let deleted = delete_user_role(conn, user_id, role.id)
+        .map_err(|_err| RoleError::RoleAssignmentFailure)?;
+
+    if deleted == 0 {
+        return Err(RoleError::RoleNotAssigned);
+    }
+
+    Ok(())
+}
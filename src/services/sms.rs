@@ -0,0 +1,64 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf, sync::Mutex};
+
+use crate::errors::sms::SmsError;
+
+#[derive(Clone, Debug)]
+pub struct SmsMessage {
+    /// In E.164 form
+    pub to: String,
+    pub body: String,
+}
+
+/// Delivers text messages through an SMS gateway. Handlers receive it as
+/// `web::Data<dyn SmsSender>` so deployments and tests can plug in their own
+/// gateway.
+pub trait SmsSender: Send + Sync {
+    fn send(&self, message: SmsMessage) -> Result<(), SmsError>;
+}
+
+/// Notes outgoing messages in the application log instead of sending them.
+/// Only the recipient is logged since bodies carry sign in codes; use
+/// [`FileSmsSender`] to read them locally.
+pub struct LogSmsSender;
+
+impl SmsSender for LogSmsSender {
+    fn send(&self, message: SmsMessage) -> Result<(), SmsError> {
+        log::info!("SMS to {} was not sent", message.to);
+
+        Ok(())
+    }
+}
+
+/// Appends outgoing messages to a local file, one per line, so they can be
+/// inspected without a gateway.
+pub struct FileSmsSender {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileSmsSender {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl SmsSender for FileSmsSender {
+    fn send(&self, message: SmsMessage) -> Result<(), SmsError> {
+        let _guard = self
+            .lock
+            .lock()
+            .map_err(|err| SmsError::DeliveryFailure(err.to_string()))?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| SmsError::DeliveryFailure(err.to_string()))?;
+
+        writeln!(file, "{}\t{}", message.to, message.body.replace('\n', " "))
+            .map_err(|err| SmsError::DeliveryFailure(err.to_string()))
+    }
+}
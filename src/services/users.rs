@@ -1,48 +1,437 @@
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
-use password_hash::{rand_core::OsRng, SaltString};
+use std::sync::LazyLock;
+
+use chrono::NaiveDateTime;
+use diesel::{
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection as _,
+};
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+use validator::Validate;
 
 use crate::{
+    config,
     db::Connection,
-    errors::users::{UserCreationError, UserValidationError},
-    models::users::{InsertableUser, LoginFields, User},
-    repository::users::{get_user_by_email, insert_new_user},
+    errors::{
+        users::{PasswordChangeError, PasswordResetError, UserCreationError, UserValidationError},
+        validation::ValidationErrors,
+    },
+    models::{
+        audit::ClientInfo,
+        roles::NewUserRole,
+        users::{
+            InsertableUser, LoginFields, PasswordChangeFields, PasswordResetFields,
+            RegistrationFields, Role, User, UserStatus,
+        },
+    },
+    repository::{
+        jwt::revoke_all_refresh_tokens_for_user,
+        profiles::get_profile_by_user_id,
+        roles::{get_role_by_name, insert_user_role},
+        users::{
+            get_user_by_email, get_user_by_id, insert_new_user, set_password_hash, update_password,
+        },
+    },
+    services::{
+        action_tokens::{redeem_action_token, ActionTokenPurpose},
+        audit::{self, AuditEntry},
+        caregivers::store_application,
+        lockout::{self, LockoutScope},
+        mail::{MailMessage, MailSender},
+        password_policy::check_password_policy,
+        passwords::{hash_password, needs_rehash, verify_password},
+    },
 };
 
+// Checked against when the email is unknown, so that a login attempt takes as
+// long whether or not the account exists
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| {
+    hash_password("pandacare-dummy-password").expect("Failed to hash dummy password")
+});
+
+/// The form of an email address that accounts are looked up and kept unique
+/// by: NFKC normalized and case folded, so that `Ａlice@Example.com` and
+/// `alice@example.com` are the same account. The address as typed is kept
+/// for sending mail.
+pub fn normalize_email(email: &str) -> String {
+    let composed = email.trim().nfkc().collect::<String>();
+    caseless::default_case_fold_str(&composed).nfkc().collect()
+}
+
+/// A phone number in E.164 form, e.g. `+6281234567890`, or `None` if it is
+/// not one. Spaces, dashes, dots and parentheses are ignored, and a number in
+/// national form (with a leading `0`) gets the default country code.
+pub fn normalize_phone_number(phone_number: &str) -> Option<String> {
+    let compact: String = phone_number
+        .trim()
+        .chars()
+        .filter(|character| !matches!(character, ' ' | '-' | '.' | '(' | ')'))
+        .collect();
+
+    let digits = if let Some(international) = compact.strip_prefix('+') {
+        international.to_string()
+    } else if let Some(international) = compact.strip_prefix("00") {
+        international.to_string()
+    } else if let Some(national) = compact.strip_prefix('0') {
+        format!("{}{}", config::get().sms.default_country_code, national)
+    } else {
+        return None;
+    };
+
+    // E.164 allows at most 15 digits, and no country code starts with 0
+    let valid = (8..=15).contains(&digits.len())
+        && digits.chars().all(|character| character.is_ascii_digit())
+        && !digits.starts_with('0');
+
+    valid.then(|| format!("+{}", digits))
+}
+
+/// Registers a new account. To avoid revealing which emails are registered,
+/// an email that is already taken gets the same result, and its owner is
+/// told about the attempt by email instead.
+///
+/// Caregivers start out as pacilians with an application waiting for an
+/// administrator, who grants the caregiver role on approval.
 pub fn create_user(
     conn: &mut Connection,
-    new_user: InsertableUser,
-) -> Result<User, UserCreationError> {
-    let password: &String = &new_user.password;
-    let salt: SaltString = SaltString::generate(&mut OsRng);
+    mailer: &dyn MailSender,
+    client: &ClientInfo,
+    new_user: RegistrationFields,
+) -> Result<(), UserCreationError> {
+    new_user.validate().map_err(ValidationErrors::from)?;
+
+    let email_normalized = normalize_email(&new_user.email);
+
+    // Compatibility characters can expand, e.g. `ﬀ` becomes `ff`
+    if email_normalized.chars().count() > 255 {
+        return Err(
+            ValidationErrors::single("email", "must be at most 255 characters long").into(),
+        );
+    }
+
+    match (&new_user.role, &new_user.caregiver) {
+        (Role::Caregiver, None) => {
+            return Err(ValidationErrors::single(
+                "caregiver",
+                "is required when registering as a caregiver",
+            )
+            .into())
+        }
+        (Role::Pacilian, Some(_caregiver)) => {
+            return Err(ValidationErrors::single(
+                "caregiver",
+                "is only for caregiver registrations",
+            )
+            .into())
+        }
+        _ => {}
+    }
+
+    check_password_policy("password", &new_user.password, &[&new_user.email])?;
 
-    let argon2 = Argon2::default();
+    // Hashed in both cases so that the response takes as long either way
+    let password_hash: String =
+        hash_password(&new_user.password).map_err(|_err| UserCreationError::PasswordHashError)?;
 
-    let password_hash: String = argon2
-        .hash_password(password.as_bytes(), &salt)
-        .map_err(|_err| UserCreationError::PasswordHashError)?
-        .to_string();
+    let email = new_user.email.clone();
 
     let final_user = InsertableUser {
-        password: password_hash,
-        ..new_user
+        email: Some(new_user.email),
+        email_normalized: Some(email_normalized.clone()),
+        password: Some(password_hash),
+        phone_number: None,
+    };
+
+    let result = conn.transaction(|conn| {
+        let user = insert_new_user(conn, final_user)?;
+        let role = get_role_by_name(conn, &Role::Pacilian.to_string())?;
+
+        insert_user_role(
+            conn,
+            NewUserRole {
+                user_id: user.id,
+                role_id: role.id,
+                granted_by: None,
+            },
+        )?;
+
+        if let Some(caregiver) = new_user.caregiver.clone() {
+            store_application(conn, user.id, caregiver)?;
+        }
+
+        Ok(user)
+    });
+
+    // The audit log is not shown to the client, so it can tell the two apart
+    let entry = match &result {
+        Ok(user) => AuditEntry::success("user.registered", Some(user.id), Some(user.id))
+            .with_detail(format!("role={}", new_user.role)),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            AuditEntry::failure(
+                "user.registered",
+                None,
+                get_user_by_email(conn, &email_normalized)
+                    .ok()
+                    .map(|user| user.id),
+                "Email is already registered",
+            )
+        }
+        Err(err) => AuditEntry::failure("user.registered", None, None, err),
     };
+    audit::record(conn, client, entry);
+
+    let message = match result {
+        Ok(_user) if new_user.caregiver.is_some() => MailMessage {
+            to: email,
+            subject: "Welcome to PandaCare".to_string(),
+            body: format!(
+                "Your PandaCare account has been created. You can sign in at {}/login.\n\n\
+                 We received your caregiver application and will check your STR and \
+                 documents. Until we email you with our decision, you can use PandaCare \
+                 as a pacilian.",
+                config::get().frontend_url
+            ),
+        },
+        Ok(_user) => MailMessage {
+            to: email,
+            subject: "Welcome to PandaCare".to_string(),
+            body: format!(
+                "Your PandaCare account has been created. You can sign in at {}/login.",
+                config::get().frontend_url
+            ),
+        },
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => MailMessage {
+            // The address may have been typed differently from the one on the account
+            to: get_user_by_email(conn, &email_normalized)
+                .ok()
+                .and_then(|user| user.email)
+                .unwrap_or(email),
+            subject: "Someone tried to register with your email".to_string(),
+            body: format!(
+                "Someone tried to create a PandaCare account with this email address, \
+                 but you already have one. If this was you, sign in at {}/login instead.\n\n\
+                 If it wasn't you, you can ignore this email. Your account has not been changed.",
+                config::get().frontend_url
+            ),
+        },
+        Err(_err) => return Err(UserCreationError::UserInsertionError),
+    };
+
+    // Best effort: a delivery failure must not make the two cases distinguishable
+    if let Err(e) = mailer.send(message) {
+        log::error!("Failed to send registration email: {}", e);
+    }
 
-    insert_new_user(conn, final_user).map_err(|_err| UserCreationError::UserInsertionError)
+    Ok(())
 }
 
+/// Checks login credentials while tracking failures per account and per
+/// client IP. Blocked attempts fail exactly like a wrong password so that a
+/// lockout does not reveal whether the account exists.
 pub fn validate_user(
     conn: &mut Connection,
+    mailer: &dyn MailSender,
     user_credentials: LoginFields,
+    client: &ClientInfo,
 ) -> Result<User, UserValidationError> {
-    let user = get_user_by_email(conn, &user_credentials.email)
-        .map_err(|_err| UserValidationError::InvalidCredentials)?;
+    user_credentials
+        .validate()
+        .map_err(ValidationErrors::from)?;
+
+    let ip_address = client.ip_address.as_deref();
+
+    let ip_blocked = match ip_address {
+        Some(ip_address) => lockout::is_blocked(conn, LockoutScope::Ip, ip_address)?,
+        None => false,
+    };
+
+    let user = match get_user_by_email(conn, &normalize_email(&user_credentials.email)) {
+        Ok(user) => user,
+        Err(_err) => {
+            let _ = verify_password(&user_credentials.password, &DUMMY_PASSWORD_HASH);
+
+            if let (Some(ip_address), false) = (ip_address, ip_blocked) {
+                lockout::record_failure(conn, LockoutScope::Ip, ip_address)?;
+            }
+
+            return Err(UserValidationError::InvalidCredentials);
+        }
+    };
+
+    let account = user.id.to_string();
+    let account_blocked = lockout::is_blocked(conn, LockoutScope::Account, &account)?;
+
+    // Verify even when blocked so the response takes as long as any other failure
+    let password_matches = match &user.password {
+        Some(password_hash) => verify_password(&user_credentials.password, password_hash)
+            .map_err(|_err| UserValidationError::InvalidPasswordFormat)?,
+        // Users without a password sign in another way
+        None => {
+            let _ = verify_password(&user_credentials.password, &DUMMY_PASSWORD_HASH);
+            false
+        }
+    };
+
+    // Attempts made while blocked are not counted, otherwise the block would
+    // keep extending for as long as an attacker keeps trying
+    if ip_blocked || account_blocked {
+        return Err(UserValidationError::InvalidCredentials);
+    }
+
+    if !password_matches {
+        if let Some(ip_address) = ip_address {
+            lockout::record_failure(conn, LockoutScope::Ip, ip_address)?;
+        }
+
+        let outcome = lockout::record_failure(conn, LockoutScope::Account, &account)?;
+
+        if let Some(locked_until) = outcome.locked_until {
+            audit::record(
+                conn,
+                client,
+                AuditEntry::success("user.locked_out", None, Some(user.id))
+                    .with_detail(format!("locked_until={}", locked_until)),
+            );
+            notify_lockout(mailer, &user, locked_until);
+        }
 
-    let parsed_hash = PasswordHash::new(&user.password)
-        .map_err(|_err| UserValidationError::InvalidPasswordFormat)?;
+        return Err(UserValidationError::InvalidCredentials);
+    }
 
-    Argon2::default()
-        .verify_password(user_credentials.password.as_bytes(), &parsed_hash)
-        .map_err(|_err| UserValidationError::InvalidCredentials)?;
+    lockout::clear_failures(conn, LockoutScope::Account, &account)?;
+
+    if user.password.as_deref().is_some_and(needs_rehash) {
+        rehash_password(conn, &user, &user_credentials.password);
+    }
+
+    check_active(&user)?;
+
+    if user.must_reset_password {
+        return Err(UserValidationError::PasswordResetRequired);
+    }
 
     Ok(user)
 }
+
+/// Refuses accounts that are not active, saying why
+pub fn check_active(user: &User) -> Result<(), UserValidationError> {
+    match user.status {
+        UserStatus::Active => Ok(()),
+        UserStatus::Suspended => Err(UserValidationError::AccountSuspended),
+        UserStatus::Disabled => Err(UserValidationError::AccountDisabled),
+        UserStatus::Deleted => Err(UserValidationError::AccountDeleted),
+    }
+}
+
+// Best effort: the login goes ahead with the old hash if this fails
+fn rehash_password(conn: &mut Connection, user: &User, password: &str) {
+    let result = hash_password(password)
+        .map_err(|err| err.to_string())
+        .and_then(|password_hash| {
+            set_password_hash(conn, user.id, &password_hash).map_err(|err| err.to_string())
+        });
+
+    if let Err(e) = result {
+        log::error!("Failed to rehash password for user {}: {}", user.id, e);
+    }
+}
+
+// Best effort: failing to notify must not change the login response
+fn notify_lockout(mailer: &dyn MailSender, user: &User, locked_until: NaiveDateTime) {
+    let Some(email) = user.email.clone() else {
+        return;
+    };
+
+    let message = MailMessage {
+        to: email,
+        subject: "Your PandaCare account has been locked".to_string(),
+        body: format!(
+            "We locked your PandaCare account after too many failed sign-in attempts.\n\n\
+             You can try again after {} UTC. If this wasn't you, we recommend resetting \
+             your password once the lock expires.",
+            locked_until.format("%Y-%m-%d %H:%M")
+        ),
+    };
+
+    if let Err(e) = mailer.send(message) {
+        log::error!("Failed to send lockout notification: {}", e);
+    }
+}
+
+/// Sets a new password using a token from a password reset email and signs
+/// the user out of every existing session.
+pub fn reset_password(
+    conn: &mut Connection,
+    reset_fields: PasswordResetFields,
+) -> Result<User, PasswordResetError> {
+    reset_fields.validate().map_err(ValidationErrors::from)?;
+
+    // A rejected password rolls the transaction back, so the token stays usable
+    conn.transaction(|conn| {
+        let user_id =
+            redeem_action_token(conn, &reset_fields.token, ActionTokenPurpose::PasswordReset)?;
+
+        let user = get_user_by_id(conn, user_id)?;
+        check_new_password(conn, &user, "password", &reset_fields.password)?;
+
+        let password_hash: String = hash_password(&reset_fields.password)
+            .map_err(|_err| PasswordResetError::PasswordHashError)?;
+
+        let user = update_password(conn, user_id, &password_hash)?;
+        revoke_all_refresh_tokens_for_user(conn, user_id)?;
+
+        Ok(user)
+    })
+}
+
+/// Changes the password of a signed in user who knows their current one, and
+/// signs them out of every existing session.
+pub fn change_password(
+    conn: &mut Connection,
+    user_id: Uuid,
+    change_fields: PasswordChangeFields,
+) -> Result<(), PasswordChangeError> {
+    change_fields.validate().map_err(ValidationErrors::from)?;
+
+    let user = get_user_by_id(conn, user_id).map_err(|_err| PasswordChangeError::UserNotFound)?;
+
+    let current_password_matches = match &user.password {
+        Some(password_hash) => verify_password(&change_fields.current_password, password_hash)
+            .map_err(|_err| PasswordChangeError::PasswordHashError)?,
+        None => false,
+    };
+
+    if !current_password_matches {
+        return Err(ValidationErrors::single("current_password", "is incorrect").into());
+    }
+
+    check_new_password(conn, &user, "new_password", &change_fields.new_password)?;
+
+    let password_hash: String = hash_password(&change_fields.new_password)
+        .map_err(|_err| PasswordChangeError::PasswordHashError)?;
+
+    conn.transaction(|conn| {
+        update_password(conn, user_id, &password_hash)?;
+        revoke_all_refresh_tokens_for_user(conn, user_id)?;
+
+        Ok(())
+    })
+}
+
+// Applies the password policy, keeping the user's email and name out of it
+fn check_new_password(
+    conn: &mut Connection,
+    user: &User,
+    field: &str,
+    password: &str,
+) -> Result<(), ValidationErrors> {
+    let full_name = get_profile_by_user_id(conn, user.id)
+        .ok()
+        .flatten()
+        .and_then(|profile| profile.full_name);
+
+    let mut personal_info: Vec<&str> = user.email.iter().map(String::as_str).collect();
+    personal_info.extend(full_name.as_deref());
+
+    check_password_policy(field, password, &personal_info)
+}
@@ -0,0 +1,466 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use ciborium::Value;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    EncodedPoint, FieldBytes,
+};
+use rand::{rng, RngCore};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config,
+    db::Connection,
+    errors::{validation::ValidationErrors, webauthn::WebAuthnError},
+    models::{
+        users::User,
+        webauthn::{
+            AuthenticationCredential, AuthenticatorSelection, CollectedClientData, CreationOptions,
+            CredentialDescriptor, CredentialParameters, CredentialSummary, NewWebAuthnChallenge,
+            NewWebAuthnCredential, RegistrationCredential, RelyingParty, RequestOptions,
+            UserEntity,
+        },
+    },
+    repository::{
+        profiles::get_profile_by_user_id,
+        users::get_user_by_id,
+        webauthn::{
+            consume_webauthn_challenge, delete_webauthn_credential, get_webauthn_credential,
+            get_webauthn_credentials_for_user, insert_webauthn_challenge,
+            insert_webauthn_credential, update_webauthn_sign_count,
+        },
+    },
+};
+
+/// COSE algorithm identifier of ECDSA with P-256 and SHA-256
+const ES256: i64 = -7;
+const CHALLENGE_BYTES: usize = 32;
+const PUBLIC_KEY_TYPE: &str = "public-key";
+
+// Bits of the authenticator data flags byte
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+// RP ID hash, flags and sign count come before anything else
+const AUTHENTICATOR_DATA_MIN_LENGTH: usize = 37;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Registration => "registration",
+            Self::Authentication => "authentication",
+        }
+    }
+
+    /// The `type` the browser puts in the client data
+    fn client_data_type(&self) -> &'static str {
+        match self {
+            Self::Registration => "webauthn.create",
+            Self::Authentication => "webauthn.get",
+        }
+    }
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<AttestedCredential>,
+}
+
+struct AttestedCredential {
+    credential_id: Vec<u8>,
+    /// Uncompressed SEC1 point
+    public_key: Vec<u8>,
+}
+
+/// Options for `navigator.credentials.create()` to add a passkey to a
+/// signed in user's account.
+pub fn start_registration(
+    conn: &mut Connection,
+    user_id: Uuid,
+) -> Result<CreationOptions, WebAuthnError> {
+    let settings = &config::get().webauthn;
+
+    let user = get_user_by_id(conn, user_id)?;
+    let display_name = get_profile_by_user_id(conn, user_id)?
+        .and_then(|profile| profile.full_name)
+        .unwrap_or_else(|| user.login_name().to_string());
+
+    // Keeps the browser from registering an authenticator twice
+    let exclude_credentials = get_webauthn_credentials_for_user(conn, user_id)?
+        .into_iter()
+        .map(|credential| descriptor(credential.credential_id))
+        .collect();
+
+    let challenge = issue_challenge(conn, Some(user_id), Ceremony::Registration)?;
+
+    Ok(CreationOptions {
+        challenge,
+        rp: RelyingParty {
+            id: settings.rp_id.clone(),
+            name: settings.rp_name.clone(),
+        },
+        user: UserEntity {
+            id: URL_SAFE_NO_PAD.encode(user.id.as_bytes()),
+            name: user.login_name().to_string(),
+            display_name,
+        },
+        pub_key_cred_params: vec![CredentialParameters {
+            kind: PUBLIC_KEY_TYPE,
+            alg: ES256,
+        }],
+        timeout: ceremony_timeout_ms(),
+        attestation: "none",
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "required",
+            user_verification: "preferred",
+        },
+        exclude_credentials,
+    })
+}
+
+/// Checks the result of `navigator.credentials.create()` and stores the new credential.
+pub fn finish_registration(
+    conn: &mut Connection,
+    user_id: Uuid,
+    credential: RegistrationCredential,
+) -> Result<CredentialSummary, WebAuthnError> {
+    credential.validate().map_err(ValidationErrors::from)?;
+
+    let client_data_json = decode(&credential.response.client_data_json)?;
+    check_client_data(
+        conn,
+        &client_data_json,
+        Ceremony::Registration,
+        Some(user_id),
+    )?;
+
+    let attestation_object: Value =
+        ciborium::from_reader(decode(&credential.response.attestation_object)?.as_slice())
+            .map_err(|_err| WebAuthnError::InvalidAuthenticatorData)?;
+
+    // With attestation "none" there is no statement to check, only the format
+    if map_field(&attestation_object, "fmt").and_then(Value::as_text) != Some("none") {
+        return Err(WebAuthnError::UnsupportedAttestation);
+    }
+
+    let authenticator_data = map_field(&attestation_object, "authData")
+        .and_then(Value::as_bytes)
+        .ok_or(WebAuthnError::InvalidAuthenticatorData)?;
+    let authenticator_data = parse_authenticator_data(authenticator_data)?;
+    check_authenticator_data(&authenticator_data)?;
+
+    let attested_credential = authenticator_data
+        .attested_credential
+        .ok_or(WebAuthnError::InvalidAuthenticatorData)?;
+    let credential_id = URL_SAFE_NO_PAD.encode(&attested_credential.credential_id);
+
+    if credential_id != credential.id.trim_end_matches('=') {
+        return Err(WebAuthnError::InvalidAuthenticatorData);
+    }
+
+    let new_credential = NewWebAuthnCredential {
+        credential_id,
+        user_id,
+        public_key: attested_credential.public_key,
+        sign_count: authenticator_data.sign_count as i64,
+        name: credential.name,
+    };
+
+    match insert_webauthn_credential(conn, new_credential) {
+        Ok(stored) => Ok(stored.into()),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(WebAuthnError::CredentialAlreadyRegistered)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Options for `navigator.credentials.get()`. No credentials are listed, so
+/// the browser offers any passkey it holds for this site and the options
+/// tell nothing about which accounts exist. Passkeys are registered as
+/// discoverable for that reason.
+pub fn start_authentication(conn: &mut Connection) -> Result<RequestOptions, WebAuthnError> {
+    let challenge = issue_challenge(conn, None, Ceremony::Authentication)?;
+
+    Ok(RequestOptions {
+        challenge,
+        rp_id: config::get().webauthn.rp_id.clone(),
+        timeout: ceremony_timeout_ms(),
+        user_verification: "required",
+    })
+}
+
+/// Checks the result of `navigator.credentials.get()` and returns the user
+/// it signs in. User verification on the authenticator (a PIN or biometric)
+/// is required, so that a passkey alone is as strong as a password and a
+/// second factor.
+pub fn finish_authentication(
+    conn: &mut Connection,
+    credential: AuthenticationCredential,
+) -> Result<User, WebAuthnError> {
+    let client_data_json = decode(&credential.response.client_data_json)?;
+    check_client_data(conn, &client_data_json, Ceremony::Authentication, None)?;
+
+    let stored = get_webauthn_credential(conn, credential.id.trim_end_matches('='))?
+        .ok_or(WebAuthnError::CredentialNotFound)?;
+
+    let raw_authenticator_data = decode(&credential.response.authenticator_data)?;
+    let authenticator_data = parse_authenticator_data(&raw_authenticator_data)?;
+    check_authenticator_data(&authenticator_data)?;
+
+    if authenticator_data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(WebAuthnError::UserNotVerified);
+    }
+
+    // The authenticator signs its data followed by the hash of the client data
+    let mut signed = raw_authenticator_data.clone();
+    signed.extend_from_slice(&Sha256::digest(&client_data_json));
+
+    let verifying_key = VerifyingKey::from_sec1_bytes(&stored.public_key)
+        .map_err(|_err| WebAuthnError::UnsupportedAlgorithm)?;
+    let signature = Signature::from_der(&decode(&credential.response.signature)?)
+        .map_err(|_err| WebAuthnError::InvalidSignature)?;
+
+    verifying_key
+        .verify(&signed, &signature)
+        .map_err(|_err| WebAuthnError::InvalidSignature)?;
+
+    // Authenticators that do not count always report zero
+    let sign_count = authenticator_data.sign_count as i64;
+    let counts = sign_count != 0 || stored.sign_count != 0;
+
+    if counts && sign_count <= stored.sign_count {
+        return Err(WebAuthnError::CounterRegression);
+    }
+
+    if update_webauthn_sign_count(conn, &stored.credential_id, stored.sign_count, sign_count)? == 0
+    {
+        return Err(WebAuthnError::CounterRegression);
+    }
+
+    let user = get_user_by_id(conn, stored.user_id)?;
+
+    if !user.is_active() {
+        return Err(WebAuthnError::AccountDisabled);
+    }
+
+    Ok(user)
+}
+
+pub fn list_credentials(
+    conn: &mut Connection,
+    user_id: Uuid,
+) -> Result<Vec<CredentialSummary>, WebAuthnError> {
+    Ok(get_webauthn_credentials_for_user(conn, user_id)?
+        .into_iter()
+        .map(CredentialSummary::from)
+        .collect())
+}
+
+pub fn delete_credential(
+    conn: &mut Connection,
+    user_id: Uuid,
+    credential_id: &str,
+) -> Result<(), WebAuthnError> {
+    match delete_webauthn_credential(conn, user_id, credential_id)? {
+        0 => Err(WebAuthnError::CredentialNotFound),
+        _ => Ok(()),
+    }
+}
+
+fn issue_challenge(
+    conn: &mut Connection,
+    user_id: Option<Uuid>,
+    ceremony: Ceremony,
+) -> Result<String, WebAuthnError> {
+    let mut bytes = [0u8; CHALLENGE_BYTES];
+    rng().fill_bytes(&mut bytes);
+    let challenge = URL_SAFE_NO_PAD.encode(bytes);
+
+    insert_webauthn_challenge(
+        conn,
+        NewWebAuthnChallenge {
+            challenge: challenge.clone(),
+            user_id,
+            ceremony: ceremony.as_str().to_string(),
+            expired_at: Utc::now().naive_utc()
+                + Duration::minutes(config::get().webauthn.challenge_ttl_minutes),
+        },
+    )?;
+
+    Ok(challenge)
+}
+
+// Checks the type and origin, and uses up the challenge it answers. A
+// registration challenge must have been issued to the same user.
+fn check_client_data(
+    conn: &mut Connection,
+    client_data_json: &[u8],
+    ceremony: Ceremony,
+    user_id: Option<Uuid>,
+) -> Result<(), WebAuthnError> {
+    let client_data: CollectedClientData = serde_json::from_slice(client_data_json)
+        .map_err(|_err| WebAuthnError::InvalidClientData)?;
+
+    if client_data.kind != ceremony.client_data_type() {
+        return Err(WebAuthnError::InvalidClientData);
+    }
+
+    let allowed_origins = &config::get().webauthn.allowed_origins;
+
+    if !allowed_origins.contains(&client_data.origin) {
+        return Err(WebAuthnError::OriginMismatch);
+    }
+
+    let issued_to = consume_webauthn_challenge(conn, &client_data.challenge, ceremony.as_str())?
+        .ok_or(WebAuthnError::InvalidChallenge)?;
+
+    if user_id.is_some() && issued_to != user_id {
+        return Err(WebAuthnError::InvalidChallenge);
+    }
+
+    Ok(())
+}
+
+fn check_authenticator_data(authenticator_data: &AuthenticatorData) -> Result<(), WebAuthnError> {
+    let expected_rp_id_hash = Sha256::digest(config::get().webauthn.rp_id.as_bytes());
+
+    if authenticator_data.rp_id_hash != expected_rp_id_hash.as_slice() {
+        return Err(WebAuthnError::RelyingPartyMismatch);
+    }
+
+    if authenticator_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(WebAuthnError::UserNotPresent);
+    }
+
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, WebAuthnError> {
+    if data.len() < AUTHENTICATOR_DATA_MIN_LENGTH {
+        return Err(WebAuthnError::InvalidAuthenticatorData);
+    }
+
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        Some(parse_attested_credential(
+            &data[AUTHENTICATOR_DATA_MIN_LENGTH..],
+        )?)
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+// A 16 byte AAGUID, the length-prefixed credential id, then the COSE public
+// key. Any extension data after the key is ignored.
+fn parse_attested_credential(data: &[u8]) -> Result<AttestedCredential, WebAuthnError> {
+    if data.len() < 18 {
+        return Err(WebAuthnError::InvalidAuthenticatorData);
+    }
+
+    let id_length = u16::from_be_bytes([data[16], data[17]]) as usize;
+    let credential_id = data
+        .get(18..18 + id_length)
+        .ok_or(WebAuthnError::InvalidAuthenticatorData)?
+        .to_vec();
+
+    let cose_key: Value = ciborium::from_reader(&data[18 + id_length..])
+        .map_err(|_err| WebAuthnError::InvalidAuthenticatorData)?;
+
+    Ok(AttestedCredential {
+        credential_id,
+        public_key: cose_key_to_sec1(&cose_key)?,
+    })
+}
+
+// Only EC2 keys on P-256 for ES256 are accepted (RFC 9053)
+fn cose_key_to_sec1(cose_key: &Value) -> Result<Vec<u8>, WebAuthnError> {
+    let entries = cose_key
+        .as_map()
+        .ok_or(WebAuthnError::InvalidAuthenticatorData)?;
+
+    let field = |label: i64| {
+        entries
+            .iter()
+            .find(|(key, _value)| {
+                key.as_integer()
+                    .is_some_and(|key| i128::from(key) == label as i128)
+            })
+            .map(|(_key, value)| value)
+    };
+
+    let integer_field = |label: i64| field(label).and_then(Value::as_integer).map(i128::from);
+
+    let key_type_ec2 = integer_field(1) == Some(2);
+    let algorithm_es256 = integer_field(3) == Some(ES256 as i128);
+    let curve_p256 = integer_field(-1) == Some(1);
+
+    if !(key_type_ec2 && algorithm_es256 && curve_p256) {
+        return Err(WebAuthnError::UnsupportedAlgorithm);
+    }
+
+    let coordinate = |label: i64| {
+        field(label)
+            .and_then(Value::as_bytes)
+            .filter(|bytes| bytes.len() == 32)
+            .ok_or(WebAuthnError::InvalidAuthenticatorData)
+    };
+
+    let point = EncodedPoint::from_affine_coordinates(
+        FieldBytes::from_slice(coordinate(-2)?),
+        FieldBytes::from_slice(coordinate(-3)?),
+        false,
+    );
+
+    // Rejects points that are not on the curve
+    VerifyingKey::from_encoded_point(&point)
+        .map_err(|_err| WebAuthnError::InvalidAuthenticatorData)?;
+
+    Ok(point.as_bytes().to_vec())
+}
+
+fn map_field<'a>(map: &'a Value, name: &str) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(key, _value)| key.as_text() == Some(name))
+        .map(|(_key, value)| value)
+}
+
+fn descriptor(credential_id: String) -> CredentialDescriptor {
+    CredentialDescriptor {
+        kind: PUBLIC_KEY_TYPE,
+        id: credential_id,
+    }
+}
+
+// Browsers accept base64url with or without padding
+fn decode(value: &str) -> Result<Vec<u8>, WebAuthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_err| WebAuthnError::InvalidClientData)
+}
+
+fn ceremony_timeout_ms() -> u64 {
+    config::get().webauthn.challenge_ttl_minutes.max(0) as u64 * 60_000
+}
@@ -1,17 +1,56 @@
-use actix_web::{http::StatusCode, test, web, App};
+use actix_http::Request;
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::StatusCode,
+    test, web, App,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use diesel::prelude::*;
 use dotenvy::dotenv;
 use once_cell::sync::Lazy;
 use serde_json::{json, Value};
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 use uuid::Uuid;
 
 // Import your handlers, db module, DbPool type, and schema
 use crate::{
+    config,
     db::{self, DbPool},
-    handlers::{get_email_by_user_id, get_jwks, obtain, refresh, register, revoke},
+    errors::{mail::MailError, sms::SmsError},
+    handlers::{
+        accept_delegation, approve_caregiver_application, begin_challenge_totp_enrollment,
+        begin_totp_enrollment, begin_webauthn_login, begin_webauthn_registration, change_password,
+        confirm_totp_enrollment, convert_dependent, create_dependent, delete_webauthn_credential,
+        disable_user, disown_login, enable_user, exchange_token, export_audit_events,
+        finish_webauthn_login, finish_webauthn_registration, force_password_reset,
+        get_caregiver_application, get_email_by_user_id, get_jwks, get_own_caregiver_application,
+        get_profile, get_recovery_code_status, get_user_roles, get_user_sessions, grant_user_role,
+        impersonate_user, invite_delegate, list_audit_events, list_caregiver_applications,
+        list_delegations, list_dependents, list_logins, list_own_impersonations, list_users,
+        list_webauthn_credentials, login_with_phone, obtain, redeem_login_link, refresh,
+        regenerate_recovery_codes, register, register_with_phone, reject_caregiver_application,
+        request_login_code, request_login_link, request_phone_code, reset_password, revoke,
+        revoke_delegation, revoke_user_role, set_user_status, step_up,
+        submit_caregiver_application, unlock_user, update_dependent, update_profile,
+        verify_login_code, verify_mfa,
+    },
+    middleware::rate_limit::{RateLimitKey, RateLimitRule, RateLimiter},
     models, // For models::users::User
+    repository,
     schema, // For schema::users, schema::refresh_tokens
+    services::{
+        self,
+        crypto::DataKey,
+        jwt::TokenKeys,
+        mail::{MailMessage, MailSender},
+        rate_limit::{MemoryRateLimitStore, PostgresRateLimitStore, RateLimitStore},
+        sms::{SmsMessage, SmsSender},
+    },
 };
 
 // --- Shared Test Resources ---
@@ -30,25 +69,85 @@ static TEST_PEM_KEY: Lazy<String> = Lazy::new(|| {
     })
 });
 
+static TEST_TOKEN_KEYS: Lazy<web::Data<TokenKeys>> =
+    Lazy::new(|| web::Data::new(TokenKeys::new(&TEST_PEM_KEY).unwrap()));
+
+// base64 of "0123456789abcdef0123456789abcdef"
+const TEST_DATA_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
 static TEST_POOL: Lazy<DbPool> = Lazy::new(|| {
     dotenv().ok();
     db::get_pool().expect("Failed to create shared test database pool")
 });
 
+/// Keeps sent mail in memory so tests can read links and codes out of it.
+#[derive(Default)]
+struct MemoryMailSender {
+    outbox: Mutex<Vec<MailMessage>>,
+}
+
+impl MemoryMailSender {
+    fn sent_to(&self, address: &str) -> Vec<MailMessage> {
+        self.outbox
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|message| message.to == address)
+            .cloned()
+            .collect()
+    }
+}
+
+impl MailSender for MemoryMailSender {
+    fn send(&self, message: MailMessage) -> Result<(), MailError> {
+        self.outbox.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+/// A mail sender for tests that don't read the mail they trigger.
+fn test_mailer() -> web::Data<dyn MailSender> {
+    web::Data::from(Arc::new(MemoryMailSender::default()) as Arc<dyn MailSender>)
+}
+
+/// Keeps sent text messages in memory so tests can read codes out of them.
+#[derive(Default)]
+struct MemorySmsSender {
+    outbox: Mutex<Vec<SmsMessage>>,
+}
+
+impl MemorySmsSender {
+    /// The code in the last message sent to the number
+    fn last_code(&self, phone_number: &str) -> String {
+        let outbox = self.outbox.lock().unwrap();
+        let message = outbox
+            .iter()
+            .rev()
+            .find(|message| message.to == phone_number)
+            .expect("No SMS was sent to this number");
+
+        message.body.split(' ').next().unwrap().to_string()
+    }
+}
+
+impl SmsSender for MemorySmsSender {
+    fn send(&self, message: SmsMessage) -> Result<(), SmsError> {
+        self.outbox.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
 // --- Cleanup Helper Functions ---
 
-/// Deletes a user by email and their associated refresh tokens.
+/// Deletes a user by email. Their refresh tokens go with them.
 fn cleanup_user_and_tokens_by_email(email_to_delete: &str) {
-    use schema::refresh_tokens::dsl as rt_dsl;
     use schema::users::dsl as users_dsl;
 
     let mut conn = TEST_POOL
         .get()
         .expect("Failed to get DB connection from pool for cleanup");
 
-    // Find the user to get their ID for deleting associated refresh tokens
-    // The User model has `id: Uuid` and the refresh_tokens table has `user_id: Varchar`.
-    // We assume user_id in refresh_tokens is the string representation of the User's Uuid.
+    // Find the user to clear the login failures recorded under their ID
     let user_result = users_dsl::users
         .filter(users_dsl::email.eq(email_to_delete))
         .select(models::users::User::as_select()) // Selects the whole User struct
@@ -58,19 +157,275 @@ fn cleanup_user_and_tokens_by_email(email_to_delete: &str) {
 
     if let Some(user) = user_result {
         let user_id_str = user.id.to_string();
-        // Delete refresh tokens associated with the user
-        diesel::delete(rt_dsl::refresh_tokens.filter(rt_dsl::user_id.eq(user_id_str)))
+
+        for scope in ["account", "mfa", "email_code"] {
+            repository::lockout::delete_login_failure(&mut conn, scope, &user_id_str)
+                .expect("Failed to delete login failures during cleanup");
+        }
+
+        // A guardian cannot be deleted while they still have dependents
+        diesel::delete(users_dsl::users.filter(users_dsl::guardian_id.eq(user.id)))
             .execute(&mut conn)
-            .expect("Failed to delete refresh tokens during cleanup");
+            .expect("Failed to delete dependents during cleanup");
     }
 
-    // Delete the user
+    // Delete the user, and with them their refresh tokens
     let _deleted_users_count =
         diesel::delete(users_dsl::users.filter(users_dsl::email.eq(email_to_delete)))
             .execute(&mut conn)
             .expect("Failed to delete user during cleanup");
 }
 
+/// Deletes a user who signed up with a phone number, their refresh tokens and
+/// any pending SMS code.
+fn cleanup_user_by_phone_number(phone_number: &str) {
+    use schema::sms_codes::dsl as sms_dsl;
+    use schema::users::dsl as users_dsl;
+
+    let mut conn = TEST_POOL.get().unwrap();
+
+    diesel::delete(users_dsl::users.filter(users_dsl::phone_number.eq(phone_number)))
+        .execute(&mut conn)
+        .unwrap();
+    diesel::delete(sms_dsl::sms_codes.filter(sms_dsl::phone_number.eq(phone_number)))
+        .execute(&mut conn)
+        .unwrap();
+}
+
+/// Grants a role straight through the service layer, e.g. to bootstrap an admin.
+fn grant_role_by_email(email: &str, role: &str) {
+    use schema::users::dsl as users_dsl;
+
+    let mut conn = TEST_POOL.get().unwrap();
+    let user_id: Uuid = users_dsl::users
+        .filter(users_dsl::email.eq(email))
+        .select(users_dsl::id)
+        .first(&mut conn)
+        .expect("User to grant a role to was not found");
+
+    services::roles::grant_role(&mut conn, None, user_id, role).expect("Failed to grant role");
+}
+
+// --- Request Helpers ---
+
+/// A caregiver application that passes validation
+fn caregiver_application_json() -> Value {
+    json!({
+        "str_number": "3121100321012345",
+        "documents": [
+            { "document_type": "str_certificate", "url": "https://files.example.com/str.pdf" }
+        ]
+    })
+}
+
+/// Logs in an existing user and returns the issued token pair.
+async fn obtain_tokens<S, B>(app: &S, email: &str, password: &str) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let login_payload = json!({ "email": email, "password": password });
+    let login_resp = test::call_service(
+        app,
+        test::TestRequest::post()
+            .uri("/api/token/obtain")
+            .set_json(&login_payload)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(login_resp.status(), StatusCode::OK);
+
+    test::read_body_json(login_resp).await
+}
+
+/// Registers a user and returns the token pair issued by `/api/token/obtain`.
+/// Caregivers register with an application, and only hold the pacilian role
+/// until it is approved.
+async fn register_and_obtain<S, B>(app: &S, email: &str, password: &str, role: &str) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let mut register_payload = json!({ "email": email, "password": password, "role": role });
+    if role == "caregiver" {
+        register_payload["caregiver"] = caregiver_application_json();
+    }
+    test::call_service(
+        app,
+        test::TestRequest::post()
+            .uri("/api/register")
+            .set_json(&register_payload)
+            .to_request(),
+    )
+    .await;
+
+    obtain_tokens(app, email, password).await
+}
+
+/// Decodes the claims of an access token from a token pair response.
+fn access_claims(tokens: &Value) -> services::jwt::Claims {
+    let access_token = tokens.get("access").unwrap().as_str().unwrap();
+    services::jwt::decode_access_token(&TEST_TOKEN_KEYS, access_token).unwrap()
+}
+
+/// The code an authenticator app with this base32 secret shows `offset_steps`
+/// time steps from now.
+fn totp_code(secret: &str, offset_steps: i64) -> String {
+    let secret = totp_rs::Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .unwrap();
+    let totp = totp_rs::TOTP::new_unchecked(
+        totp_rs::Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        None,
+        String::new(),
+    );
+
+    totp.generate((chrono::Utc::now().timestamp() + offset_steps * 30) as u64)
+}
+
+/// Sets up TOTP for a signed in user, returning the secret and recovery codes.
+async fn enroll_totp<S, B>(app: &S, access: &str) -> (String, Vec<String>)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/api/me/mfa/totp")
+        .insert_header(("Authorization", format!("Bearer {}", access)))
+        .to_request();
+    let enrollment: Value = test::call_and_read_body_json(app, req).await;
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/api/me/mfa/totp/confirm")
+        .insert_header(("Authorization", format!("Bearer {}", access)))
+        .set_json(json!({ "code": totp_code(&secret, 0) }))
+        .to_request();
+    let confirmation: Value = test::call_and_read_body_json(app, req).await;
+    let recovery_codes = confirmation["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+
+    (secret, recovery_codes)
+}
+
+/// A passkey authenticator in software: one ES256 credential with a
+/// signature counter, answering ceremonies the way a browser would.
+struct SoftwareAuthenticator {
+    signing_key: p256::ecdsa::SigningKey,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+}
+
+impl SoftwareAuthenticator {
+    fn new(seed: &str) -> Self {
+        use sha2::{Digest, Sha256};
+
+        SoftwareAuthenticator {
+            signing_key: p256::ecdsa::SigningKey::from_bytes(&Sha256::digest(seed.as_bytes()))
+                .unwrap(),
+            credential_id: Sha256::digest(format!("{}-id", seed).as_bytes())[..16].to_vec(),
+            sign_count: 0,
+        }
+    }
+
+    fn credential_id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&json!({ "type": kind, "challenge": challenge, "origin": origin }))
+            .unwrap()
+    }
+
+    fn authenticator_data(&self, flags: u8, attested: bool) -> Vec<u8> {
+        use sha2::{Digest, Sha256};
+
+        let mut data = Sha256::digest(config::get().webauthn.rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+
+        if attested {
+            let point = self.signing_key.verifying_key().to_encoded_point(false);
+            let cose_key = ciborium::Value::Map(vec![
+                (1.into(), 2.into()),
+                (3.into(), (-7).into()),
+                ((-1).into(), 1.into()),
+                (
+                    (-2).into(),
+                    ciborium::Value::Bytes(point.x().unwrap().to_vec()),
+                ),
+                (
+                    (-3).into(),
+                    ciborium::Value::Bytes(point.y().unwrap().to_vec()),
+                ),
+            ]);
+
+            data.extend_from_slice(&[0u8; 16]);
+            data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(&self.credential_id);
+            ciborium::into_writer(&cose_key, &mut data).unwrap();
+        }
+
+        data
+    }
+
+    /// The body for `/api/me/webauthn/register`
+    fn register(&self, options: &Value, origin: &str) -> Value {
+        let auth_data = self.authenticator_data(0x01 | 0x04 | 0x40, true);
+        let attestation_object = ciborium::Value::Map(vec![
+            ("fmt".into(), "none".into()),
+            ("attStmt".into(), ciborium::Value::Map(vec![])),
+            ("authData".into(), ciborium::Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_bytes = Vec::new();
+        ciborium::into_writer(&attestation_object, &mut attestation_bytes).unwrap();
+
+        let challenge = options["challenge"].as_str().unwrap();
+        json!({
+            "id": self.credential_id(),
+            "name": "Test key",
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD
+                    .encode(Self::client_data("webauthn.create", challenge, origin)),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_bytes),
+            },
+        })
+    }
+
+    /// The body for `/api/webauthn/login`, counting the signature
+    fn sign_in(&mut self, options: &Value, origin: &str) -> Value {
+        use p256::ecdsa::{signature::Signer, Signature};
+        use sha2::{Digest, Sha256};
+
+        self.sign_count += 1;
+
+        let challenge = options["challenge"].as_str().unwrap();
+        let client_data = Self::client_data("webauthn.get", challenge, origin);
+        let auth_data = self.authenticator_data(0x01 | 0x04, false);
+
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.signing_key.sign(&signed);
+
+        json!({
+            "id": self.credential_id(),
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+            },
+        })
+    }
+}
+
 // --- Test Functions (with cleanup) ---
 
 #[actix_web::test]
@@ -81,13 +436,14 @@ async fn test_register_pacilian_endpoint() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool))
+            .app_data(test_mailer())
             .service(web::scope("/api").service(register)),
     )
     .await;
 
     let user_payload = json!({
         "email": test_email,
-        "password": "password123",
+        "password": "Sunflower-Orbit-42",
         "role": "pacilian"
     });
 
@@ -99,13 +455,16 @@ async fn test_register_pacilian_endpoint() {
     let resp = test::call_service(&app, req).await;
     assert_eq!(
         resp.status(),
-        StatusCode::CREATED,
+        StatusCode::ACCEPTED,
         "Registration for 'pacilian' failed."
     );
 
-    if resp.status() == StatusCode::CREATED {
+    if resp.status() == StatusCode::ACCEPTED {
         let body_bytes = test::read_body(resp).await;
-        assert_eq!(body_bytes, "User created successfully");
+        assert_eq!(
+            body_bytes,
+            "Registration received. Please check your email to continue"
+        );
     }
     cleanup_user_and_tokens_by_email(test_email);
 }
@@ -118,14 +477,16 @@ async fn test_register_caregiver_endpoint() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool))
+            .app_data(test_mailer())
             .service(web::scope("/api").service(register)),
     )
     .await;
 
     let user_payload = json!({
         "email": test_email,
-        "password": "password123",
-        "role": "caregiver"
+        "password": "Sunflower-Orbit-42",
+        "role": "caregiver",
+        "caregiver": caregiver_application_json()
     });
 
     let req = test::TestRequest::post()
@@ -135,7 +496,7 @@ async fn test_register_caregiver_endpoint() {
     let resp = test::call_service(&app, req).await;
     assert_eq!(
         resp.status(),
-        StatusCode::CREATED,
+        StatusCode::ACCEPTED,
         "Registration for 'caregiver' failed."
     );
 
@@ -145,15 +506,15 @@ async fn test_register_caregiver_endpoint() {
 #[actix_web::test]
 async fn test_login_endpoint_pacilian() {
     let pool = TEST_POOL.clone();
-    let secret_key_for_test = TEST_PEM_KEY.clone();
     let user_email = "login_pacilian_cl@example.com";
-    let user_password = "password123";
+    let user_password = "Sunflower-Orbit-42";
     let user_role_for_registration = "pacilian";
 
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool))
-            .app_data(web::Data::new(secret_key_for_test))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
             .service(web::scope("/api").service(register).service(obtain)),
     )
     .await;
@@ -183,21 +544,25 @@ async fn test_login_endpoint_pacilian() {
 #[actix_web::test]
 async fn test_login_endpoint_caregiver() {
     let pool = TEST_POOL.clone();
-    let secret_key_for_test = TEST_PEM_KEY.clone();
     let user_email = "login_caregiver_cl@example.com";
-    let user_password = "password123";
+    let user_password = "Sunflower-Orbit-42";
     let user_role_for_registration = "caregiver";
 
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool))
-            .app_data(web::Data::new(secret_key_for_test))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
             .service(web::scope("/api").service(register).service(obtain)),
     )
     .await;
 
-    let register_payload =
-        json!({"email": user_email, "password": user_password, "role": user_role_for_registration});
+    let register_payload = json!({
+        "email": user_email,
+        "password": user_password,
+        "role": user_role_for_registration,
+        "caregiver": caregiver_application_json()
+    });
     test::call_service(
         &app,
         test::TestRequest::post()
@@ -221,15 +586,15 @@ async fn test_login_endpoint_caregiver() {
 #[actix_web::test]
 async fn test_refresh_token_endpoint() {
     let pool = TEST_POOL.clone();
-    let secret_key_for_test = TEST_PEM_KEY.clone();
     let user_email = "refresh_cl@example.com";
-    let user_password = "password123";
+    let user_password = "Sunflower-Orbit-42";
     let user_role_for_registration = "pacilian";
 
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool))
-            .app_data(web::Data::new(secret_key_for_test))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
             .service(
                 web::scope("/api")
                     .service(register)
@@ -276,15 +641,15 @@ async fn test_refresh_token_endpoint() {
 #[actix_web::test]
 async fn test_revoke_token_endpoint() {
     let pool = TEST_POOL.clone();
-    let secret_key_for_test = TEST_PEM_KEY.clone();
     let user_email = "revoke_cl@example.com";
-    let user_password = "password123";
+    let user_password = "Sunflower-Orbit-42";
     let user_role_for_registration = "caregiver";
 
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool))
-            .app_data(web::Data::new(secret_key_for_test))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
             .service(
                 web::scope("/api")
                     .service(register)
@@ -294,8 +659,12 @@ async fn test_revoke_token_endpoint() {
     )
     .await;
 
-    let register_payload =
-        json!({"email": user_email, "password": user_password, "role": user_role_for_registration});
+    let register_payload = json!({
+        "email": user_email,
+        "password": user_password,
+        "role": user_role_for_registration,
+        "caregiver": caregiver_application_json()
+    });
     test::call_service(
         &app,
         test::TestRequest::post()
@@ -341,11 +710,14 @@ async fn test_get_email_by_user_id_endpoint() {
     // For this test to check a successful case, a user would need to be created.
     // If so, that user should be cleaned up. For now, it tests error cases.
     let app = test::init_service(
-        App::new().app_data(web::Data::new(pool)).service(
-            web::scope("/api")
-                .service(register)
-                .service(get_email_by_user_id),
-        ),
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(get_email_by_user_id),
+            ),
     )
     .await;
 
@@ -379,3 +751,3315 @@ async fn test_get_jwks_endpoint() {
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::OK);
 }
+
+#[actix_web::test]
+async fn test_profile_endpoints_round_trip() {
+    let pool = TEST_POOL.clone();
+    let user_email = "profile_round_trip@example.com";
+    let nik = "3174015708990003";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(get_profile)
+                    .service(update_profile),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let access_token = tokens.get("access").unwrap().as_str().unwrap();
+
+    let update_payload = json!({
+        "full_name": "  Budi Santoso ",
+        "nik": nik,
+        "phone_number": "+6281234567890",
+        "date_of_birth": "1999-08-17"
+    });
+    let update_req = test::TestRequest::patch()
+        .uri("/api/me/profile")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(&update_payload)
+        .to_request();
+    let update_resp = test::call_service(&app, update_req).await;
+    assert_eq!(update_resp.status(), StatusCode::OK);
+
+    let get_req = test::TestRequest::get()
+        .uri("/api/me/profile")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .to_request();
+    let profile: Value = test::call_and_read_body_json(&app, get_req).await;
+    assert_eq!(profile["full_name"], "Budi Santoso");
+    assert_eq!(profile["nik"], nik);
+    assert_eq!(profile["phone_number"], "+6281234567890");
+    assert_eq!(profile["date_of_birth"], "1999-08-17");
+
+    // The NIK must never be stored in plain text
+    let stored_nik: Option<String> = {
+        use schema::{profiles, users};
+        let mut conn = TEST_POOL.get().unwrap();
+        profiles::table
+            .inner_join(users::table)
+            .filter(users::email.eq(user_email))
+            .select(profiles::nik_encrypted)
+            .first(&mut conn)
+            .unwrap()
+    };
+    assert!(!stored_nik.unwrap().contains(nik));
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_update_profile_rejects_invalid_fields() {
+    let pool = TEST_POOL.clone();
+    let user_email = "profile_invalid@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(update_profile),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let access_token = tokens.get("access").unwrap().as_str().unwrap();
+
+    for payload in [
+        json!({ "nik": "1234" }),
+        json!({ "nik": "3174019913990003" }),
+        json!({ "phone_number": "12345" }),
+        json!({ "date_of_birth": "2999-01-01" }),
+    ] {
+        let req = test::TestRequest::patch()
+            .uri("/api/me/profile")
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .set_json(&payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{}",
+            payload
+        );
+
+        // Reported against the field, like other invalid values
+        let body: Value = test::read_body_json(resp).await;
+        let field = payload.as_object().unwrap().keys().next().unwrap();
+        assert!(body["errors"][field].is_array(), "{}", body);
+    }
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_get_profile_requires_access_token() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .service(web::scope("/api").service(get_profile)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/api/me/profile").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri("/api/me/profile")
+        .insert_header(("Authorization", "Bearer not-a-jwt"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_registration_role_is_carried_into_tokens() {
+    let user_email = "roles_claims@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(web::scope("/api").service(register).service(obtain)),
+    )
+    .await;
+
+    // Caregivers only get pacilian access while their application is pending
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "caregiver").await;
+    let claims = access_claims(&tokens);
+    assert_eq!(claims.roles, vec!["pacilian"]);
+    assert!(!claims.permissions.contains(&"patients:read".to_string()));
+
+    grant_role_by_email(user_email, "caregiver");
+    let tokens = obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+    let claims = access_claims(&tokens);
+    assert_eq!(claims.roles, vec!["caregiver", "pacilian"]);
+    assert!(claims.permissions.contains(&"patients:read".to_string()));
+    assert!(!claims.permissions.contains(&"roles:manage".to_string()));
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_admin_grants_and_revokes_roles() {
+    let admin_email = "roles_admin@example.com";
+    let user_email = "roles_target@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(get_user_roles)
+                    .service(grant_user_role)
+                    .service(revoke_user_role),
+            ),
+    )
+    .await;
+
+    let user_tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let user_id = access_claims(&user_tokens).user_id;
+    let user_access = user_tokens.get("access").unwrap().as_str().unwrap();
+
+    register_and_obtain(&app, admin_email, "Sunflower-Orbit-42", "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, "Sunflower-Orbit-42").await;
+    let admin_access = admin_tokens.get("access").unwrap().as_str().unwrap();
+
+    // Regular users cannot manage roles
+    let forbidden_req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/roles", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", user_access)))
+        .set_json(json!({ "role": "admin" }))
+        .to_request();
+    let forbidden_resp = test::call_service(&app, forbidden_req).await;
+    assert_eq!(forbidden_resp.status(), StatusCode::FORBIDDEN);
+
+    let grant_req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/roles", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .set_json(json!({ "role": "doctor" }))
+        .to_request();
+    let grant_resp = test::call_service(&app, grant_req).await;
+    assert_eq!(grant_resp.status(), StatusCode::OK);
+
+    let claims = access_claims(&obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await);
+    assert_eq!(claims.roles, vec!["doctor", "pacilian"]);
+    assert!(claims
+        .permissions
+        .contains(&"prescriptions:write".to_string()));
+
+    let unknown_role_req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/roles", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .set_json(json!({ "role": "wizard" }))
+        .to_request();
+    let unknown_role_resp = test::call_service(&app, unknown_role_req).await;
+    assert_eq!(unknown_role_resp.status(), StatusCode::NOT_FOUND);
+
+    let revoke_req = test::TestRequest::delete()
+        .uri(&format!("/api/admin/users/{}/roles/doctor", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let revoke_resp = test::call_service(&app, revoke_req).await;
+    assert_eq!(revoke_resp.status(), StatusCode::OK);
+
+    let list_req = test::TestRequest::get()
+        .uri(&format!("/api/admin/users/{}/roles", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let roles: Value = test::call_and_read_body_json(&app, list_req).await;
+    assert_eq!(roles.as_array().unwrap().len(), 1);
+    assert_eq!(roles[0]["name"], "pacilian");
+
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+}
+
+#[actix_web::test]
+async fn test_admin_lists_users_with_filters_and_cursor() {
+    let admin_email = "admin_list_admin@example.com";
+    let user_emails = [
+        "admin_list_a@example.com",
+        "admin_list_b@example.com",
+        "admin_list_c@example.com",
+    ];
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(list_users),
+            ),
+    )
+    .await;
+
+    for (email, role) in user_emails
+        .iter()
+        .zip(["pacilian", "caregiver", "pacilian"])
+    {
+        register_and_obtain(&app, email, "Sunflower-Orbit-42", role).await;
+    }
+    grant_role_by_email(user_emails[1], "caregiver");
+    let user_tokens = obtain_tokens(&app, user_emails[0], "Sunflower-Orbit-42").await;
+    let user_access = user_tokens.get("access").unwrap().as_str().unwrap();
+
+    register_and_obtain(&app, admin_email, "Sunflower-Orbit-42", "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, "Sunflower-Orbit-42").await;
+    let admin_access = admin_tokens.get("access").unwrap().as_str().unwrap();
+    let admin_id = Uuid::parse_str(&access_claims(&admin_tokens).user_id).unwrap();
+
+    let forbidden_req = test::TestRequest::get()
+        .uri("/api/admin/users")
+        .insert_header(("Authorization", format!("Bearer {}", user_access)))
+        .to_request();
+    let forbidden_resp = test::call_service(&app, forbidden_req).await;
+    assert_eq!(forbidden_resp.status(), StatusCode::FORBIDDEN);
+
+    let first_page_req = test::TestRequest::get()
+        .uri("/api/admin/users?email_prefix=admin_list_&limit=2")
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let first_page: Value = test::call_and_read_body_json(&app, first_page_req).await;
+    let first_users = first_page["users"].as_array().unwrap();
+    assert_eq!(first_users.len(), 2);
+    assert_eq!(first_users[0]["email"], user_emails[0]);
+    assert_eq!(first_users[1]["email"], admin_email);
+    let cursor = first_page["next_cursor"].as_str().unwrap();
+
+    let second_page_req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/admin/users?email_prefix=admin_list_&limit=2&cursor={}",
+            cursor
+        ))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let second_page: Value = test::call_and_read_body_json(&app, second_page_req).await;
+    let second_users = second_page["users"].as_array().unwrap();
+    assert_eq!(second_users.len(), 2);
+    assert_eq!(second_users[1]["email"], user_emails[2]);
+    assert!(second_page["next_cursor"].is_null());
+
+    let role_filter_req = test::TestRequest::get()
+        .uri("/api/admin/users?email_prefix=admin_list_&role=caregiver&status=active")
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let role_filtered: Value = test::call_and_read_body_json(&app, role_filter_req).await;
+    let role_users = role_filtered["users"].as_array().unwrap();
+    assert_eq!(role_users.len(), 1);
+    assert_eq!(role_users[0]["email"], user_emails[1]);
+    assert_eq!(role_users[0]["roles"], json!(["caregiver", "pacilian"]));
+
+    let audited: i64 = {
+        use schema::audit_events::dsl::*;
+        let mut conn = TEST_POOL.get().unwrap();
+        audit_events
+            .filter(actor_id.eq(admin_id))
+            .filter(event_type.eq("admin.users.listed"))
+            .count()
+            .get_result(&mut conn)
+            .unwrap()
+    };
+    assert_eq!(audited, 3);
+
+    // Dependents have no email or phone number to sort by, and paging still
+    // reaches them one at a time
+    let role_name = format!("paging_{}", &Uuid::new_v4().simple().to_string()[..8]);
+    let mut expected_ids = Vec::new();
+    {
+        let mut conn = TEST_POOL.get().unwrap();
+        diesel::insert_into(schema::roles::table)
+            .values((
+                schema::roles::name.eq(&role_name),
+                schema::roles::description.eq("Paging test"),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+
+        let guardian_id: Uuid = schema::users::table
+            .filter(schema::users::email.eq(user_emails[0]))
+            .select(schema::users::id)
+            .first(&mut conn)
+            .unwrap();
+        expected_ids.push(guardian_id);
+
+        for _ in 0..2 {
+            let dependent = repository::users::insert_dependent_user(
+                &mut conn,
+                models::users::NewDependentUser { guardian_id },
+            )
+            .unwrap();
+            expected_ids.push(dependent.id);
+        }
+
+        for user_id in &expected_ids {
+            services::roles::grant_role(&mut conn, None, *user_id, &role_name).unwrap();
+        }
+    }
+
+    let mut listed_ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    for _ in 0..expected_ids.len() + 1 {
+        let mut uri = format!("/api/admin/users?role={}&limit=1", role_name);
+        if let Some(cursor) = &cursor {
+            uri.push_str(&format!("&cursor={}", cursor));
+        }
+        let req = test::TestRequest::get()
+            .uri(&uri)
+            .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+            .to_request();
+        let page: Value = test::call_and_read_body_json(&app, req).await;
+        listed_ids.extend(
+            page["users"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|user| Uuid::parse_str(user["id"].as_str().unwrap()).unwrap()),
+        );
+
+        match page["next_cursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    assert_eq!(listed_ids.len(), expected_ids.len());
+    assert_eq!(listed_ids[0], expected_ids[0]);
+    let mut dependent_ids = expected_ids[1..].to_vec();
+    dependent_ids.sort();
+    assert_eq!(listed_ids[1..], dependent_ids[..]);
+
+    diesel::delete(schema::roles::table.filter(schema::roles::name.eq(&role_name)))
+        .execute(&mut TEST_POOL.get().unwrap())
+        .unwrap();
+
+    for email in user_emails.iter().chain([&admin_email]) {
+        cleanup_user_and_tokens_by_email(email);
+    }
+}
+
+#[actix_web::test]
+async fn test_admin_disables_user_and_revokes_sessions() {
+    let admin_email = "admin_disable_admin@example.com";
+    let user_email = "admin_disable_target@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(refresh)
+                    .service(get_user_sessions)
+                    .service(disable_user)
+                    .service(enable_user),
+            ),
+    )
+    .await;
+
+    let user_tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let user_id = access_claims(&user_tokens).user_id;
+
+    register_and_obtain(&app, admin_email, "Sunflower-Orbit-42", "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, "Sunflower-Orbit-42").await;
+    let admin_access = admin_tokens.get("access").unwrap().as_str().unwrap();
+
+    let sessions_req = test::TestRequest::get()
+        .uri(&format!("/api/admin/users/{}/sessions", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let sessions: Value = test::call_and_read_body_json(&app, sessions_req).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+
+    let disable_req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/disable", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let disabled: Value = test::call_and_read_body_json(&app, disable_req).await;
+    assert_eq!(disabled["status"], "disabled");
+
+    let login_req = test::TestRequest::post()
+        .uri("/api/token/obtain")
+        .set_json(json!({ "email": user_email, "password": "Sunflower-Orbit-42" }))
+        .to_request();
+    let login_resp = test::call_service(&app, login_req).await;
+    assert_eq!(login_resp.status(), StatusCode::UNAUTHORIZED);
+
+    let refresh_req = test::TestRequest::post()
+        .uri("/api/token/refresh")
+        .set_json(json!({ "refresh_token": user_tokens["refresh"] }))
+        .to_request();
+    let refresh_resp = test::call_service(&app, refresh_req).await;
+    assert!(!refresh_resp.status().is_success());
+
+    let enable_req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/enable", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let enable_resp = test::call_service(&app, enable_req).await;
+    assert_eq!(enable_resp.status(), StatusCode::OK);
+
+    obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+}
+
+#[actix_web::test]
+async fn test_admin_forces_password_reset() {
+    let admin_email = "admin_reset_admin@example.com";
+    let user_email = "admin_reset_target@example.com";
+    let mailer = Arc::new(MemoryMailSender::default());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(reset_password)
+                    .service(force_password_reset),
+            ),
+    )
+    .await;
+
+    let user_tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let user_id = access_claims(&user_tokens).user_id;
+
+    register_and_obtain(&app, admin_email, "Sunflower-Orbit-42", "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, "Sunflower-Orbit-42").await;
+    let admin_access = admin_tokens.get("access").unwrap().as_str().unwrap();
+
+    let force_req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/password-reset", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let force_resp = test::call_service(&app, force_req).await;
+    assert_eq!(force_resp.status(), StatusCode::OK);
+
+    let login_req = test::TestRequest::post()
+        .uri("/api/token/obtain")
+        .set_json(json!({ "email": user_email, "password": "Sunflower-Orbit-42" }))
+        .to_request();
+    let login_resp = test::call_service(&app, login_req).await;
+    assert_eq!(login_resp.status(), StatusCode::UNAUTHORIZED);
+
+    let mail = mailer
+        .sent_to(user_email)
+        .pop()
+        .expect("No reset email sent");
+    let reset_token = mail
+        .body
+        .split("token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap()
+        .to_string();
+
+    let reset_payload = json!({ "token": reset_token, "password": "Lantern-Meadow-77" });
+    let reset_req = test::TestRequest::post()
+        .uri("/api/password/reset")
+        .set_json(&reset_payload)
+        .to_request();
+    let reset_resp = test::call_service(&app, reset_req).await;
+    assert_eq!(reset_resp.status(), StatusCode::OK);
+
+    obtain_tokens(&app, user_email, "Lantern-Meadow-77").await;
+
+    // Reset tokens are single-use
+    let reuse_req = test::TestRequest::post()
+        .uri("/api/password/reset")
+        .set_json(&reset_payload)
+        .to_request();
+    let reuse_resp = test::call_service(&app, reuse_req).await;
+    assert_eq!(reuse_resp.status(), StatusCode::BAD_REQUEST);
+
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+}
+
+#[actix_web::test]
+async fn test_account_lockout_and_admin_unlock() {
+    let admin_email = "lockout_admin@example.com";
+    let user_email = "lockout_target@example.com";
+    let mailer = Arc::new(MemoryMailSender::default());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(unlock_user),
+            ),
+    )
+    .await;
+
+    let user_tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let user_id = access_claims(&user_tokens).user_id;
+
+    register_and_obtain(&app, admin_email, "Sunflower-Orbit-42", "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, "Sunflower-Orbit-42").await;
+    let admin_access = admin_tokens.get("access").unwrap().as_str().unwrap();
+
+    let attempt = |password: &str| {
+        test::TestRequest::post()
+            .uri("/api/token/obtain")
+            .set_json(json!({ "email": user_email, "password": password }))
+            .to_request()
+    };
+
+    let unknown_req = test::TestRequest::post()
+        .uri("/api/token/obtain")
+        .set_json(
+            json!({ "email": "lockout_nobody@example.com", "password": "Sunflower-Orbit-42" }),
+        )
+        .to_request();
+    let unknown_resp = test::call_service(&app, unknown_req).await;
+    assert_eq!(unknown_resp.status(), StatusCode::UNAUTHORIZED);
+    let invalid_body = test::read_body(unknown_resp).await;
+
+    // Skip ahead to one failure short of the lockout instead of waiting out every delay
+    let threshold = config::get().lockout.account_lockout_threshold;
+    services::lockout::record_failure(
+        &mut TEST_POOL.get().unwrap(),
+        services::lockout::LockoutScope::Account,
+        &user_id,
+    )
+    .unwrap();
+    diesel::update(schema::login_failures::table.find(("account", &user_id)))
+        .set((
+            schema::login_failures::failure_count.eq(threshold - 1),
+            schema::login_failures::blocked_until.eq(None::<chrono::NaiveDateTime>),
+        ))
+        .execute(&mut TEST_POOL.get().unwrap())
+        .unwrap();
+
+    let wrong_resp = test::call_service(&app, attempt("wrong password")).await;
+    assert_eq!(wrong_resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(test::read_body(wrong_resp).await, invalid_body);
+
+    let notices = mailer
+        .sent_to(user_email)
+        .into_iter()
+        .filter(|message| message.subject.contains("locked"))
+        .count();
+    assert_eq!(notices, 1);
+
+    // A locked account rejects even the right password, indistinguishably
+    let locked_resp = test::call_service(&app, attempt("Sunflower-Orbit-42")).await;
+    assert_eq!(locked_resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(test::read_body(locked_resp).await, invalid_body);
+
+    let unlock_req = test::TestRequest::post()
+        .uri(&format!("/api/admin/users/{}/unlock", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", admin_access)))
+        .to_request();
+    let unlock_resp = test::call_service(&app, unlock_req).await;
+    assert_eq!(unlock_resp.status(), StatusCode::OK);
+
+    obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+}
+
+#[actix_web::test]
+async fn test_rate_limit_by_email_in_body() {
+    let limiter =
+        RateLimiter::new(Arc::new(MemoryRateLimitStore::default())).rule(RateLimitRule::new(
+            "obtain_email",
+            actix_web::http::Method::POST,
+            "/api/token/obtain",
+            RateLimitKey::EmailInBody,
+            models::rate_limit::Quota::new(2, 60),
+        ));
+
+    let app = test::init_service(
+        App::new()
+            .wrap(limiter)
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(web::scope("/api").service(obtain)),
+    )
+    .await;
+
+    let attempt = |email: &str| {
+        test::TestRequest::post()
+            .uri("/api/token/obtain")
+            .set_json(json!({ "email": email, "password": "wrong password" }))
+            .to_request()
+    };
+
+    for remaining in ["1", "0"] {
+        let resp = test::call_service(&app, attempt("rate_limited@example.com")).await;
+        // The body still reaches the handler after the limiter has read it
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers().get("ratelimit-limit").unwrap(), "2");
+        assert_eq!(
+            resp.headers().get("ratelimit-remaining").unwrap(),
+            remaining
+        );
+    }
+
+    // Differently cased addresses share a bucket
+    let limited_resp = test::call_service(&app, attempt("Rate_Limited@example.com")).await;
+    assert_eq!(limited_resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = limited_resp
+        .headers()
+        .get("retry-after")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=30).contains(&retry_after));
+
+    let other_resp = test::call_service(&app, attempt("not_rate_limited@example.com")).await;
+    assert_eq!(other_resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_rate_limit_keys_fit_the_postgres_store() {
+    use sha2::{Digest, Sha256};
+
+    let limiter = RateLimiter::new(Arc::new(PostgresRateLimitStore::new(TEST_POOL.clone()))).rule(
+        RateLimitRule::new(
+            "test_long_email",
+            actix_web::http::Method::POST,
+            "/api/token/obtain",
+            RateLimitKey::EmailInBody,
+            models::rate_limit::Quota::new(1, 60),
+        ),
+    );
+
+    let app = test::init_service(
+        App::new()
+            .wrap(limiter)
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(web::scope("/api").service(obtain)),
+    )
+    .await;
+
+    // Far longer than a bucket key column, and never validated by the limiter
+    let email = format!("{}{}@example.com", Uuid::new_v4(), "a".repeat(300));
+    let attempt = || {
+        test::TestRequest::post()
+            .uri("/api/token/obtain")
+            .set_json(json!({ "email": email, "password": "wrong password" }))
+            .to_request()
+    };
+
+    let first_resp = test::call_service(&app, attempt()).await;
+    assert_ne!(first_resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        first_resp.headers().get("ratelimit-remaining").unwrap(),
+        "0"
+    );
+
+    let limited_resp = test::call_service(&app, attempt()).await;
+    assert_eq!(limited_resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let bucket_key = format!(
+        "test_long_email:{:x}",
+        Sha256::digest(format!("email:{}", services::users::normalize_email(&email)).as_bytes())
+    );
+    let deleted = diesel::delete(schema::rate_limit_buckets::table.find(&bucket_key))
+        .execute(&mut TEST_POOL.get().unwrap())
+        .unwrap();
+    assert_eq!(deleted, 1);
+}
+
+#[actix_web::test]
+async fn test_postgres_rate_limit_store_is_shared() {
+    use models::rate_limit::Quota;
+
+    let bucket_key = format!("test:{}", Uuid::new_v4());
+    let quota = Quota::new(2, 3600);
+
+    // Two stores stand in for two instances of the service
+    let first = PostgresRateLimitStore::new(TEST_POOL.clone());
+    let second = PostgresRateLimitStore::new(TEST_POOL.clone());
+
+    assert!(first.take(&bucket_key, quota).unwrap().allowed);
+    assert!(second.take(&bucket_key, quota).unwrap().allowed);
+
+    let denied = first.take(&bucket_key, quota).unwrap();
+    assert!(!denied.allowed);
+    assert_eq!(denied.remaining, 0);
+    assert!(denied.retry_after_seconds > 0);
+
+    diesel::delete(schema::rate_limit_buckets::table.find(&bucket_key))
+        .execute(&mut TEST_POOL.get().unwrap())
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn test_rate_limit_stores_forget_full_buckets() {
+    use models::rate_limit::Quota;
+
+    let stores: [Box<dyn RateLimitStore>; 2] = [
+        Box::new(MemoryRateLimitStore::default()),
+        Box::new(PostgresRateLimitStore::new(TEST_POOL.clone())),
+    ];
+
+    let mut keys = Vec::new();
+
+    for store in stores {
+        let refilled_key = format!("test:{}", Uuid::new_v4());
+        let draining_key = format!("test:{}", Uuid::new_v4());
+
+        // One token refills in a second, while the other bucket takes an hour
+        store.take(&refilled_key, Quota::new(1, 1)).unwrap();
+        store.take(&draining_key, Quota::new(2, 3600)).unwrap();
+
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(1100)).await;
+        assert!(store.prune().unwrap() >= 1);
+
+        let stored: Vec<String> = schema::rate_limit_buckets::table
+            .filter(schema::rate_limit_buckets::bucket_key.eq_any([&refilled_key, &draining_key]))
+            .select(schema::rate_limit_buckets::bucket_key)
+            .load(&mut TEST_POOL.get().unwrap())
+            .unwrap();
+        assert!(!stored.contains(&refilled_key));
+
+        // A forgotten bucket starts full again, the other one keeps counting
+        assert!(store.take(&refilled_key, Quota::new(1, 1)).unwrap().allowed);
+
+        let draining = store.take(&draining_key, Quota::new(2, 3600)).unwrap();
+        assert!(draining.allowed);
+        assert_eq!(draining.remaining, 0);
+
+        keys.extend([refilled_key, draining_key]);
+    }
+
+    diesel::delete(
+        schema::rate_limit_buckets::table
+            .filter(schema::rate_limit_buckets::bucket_key.eq_any(keys)),
+    )
+    .execute(&mut TEST_POOL.get().unwrap())
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn test_full_memory_rate_limit_store_keeps_live_buckets() {
+    use models::rate_limit::Quota;
+    use services::rate_limit::MEMORY_STORE_MAX_BUCKETS;
+
+    let store = MemoryRateLimitStore::default();
+    let quota = Quota::new(1, 3600);
+
+    assert!(store.take("victim", quota).unwrap().allowed);
+    assert!(!store.take("victim", quota).unwrap().allowed);
+
+    for i in 1..MEMORY_STORE_MAX_BUCKETS {
+        store.take(&format!("flood:{}", i), quota).unwrap();
+    }
+
+    // With no full bucket to forget, a new key is refused and the exhausted
+    // one stays exhausted
+    let newcomer = store.take("newcomer", quota).unwrap();
+    assert!(!newcomer.allowed);
+    assert!(newcomer.retry_after_seconds > 0);
+    assert!(!store.take("victim", quota).unwrap().allowed);
+}
+
+#[actix_web::test]
+async fn test_register_does_not_reveal_existing_accounts() {
+    let user_email = "register_existing@example.com";
+    let mailer = Arc::new(MemoryMailSender::default());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(web::scope("/api").service(register).service(obtain)),
+    )
+    .await;
+
+    let register_req = |password: &str| {
+        test::TestRequest::post()
+            .uri("/api/register")
+            .set_json(json!({ "email": user_email, "password": password, "role": "pacilian" }))
+            .to_request()
+    };
+
+    let first_resp = test::call_service(&app, register_req("Sunflower-Orbit-42")).await;
+    let first_status = first_resp.status();
+    let first_body = test::read_body(first_resp).await;
+
+    let second_resp = test::call_service(&app, register_req("another password")).await;
+    assert_eq!(second_resp.status(), first_status);
+    assert_eq!(test::read_body(second_resp).await, first_body);
+
+    let mail = mailer.sent_to(user_email);
+    assert_eq!(mail.len(), 2);
+    assert!(mail[0].subject.contains("Welcome"));
+    assert!(mail[1].subject.contains("tried to register"));
+
+    // The second attempt must not have touched the existing account
+    obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_email_variants_share_one_account() {
+    let user_email = "Ｍixed.Case@Example.com";
+    let mailer = Arc::new(MemoryMailSender::default());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(web::scope("/api").service(register).service(obtain)),
+    )
+    .await;
+
+    let register_req = |email: &str| {
+        test::TestRequest::post()
+            .uri("/api/register")
+            .set_json(
+                json!({ "email": email, "password": "Sunflower-Orbit-42", "role": "pacilian" }),
+            )
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, register_req(&format!("  {}  ", user_email))).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    // Differently cased and composed, but the same address
+    let resp = test::call_service(&app, register_req("MIXED.CASE@example.COM")).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    let mail = mailer.sent_to(user_email);
+    assert_eq!(mail.len(), 2);
+    assert!(mail[0].subject.contains("Welcome"));
+    assert!(mail[1].subject.contains("tried to register"));
+
+    obtain_tokens(&app, "mixed.case@example.com", "Sunflower-Orbit-42").await;
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_invalid_payloads_get_field_errors() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .app_data(crate::extractors::json_config())
+            .service(web::scope("/api").service(register).service(obtain)),
+    )
+    .await;
+
+    let cases = [
+        (
+            "/api/register",
+            json!({ "email": "not-an-email", "password": "Sunflower-Orbit-42", "role": "pacilian" }),
+            "email",
+            "must be a valid email address",
+        ),
+        (
+            "/api/token/obtain",
+            json!({ "email": "someone@example.com", "password": "x".repeat(2000) }),
+            "password",
+            "must be between 1 and 1024 characters long",
+        ),
+        (
+            "/api/token/obtain",
+            json!({ "email": "someone@example.com" }),
+            "body",
+            "missing field `password`",
+        ),
+    ];
+
+    for (uri, payload, field, expected) in cases {
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(&payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY, "{}", field);
+
+        let body: Value = test::read_body_json(resp).await;
+        let messages = body["errors"][field].as_array().unwrap();
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.as_str().unwrap().contains(expected)),
+            "{}: {:?}",
+            field,
+            messages
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_outdated_password_hash_is_upgraded_on_login() {
+    use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+    use schema::users::dsl as users_dsl;
+
+    let user_email = "rehash_on_login@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(web::scope("/api").service(register).service(obtain)),
+    )
+    .await;
+
+    register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+
+    // Swap in a hash made with cheaper parameters than the configured ones
+    let weak_hasher = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(8192, 1, 1, None).unwrap(),
+    );
+    let salt = SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+    let weak_hash = weak_hasher
+        .hash_password(b"Sunflower-Orbit-42", &salt)
+        .unwrap()
+        .to_string();
+    assert!(services::passwords::needs_rehash(&weak_hash));
+
+    let mut conn = TEST_POOL.get().unwrap();
+    diesel::update(users_dsl::users.filter(users_dsl::email.eq(user_email)))
+        .set(users_dsl::password.eq(&weak_hash))
+        .execute(&mut conn)
+        .unwrap();
+
+    obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+
+    let stored_hash: String = users_dsl::users
+        .filter(users_dsl::email.eq(user_email))
+        .select(users_dsl::password.assume_not_null())
+        .first(&mut conn)
+        .unwrap();
+    assert_ne!(stored_hash, weak_hash);
+    assert!(!services::passwords::needs_rehash(&stored_hash));
+
+    // The upgraded hash still accepts the same password
+    obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_imported_legacy_hashes_log_in_and_upgrade() {
+    use schema::users::dsl as users_dsl;
+
+    let bcrypt_email = "import_bcrypt@example.com";
+    let pbkdf2_email = "import_pbkdf2@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(web::scope("/api").service(obtain)),
+    )
+    .await;
+
+    let mut conn = TEST_POOL.get().unwrap();
+
+    let bcrypt_hash = bcrypt::hash("bcrypt password", 4).unwrap();
+    let jsonl = format!(
+        "{}\n\n{}\n",
+        json!({ "email": bcrypt_email, "password_hash": bcrypt_hash, "roles": ["pacilian"] }),
+        json!({ "email": "import_unsupported@example.com", "password_hash": "md5:abc" }),
+    );
+    let summary =
+        services::import::import_users(&mut conn, services::import::read_jsonl(jsonl.as_bytes()));
+    assert_eq!(summary.imported, 1);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].0, 2);
+
+    // Reference hash of "password" from the passlib documentation
+    let csv = format!(
+        "email,password_hash,roles\n{},{},pacilian;caregiver\n",
+        pbkdf2_email,
+        "$pbkdf2-sha256$6400$0ZrzXitFSGltTQnBWOsdAw$Y11AchqV4b0sUisdZd0Xr97KWoymNE0LNNrnEgY4H9M"
+    );
+    let summary =
+        services::import::import_users(&mut conn, services::import::read_csv(csv.as_bytes()));
+    assert_eq!(summary.imported, 1, "{:?}", summary.failed);
+
+    // Importing the same export again leaves existing users alone
+    let summary =
+        services::import::import_users(&mut conn, services::import::read_csv(csv.as_bytes()));
+    assert_eq!(summary.already_existing, 1);
+
+    let pbkdf2_tokens = obtain_tokens(&app, pbkdf2_email, "password").await;
+    assert_eq!(
+        access_claims(&pbkdf2_tokens).roles,
+        vec!["caregiver", "pacilian"]
+    );
+    obtain_tokens(&app, bcrypt_email, "bcrypt password").await;
+
+    for email in [bcrypt_email, pbkdf2_email] {
+        let stored_hash: String = users_dsl::users
+            .filter(users_dsl::email.eq(email))
+            .select(users_dsl::password.assume_not_null())
+            .first(&mut conn)
+            .unwrap();
+        assert!(stored_hash.starts_with("$argon2id$"));
+    }
+
+    obtain_tokens(&app, pbkdf2_email, "password").await;
+
+    cleanup_user_and_tokens_by_email(bcrypt_email);
+    cleanup_user_and_tokens_by_email(pbkdf2_email);
+}
+
+#[actix_web::test]
+async fn test_register_rejects_weak_passwords() {
+    let user_email = "weak_password@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(test_mailer())
+            .service(web::scope("/api").service(register)),
+    )
+    .await;
+
+    for (password, expected) in [
+        ("", "must be at least 8 characters long"),
+        ("password123", "is too easy to guess"),
+        ("qwertyuiop", "is too easy to guess"),
+        (
+            "weak_password-Orbit-42",
+            "must not contain your email address or name",
+        ),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/api/register")
+            .set_json(json!({ "email": user_email, "password": password, "role": "pacilian" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{}",
+            password
+        );
+
+        let body: Value = test::read_body_json(resp).await;
+        let messages = body["errors"]["password"].as_array().unwrap();
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.as_str().unwrap().starts_with(expected)),
+            "{}: {:?}",
+            password,
+            messages
+        );
+    }
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_change_password() {
+    let user_email = "change_password@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(refresh)
+                    .service(change_password),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let access = tokens["access"].as_str().unwrap();
+
+    let change_req = |current: &str, new: &str| {
+        test::TestRequest::post()
+            .uri("/api/me/password")
+            .insert_header(("Authorization", format!("Bearer {}", access)))
+            .set_json(json!({ "current_password": current, "new_password": new }))
+            .to_request()
+    };
+
+    let wrong_resp = test::call_service(&app, change_req("not it", "Lantern-Meadow-77")).await;
+    assert_eq!(wrong_resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(wrong_resp).await;
+    assert_eq!(body["errors"]["current_password"][0], "is incorrect");
+
+    let weak_resp = test::call_service(&app, change_req("Sunflower-Orbit-42", "12345678")).await;
+    assert_eq!(weak_resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(weak_resp).await;
+    assert!(body["errors"]["new_password"].is_array());
+
+    let ok_resp =
+        test::call_service(&app, change_req("Sunflower-Orbit-42", "Lantern-Meadow-77")).await;
+    assert_eq!(ok_resp.status(), StatusCode::OK);
+
+    // Existing sessions end with the password change
+    let refresh_req = test::TestRequest::post()
+        .uri("/api/token/refresh")
+        .set_json(json!({ "refresh_token": tokens["refresh"] }))
+        .to_request();
+    let refresh_resp = test::call_service(&app, refresh_req).await;
+    assert!(!refresh_resp.status().is_success());
+
+    obtain_tokens(&app, user_email, "Lantern-Meadow-77").await;
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_breached_password_corpus_lookup() {
+    use sha1::{Digest, Sha1};
+    use std::path::Path;
+
+    let sha1_hex = |password: &str| {
+        Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<String>()
+    };
+
+    let breached = [
+        "password",
+        "letmein",
+        "hunter2",
+        "correct horse battery staple",
+    ];
+    let mut hashes: Vec<String> = breached.iter().map(|p| sha1_hex(p)).collect();
+    hashes.sort();
+
+    let corpus_dir = std::env::temp_dir().join(format!("breached-{}", Uuid::new_v4()));
+    fs::create_dir_all(&corpus_dir).unwrap();
+
+    let sorted_file = corpus_dir.join("sorted.txt");
+    let lines: Vec<String> = hashes
+        .iter()
+        .enumerate()
+        .map(|(count, hash)| format!("{}:{}", hash, count + 1))
+        .collect();
+    fs::write(&sorted_file, lines.join("\r\n")).unwrap();
+
+    let range_dir = corpus_dir.join("ranges");
+    fs::create_dir_all(&range_dir).unwrap();
+    for hash in &hashes {
+        let (prefix, suffix) = hash.split_at(5);
+        fs::write(
+            range_dir.join(format!("{}.txt", prefix)),
+            format!("{}:3\n", suffix),
+        )
+        .unwrap();
+    }
+
+    for corpus in [sorted_file.as_path(), range_dir.as_path()] {
+        for password in breached {
+            assert!(services::breached_passwords::is_breached(corpus, password).unwrap());
+        }
+        for password in ["Sunflower-Orbit-42", "", "Password"] {
+            assert!(!services::breached_passwords::is_breached(corpus, password).unwrap());
+        }
+    }
+
+    assert!(services::breached_passwords::is_breached(Path::new("/nonexistent"), "x").is_err());
+
+    fs::remove_dir_all(corpus_dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_totp_enrollment_and_mfa_login() {
+    let user_email = "totp_login@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(refresh)
+                    .service(verify_mfa)
+                    .service(begin_totp_enrollment)
+                    .service(confirm_totp_enrollment),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    assert_eq!(access_claims(&tokens).amr, vec!["pwd"]);
+    let access = tokens["access"].as_str().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/me/mfa/totp")
+        .insert_header(("Authorization", format!("Bearer {}", access)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let enrollment: Value = test::read_body_json(resp).await;
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+    assert!(enrollment["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+
+    let confirm_req = |code: &str| {
+        test::TestRequest::post()
+            .uri("/api/me/mfa/totp/confirm")
+            .insert_header(("Authorization", format!("Bearer {}", access)))
+            .set_json(json!({ "code": code }))
+            .to_request()
+    };
+
+    let wrong_code = if totp_code(&secret, 0) == "000000" {
+        "111111"
+    } else {
+        "000000"
+    };
+    let resp = test::call_service(&app, confirm_req(wrong_code)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let confirm_code = totp_code(&secret, 0);
+    let resp = test::call_service(&app, confirm_req(&confirm_code)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let recovery_codes: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        recovery_codes["recovery_codes"].as_array().unwrap().len(),
+        10
+    );
+
+    // The password alone now only gets a challenge
+    let challenge = obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+    assert_eq!(challenge["mfa_required"], true);
+    assert_eq!(challenge["enrollment_required"], false);
+    assert!(challenge.get("access").is_none());
+    let mfa_token = challenge["mfa_token"].as_str().unwrap();
+
+    let verify_req = |code: &str| {
+        test::TestRequest::post()
+            .uri("/api/token/mfa")
+            .set_json(json!({ "mfa_token": mfa_token, "code": code }))
+            .to_request()
+    };
+
+    // The code used to confirm cannot be used again
+    let resp = test::call_service(&app, verify_req(&confirm_code)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let next_code = totp_code(&secret, 1);
+    let resp = test::call_service(&app, verify_req(&next_code)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&tokens).amr, vec!["pwd", "otp", "mfa"]);
+
+    // The challenge is used up
+    let resp = test::call_service(&app, verify_req(&next_code)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Refreshed access tokens keep the methods of the original sign in
+    let req = test::TestRequest::post()
+        .uri("/api/token/refresh")
+        .set_json(json!({ "refresh_token": tokens["refresh"] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let refreshed: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&refreshed).amr, vec!["pwd", "otp", "mfa"]);
+
+    // An account suspended after the password step gets no tokens
+    let challenge = obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+    let user_id = Uuid::parse_str(&access_claims(&tokens).user_id).unwrap();
+    repository::users::set_user_status(
+        &mut TEST_POOL.get().unwrap(),
+        user_id,
+        models::users::UserStatus::Suspended,
+    )
+    .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/token/mfa")
+        .set_json(json!({ "mfa_token": challenge["mfa_token"], "code": totp_code(&secret, 2) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body = test::read_body(resp).await;
+    assert!(std::str::from_utf8(&body).unwrap().contains("suspended"));
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_mfa_required_role_enrolls_while_signing_in() {
+    let caregiver_email = "mfa_policy_caregiver@example.com";
+    let pacilian_email = "mfa_policy_pacilian@example.com";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(verify_mfa)
+                    .service(begin_challenge_totp_enrollment),
+            ),
+    )
+    .await;
+
+    register_and_obtain(&app, caregiver_email, "Sunflower-Orbit-42", "caregiver").await;
+    grant_role_by_email(caregiver_email, "caregiver");
+    register_and_obtain(&app, pacilian_email, "Sunflower-Orbit-42", "pacilian").await;
+
+    let required_roles = vec!["caregiver".to_string()];
+    let mut conn = TEST_POOL.get().unwrap();
+
+    let pacilian = repository::users::get_user_by_email(&mut conn, pacilian_email).unwrap();
+    assert!(
+        services::mfa::start_login(&mut conn, &pacilian, &required_roles)
+            .unwrap()
+            .is_none()
+    );
+
+    let caregiver = repository::users::get_user_by_email(&mut conn, caregiver_email).unwrap();
+    let challenge = services::mfa::start_login(&mut conn, &caregiver, &required_roles)
+        .unwrap()
+        .expect("Caregivers must not get tokens without a second factor");
+    assert!(challenge.enrollment_required);
+
+    let req = test::TestRequest::post()
+        .uri("/api/token/mfa/enroll")
+        .set_json(json!({ "mfa_token": challenge.mfa_token }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let enrollment: Value = test::read_body_json(resp).await;
+    let secret = enrollment["secret"].as_str().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/token/mfa")
+        .set_json(json!({ "mfa_token": challenge.mfa_token, "code": totp_code(secret, 0) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&tokens).amr, vec!["pwd", "otp", "mfa"]);
+    assert_eq!(tokens["recovery_codes"].as_array().unwrap().len(), 10);
+
+    // Setting up TOTP turned it on for every later sign in
+    let challenge = obtain_tokens(&app, caregiver_email, "Sunflower-Orbit-42").await;
+    assert_eq!(challenge["mfa_required"], true);
+    assert_eq!(challenge["enrollment_required"], false);
+
+    cleanup_user_and_tokens_by_email(caregiver_email);
+    cleanup_user_and_tokens_by_email(pacilian_email);
+}
+
+#[actix_web::test]
+async fn test_recovery_codes() {
+    let user_email = "recovery_codes@example.com";
+    let mailer = Arc::new(MemoryMailSender::default());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(verify_mfa)
+                    .service(begin_totp_enrollment)
+                    .service(confirm_totp_enrollment)
+                    .service(get_recovery_code_status)
+                    .service(regenerate_recovery_codes),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let access = tokens["access"].as_str().unwrap().to_string();
+    let (_secret, recovery_codes) = enroll_totp(&app, &access).await;
+
+    let remaining = |access: String| {
+        test::TestRequest::get()
+            .uri("/api/me/mfa/recovery-codes")
+            .insert_header(("Authorization", format!("Bearer {}", access)))
+            .to_request()
+    };
+    let status: Value = test::call_and_read_body_json(&app, remaining(access.clone())).await;
+    assert_eq!(status["remaining"], 10);
+
+    let sign_in_with = |mfa_token: &str, recovery_code: &str| {
+        test::TestRequest::post()
+            .uri("/api/token/mfa")
+            .set_json(json!({ "mfa_token": mfa_token, "recovery_code": recovery_code }))
+            .to_request()
+    };
+
+    // Accepted without the dash and in any case
+    let challenge = obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+    let mfa_token = challenge["mfa_token"].as_str().unwrap();
+    let typed_code = recovery_codes[0].replace('-', "").to_uppercase();
+    let resp = test::call_service(&app, sign_in_with(mfa_token, &typed_code)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(tokens["recovery_codes_remaining"], 9);
+
+    let mail = mailer.sent_to(user_email);
+    assert!(mail.last().unwrap().subject.contains("recovery code"));
+
+    // Each code works once
+    let challenge = obtain_tokens(&app, user_email, "Sunflower-Orbit-42").await;
+    let mfa_token = challenge["mfa_token"].as_str().unwrap();
+    let resp = test::call_service(&app, sign_in_with(mfa_token, &recovery_codes[0])).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let regenerate = |password: &str| {
+        test::TestRequest::post()
+            .uri("/api/me/mfa/recovery-codes")
+            .insert_header(("Authorization", format!("Bearer {}", access)))
+            .set_json(json!({ "password": password }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, regenerate("Lantern-Meadow-77")).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = test::call_service(&app, regenerate("Sunflower-Orbit-42")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let regenerated: Value = test::read_body_json(resp).await;
+    assert_eq!(regenerated["recovery_codes"].as_array().unwrap().len(), 10);
+
+    // The old codes are gone
+    let resp = test::call_service(&app, sign_in_with(mfa_token, &recovery_codes[1])).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let status: Value = test::call_and_read_body_json(&app, remaining(access.clone())).await;
+    assert_eq!(status["remaining"], 10);
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_passkey_registration_and_login() {
+    let user_email = "passkey_login@example.com";
+    let origin = config::get().webauthn.allowed_origins[0].clone();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(begin_webauthn_registration)
+                    .service(finish_webauthn_registration)
+                    .service(list_webauthn_credentials)
+                    .service(delete_webauthn_credential)
+                    .service(begin_webauthn_login)
+                    .service(finish_webauthn_login),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let access = tokens["access"].as_str().unwrap().to_string();
+    let mut authenticator = SoftwareAuthenticator::new(user_email);
+
+    let registration_options = || {
+        test::TestRequest::post()
+            .uri("/api/me/webauthn/register/options")
+            .insert_header(("Authorization", format!("Bearer {}", access)))
+            .to_request()
+    };
+    let finish_registration = |body: Value| {
+        test::TestRequest::post()
+            .uri("/api/me/webauthn/register")
+            .insert_header(("Authorization", format!("Bearer {}", access)))
+            .set_json(body)
+            .to_request()
+    };
+
+    let options: Value = test::call_and_read_body_json(&app, registration_options()).await;
+    assert_eq!(options["rp"]["id"], config::get().webauthn.rp_id);
+    assert_eq!(options["pubKeyCredParams"][0]["alg"], -7);
+    assert_eq!(options["authenticatorSelection"]["residentKey"], "required");
+
+    // An origin the relying party does not know is refused
+    let resp = test::call_service(
+        &app,
+        finish_registration(authenticator.register(&options, "https://evil.example")),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(
+        &app,
+        finish_registration(authenticator.register(&options, &origin)),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // The challenge was used up by the registration
+    let resp = test::call_service(
+        &app,
+        finish_registration(authenticator.register(&options, &origin)),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // The new credential is excluded from later registrations
+    let options: Value = test::call_and_read_body_json(&app, registration_options()).await;
+    assert_eq!(
+        options["excludeCredentials"][0]["id"],
+        authenticator.credential_id()
+    );
+    let resp = test::call_service(
+        &app,
+        finish_registration(authenticator.register(&options, &origin)),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let login_options = || {
+        test::TestRequest::post()
+            .uri("/api/webauthn/login/options")
+            .set_json(json!({ "email": user_email }))
+            .to_request()
+    };
+    let finish_login = |body: Value| {
+        test::TestRequest::post()
+            .uri("/api/webauthn/login")
+            .set_json(body)
+            .to_request()
+    };
+
+    // Nothing in the options depends on the account, so they cannot be
+    // used to find out whether it exists
+    let options: Value = test::call_and_read_body_json(&app, login_options()).await;
+    assert!(options.get("allowCredentials").is_none());
+    let resp =
+        test::call_service(&app, finish_login(authenticator.sign_in(&options, &origin))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&tokens).amr, vec!["hwk", "mfa"]);
+
+    // A cloned authenticator replaying an old counter value is refused
+    let options: Value = test::call_and_read_body_json(&app, login_options()).await;
+    authenticator.sign_count -= 1;
+    let resp =
+        test::call_service(&app, finish_login(authenticator.sign_in(&options, &origin))).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let options: Value = test::call_and_read_body_json(&app, login_options()).await;
+    let resp =
+        test::call_service(&app, finish_login(authenticator.sign_in(&options, &origin))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri("/api/me/webauthn/credentials")
+        .insert_header(("Authorization", format!("Bearer {}", access)))
+        .to_request();
+    let credentials: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(credentials[0]["name"], "Test key");
+    assert!(!credentials[0]["last_used_at"].is_null());
+
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/api/me/webauthn/credentials/{}",
+            authenticator.credential_id()
+        ))
+        .insert_header(("Authorization", format!("Bearer {}", access)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // A removed passkey no longer signs in
+    let options: Value = test::call_and_read_body_json(&app, login_options()).await;
+    let resp =
+        test::call_service(&app, finish_login(authenticator.sign_in(&options, &origin))).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_email_link_and_code_login() {
+    let user_email = "email_login@example.com";
+    let mailer = Arc::new(MemoryMailSender::default());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(begin_totp_enrollment)
+                    .service(confirm_totp_enrollment)
+                    .service(request_login_link)
+                    .service(request_login_code)
+                    .service(redeem_login_link)
+                    .service(verify_login_code),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, "Sunflower-Orbit-42", "pacilian").await;
+    let access = tokens["access"].as_str().unwrap().to_string();
+
+    let request = |uri: &str, email: &str| {
+        test::TestRequest::post()
+            .uri(uri)
+            .set_json(json!({ "email": email }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request("/api/login/email-link", user_email)).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let started: Value = test::read_body_json(resp).await;
+    let device_token = started["device_token"].as_str().unwrap().to_string();
+
+    let mail = mailer.sent_to(user_email);
+    let body = &mail.last().unwrap().body;
+    let link_token = body
+        .split("token=")
+        .nth(1)
+        .unwrap()
+        .split_whitespace()
+        .next()
+        .unwrap();
+
+    let redeem = |token: &str, device_token: &str| {
+        test::TestRequest::post()
+            .uri("/api/login/email-link/redeem")
+            .set_json(json!({ "token": token, "device_token": device_token }))
+            .to_request()
+    };
+
+    // The link is useless on any other device
+    let resp = test::call_service(&app, redeem(link_token, "another-device")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app, redeem(link_token, &device_token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&tokens).amr, vec!["otp"]);
+
+    let resp = test::call_service(&app, redeem(link_token, &device_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Unknown addresses get the same answer and no email
+    let resp =
+        test::call_service(&app, request("/api/login/email-otp", "nobody@example.com")).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    assert!(mailer.sent_to("nobody@example.com").is_empty());
+
+    let resp = test::call_service(&app, request("/api/login/email-otp", user_email)).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let started: Value = test::read_body_json(resp).await;
+    let device_token = started["device_token"].as_str().unwrap().to_string();
+
+    let mail = mailer.sent_to(user_email);
+    let code = mail
+        .last()
+        .unwrap()
+        .subject
+        .rsplit(' ')
+        .next()
+        .unwrap()
+        .to_string();
+    assert_eq!(code.len(), 6);
+
+    let verify = |code: &str, device_token: &str| {
+        test::TestRequest::post()
+            .uri("/api/login/email-otp/verify")
+            .set_json(json!({ "email": user_email, "code": code, "device_token": device_token }))
+            .to_request()
+    };
+
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    let resp = test::call_service(&app, verify(wrong_code, &device_token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app, verify(&code, "another-device")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app, verify(&code, &device_token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&tokens).amr, vec!["otp"]);
+
+    // Once a second factor is set up, an email alone is no longer enough
+    enroll_totp(&app, &access).await;
+
+    let resp = test::call_service(&app, request("/api/login/email-otp", user_email)).await;
+    let started: Value = test::read_body_json(resp).await;
+    let device_token = started["device_token"].as_str().unwrap().to_string();
+    let mail = mailer.sent_to(user_email);
+    let code = mail
+        .last()
+        .unwrap()
+        .subject
+        .rsplit(' ')
+        .next()
+        .unwrap()
+        .to_string();
+
+    let resp = test::call_service(&app, verify(&code, &device_token)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_phone_registration_and_sms_login() {
+    let phone_number = "+6281234567890";
+    let sms_sender = Arc::new(MemorySmsSender::default());
+    cleanup_user_by_phone_number(phone_number);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::from(sms_sender.clone() as Arc<dyn SmsSender>))
+            .service(
+                web::scope("/api")
+                    .service(request_phone_code)
+                    .service(register_with_phone)
+                    .service(login_with_phone),
+            ),
+    )
+    .await;
+
+    let request_code = || {
+        test::TestRequest::post()
+            .uri("/api/phone/code")
+            // National form, as most users type it
+            .set_json(json!({ "phone_number": "0812-3456-7890" }))
+            .to_request()
+    };
+    let end_cooldown = || {
+        use schema::sms_codes::dsl::*;
+
+        diesel::update(sms_codes.filter(phone_number.eq("+6281234567890")))
+            .set(sent_at.eq(chrono::Utc::now().naive_utc() - chrono::Duration::hours(1)))
+            .execute(&mut TEST_POOL.get().unwrap())
+            .unwrap();
+    };
+
+    let resp = test::call_service(&app, request_code()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let code = sms_sender.last_code(phone_number);
+
+    // Asking again right away is refused
+    let resp = test::call_service(&app, request_code()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp.headers().contains_key("retry-after"));
+
+    let phone_register = |code: &str| {
+        test::TestRequest::post()
+            .uri("/api/phone/register")
+            .set_json(json!({ "phone_number": phone_number, "code": code, "role": "pacilian" }))
+            .to_request()
+    };
+
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    let resp = test::call_service(&app, phone_register(wrong_code)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&app, phone_register(&code)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&tokens).amr, vec!["sms"]);
+
+    let phone_login = |code: &str| {
+        test::TestRequest::post()
+            .uri("/api/phone/login")
+            .set_json(json!({ "phone_number": phone_number, "code": code }))
+            .to_request()
+    };
+
+    // A code stops working after too many wrong guesses, even the right one
+    end_cooldown();
+    test::call_service(&app, request_code()).await;
+    let code = sms_sender.last_code(phone_number);
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    let max_attempts = config::get().sms.max_code_attempts;
+
+    for attempt in 1..=max_attempts {
+        let resp = test::call_service(&app, phone_login(wrong_code)).await;
+        let expected = if attempt < max_attempts {
+            StatusCode::UNAUTHORIZED
+        } else {
+            StatusCode::TOO_MANY_REQUESTS
+        };
+        assert_eq!(resp.status(), expected);
+    }
+    let resp = test::call_service(&app, phone_login(&code)).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    end_cooldown();
+    test::call_service(&app, request_code()).await;
+    let resp = test::call_service(&app, phone_login(&sms_sender.last_code(phone_number))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tokens: Value = test::read_body_json(resp).await;
+    assert_eq!(access_claims(&tokens).amr, vec!["sms"]);
+
+    // The number can only be registered once
+    end_cooldown();
+    test::call_service(&app, request_code()).await;
+    let resp = test::call_service(&app, phone_register(&sms_sender.last_code(phone_number))).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    cleanup_user_by_phone_number(phone_number);
+}
+
+#[actix_web::test]
+async fn test_step_up_renews_and_raises_authentication() {
+    let user_email = "step_up@example.com";
+    let user_password = "Sunflower-Orbit-42";
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(refresh)
+                    .service(step_up)
+                    .service(begin_totp_enrollment)
+                    .service(confirm_totp_enrollment)
+                    .service(get_profile)
+                    .service(update_profile),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, user_password, "pacilian").await;
+    let claims = access_claims(&tokens);
+    assert_eq!(claims.acr, "1");
+    let signed_in_at = claims.auth_time as i64;
+    assert!((chrono::Utc::now().timestamp() - signed_in_at).abs() < 60);
+
+    let get_profile_req = |tokens: &Value| {
+        test::TestRequest::get()
+            .uri("/api/me/profile")
+            .insert_header((
+                "Authorization",
+                format!("Bearer {}", tokens["access"].as_str().unwrap()),
+            ))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, get_profile_req(&tokens)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Pretend the sign in was an hour ago; refreshing must not renew it
+    {
+        use schema::refresh_tokens::dsl::*;
+
+        diesel::update(refresh_tokens.filter(token_str.eq(tokens["refresh"].as_str().unwrap())))
+            .set(auth_time.eq(chrono::Utc::now().naive_utc() - chrono::Duration::hours(1)))
+            .execute(&mut TEST_POOL.get().unwrap())
+            .unwrap();
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/token/refresh")
+        .set_json(json!({ "refresh_token": tokens["refresh"] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let refreshed: Value = test::read_body_json(resp).await;
+    let claims = access_claims(&refreshed);
+    assert_eq!(claims.acr, "1");
+    assert!(chrono::Utc::now().timestamp() - claims.auth_time as i64 >= 3600);
+
+    let resp = test::call_service(&app, get_profile_req(&refreshed)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let challenge = resp
+        .headers()
+        .get("www-authenticate")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(challenge.contains("error=\"insufficient_user_authentication\""));
+    assert!(challenge.contains("max_age=900"));
+
+    // An empty update would send the profile back all the same
+    let req = test::TestRequest::patch()
+        .uri("/api/me/profile")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", refreshed["access"].as_str().unwrap()),
+        ))
+        .set_json(json!({}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let step_up_req = |tokens: &Value, body: Value| {
+        test::TestRequest::post()
+            .uri("/api/token/step-up")
+            .insert_header((
+                "Authorization",
+                format!("Bearer {}", tokens["access"].as_str().unwrap()),
+            ))
+            .set_json(body)
+            .to_request()
+    };
+
+    let resp = test::call_service(
+        &app,
+        step_up_req(&refreshed, json!({ "password": "Wrong-Orbit-42" })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(
+        &app,
+        step_up_req(&refreshed, json!({ "password": user_password })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let stepped_up: Value = test::read_body_json(resp).await;
+    let claims = access_claims(&stepped_up);
+    assert_eq!(claims.amr, vec!["pwd"]);
+    assert_eq!(claims.acr, "1");
+    assert!(chrono::Utc::now().timestamp() - (claims.auth_time as i64) < 60);
+
+    let resp = test::call_service(&app, get_profile_req(&stepped_up)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Once TOTP is on, stepping up takes a code too and reaches the higher level
+    let req = test::TestRequest::post()
+        .uri("/api/me/mfa/totp")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", stepped_up["access"].as_str().unwrap()),
+        ))
+        .to_request();
+    let enrollment: Value = test::call_and_read_body_json(&app, req).await;
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/api/me/mfa/totp/confirm")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", stepped_up["access"].as_str().unwrap()),
+        ))
+        .set_json(json!({ "code": totp_code(&secret, 0) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(
+        &app,
+        step_up_req(&stepped_up, json!({ "password": user_password })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = test::call_service(
+        &app,
+        step_up_req(
+            &stepped_up,
+            json!({ "password": user_password, "code": totp_code(&secret, 1) }),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let stepped_up: Value = test::read_body_json(resp).await;
+    let claims = access_claims(&stepped_up);
+    assert_eq!(claims.amr, vec!["pwd", "otp", "mfa"]);
+    assert_eq!(claims.acr, "2");
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_caregiver_application_review() {
+    let applicant_email = "caregiver_review_applicant@example.com";
+    let admin_email = "caregiver_review_admin@example.com";
+    let password = "Sunflower-Orbit-42";
+    let mailer = Arc::new(MemoryMailSender::default());
+    cleanup_user_and_tokens_by_email(applicant_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(submit_caregiver_application)
+                    .service(get_own_caregiver_application)
+                    .service(list_caregiver_applications)
+                    .service(get_caregiver_application)
+                    .service(approve_caregiver_application)
+                    .service(reject_caregiver_application),
+            ),
+    )
+    .await;
+
+    let register_req = |payload: Value| {
+        test::TestRequest::post()
+            .uri("/api/register")
+            .set_json(payload)
+            .to_request()
+    };
+
+    // Caregivers have to say what to verify, with documents behind https URLs
+    let resp = test::call_service(
+        &app,
+        register_req(
+            json!({ "email": applicant_email, "password": password, "role": "caregiver" }),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let mut insecure_application = caregiver_application_json();
+    insecure_application["documents"][0]["url"] = json!("http://files.example.com/str.pdf");
+    let resp = test::call_service(
+        &app,
+        register_req(json!({
+            "email": applicant_email,
+            "password": password,
+            "role": "caregiver",
+            "caregiver": insecure_application
+        })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let errors: Value = test::read_body_json(resp).await;
+    assert!(errors["errors"]["caregiver.documents[0].url"].is_array());
+
+    let tokens = register_and_obtain(&app, applicant_email, password, "caregiver").await;
+    assert_eq!(access_claims(&tokens).roles, vec!["pacilian"]);
+    let applicant_auth = (
+        "Authorization",
+        format!("Bearer {}", tokens["access"].as_str().unwrap()),
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/me/caregiver-application")
+        .insert_header(applicant_auth.clone())
+        .to_request();
+    let application: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(application["status"], "pending_verification");
+    assert_eq!(application["str_number"], "3121100321012345");
+    assert_eq!(
+        application["documents"][0]["document_type"],
+        "str_certificate"
+    );
+    let application_id = application["id"].as_str().unwrap().to_string();
+
+    let resubmit_req = || {
+        test::TestRequest::post()
+            .uri("/api/me/caregiver-application")
+            .insert_header(applicant_auth.clone())
+            .set_json(caregiver_application_json())
+            .to_request()
+    };
+    let resp = test::call_service(&app, resubmit_req()).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    // Reviewing takes the caregivers:verify permission
+    let req = test::TestRequest::get()
+        .uri("/api/admin/caregiver-applications")
+        .insert_header(applicant_auth.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    register_and_obtain(&app, admin_email, password, "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, password).await;
+    let admin_auth = (
+        "Authorization",
+        format!("Bearer {}", admin_tokens["access"].as_str().unwrap()),
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/caregiver-applications?status=pending_verification")
+        .insert_header(admin_auth.clone())
+        .to_request();
+    let pending: Value = test::call_and_read_body_json(&app, req).await;
+    assert!(pending
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|application| application["id"] == application_id.as_str()));
+
+    let reject_req = |application_id: &str| {
+        test::TestRequest::post()
+            .uri(&format!(
+                "/api/admin/caregiver-applications/{}/reject",
+                application_id
+            ))
+            .insert_header(admin_auth.clone())
+            .set_json(json!({ "reason": "The STR certificate is not readable" }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, reject_req(&application_id)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let rejected: Value = test::read_body_json(resp).await;
+    assert_eq!(rejected["status"], "rejected");
+
+    // A decision is final; the applicant applies again instead
+    let resp = test::call_service(&app, reject_req(&application_id)).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = test::call_service(&app, resubmit_req()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resubmitted: Value = test::read_body_json(resp).await;
+    let resubmitted_id = resubmitted["id"].as_str().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/api/admin/caregiver-applications/{}/approve",
+            resubmitted_id
+        ))
+        .insert_header(admin_auth.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let claims = access_claims(&obtain_tokens(&app, applicant_email, password).await);
+    assert_eq!(claims.roles, vec!["caregiver", "pacilian"]);
+    assert!(claims.permissions.contains(&"patients:read".to_string()));
+
+    // The applicant heard about every step
+    let subjects: Vec<String> = mailer
+        .sent_to(applicant_email)
+        .into_iter()
+        .map(|message| message.subject)
+        .collect();
+    assert_eq!(
+        subjects,
+        vec![
+            "Welcome to PandaCare",
+            "Your caregiver application was not approved",
+            "We received your caregiver application",
+            "Your caregiver application has been approved",
+        ]
+    );
+
+    cleanup_user_and_tokens_by_email(applicant_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+}
+
+#[actix_web::test]
+async fn test_delegation_invite_accept_exchange_and_revoke() {
+    let patient_email = "delegation_patient@example.com";
+    let delegate_email = "delegation_delegate@example.com";
+    let stranger_email = "delegation_stranger@example.com";
+    let password = "Sunflower-Orbit-42";
+    let mailer = Arc::new(MemoryMailSender::default());
+    cleanup_user_and_tokens_by_email(patient_email);
+    cleanup_user_and_tokens_by_email(delegate_email);
+    cleanup_user_and_tokens_by_email(stranger_email);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(get_profile)
+                    .service(invite_delegate)
+                    .service(list_delegations)
+                    .service(revoke_delegation)
+                    .service(accept_delegation)
+                    .service(exchange_token),
+            ),
+    )
+    .await;
+
+    let bearer = |tokens: &Value| {
+        (
+            "Authorization",
+            format!("Bearer {}", tokens["access"].as_str().unwrap()),
+        )
+    };
+    let patient_tokens = register_and_obtain(&app, patient_email, password, "pacilian").await;
+    let delegate_tokens = register_and_obtain(&app, delegate_email, password, "pacilian").await;
+    let stranger_tokens = register_and_obtain(&app, stranger_email, password, "pacilian").await;
+    let patient_id = access_claims(&patient_tokens).user_id;
+    let delegate_id = access_claims(&delegate_tokens).user_id;
+
+    let req = test::TestRequest::post()
+        .uri("/api/me/delegations")
+        .insert_header(bearer(&patient_tokens))
+        .set_json(json!({ "email": delegate_email, "scopes": [] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::post()
+        .uri("/api/me/delegations")
+        .insert_header(bearer(&patient_tokens))
+        .set_json(json!({
+            "email": delegate_email.to_uppercase(),
+            "scopes": ["appointments:read", "records:read"]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let delegation: Value = test::read_body_json(resp).await;
+    assert_eq!(delegation["status"], "pending");
+    assert_eq!(delegation["scopes"], "appointments:read records:read");
+    let delegation_id = delegation["id"].as_str().unwrap().to_string();
+
+    let invitation = mailer
+        .sent_to(&delegate_email.to_uppercase())
+        .pop()
+        .unwrap();
+    let token = invitation
+        .body
+        .split("token=")
+        .nth(1)
+        .unwrap()
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_string();
+
+    let accept_req = |tokens: &Value, token: &str| {
+        test::TestRequest::post()
+            .uri("/api/delegations/accept")
+            .insert_header(bearer(tokens))
+            .set_json(json!({ "token": token }))
+            .to_request()
+    };
+    let exchange_req = |tokens: &Value, scope: Option<&str>| {
+        test::TestRequest::post()
+            .uri("/api/token/exchange")
+            .insert_header(bearer(tokens))
+            .set_json(json!({ "subject_id": patient_id, "scope": scope }))
+            .to_request()
+    };
+
+    // Nothing to exchange before the invitation is accepted
+    let resp = test::call_service(&app, exchange_req(&delegate_tokens, None)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // Only the invited address can accept
+    let resp = test::call_service(&app, accept_req(&stranger_tokens, &token)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = test::call_service(&app, accept_req(&delegate_tokens, &token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let accepted: Value = test::read_body_json(resp).await;
+    assert_eq!(accepted["status"], "active");
+    assert_eq!(accepted["actor_id"], delegate_id.as_str());
+
+    let resp = test::call_service(&app, accept_req(&delegate_tokens, &token)).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get()
+        .uri("/api/me/delegations")
+        .insert_header(bearer(&delegate_tokens))
+        .to_request();
+    let list: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(list["granted"].as_array().unwrap().len(), 0);
+    assert_eq!(list["received"][0]["id"], delegation_id.as_str());
+
+    // Scopes can only be narrowed
+    let resp = test::call_service(
+        &app,
+        exchange_req(&delegate_tokens, Some("prescriptions:read")),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, exchange_req(&stranger_tokens, None)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = test::call_service(
+        &app,
+        exchange_req(&delegate_tokens, Some("appointments:read")),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let exchanged: Value = test::read_body_json(resp).await;
+    assert_eq!(exchanged["token_type"], "Bearer");
+    assert_eq!(exchanged["scope"], "appointments:read");
+
+    let claims = services::jwt::decode_access_token(
+        &TEST_TOKEN_KEYS,
+        exchanged["access_token"].as_str().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims.registered_claims.sub, patient_id);
+    assert_eq!(claims.act.unwrap().sub, delegate_id);
+    assert_eq!(claims.scope.as_deref(), Some("appointments:read"));
+    assert!(claims.roles.is_empty());
+    assert!(claims.permissions.is_empty());
+
+    // Delegated tokens cannot be used on the patient's own account
+    let req = test::TestRequest::get()
+        .uri("/api/me/profile")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", exchanged["access_token"].as_str().unwrap()),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // Only the patient can revoke, after which no more tokens are exchanged
+    let revoke_req = |tokens: &Value| {
+        test::TestRequest::delete()
+            .uri(&format!("/api/me/delegations/{}", delegation_id))
+            .insert_header(bearer(tokens))
+            .to_request()
+    };
+    let resp = test::call_service(&app, revoke_req(&delegate_tokens)).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = test::call_service(&app, revoke_req(&patient_tokens)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let revoked: Value = test::read_body_json(resp).await;
+    assert_eq!(revoked["status"], "revoked");
+
+    let resp = test::call_service(&app, revoke_req(&patient_tokens)).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let resp = test::call_service(&app, exchange_req(&delegate_tokens, None)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    cleanup_user_and_tokens_by_email(patient_email);
+    cleanup_user_and_tokens_by_email(delegate_email);
+    cleanup_user_and_tokens_by_email(stranger_email);
+}
+
+#[actix_web::test]
+async fn test_dependent_accounts() {
+    let guardian_email = "dependent_guardian@example.com";
+    let dependent_email = "dependent_grown_up@example.com";
+    let stranger_email = "dependent_stranger@example.com";
+    let password = "Sunflower-Orbit-42";
+    let mailer = Arc::new(MemoryMailSender::default());
+    cleanup_user_and_tokens_by_email(guardian_email);
+    cleanup_user_and_tokens_by_email(dependent_email);
+    cleanup_user_and_tokens_by_email(stranger_email);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::new(DataKey::from_base64(TEST_DATA_KEY).unwrap()))
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(reset_password)
+                    .service(exchange_token)
+                    .service(create_dependent)
+                    .service(list_dependents)
+                    .service(update_dependent)
+                    .service(convert_dependent),
+            ),
+    )
+    .await;
+
+    let bearer = |tokens: &Value| {
+        (
+            "Authorization",
+            format!("Bearer {}", tokens["access"].as_str().unwrap()),
+        )
+    };
+    let guardian_tokens = register_and_obtain(&app, guardian_email, password, "pacilian").await;
+    let stranger_tokens = register_and_obtain(&app, stranger_email, password, "pacilian").await;
+    let guardian_id = access_claims(&guardian_tokens).user_id;
+
+    let create_req = |date_of_birth: &str| {
+        test::TestRequest::post()
+            .uri("/api/me/dependents")
+            .insert_header(bearer(&guardian_tokens))
+            .set_json(json!({ "full_name": "Budi Santoso", "date_of_birth": date_of_birth }))
+            .to_request()
+    };
+
+    // Adults register on their own
+    let resp = test::call_service(&app, create_req("1990-01-01")).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = test::call_service(&app, create_req("2020-03-14")).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let dependent: Value = test::read_body_json(resp).await;
+    assert_eq!(dependent["full_name"], "Budi Santoso");
+    let dependent_id = dependent["id"].as_str().unwrap().to_string();
+
+    let list_req = || {
+        test::TestRequest::get()
+            .uri("/api/me/dependents")
+            .insert_header(bearer(&guardian_tokens))
+            .to_request()
+    };
+    let dependents: Value = test::call_and_read_body_json(&app, list_req()).await;
+    assert_eq!(dependents[0]["id"], dependent_id.as_str());
+    assert_eq!(dependents[0]["date_of_birth"], "2020-03-14");
+
+    // Dependents carry their NIK, so an old sign in has to step up first
+    let stale_tokens = {
+        use services::jwt::signing::TokenSigner;
+
+        let mut claims = access_claims(&guardian_tokens);
+        claims.auth_time = (chrono::Utc::now().timestamp() - 3600) as usize;
+        json!({ "access": TEST_TOKEN_KEYS.sign(claims).unwrap() })
+    };
+    let req = test::TestRequest::get()
+        .uri("/api/me/dependents")
+        .insert_header(bearer(&stale_tokens))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/me/dependents/{}", dependent_id))
+        .insert_header(bearer(&stale_tokens))
+        .set_json(json!({}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // The guardian's row cannot be deleted out from under the dependent
+    let deleted =
+        diesel::delete(schema::users::table.filter(schema::users::email.eq(guardian_email)))
+            .execute(&mut TEST_POOL.get().unwrap());
+    assert!(matches!(
+        deleted,
+        Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+            _
+        ))
+    ));
+
+    // The guardian acts for the dependent with every scope, others cannot
+    let exchange_req = |tokens: &Value| {
+        test::TestRequest::post()
+            .uri("/api/token/exchange")
+            .insert_header(bearer(tokens))
+            .set_json(json!({ "subject_id": dependent_id }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, exchange_req(&guardian_tokens)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let exchanged: Value = test::read_body_json(resp).await;
+    let claims = services::jwt::decode_access_token(
+        &TEST_TOKEN_KEYS,
+        exchanged["access_token"].as_str().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims.registered_claims.sub, dependent_id);
+    assert_eq!(claims.act.unwrap().sub, guardian_id);
+    assert_eq!(
+        claims.scope.as_deref(),
+        Some("appointments:read appointments:write records:read prescriptions:read")
+    );
+
+    let resp = test::call_service(&app, exchange_req(&stranger_tokens)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/me/dependents/{}", dependent_id))
+        .insert_header(bearer(&stranger_tokens))
+        .set_json(json!({ "full_name": "Someone Else" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let convert_req = || {
+        test::TestRequest::post()
+            .uri(&format!("/api/me/dependents/{}/convert", dependent_id))
+            .insert_header(bearer(&guardian_tokens))
+            .set_json(json!({ "email": dependent_email }))
+            .to_request()
+    };
+
+    // Not before they come of age
+    let resp = test::call_service(&app, convert_req()).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/me/dependents/{}", dependent_id))
+        .insert_header(bearer(&guardian_tokens))
+        .set_json(json!({ "date_of_birth": "2000-03-14" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, convert_req()).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    // The former dependent picks their own password
+    let reset_token = mailer
+        .sent_to(dependent_email)
+        .pop()
+        .unwrap()
+        .body
+        .split("token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap()
+        .to_string();
+    let req = test::TestRequest::post()
+        .uri("/api/password/reset")
+        .set_json(json!({ "token": reset_token, "password": "Lantern-Meadow-77" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let claims = access_claims(&obtain_tokens(&app, dependent_email, "Lantern-Meadow-77").await);
+    assert_eq!(claims.user_id, dependent_id);
+    assert_eq!(claims.roles, vec!["pacilian"]);
+
+    // The guardianship has ended
+    let resp = test::call_service(&app, exchange_req(&guardian_tokens)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let dependents: Value = test::call_and_read_body_json(&app, list_req()).await;
+    assert_eq!(dependents, json!([]));
+
+    cleanup_user_and_tokens_by_email(guardian_email);
+    cleanup_user_and_tokens_by_email(dependent_email);
+    cleanup_user_and_tokens_by_email(stranger_email);
+}
+
+#[actix_web::test]
+async fn test_admin_impersonation_is_audited_and_visible() {
+    let admin_email = "impersonation_admin@example.com";
+    let other_admin_email = "impersonation_other_admin@example.com";
+    let user_email = "impersonation_user@example.com";
+    let password = "Sunflower-Orbit-42";
+    cleanup_user_and_tokens_by_email(admin_email);
+    cleanup_user_and_tokens_by_email(other_admin_email);
+    cleanup_user_and_tokens_by_email(user_email);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(impersonate_user)
+                    .service(list_own_impersonations),
+            ),
+    )
+    .await;
+
+    let bearer = |tokens: &Value| {
+        (
+            "Authorization",
+            format!("Bearer {}", tokens["access"].as_str().unwrap()),
+        )
+    };
+    let user_tokens = register_and_obtain(&app, user_email, password, "pacilian").await;
+    let user_id = access_claims(&user_tokens).user_id;
+    register_and_obtain(&app, admin_email, password, "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, password).await;
+    let admin_id = access_claims(&admin_tokens).user_id;
+    register_and_obtain(&app, other_admin_email, password, "pacilian").await;
+    grant_role_by_email(other_admin_email, "admin");
+    let other_admin_id =
+        access_claims(&obtain_tokens(&app, other_admin_email, password).await).user_id;
+
+    let impersonate_req = |tokens: &Value, target: &str, payload: Value| {
+        test::TestRequest::post()
+            .uri(&format!("/api/admin/impersonate/{}", target))
+            .insert_header(bearer(tokens))
+            .set_json(payload)
+            .to_request()
+    };
+    let reason = json!({ "reason": "Ticket #4521: appointment list is empty" });
+
+    let resp = test::call_service(
+        &app,
+        impersonate_req(&user_tokens, &admin_id, reason.clone()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // A reason is required, and only read-only scopes are available
+    let resp = test::call_service(
+        &app,
+        impersonate_req(&admin_tokens, &user_id, json!({ "reason": " " })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp = test::call_service(
+        &app,
+        impersonate_req(
+            &admin_tokens,
+            &user_id,
+            json!({ "reason": "Ticket #4521", "scope": "appointments:write" }),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Administrators cannot borrow each other's permissions
+    let resp = test::call_service(
+        &app,
+        impersonate_req(&admin_tokens, &other_admin_id, reason.clone()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = test::call_service(
+        &app,
+        impersonate_req(&admin_tokens, &user_id, reason.clone()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let grant: Value = test::read_body_json(resp).await;
+    assert!(grant.get("refresh_token").is_none());
+    assert_eq!(grant["expires_in"], 300);
+    assert_eq!(
+        grant["scope"],
+        "appointments:read records:read prescriptions:read"
+    );
+
+    let claims = services::jwt::decode_access_token(
+        &TEST_TOKEN_KEYS,
+        grant["access_token"].as_str().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims.registered_claims.sub, user_id);
+    assert_eq!(claims.act.unwrap().sub, admin_id);
+    assert!(claims.impersonated);
+    assert!(claims.roles.is_empty());
+    assert!(claims.permissions.is_empty());
+
+    // Ordinary tokens do not carry the flag
+    assert!(!access_claims(&user_tokens).impersonated);
+
+    let req = test::TestRequest::get()
+        .uri("/api/me/impersonations")
+        .insert_header(bearer(&user_tokens))
+        .to_request();
+    let impersonations: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(impersonations.as_array().unwrap().len(), 1);
+    assert_eq!(impersonations[0]["admin_id"], admin_id.as_str());
+    assert_eq!(
+        impersonations[0]["reason"],
+        "Ticket #4521: appointment list is empty"
+    );
+
+    let mut conn = TEST_POOL.get().unwrap();
+    let audited: Vec<(String, Option<String>)> = schema::audit_events::table
+        .filter(schema::audit_events::event_type.eq("admin.user.impersonated"))
+        .filter(schema::audit_events::subject_id.eq(Uuid::parse_str(&user_id).unwrap()))
+        .select((schema::audit_events::outcome, schema::audit_events::reason))
+        .load(&mut conn)
+        .unwrap();
+    assert!(audited.iter().any(|(outcome, reason)| outcome == "success"
+        && reason
+            .as_deref()
+            .is_some_and(|reason| reason.contains("Ticket #4521: appointment list is empty"))));
+
+    cleanup_user_and_tokens_by_email(admin_email);
+    cleanup_user_and_tokens_by_email(other_admin_email);
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_audit_log_is_append_only_and_searchable() {
+    let user_email = "audit_log_user@example.com";
+    let admin_email = "audit_log_admin@example.com";
+    let password = "Sunflower-Orbit-42";
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(refresh)
+                    .service(revoke)
+                    .service(list_audit_events)
+                    .service(export_audit_events),
+            ),
+    )
+    .await;
+
+    let tokens = register_and_obtain(&app, user_email, password, "pacilian").await;
+    let user_id = access_claims(&tokens).user_id;
+
+    let req = test::TestRequest::post()
+        .uri("/api/token/obtain")
+        .set_json(json!({ "email": user_email, "password": "Wrong-Password-1" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri("/api/token/refresh")
+        .set_json(json!({ "refresh_token": tokens["refresh"] }))
+        .to_request();
+    let refreshed: Value = test::call_and_read_body_json(&app, req).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/token/revoke")
+        .set_json(json!({ "refresh_token": refreshed["refresh"] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Searching the trail takes the audit:read permission
+    let req = test::TestRequest::get()
+        .uri("/api/admin/audit-events")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", tokens["access"].as_str().unwrap()),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    register_and_obtain(&app, admin_email, password, "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, password).await;
+    let admin_auth = (
+        "Authorization",
+        format!("Bearer {}", admin_tokens["access"].as_str().unwrap()),
+    );
+
+    let mut event_types = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut uri = format!("/api/admin/audit-events?subject_id={}&limit=2", user_id);
+        if let Some(cursor) = &cursor {
+            uri.push_str(&format!("&cursor={}", cursor));
+        }
+        let req = test::TestRequest::get()
+            .uri(&uri)
+            .insert_header(admin_auth.clone())
+            .to_request();
+        let page: Value = test::call_and_read_body_json(&app, req).await;
+        let events = page["events"].as_array().unwrap();
+        assert!(events.len() <= 2);
+        event_types.extend(
+            events
+                .iter()
+                .map(|event| event["event_type"].as_str().unwrap().to_string()),
+        );
+
+        match page["next_cursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    // Newest first
+    assert_eq!(
+        event_types,
+        vec![
+            "token.revoked",
+            "token.refreshed",
+            "user.login",
+            "user.registered"
+        ]
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/audit-events?event_type=user.login&outcome=failure&limit=500")
+        .insert_header(admin_auth.clone())
+        .to_request();
+    let failures: Value = test::call_and_read_body_json(&app, req).await;
+    assert!(failures["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|event| event["outcome"] == "failure" && event["event_type"] == "user.login"));
+    assert!(!failures["events"].as_array().unwrap().is_empty());
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/admin/audit-events/export?subject_id={}",
+            user_id
+        ))
+        .insert_header(admin_auth.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/x-ndjson"
+    );
+    let body = test::read_body(resp).await;
+    let exported: Vec<Value> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(exported.len(), 4);
+    assert!(exported
+        .iter()
+        .all(|event| event["subject_id"] == user_id.as_str()));
+
+    // Rows can be added but never changed or removed
+    let mut conn = TEST_POOL.get().unwrap();
+    let subject = Uuid::parse_str(&user_id).unwrap();
+    assert!(diesel::update(
+        schema::audit_events::table.filter(schema::audit_events::subject_id.eq(subject))
+    )
+    .set(schema::audit_events::outcome.eq("success"))
+    .execute(&mut conn)
+    .is_err());
+    assert!(diesel::delete(
+        schema::audit_events::table.filter(schema::audit_events::subject_id.eq(subject))
+    )
+    .execute(&mut conn)
+    .is_err());
+
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+}
+
+#[actix_web::test]
+async fn test_audit_log_hash_chain_and_checkpoints() {
+    use crate::{
+        errors::audit::ChainBreak,
+        models::audit::ClientInfo,
+        services::{
+            audit::{AuditEntry, ChainVerifier},
+            jwt::signing::rs256::RS256Verifier,
+        },
+    };
+
+    let mut conn = TEST_POOL.get().unwrap();
+    let subject = Uuid::new_v4();
+    let client = ClientInfo {
+        ip_address: Some("203.0.113.7".to_string()),
+        user_agent: Some("chain-test".to_string()),
+    };
+
+    for event_type in ["user.login", "token.refreshed", "token.revoked"] {
+        services::audit::record(
+            &mut conn,
+            &client,
+            AuditEntry::success(event_type, Some(subject), Some(subject)),
+        );
+    }
+
+    let checkpoint = services::audit::create_checkpoint(&mut conn, &TEST_PEM_KEY)
+        .unwrap()
+        .expect("new events should be signed");
+
+    let summary = services::audit::verify_chain(&mut conn, &TEST_PEM_KEY).unwrap();
+    assert!(summary.chained_events >= 3);
+    assert!(summary.checkpoints >= 1);
+
+    // Each entry points at the hash of the one before it
+    let checkpoints = repository::audit::get_audit_checkpoints(&mut conn).unwrap();
+    let events = repository::audit::get_audit_events_after(&mut conn, 0, i64::MAX).unwrap();
+    let ours: Vec<usize> = events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| event.subject_id == Some(subject))
+        .map(|(index, _)| index)
+        .collect();
+    assert_eq!(ours.len(), 3);
+    assert_eq!(
+        events[ours[1]].previous_hash,
+        events[ours[1] - 1].entry_hash
+    );
+    assert!(events[ours[0]].id <= checkpoint.last_event_id);
+
+    let verifier = RS256Verifier::new(&TEST_PEM_KEY).unwrap();
+    let verify = |events: &[models::audit::AuditEvent], checkpoints| {
+        let mut chain = ChainVerifier::new(&verifier, checkpoints);
+        for event in events {
+            chain.check(event)?;
+        }
+        chain.finish()
+    };
+    assert!(verify(&events, checkpoints.clone()).is_ok());
+
+    // An edited entry no longer matches its hash
+    let mut edited = events.clone();
+    edited[ours[1]].reason = Some("edited".to_string());
+    assert_eq!(
+        verify(&edited, checkpoints.clone()).unwrap_err(),
+        ChainBreak::HashMismatch(events[ours[1]].id)
+    );
+
+    // A removed entry breaks the link from the one after it
+    let mut removed = events.clone();
+    removed.remove(ours[1]);
+    assert_eq!(
+        verify(&removed, checkpoints.clone()).unwrap_err(),
+        ChainBreak::BrokenLink(events[ours[2]].id)
+    );
+
+    // Checkpoints only count with the service's signature
+    let mut forged = checkpoints.clone();
+    let last = forged.len() - 1;
+    forged[last].signature = forged[0].signature.clone() + "A";
+    assert_eq!(
+        verify(&events, forged).unwrap_err(),
+        ChainBreak::InvalidSignature(checkpoints[last].id)
+    );
+}
+
+#[actix_web::test]
+async fn test_login_history_and_new_device_alerts() {
+    let user_email = "login_history_user@example.com";
+    let password = "Sunflower-Orbit-42";
+    cleanup_user_and_tokens_by_email(user_email);
+    let mailer = Arc::new(MemoryMailSender::default());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(web::Data::from(mailer.clone() as Arc<dyn MailSender>))
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(refresh)
+                    .service(list_logins)
+                    .service(disown_login),
+            ),
+    )
+    .await;
+
+    let obtain_with = |user_agent: &'static str| {
+        test::TestRequest::post()
+            .uri("/api/token/obtain")
+            .insert_header(("User-Agent", user_agent))
+            .set_json(json!({ "email": user_email, "password": password }))
+            .to_request()
+    };
+
+    // Nothing is new about the first sign in
+    register_and_obtain(&app, user_email, password, "pacilian").await;
+    let sent = mailer.sent_to(user_email).len();
+
+    let laptop = obtain_with("Laptop/1.0");
+    assert_eq!(
+        test::call_service(&app, laptop).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(mailer.sent_to(user_email).len(), sent + 1);
+    let laptop_again = obtain_with("Laptop/1.0");
+    let laptop_tokens: Value = test::call_and_read_body_json(&app, laptop_again).await;
+    assert_eq!(mailer.sent_to(user_email).len(), sent + 1);
+
+    let phone = obtain_with("Phone/2.0");
+    let phone_tokens: Value = test::call_and_read_body_json(&app, phone).await;
+    let alert = mailer.sent_to(user_email).pop().unwrap();
+    assert_eq!(mailer.sent_to(user_email).len(), sent + 2);
+    assert_eq!(alert.subject, "New sign in to your PandaCare account");
+    assert!(alert.body.contains("Device: Phone/2.0"));
+
+    let phone_again = obtain_with("Phone/2.0");
+    assert_eq!(
+        test::call_service(&app, phone_again).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(mailer.sent_to(user_email).len(), sent + 2);
+
+    let req = test::TestRequest::get()
+        .uri("/api/me/logins")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", phone_tokens["access"].as_str().unwrap()),
+        ))
+        .to_request();
+    let logins: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let devices: Vec<(&str, bool)> = logins
+        .iter()
+        .map(|login| {
+            (
+                login["user_agent"].as_str().unwrap_or_default(),
+                login["new_device"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    // Newest first
+    assert_eq!(
+        devices,
+        vec![
+            ("Phone/2.0", false),
+            ("Phone/2.0", true),
+            ("Laptop/1.0", false),
+            ("Laptop/1.0", true),
+            ("", false),
+        ]
+    );
+
+    // "This wasn't me" signs the user out everywhere
+    let token = alert
+        .body
+        .split("token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap()
+        .to_string();
+    let req = test::TestRequest::post()
+        .uri("/api/logins/disown")
+        .set_json(json!({ "token": token }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    for tokens in [&laptop_tokens, &phone_tokens] {
+        let req = test::TestRequest::post()
+            .uri("/api/token/refresh")
+            .set_json(json!({ "refresh_token": tokens["refresh"] }))
+            .to_request();
+        assert!(!test::call_service(&app, req).await.status().is_success());
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/logins/disown")
+        .set_json(json!({ "token": token }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    cleanup_user_and_tokens_by_email(user_email);
+}
+
+#[actix_web::test]
+async fn test_geoip_database_lookup() {
+    use crate::services::geoip::{GeoIpDatabase, Location};
+
+    let csv = "network,country_code,city\n\
+               203.0.113.0/24,id,Depok\n\
+               198.51.100.0/25,SG,\n\
+               2001:db8::/32,JP,Tokyo\n";
+    let database = GeoIpDatabase::from_reader(csv.as_bytes()).unwrap();
+
+    assert_eq!(
+        database.locate("203.0.113.200".parse().unwrap()),
+        Some(&Location {
+            country_code: "ID".to_string(),
+            city: Some("Depok".to_string()),
+        })
+    );
+    assert_eq!(
+        database.locate("198.51.100.1".parse().unwrap()),
+        Some(&Location {
+            country_code: "SG".to_string(),
+            city: None,
+        })
+    );
+    assert_eq!(database.locate("198.51.100.128".parse().unwrap()), None);
+    assert_eq!(
+        database
+            .locate("2001:db8::1".parse().unwrap())
+            .map(|location| location.country_code.as_str()),
+        Some("JP")
+    );
+    assert_eq!(database.locate("192.0.2.1".parse().unwrap()), None);
+
+    assert!(
+        GeoIpDatabase::from_reader("network,country_code,city\n10.0.0.0/33,ID,\n".as_bytes())
+            .is_err()
+    );
+}
+
+#[actix_web::test]
+async fn test_admin_suspends_and_soft_deletes_user() {
+    let admin_email = "status_admin@example.com";
+    let user_email = "status_target@example.com";
+    let password = "Sunflower-Orbit-42";
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(TEST_POOL.clone()))
+            .app_data(TEST_TOKEN_KEYS.clone())
+            .app_data(test_mailer())
+            .service(
+                web::scope("/api")
+                    .service(register)
+                    .service(obtain)
+                    .service(refresh)
+                    .service(set_user_status),
+            ),
+    )
+    .await;
+
+    let user_tokens = register_and_obtain(&app, user_email, password, "pacilian").await;
+    let user_id = access_claims(&user_tokens).user_id;
+
+    register_and_obtain(&app, admin_email, password, "pacilian").await;
+    grant_role_by_email(admin_email, "admin");
+    let admin_tokens = obtain_tokens(&app, admin_email, password).await;
+    let admin_auth = (
+        "Authorization",
+        format!("Bearer {}", admin_tokens["access"].as_str().unwrap()),
+    );
+
+    let set_status = |status: &str| {
+        test::TestRequest::put()
+            .uri(&format!("/api/admin/users/{}/status", user_id))
+            .insert_header(admin_auth.clone())
+            .set_json(json!({ "status": status }))
+            .to_request()
+    };
+    let login = || {
+        test::TestRequest::post()
+            .uri("/api/token/obtain")
+            .set_json(json!({ "email": user_email, "password": password }))
+            .to_request()
+    };
+
+    let suspended: Value = test::call_and_read_body_json(&app, set_status("suspended")).await;
+    assert_eq!(suspended["status"], "suspended");
+    assert!(suspended["deleted_at"].is_null());
+
+    let resp = test::call_service(&app, login()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body = test::read_body(resp).await;
+    assert!(std::str::from_utf8(&body).unwrap().contains("suspended"));
+
+    // Outstanding refresh tokens are refused with the reason
+    let req = test::TestRequest::post()
+        .uri("/api/token/refresh")
+        .set_json(json!({ "refresh_token": user_tokens["refresh"] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body = test::read_body(resp).await;
+    assert!(std::str::from_utf8(&body).unwrap().contains("suspended"));
+
+    let active: Value = test::call_and_read_body_json(&app, set_status("active")).await;
+    assert_eq!(active["status"], "active");
+    // The suspension revoked the earlier session
+    let req = test::TestRequest::post()
+        .uri("/api/token/refresh")
+        .set_json(json!({ "refresh_token": user_tokens["refresh"] }))
+        .to_request();
+    assert!(!test::call_service(&app, req).await.status().is_success());
+    obtain_tokens(&app, user_email, password).await;
+
+    let deleted: Value = test::call_and_read_body_json(&app, set_status("deleted")).await;
+    assert_eq!(deleted["status"], "deleted");
+    assert!(deleted["deleted_at"].is_string());
+
+    let resp = test::call_service(&app, login()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body = test::read_body(resp).await;
+    assert!(std::str::from_utf8(&body).unwrap().contains("deleted"));
+
+    // The row stays for whatever points at it
+    let mut conn = TEST_POOL.get().unwrap();
+    let user =
+        repository::users::get_user_by_id(&mut conn, Uuid::parse_str(&user_id).unwrap()).unwrap();
+    assert!(user.deleted_at.is_some());
+
+    let resp = test::call_service(&app, set_status("archived")).await;
+    assert!(resp.status().is_client_error());
+
+    cleanup_user_and_tokens_by_email(user_email);
+    cleanup_user_and_tokens_by_email(admin_email);
+
+    // Removing the row for good takes the user's refresh tokens with it
+    let remaining: i64 = schema::refresh_tokens::table
+        .filter(schema::refresh_tokens::user_id.eq(user.id))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(remaining, 0);
+}